@@ -1,6 +1,6 @@
 // ── helpers ───────────────────────────────────────────────────────────────
 
-function stemFromUrl() {
+function noteIdFromUrl() {
     var m = location.pathname.match(/^\/(note|f)\/(.+)/);
     return m ? decodeURIComponent(m[2]) : null;
 }
@@ -17,14 +17,16 @@ function currentMode() {
 // ── view sync ─────────────────────────────────────────────────────────────
 
 function syncView(scroll) {
-    var stem = stemFromUrl();
-    document.body.toggleAttribute('data-note', !!stem);
+    var noteId = noteIdFromUrl();
+    // The tags, health and graph pages take the note column as well.
+    var page = ['/tags', '/health', '/graph'].indexOf(location.pathname) >= 0;
+    document.body.toggleAttribute('data-note', !!noteId || page);
 
     document.querySelectorAll('.note-row.is-active').forEach(function(el) {
         el.classList.remove('is-active');
     });
-    if (stem) {
-        var row = document.querySelector('.note-row[data-stem="' + CSS.escape(stem) + '"]');
+    if (noteId) {
+        var row = document.querySelector('.note-row[data-id="' + CSS.escape(noteId) + '"]');
         if (row) {
             row.classList.add('is-active');
             if (scroll) row.scrollIntoView({ block: 'nearest' });
@@ -228,6 +230,7 @@ function clearClipStatus() {
 function openClip() {
     var d = document.getElementById('clip-drawer');
     if (!d) return;
+    closeCreate();
     clearClipStatus();
     d.classList.add('is-open');
     var i = document.getElementById('clip-input');
@@ -247,6 +250,32 @@ function clipOpen() {
     return !!(d && d.classList.contains('is-open'));
 }
 
+// ── create drawer ─────────────────────────────────────────────────────────
+
+function openCreate() {
+    var d = document.getElementById('create-drawer');
+    if (!d) return;
+    closeClip();
+    d.classList.add('is-open');
+    var i = document.getElementById('create-title');
+    if (i) i.focus();
+}
+
+function closeCreate() {
+    var d = document.getElementById('create-drawer');
+    if (!d) return;
+    d.classList.remove('is-open');
+    var f = document.getElementById('create-form');
+    if (f) f.reset();
+    var i = document.getElementById('create-title');
+    if (i) i.blur();
+}
+
+function createOpen() {
+    var d = document.getElementById('create-drawer');
+    return !!(d && d.classList.contains('is-open'));
+}
+
 // ── search ────────────────────────────────────────────────────────────────
 
 function focusSearch() {
@@ -258,36 +287,308 @@ function clearSearch() {
     var i = document.getElementById('filter-input');
     if (!i) return;
     i.value = '';
-    htmx.ajax('POST', '/f/search', { target: '#search-list', values: { query: '' } });
+    htmx.ajax('POST', '/f/search', { target: '#search-list', values: { query: '', view: currentView() } });
     i.blur();
 }
 
+// ── views (saved filters) ─────────────────────────────────────────────────
+
+function currentView() {
+    var v = document.getElementById('view-input');
+    return v ? v.value : '';
+}
+
+function selectView(name) {
+    var v = document.getElementById('view-input');
+    if (!v) return;
+    v.value = name;
+    document.querySelectorAll('.view-chip[data-view]').forEach(function(el) {
+        el.classList.toggle('is-on', el.getAttribute('data-view') === name);
+    });
+    htmx.trigger('#filter-input', 'view-changed');
+}
+
+// Save the current search (within the current view) as a new view, then
+// show it with an empty search box.
+function saveView() {
+    var i = document.getElementById('filter-input');
+    var name = (window.prompt('Name of the new view') || '').trim();
+    if (!name) return;
+    htmx.ajax('POST', '/filter', {
+        target: '#views',
+        swap: 'outerHTML',
+        values: { name: name, query: i ? i.value : '', view: currentView() }
+    }).then(function() {
+        // Errors leave the old views in place.
+        if (currentView() !== name) return;
+        if (i) i.value = '';
+        htmx.trigger('#filter-input', 'view-changed');
+    });
+}
+
 // ── mode toggle (Read / Edit) ─────────────────────────────────────────────
 
 function switchMode(mode) {
-    var stem = stemFromUrl();
-    if (!stem) return;
+    var noteId = noteIdFromUrl();
+    if (!noteId) return;
     if (mode === currentMode()) return;
     if (mode === 'read' && currentMode() === 'edit') {
         // Auto-save: PUT the textarea body; the server returns the read-mode
         // article, which HTMX swaps into #note-content.
         var ta = document.getElementById('editor-textarea');
-        htmx.ajax('PUT', '/f/' + encodeURIComponent(stem), {
+        htmx.ajax('PUT', '/f/' + encodeURIComponent(noteId), {
             target: '#note-content',
             values: { body: ta ? ta.value : '' }
         });
         return;
     }
-    var url = mode === 'edit' ? '/f/' + encodeURIComponent(stem) + '/edit'
-                              : '/f/' + encodeURIComponent(stem);
+    var url = mode === 'edit' ? '/f/' + encodeURIComponent(noteId) + '/edit'
+                              : '/f/' + encodeURIComponent(noteId);
     htmx.ajax('GET', url, { target: '#note-content' });
 }
 
+// Select `line` (starting at 1) in the editor, e.g. to show a dead link.
+function gotoLine(line) {
+    var ta = document.getElementById('editor-textarea');
+    if (!ta) return;
+    var lines = ta.value.split('\n');
+    var start = 0;
+    for (var i = 0; i < line - 1 && i < lines.length; i++) start += lines[i].length + 1;
+    var end = start + (lines[line - 1] || '').length;
+    ta.focus({ preventScroll: true });
+    ta.setSelectionRange(start, end);
+    var lineHeight = parseFloat(getComputedStyle(ta).lineHeight) || 20;
+    ta.scrollTop = Math.max(0, (line - 3) * lineHeight);
+}
+
+// ── link graph ────────────────────────────────────────────────────────────
+
+var SVG_NS = 'http://www.w3.org/2000/svg';
+
+// Load and draw every graph view whose data source changed.
+function initGraphs() {
+    document.querySelectorAll('.graph-view[data-src]').forEach(function(view) {
+        var src = view.getAttribute('data-src');
+        if (view.getAttribute('data-loaded') === src) return;
+        view.setAttribute('data-loaded', src);
+        fetch(src, { credentials: 'same-origin' })
+            .then(function(r) { return r.ok ? r.json() : Promise.reject(r.status); })
+            .then(function(graph) { drawGraph(view, graph); })
+            .catch(function() { view.textContent = 'graph could not be loaded'; });
+    });
+}
+
+function setGraphDepth(select) {
+    var view = select.closest('.note-nav-section').querySelector('.graph-view');
+    view.setAttribute('data-src',
+        view.getAttribute('data-src').replace(/depth=\d+/, 'depth=' + select.value));
+    initGraphs();
+}
+
+// Place the nodes with a Fruchterman-Reingold simulation: nodes repel each
+// other, links pull their ends together and gravity keeps unlinked notes
+// close. The center note of a local graph stays in the middle.
+function layoutGraph(graph, width, height) {
+    var nodes = graph.nodes;
+    var local = isLocal(graph);
+    nodes.forEach(function(node, i) {
+        var angle = i * 2.4;
+        var radius = 10 * Math.sqrt(i + 1);
+        node.x = width / 2 + radius * Math.cos(angle);
+        node.y = height / 2 + radius * Math.sin(angle);
+    });
+
+    var ticks = nodes.length > 400 ? 80 : 250;
+    var k = Math.sqrt(width * height / Math.max(nodes.length, 1)) * 0.6;
+    for (var tick = 0; tick < ticks; tick++) {
+        var temperature = width / 10 * (1 - tick / ticks) + 1;
+        nodes.forEach(function(node) { node.dx = 0; node.dy = 0; });
+
+        for (var i = 0; i < nodes.length; i++) {
+            for (var j = i + 1; j < nodes.length; j++) {
+                var a = nodes[i], b = nodes[j];
+                var dx = a.x - b.x, dy = a.y - b.y;
+                var dist2 = Math.max(dx * dx + dy * dy, 0.01);
+                var force = k * k / dist2;
+                a.dx += dx * force; a.dy += dy * force;
+                b.dx -= dx * force; b.dy -= dy * force;
+            }
+        }
+        graph.links.forEach(function(link) {
+            var a = nodes[link[0]], b = nodes[link[1]];
+            var dx = a.x - b.x, dy = a.y - b.y;
+            var force = Math.sqrt(dx * dx + dy * dy) / k;
+            a.dx -= dx * force; a.dy -= dy * force;
+            b.dx += dx * force; b.dy += dy * force;
+        });
+        nodes.forEach(function(node) {
+            node.dx += width / 2 - node.x;
+            node.dy += height / 2 - node.y;
+            var step = Math.max(Math.sqrt(node.dx * node.dx + node.dy * node.dy), 0.01);
+            var move = Math.min(step, temperature);
+            node.x += node.dx / step * move;
+            node.y += node.dy / step * move;
+        });
+        if (local) { nodes[0].x = width / 2; nodes[0].y = height / 2; }
+    }
+}
+
+// Return the view box showing all `nodes`, with the aspect ratio of the view.
+function fitBox(nodes, width, height) {
+    var pad = 40;
+    var xs = nodes.map(function(node) { return node.x; });
+    var ys = nodes.map(function(node) { return node.y; });
+    var box = {
+        x: Math.min.apply(null, xs) - pad,
+        y: Math.min.apply(null, ys) - pad,
+        w: Math.max.apply(null, xs) - Math.min.apply(null, xs) + 2 * pad,
+        h: Math.max.apply(null, ys) - Math.min.apply(null, ys) + 2 * pad,
+    };
+    var scale = Math.max(box.w / width, box.h / height);
+    box.x -= (width * scale - box.w) / 2;
+    box.y -= (height * scale - box.h) / 2;
+    box.w = width * scale;
+    box.h = height * scale;
+    return box;
+}
+
+// A local graph has the center note first, the other notes are further away.
+function isLocal(graph) {
+    return graph.nodes.some(function(node) { return node.depth > 0; });
+}
+
+function svgElement(name, attrs) {
+    var el = document.createElementNS(SVG_NS, name);
+    for (var key in attrs) el.setAttribute(key, attrs[key]);
+    return el;
+}
+
+// Draw `graph` into `view` as SVG. Clicking a node opens the note, nodes can
+// be dragged, the wheel zooms and dragging the background pans.
+function drawGraph(view, graph) {
+    view.textContent = '';
+    if (graph.nodes.length === 0) {
+        view.textContent = 'no notes to show';
+        return;
+    }
+
+    var width = view.clientWidth || 600;
+    var height = view.clientHeight || 400;
+    layoutGraph(graph, width, height);
+
+    var degree = graph.nodes.map(function() { return 0; });
+    graph.links.forEach(function(link) { degree[link[0]]++; degree[link[1]]++; });
+    var labels = graph.nodes.length <= 60;
+
+    var box = fitBox(graph.nodes, width, height);
+    var svg = svgElement('svg', { class: 'graph-svg', role: 'img', 'aria-label': 'Link graph' });
+    function setBox() { svg.setAttribute('viewBox', [box.x, box.y, box.w, box.h].join(' ')); }
+    setBox();
+
+    var lines = graph.links.map(function(link) {
+        var line = svgElement('line', { class: 'graph-link' });
+        svg.appendChild(line);
+        return line;
+    });
+    var groups = graph.nodes.map(function(node, i) {
+        var group = svgElement('g', {
+            class: 'graph-node' + (node.depth === 0 && i === 0 && isLocal(graph) ? ' is-center' : ''),
+            tabindex: '0',
+        });
+        group.appendChild(svgElement('circle', { r: 4 + Math.sqrt(degree[i]) * 1.5 }));
+        var title = svgElement('title', {});
+        title.textContent = node.title;
+        group.appendChild(title);
+        if (labels) {
+            var text = svgElement('text', { x: 0, y: -9 });
+            text.textContent = node.title;
+            group.appendChild(text);
+        }
+        svg.appendChild(group);
+        return group;
+    });
+
+    function render() {
+        graph.links.forEach(function(link, i) {
+            var a = graph.nodes[link[0]], b = graph.nodes[link[1]];
+            lines[i].setAttribute('x1', a.x); lines[i].setAttribute('y1', a.y);
+            lines[i].setAttribute('x2', b.x); lines[i].setAttribute('y2', b.y);
+        });
+        graph.nodes.forEach(function(node, i) {
+            groups[i].setAttribute('transform', 'translate(' + node.x + ',' + node.y + ')');
+        });
+    }
+    render();
+
+    function openNode(node) {
+        var url = '/note/' + encodeURIComponent(node.id);
+        history.pushState({}, '', url);
+        htmx.ajax('GET', '/f/' + encodeURIComponent(node.id), { target: '#note-content' });
+    }
+
+    // Pointer position in graph coordinates.
+    function point(e) {
+        var rect = svg.getBoundingClientRect();
+        return {
+            x: box.x + (e.clientX - rect.left) / rect.width * box.w,
+            y: box.y + (e.clientY - rect.top) / rect.height * box.h,
+        };
+    }
+
+    var drag = null;
+    groups.forEach(function(group, i) {
+        group.addEventListener('pointerdown', function(e) {
+            e.stopPropagation();
+            drag = { node: graph.nodes[i], x: e.clientX, y: e.clientY, moved: false };
+            svg.setPointerCapture(e.pointerId);
+        });
+        group.addEventListener('keydown', function(e) {
+            if (e.key === 'Enter') openNode(graph.nodes[i]);
+        });
+    });
+    svg.addEventListener('pointerdown', function(e) {
+        drag = { pan: point(e), x: e.clientX, y: e.clientY, moved: false };
+        svg.setPointerCapture(e.pointerId);
+    });
+    svg.addEventListener('pointermove', function(e) {
+        if (!drag) return;
+        var p = point(e);
+        // Small movements still count as a click.
+        drag.moved = drag.moved || Math.abs(e.clientX - drag.x) + Math.abs(e.clientY - drag.y) > 4;
+        if (!drag.moved) return;
+        if (drag.node) {
+            drag.node.x = p.x;
+            drag.node.y = p.y;
+            render();
+        } else {
+            box.x -= p.x - drag.pan.x;
+            box.y -= p.y - drag.pan.y;
+            setBox();
+        }
+    });
+    svg.addEventListener('pointerup', function() {
+        if (drag && drag.node && !drag.moved) openNode(drag.node);
+        drag = null;
+    });
+    svg.addEventListener('wheel', function(e) {
+        e.preventDefault();
+        var p = point(e);
+        var scale = e.deltaY > 0 ? 1.1 : 1 / 1.1;
+        box.x = p.x - (p.x - box.x) * scale;
+        box.y = p.y - (p.y - box.y) * scale;
+        box.w *= scale;
+        box.h *= scale;
+        setBox();
+    }, { passive: false });
+
+    view.appendChild(svg);
+}
+
 // ── raw markdown ──────────────────────────────────────────────────────────
 
 function openRaw() {
-    var stem = stemFromUrl();
-    if (stem) location.href = '/raw/' + encodeURIComponent(stem);
+    var noteId = noteIdFromUrl();
+    if (noteId) location.href = '/raw/' + encodeURIComponent(noteId);
 }
 
 // ── keyboard ──────────────────────────────────────────────────────────────
@@ -299,6 +600,7 @@ document.addEventListener('keydown', function(e) {
 
     if (e.key === 'Escape') {
         if (clipOpen()) { closeClip(); return; }
+        if (createOpen()) { closeCreate(); return; }
         var fi = document.getElementById('filter-input');
         if (fi && fi.value) { clearSearch(); return; }
         if (document.querySelector('.shell.is-focus')) { exitFocus(); return; }
@@ -337,7 +639,7 @@ document.addEventListener('keydown', function(e) {
         }
         case 'n': {
             var newBtn = document.getElementById('new-note');
-            if (newBtn) { e.preventDefault(); newBtn.click(); }
+            if (newBtn) { e.preventDefault(); openCreate(); }
             return;
         }
         case 'j':
@@ -363,7 +665,13 @@ document.addEventListener('click', function(e) {
 
     if (e.target.closest('#clip-toggle')) { openClip(); }
     else if (e.target.closest('#clip-cancel')) { closeClip(); }
+    else if (e.target.closest('#new-note')) { openCreate(); }
+    else if (e.target.closest('#create-cancel')) { closeCreate(); }
     else if (e.target.closest('#theme-toggle')) { toggleTheme(); }
+    else if (e.target.closest('#save-view')) { saveView(); }
+    else if (e.target.closest('.view-chip[data-view]')) {
+        selectView(e.target.closest('.view-chip[data-view]').getAttribute('data-view'));
+    }
     else if (e.target.closest('#mode-segctl')) {
         var m = currentMode();
         if (m) switchMode(m === 'edit' ? 'read' : 'edit');
@@ -379,6 +687,7 @@ document.addEventListener('DOMContentLoaded', function() {
     if (persisted) enterFocus();
     syncView(true);
     initTocSpy();
+    initGraphs();
 });
 
 // Follow OS preference live, unless user has picked manually
@@ -390,8 +699,8 @@ try {
 } catch (e) {}
 
 window.addEventListener('popstate', function() {
-    var stem = stemFromUrl();
-    if (stem) htmx.ajax('GET', '/f/' + encodeURIComponent(stem), { target: '#note-content' });
+    var noteId = noteIdFromUrl();
+    if (noteId) htmx.ajax('GET', '/f/' + encodeURIComponent(noteId), { target: '#note-content' });
     syncView(true);
 });
 
@@ -415,6 +724,11 @@ document.addEventListener('htmx:beforeSwap', function(e) {
 });
 
 document.addEventListener('htmx:afterRequest', function(e) {
+    // A created note is opened through HX-Redirect; just tidy the drawer.
+    if (e.detail.elt && e.detail.elt.id === 'create-form' && e.detail.successful) {
+        closeCreate();
+        return;
+    }
     if (!e.detail.elt || e.detail.elt.id !== 'clip-form') return;
     if (!e.detail.successful) return;
     // Success: the new note shows up via SSE. Clear the field and close the
@@ -429,7 +743,11 @@ document.addEventListener('htmx:afterSettle', function(e) {
     if (e.detail.target && e.detail.target.id === 'note-content') {
         syncView(false);
         initTocSpy();
+        initGraphs();
         scrollToHash();
+        // Links from the health page open the editor at a line.
+        var elt = e.detail.requestConfig && e.detail.requestConfig.elt;
+        if (elt && elt.dataset && elt.dataset.line) gotoLine(Number(elt.dataset.line));
     }
 });
 
@@ -460,10 +778,21 @@ document.addEventListener('htmx:responseError', function(e) {
         htmx.trigger(document.body, 'notes-updated');
         try {
             var data = JSON.parse(e.data);
-            var current = stemFromUrl();
-            if (current && current === data.stem) {
+            var current = noteIdFromUrl();
+            if (current && current === data.id) {
                 if (data.removed) showNoteError('note was removed');
-                else htmx.ajax('GET', '/f/' + encodeURIComponent(data.stem), { target: '#note-content' });
+                else htmx.ajax('GET', '/f/' + encodeURIComponent(data.id), { target: '#note-content' });
+            }
+        } catch (err) {}
+    });
+    source.addEventListener('renamed', function(e) {
+        htmx.trigger(document.body, 'notes-updated');
+        try {
+            var data = JSON.parse(e.data);
+            if (noteIdFromUrl() === data.id) {
+                // Follow the open note to its new id.
+                history.replaceState({}, '', '/note/' + encodeURIComponent(data.renamed));
+                htmx.ajax('GET', '/f/' + encodeURIComponent(data.renamed), { target: '#note-content' });
             }
         } catch (err) {}
     });
@@ -60,6 +60,33 @@ pub(crate) fn pin() -> Markup {
     }
 }
 
+pub(crate) fn tag() -> Markup {
+    icon(&[
+        "M20.59 13.41l-7.17 7.17a2 2 0 0 1-2.83 0L2 12V2h10l8.59 8.59a2 2 0 0 1 0 2.82z",
+        "M7 7h.01",
+    ])
+}
+
+pub(crate) fn graph() -> Markup {
+    icon(&[
+        "M18 8a3 3 0 1 0 0-6 3 3 0 0 0 0 6z",
+        "M6 15a3 3 0 1 0 0-6 3 3 0 0 0 0 6z",
+        "M18 22a3 3 0 1 0 0-6 3 3 0 0 0 0 6z",
+        "M8.59 13.51l6.83 3.98",
+        "M15.41 6.51l-6.82 3.98",
+    ])
+}
+
+pub(crate) fn activity() -> Markup {
+    icon(&["M22 12h-4l-3 9L9 3l-3 9H2"])
+}
+
+pub(crate) fn paperclip() -> Markup {
+    icon(&[
+        "m21.44 11.05-9.19 9.19a6 6 0 0 1-8.49-8.49l8.57-8.57A4 4 0 1 1 18 8.84l-8.59 8.57a2 2 0 0 1-2.83-2.83l8.49-8.48",
+    ])
+}
+
 pub(crate) fn plus() -> Markup {
     icon(&["M12 5v14", "M5 12h14"])
 }
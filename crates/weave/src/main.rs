@@ -9,7 +9,7 @@ mod zk;
 use std::convert::Infallible;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, mpsc};
+use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 use axum::Router;
@@ -20,9 +20,7 @@ use axum::routing::{get, post};
 use axum_extra::extract::SignedCookieJar;
 use axum_extra::extract::cookie::{Cookie, Key};
 use futures_concurrency::future::Join;
-use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
-use notify::{EventKind, Watcher};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tokio_stream::StreamExt;
 use tokio_stream::wrappers::BroadcastStream;
 use tower::ServiceBuilder;
@@ -33,10 +31,13 @@ use tower_http::trace::TraceLayer;
 type Notebook = Arc<Mutex<zk::Notebook>>;
 type EventSender = tokio::sync::broadcast::Sender<NoteEvent>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 struct NoteEvent {
-    stem: String,
+    id: String,
     removed: bool,
+    /// New id of a renamed note.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    renamed: Option<String>,
 }
 
 pub(crate) type Issuer = Arc<jwt::Issuer>;
@@ -105,104 +106,47 @@ async fn logout(jar: SignedCookieJar) -> (SignedCookieJar, Redirect) {
     (jar.remove("jwt"), Redirect::to("/"))
 }
 
-enum WatchEvent {
-    Modified(std::path::PathBuf),
-    Removed(std::path::PathBuf),
-}
-
 async fn watch(notebook: Notebook, events_tx: EventSender) -> Result<()> {
     let path = notebook.lock().unwrap().path.clone();
 
     tokio::task::spawn_blocking(move || {
-        let (tx, rx) = mpsc::channel::<WatchEvent>();
-
-        let mut watcher = notify::recommended_watcher(move |result| {
-            let Ok(notify::Event { kind, paths, .. }) = result else {
-                return;
-            };
-
-            let is_md = |p: &std::path::Path| p.extension().is_some_and(|e| e == "md");
-
-            match kind {
-                // File deleted outright, or a note was renamed away.
-                EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
-                    for path in paths {
-                        if is_md(&path) {
-                            tracing::debug!(?path, "removed");
-                            let _ = tx.send(WatchEvent::Removed(path));
-                        }
-                    }
-                }
-                // Atomic rename (both paths in one event): first is old, second is new.
-                EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
-                    if let [from, to] = paths.as_slice() {
-                        if is_md(from) {
-                            tracing::debug!(?from, "renamed away");
-                            let _ = tx.send(WatchEvent::Removed(from.clone()));
-                        }
-                        if is_md(to) {
-                            tracing::debug!(?to, "renamed to");
-                            let _ = tx.send(WatchEvent::Modified(to.clone()));
-                        }
-                    }
-                }
-                // Normal write/create/rename-to.
-                EventKind::Access(AccessKind::Close(AccessMode::Write))
-                | EventKind::Create(_)
-                | EventKind::Modify(ModifyKind::Name(_)) => {
-                    for path in paths {
-                        if is_md(&path) {
-                            tracing::debug!(?path, "changed");
-                            let _ = tx.send(WatchEvent::Modified(path));
-                        }
-                    }
-                }
-                _ => {}
-            }
-        })?;
-
-        // Watch recursively so notes in subdirectories are also tracked.
-        watcher.watch(&path, notify::RecursiveMode::Recursive)?;
-
-        while let Ok(event) = rx.recv() {
-            // Debounce: collect events for 200ms after the first one.
-            let mut events = vec![event];
-            std::thread::sleep(std::time::Duration::from_millis(200));
-            while let Ok(e) = rx.try_recv() {
-                events.push(e);
-            }
-
-            // Deduplicate by stem, keeping the last event for each stem.
-            let mut seen = std::collections::HashMap::<String, bool>::new();
-            for event in &events {
-                match event {
-                    WatchEvent::Modified(path) => {
-                        if let Some(stem) = path.file_stem().and_then(|n| n.to_str()) {
-                            seen.insert(stem.to_owned(), false);
-                        }
-                    }
-                    WatchEvent::Removed(path) => {
-                        if let Some(stem) = path.file_stem().and_then(|n| n.to_str()) {
-                            seen.insert(stem.to_owned(), true);
-                        }
-                    }
-                }
-            }
-
-            for (stem, removed) in &seen {
-                if *removed {
-                    tracing::info!(stem, "removing note");
-                    notebook.lock().unwrap().remove(stem);
-                } else {
-                    if let Err(err) = notebook.lock().unwrap().reload(stem) {
-                        tracing::error!(?err, "failed to reload {stem}");
+        // Watches notes in subdirectories too and debounces the changes.
+        let watcher = zk::NotebookWatcher::new(&path)?;
+
+        while let Some(changes) = watcher.recv() {
+            let mut notebook = notebook.lock().unwrap();
+            // Moves done by copying and deleting the note.
+            let changes = notebook.match_renames(changes);
+            for change in changes {
+                let event = match notebook.apply(&change) {
+                    Ok(Some(event)) => event,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        tracing::error!(?err, ?change, "failed to update the notebook");
                         continue;
                     }
-                }
-                let _ = events_tx.send(NoteEvent {
-                    stem: stem.clone(),
-                    removed: *removed,
-                });
+                };
+                tracing::info!(?event, "notebook changed");
+
+                let event = match event {
+                    zk::NotebookEvent::Created { path }
+                    | zk::NotebookEvent::Modified { path, .. } => NoteEvent {
+                        id: notebook.id_for(&path),
+                        removed: false,
+                        renamed: None,
+                    },
+                    zk::NotebookEvent::Removed { path } => NoteEvent {
+                        id: notebook.id_for(&path),
+                        removed: true,
+                        renamed: None,
+                    },
+                    zk::NotebookEvent::Renamed { from, to } => NoteEvent {
+                        id: notebook.id_for(&from),
+                        removed: false,
+                        renamed: Some(notebook.id_for(&to)),
+                    },
+                };
+                let _ = events_tx.send(event);
             }
         }
 
@@ -217,14 +161,14 @@ async fn events(
     State(tx): State<EventSender>,
 ) -> Sse<impl tokio_stream::Stream<Item = Result<Event, Infallible>>> {
     let stream = BroadcastStream::new(tx.subscribe()).filter_map(|r| {
-        r.ok().map(|e| {
-            let data = format!(
-                r#"{{"stem":"{}","removed":{}}}"#,
-                e.stem.replace('\\', "\\\\").replace('"', "\\\""),
-                e.removed
-            );
-            Ok(Event::default().event("notes-updated").data(data))
-        })
+        let note_event = r.ok()?;
+        let name = if note_event.renamed.is_some() {
+            "renamed"
+        } else {
+            "notes-updated"
+        };
+        let data = serde_json::to_string(&note_event).ok()?;
+        Some(Ok(Event::default().event(name).data(data)))
     });
     Sse::new(stream).keep_alive(KeepAlive::default())
 }
@@ -250,13 +194,13 @@ async fn main() -> Result<()> {
         .transpose()?
         .unwrap_or(8000);
 
-    let notebook = zk::Notebook::load()?;
+    let mut notebook = zk::Notebook::load()?;
 
     let attachments = std::env::var("WEAVE_ATTACHMENTS")
         .ok()
         .map(PathBuf::from)
         .map(|subdir| {
-            let fs_path = notebook.attachments(&subdir)?;
+            let fs_path = notebook.serve_attachments(&subdir)?;
             Ok::<_, zk::Error>((subdir, fs_path))
         })
         .transpose()?;
@@ -276,19 +220,35 @@ async fn main() -> Result<()> {
 
     let mut app = Router::new()
         .route("/", get(pages::index::index))
-        .route("/note/{stem}", get(pages::note::note))
-        .route("/raw/{stem}", get(pages::raw::raw))
+        .route("/note/{id}", get(pages::note::note))
+        .route("/raw/{id}", get(pages::raw::raw))
+        .route("/tags", get(pages::tags::tags))
+        .route("/tags/rename", post(partials::tags::rename))
+        .route("/tags/delete", post(partials::tags::delete))
+        .route("/graph", get(pages::graph::graph))
+        .route("/graph.json", get(partials::graph::data))
+        .route("/health", get(pages::health::health))
+        .route("/health/create", post(partials::health::create))
+        .route("/attachments", get(pages::attachments::attachments))
+        .route("/attachments/delete", post(partials::attachments::delete))
+        .route(
+            "/attachments/cleanup",
+            get(partials::attachments::preview).post(partials::attachments::cleanup),
+        )
         .route("/login", get(pages::login::login).post(do_login))
         .route("/logout", get(logout))
         .route("/clip", post(partials::clip::clip))
         .route("/note", post(partials::create::create))
+        .route("/filter", post(partials::views::save))
         .route("/f/search", post(partials::search::search))
         .route(
-            "/f/{stem}",
+            "/f/{id}",
             get(partials::note::note).put(partials::edit::save),
         )
-        .route("/f/{stem}/edit", get(partials::edit::edit))
-        .route("/f/{stem}/preview", post(partials::edit::preview))
+        .route("/f/{id}/edit", get(partials::edit::edit))
+        .route("/f/{id}/preview", post(partials::edit::preview))
+        .route("/f/{id}/rename", post(partials::edit::rename))
+        .route("/f/{id}/frontmatter", post(partials::frontmatter::update))
         .route("/events", get(events))
         .route("/app.css", get(assets::app_css))
         .route("/app.js", get(assets::app_js))
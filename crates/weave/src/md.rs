@@ -7,10 +7,14 @@ use std::sync::{LazyLock, Mutex};
 use maud::{Markup, PreEscaped, html};
 use merman::render::HeadlessRenderer;
 use merman::MermaidConfig;
-use pulldown_cmark::{BlockQuoteKind, CodeBlockKind, Event, Options, Parser, Tag as CmarkTag};
+use pulldown_cmark::{
+    BlockQuoteKind, CodeBlockKind, Event, LinkType, Options, Parser, Tag as CmarkTag,
+};
 use syntect::html::{ClassStyle, ClassedHTMLGenerator};
 use syntect::parsing::SyntaxSet;
 
+use crate::zk::encode_id;
+
 #[derive(Debug, Clone, Copy)]
 enum Segment<'a> {
     Text(&'a str),
@@ -104,7 +108,10 @@ enum MdTag {
     Emphasis,
     Strong,
     Strikethrough,
-    WikiLink(String),
+    WikiLink {
+        id: String,
+        anchor: Option<String>,
+    },
     ExternalLink(String),
     Table,
     TableHead,
@@ -130,7 +137,45 @@ enum MdNode {
 static WIKI_LINK_RE: LazyLock<regex::Regex> =
     LazyLock::new(|| regex::Regex::new(r"^(?:\.{0,2}/)*(?P<stem>[\w-]+)$").expect("compiling regex"));
 
-fn build_tree(parser: Parser) -> MdNode {
+/// Maps a link target to the id of the note it points to.
+pub type Resolve<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// Turn a link destination into a [`MdTag::WikiLink`] if it points to a note.
+///
+/// Targets are looked up with `resolve` first. Unresolved `[[target]]` links
+/// fall back to the target's file stem, Markdown links to a bare stem, which
+/// the server resolves if it is unique.
+fn note_link(dest: &str, double_bracket: bool, resolve: Resolve) -> Option<MdTag> {
+    let (target, heading) = match dest.split_once('#') {
+        Some((target, heading)) => (target.trim(), Some(heading)),
+        None => (dest.trim(), None),
+    };
+
+    let id = if target.is_empty() {
+        // `[[#heading]]` points into the current note.
+        double_bracket.then(String::new)?
+    } else if let Some(id) = resolve(target) {
+        id
+    } else if double_bracket {
+        let file = target.rsplit('/').next().unwrap_or(target);
+        file.strip_suffix(".md").unwrap_or(file).to_owned()
+    } else {
+        WIKI_LINK_RE.captures(target)?["stem"].to_owned()
+    };
+
+    Some(MdTag::WikiLink {
+        id,
+        anchor: heading.map(heading_anchor),
+    })
+}
+
+/// Check if a Markdown link destination starts with a URL scheme.
+fn has_scheme(dest: &str) -> bool {
+    dest.split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.is_empty() && !scheme.contains(['/', '#']))
+}
+
+fn build_tree(parser: Parser, resolve: Resolve) -> MdNode {
     let mut stack: Vec<(MdTag, Vec<MdNode>)> = vec![(MdTag::Root, Vec::new())];
 
     for event in parser {
@@ -156,12 +201,16 @@ fn build_tree(parser: Parser) -> MdNode {
                     CmarkTag::Emphasis => MdTag::Emphasis,
                     CmarkTag::Strong => MdTag::Strong,
                     CmarkTag::Strikethrough => MdTag::Strikethrough,
-                    CmarkTag::Link { dest_url, .. } => {
-                        if let Some(caps) = WIKI_LINK_RE.captures(&dest_url) {
-                            MdTag::WikiLink(caps["stem"].to_string())
-                        } else {
-                            MdTag::ExternalLink(dest_url.to_string())
-                        }
+                    CmarkTag::Link {
+                        link_type,
+                        dest_url,
+                        ..
+                    } => {
+                        let double_bracket = matches!(link_type, LinkType::WikiLink { .. });
+                        (double_bracket || !has_scheme(&dest_url))
+                            .then(|| note_link(&dest_url, double_bracket, resolve))
+                            .flatten()
+                            .unwrap_or_else(|| MdTag::ExternalLink(dest_url.to_string()))
                     }
                     CmarkTag::Table(_) => MdTag::Table,
                     CmarkTag::TableHead => MdTag::TableHead,
@@ -199,7 +248,7 @@ fn build_tree(parser: Parser) -> MdNode {
                 let suppress_splitter = stack.iter().any(|(tag, _)| {
                     matches!(
                         tag,
-                        MdTag::CodeBlock(_) | MdTag::WikiLink(_) | MdTag::ExternalLink(_)
+                        MdTag::CodeBlock(_) | MdTag::WikiLink { .. } | MdTag::ExternalLink(_)
                     )
                 });
                 let node = if suppress_splitter {
@@ -361,13 +410,24 @@ fn render_node(node: &MdNode) -> Markup {
             MdTag::Emphasis => html! { em { (render_children(children)) } },
             MdTag::Strong => html! { strong { (render_children(children)) } },
             MdTag::Strikethrough => html! { del { (render_children(children)) } },
-            MdTag::WikiLink(url) => html! {
-                a href="#" class="md-wikilink"
-                    hx-get={ "/f/" (url) }
-                    hx-target="#note-content"
-                    hx-push-url={ "/note/" (url) }
-                { (render_children(children)) }
-            },
+            MdTag::WikiLink { id, anchor } => {
+                let fragment = anchor.as_ref().map(|a| format!("#{a}")).unwrap_or_default();
+
+                if id.is_empty() {
+                    return html! {
+                        a href=(fragment) class="md-wikilink" { (render_children(children)) }
+                    };
+                }
+
+                let id = encode_id(id);
+                html! {
+                    a href="#" class="md-wikilink"
+                        hx-get={ "/f/" (id) }
+                        hx-target="#note-content"
+                        hx-push-url={ "/note/" (id) (fragment) }
+                    { (render_children(children)) }
+                }
+            }
             MdTag::ExternalLink(url) => html! {
                 a href=(url) {
                     (render_children(children))
@@ -663,16 +723,18 @@ fn fit_svg(svg: &str) -> String {
     format!("{open_tag}{rest}")
 }
 
-pub fn markdown_to_html(source: &str) -> Markup {
+/// Render `source` as HTML. Links to other notes are resolved with `resolve`.
+pub fn markdown_to_html(source: &str, resolve: Resolve) -> Markup {
     let parser = Parser::new_ext(
         source,
         Options::ENABLE_TABLES
             | Options::ENABLE_STRIKETHROUGH
             | Options::ENABLE_SMART_PUNCTUATION
-            | Options::ENABLE_GFM,
+            | Options::ENABLE_GFM
+            | Options::ENABLE_WIKILINKS,
     );
 
-    let tree = build_tree(parser);
+    let tree = build_tree(parser, resolve);
 
     render_node(&tree)
 }
@@ -698,15 +760,16 @@ pub fn heading_anchor(text: &str) -> String {
 }
 
 /// Parse markdown once; return rendered HTML and extracted headings together.
-pub fn markdown_to_html_with_headings(source: &str) -> (Markup, Vec<Heading>) {
+pub fn markdown_to_html_with_headings(source: &str, resolve: Resolve) -> (Markup, Vec<Heading>) {
     let parser = Parser::new_ext(
         source,
         Options::ENABLE_TABLES
             | Options::ENABLE_STRIKETHROUGH
             | Options::ENABLE_SMART_PUNCTUATION
-            | Options::ENABLE_GFM,
+            | Options::ENABLE_GFM
+            | Options::ENABLE_WIKILINKS,
     );
-    let tree = build_tree(parser);
+    let tree = build_tree(parser, resolve);
     let headings = collect_headings_from_tree(&tree);
     let html = render_node(&tree);
     (html, headings)
@@ -742,6 +805,10 @@ fn collect_headings_inner(node: &MdNode, out: &mut Vec<Heading>) {
 mod tests {
     use super::*;
 
+    fn unresolved(_: &str) -> Option<String> {
+        None
+    }
+
     fn wiki_stem(url: &str) -> Option<String> {
         WIKI_LINK_RE
             .captures(url)
@@ -788,7 +855,7 @@ mod tests {
 
     #[test]
     fn test_render_wiki_link_with_hyphens() {
-        let html = markdown_to_html("[note](my-note)").into_string();
+        let html = markdown_to_html("[note](my-note)", &unresolved).into_string();
         assert!(html.contains(r#"hx-get="/f/my-note""#), "{html}");
         assert!(html.contains(r#"hx-push-url="/note/my-note""#), "{html}");
         assert!(html.contains("note"));
@@ -796,7 +863,7 @@ mod tests {
 
     #[test]
     fn test_render_bare_wiki_link() {
-        let html = markdown_to_html("[note](abc1)").into_string();
+        let html = markdown_to_html("[note](abc1)", &unresolved).into_string();
         assert!(html.contains(r#"hx-get="/f/abc1""#), "{html}");
         assert!(html.contains(r#"hx-push-url="/note/abc1""#), "{html}");
         assert!(html.contains("note"));
@@ -804,7 +871,7 @@ mod tests {
 
     #[test]
     fn test_render_relative_wiki_link() {
-        let html = markdown_to_html("[weave](../65bs)").into_string();
+        let html = markdown_to_html("[weave](../65bs)", &unresolved).into_string();
         assert!(html.contains(r#"hx-get="/f/65bs""#), "{html}");
         assert!(html.contains(r#"hx-push-url="/note/65bs""#), "{html}");
         assert!(html.contains("weave"));
@@ -812,62 +879,123 @@ mod tests {
 
     #[test]
     fn test_render_external_link() {
-        let html = markdown_to_html("[site](https://example.com)").into_string();
+        let html = markdown_to_html("[site](https://example.com)", &unresolved).into_string();
         assert!(html.contains(r#"href="https://example.com""#), "{html}");
         assert!(!html.contains("hx-get"), "{html}");
     }
 
     #[test]
     fn test_external_link_with_url_label_has_single_icon() {
-        let html =
-            markdown_to_html("[http://localhost:8000](http://localhost:8000)").into_string();
+        let html = markdown_to_html(
+            "[http://localhost:8000](http://localhost:8000)",
+            &unresolved,
+        )
+        .into_string();
         assert_eq!(html.matches("md-ext-icon").count(), 1, "{html}");
         assert_eq!(html.matches("<a ").count(), 1, "{html}");
     }
 
     #[test]
     fn test_autolink_strips_trailing_period() {
-        let html = markdown_to_html("see https://example.com.").into_string();
+        let html = markdown_to_html("see https://example.com.", &unresolved).into_string();
         assert!(html.contains(r#"href="https://example.com""#), "{html}");
         assert!(html.contains("</a>."), "{html}");
     }
 
     #[test]
     fn test_hashtag_calls_show_list() {
-        let html = markdown_to_html("hello #topic world").into_string();
+        let html = markdown_to_html("hello #topic world", &unresolved).into_string();
         assert!(html.contains(r#"onclick="showList()""#), "{html}");
         assert!(!html.contains("showSidebar"), "{html}");
     }
 
     #[test]
     fn test_hashtag_targets_search_list() {
-        let html = markdown_to_html("see #topic").into_string();
+        let html = markdown_to_html("see #topic", &unresolved).into_string();
         assert!(html.contains(r##"hx-target="#search-list""##), "{html}");
         assert!(html.contains(r#"hx-post="/f/search""#), "{html}");
     }
 
     #[test]
     fn test_colon_tags_call_show_list() {
-        let html = markdown_to_html("status :draft:review:").into_string();
+        let html = markdown_to_html("status :draft:review:", &unresolved).into_string();
         assert!(html.contains(r#"onclick="showList()""#), "{html}");
         assert!(!html.contains("showSidebar"), "{html}");
     }
 
     #[test]
     fn test_wiki_link_has_push_url() {
-        let html = markdown_to_html("[my note](abc1)").into_string();
+        let html = markdown_to_html("[my note](abc1)", &unresolved).into_string();
         assert!(html.contains(r#"hx-push-url="/note/abc1""#), "{html}");
         assert!(html.contains(r##"hx-target="#note-content""##), "{html}");
     }
 
     #[test]
     fn test_wiki_link_no_sidebar_call() {
-        let html = markdown_to_html("[link](abc1)").into_string();
+        let html = markdown_to_html("[link](abc1)", &unresolved).into_string();
         assert!(!html.contains("showSidebar"), "{html}");
         assert!(!html.contains("showList"), "{html}");
         assert!(!html.contains("goBack"), "{html}");
     }
 
+    #[test]
+    fn test_render_double_bracket_link() {
+        let html = markdown_to_html("see [[my-note]]", &unresolved).into_string();
+        assert!(html.contains(r#"hx-get="/f/my-note""#), "{html}");
+        assert!(html.contains(">my-note</a>"), "{html}");
+    }
+
+    #[test]
+    fn test_render_double_bracket_link_with_label() {
+        let html = markdown_to_html("see [[abc1|the label]]", &unresolved).into_string();
+        assert!(html.contains(r#"hx-push-url="/note/abc1""#), "{html}");
+        assert!(html.contains(">the label</a>"), "{html}");
+    }
+
+    #[test]
+    fn test_render_double_bracket_link_with_heading() {
+        let html = markdown_to_html("[[dir/abc1.md#Some Heading]]", &unresolved).into_string();
+        assert!(html.contains(r#"hx-get="/f/abc1""#), "{html}");
+        assert!(
+            html.contains(r#"hx-push-url="/note/abc1#some-heading""#),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn test_render_double_bracket_link_same_note_heading() {
+        let html = markdown_to_html("[[#Setup]]", &unresolved).into_string();
+        assert!(html.contains(r##"href="#setup""##), "{html}");
+        assert!(!html.contains("hx-get"), "{html}");
+    }
+
+    #[test]
+    fn test_render_resolved_links() {
+        let resolve = |link: &str| match link {
+            "sub/note3" | "My Title" => Some("note3".to_owned()),
+            _ => None,
+        };
+        let html = markdown_to_html("[a](sub/note3) [[My Title#Part]] [b](sub/other)", &resolve)
+            .into_string();
+        assert!(html.contains(r#"hx-push-url="/note/note3""#), "{html}");
+        assert!(html.contains(r#"hx-push-url="/note/note3#part""#), "{html}");
+        assert!(html.contains(r#"href="sub/other""#), "{html}");
+    }
+
+    #[test]
+    fn test_render_links_to_notes_in_subdirectories() {
+        let resolve = |_: &str| Some("sub dir/note3".to_owned());
+        let html = markdown_to_html("[[note3]]", &resolve).into_string();
+        assert!(html.contains(r#"hx-get="/f/sub%20dir%2Fnote3""#), "{html}");
+    }
+
+    #[test]
+    fn test_scheme_links_are_not_resolved() {
+        let resolve = |_: &str| Some("note3".to_owned());
+        let html = markdown_to_html("[a](https://example.com) [b](#top)", &resolve).into_string();
+        assert!(!html.contains("hx-get"), "{html}");
+    }
+
     #[test]
     fn test_heading_anchor_basic() {
         assert_eq!(heading_anchor("Hello World"), "hello-world");
@@ -892,7 +1020,7 @@ mod tests {
     #[test]
     fn test_markdown_to_html_with_headings_extracts_headings() {
         let src = "# First\n\nBody.\n\n## Second\n\nMore body.";
-        let (html, headings) = markdown_to_html_with_headings(src);
+        let (html, headings) = markdown_to_html_with_headings(src, &unresolved);
         assert_eq!(headings.len(), 2);
         assert_eq!(headings[0].level, 1);
         assert_eq!(headings[0].text, "First");
@@ -908,14 +1036,14 @@ mod tests {
     #[test]
     fn test_markdown_to_html_with_headings_empty() {
         let src = "Just a paragraph.";
-        let (_, headings) = markdown_to_html_with_headings(src);
+        let (_, headings) = markdown_to_html_with_headings(src, &unresolved);
         assert!(headings.is_empty());
     }
 
     #[test]
     fn test_mermaid_renders_light_and_dark_svg() {
         let src = "```mermaid\nflowchart TD\n  A[Start] --> B[Done]\n```";
-        let html = markdown_to_html(src).into_string();
+        let html = markdown_to_html(src, &unresolved).into_string();
         assert!(html.contains(r#"class="mermaid""#), "{html}");
         assert!(html.contains(r#"class="mermaid-light""#), "{html}");
         assert!(html.contains(r#"class="mermaid-dark""#), "{html}");
@@ -926,7 +1054,7 @@ mod tests {
     #[test]
     fn test_mermaid_invalid_falls_back_to_code_block() {
         let src = "```mermaid\nnot a real diagram @#$%\n```";
-        let html = markdown_to_html(src).into_string();
+        let html = markdown_to_html(src, &unresolved).into_string();
         assert!(!html.contains("<svg"), "{html}");
         assert!(html.contains("<pre>"), "{html}");
     }
@@ -942,7 +1070,7 @@ mod tests {
             "```mermaid\nstateDiagram-v2\n [*]-->Read\n Read-->Edit\n Edit-->[*]\n```",
         ];
         for src in diagrams {
-            let html = markdown_to_html(src).into_string();
+            let html = markdown_to_html(src, &unresolved).into_string();
             // Markup is light variant then dark variant; the dark SVG follows the
             // `mermaid-dark` class.
             let (_, dark) = html.split_once("mermaid-dark").expect("dark variant present");
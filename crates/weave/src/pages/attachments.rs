@@ -0,0 +1,30 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use maud::Markup;
+
+use crate::extract::Authenticated;
+use crate::{Notebook, partials};
+
+pub(crate) async fn attachments(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+) -> Result<Markup, StatusCode> {
+    if !authenticated {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let content = {
+        let notebook = notebook.lock().unwrap();
+        partials::attachments::attachments(
+            &notebook.attachments(),
+            notebook.attachments_dir(),
+            None,
+        )
+    };
+    Ok(partials::layout::layout(
+        authenticated,
+        notebook,
+        content,
+        true,
+    ))
+}
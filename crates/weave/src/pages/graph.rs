@@ -0,0 +1,28 @@
+use axum::extract::{Query, State};
+use maud::Markup;
+
+use crate::extract::Authenticated;
+use crate::partials::graph::GraphParams;
+use crate::{Notebook, partials};
+
+pub(crate) async fn graph(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+    Query(params): Query<GraphParams>,
+) -> Markup {
+    // Tags of hidden notes are not offered to visitors.
+    let tags = if authenticated {
+        notebook
+            .lock()
+            .unwrap()
+            .tag_counts()
+            .into_iter()
+            .map(|(tag, _)| tag)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let content = partials::graph::page(&params, &tags);
+    partials::layout::layout(authenticated, notebook, content, true)
+}
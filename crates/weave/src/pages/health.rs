@@ -0,0 +1,24 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use maud::Markup;
+
+use crate::extract::Authenticated;
+use crate::{Notebook, partials};
+
+pub(crate) async fn health(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+) -> Result<Markup, StatusCode> {
+    if !authenticated {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let health = notebook.lock().unwrap().health();
+    let content = partials::health::health(&health, None);
+    Ok(partials::layout::layout(
+        authenticated,
+        notebook,
+        content,
+        true,
+    ))
+}
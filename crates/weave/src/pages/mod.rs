@@ -1,4 +1,8 @@
+pub(crate) mod attachments;
+pub(crate) mod graph;
+pub(crate) mod health;
 pub(crate) mod index;
 pub(crate) mod login;
 pub(crate) mod note;
 pub(crate) mod raw;
+pub(crate) mod tags;
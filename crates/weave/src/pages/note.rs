@@ -2,16 +2,17 @@ use axum::extract::{Path, State};
 use maud::{Markup, html};
 
 use crate::extract::Authenticated;
+use crate::zk::encode_id;
 use crate::{Notebook, partials};
 
 pub(crate) async fn note(
     State(notebook): State<Notebook>,
     Authenticated(authenticated): Authenticated,
-    Path(stem): Path<String>,
+    Path(id): Path<String>,
 ) -> Markup {
     let content = html! {
         div
-            hx-get={ "/f/" (stem) }
+            hx-get={ "/f/" (encode_id(&id)) }
             hx-trigger="load"
             hx-target="#note-content"
             hx-swap="innerHTML"
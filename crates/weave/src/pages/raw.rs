@@ -12,9 +12,9 @@ use crate::extract::Authenticated;
 pub(crate) async fn raw(
     State(notebook): State<Notebook>,
     Authenticated(authenticated): Authenticated,
-    Path(stem): Path<String>,
+    Path(id): Path<String>,
 ) -> Response {
-    let Some(note) = notebook.lock().unwrap().note(&stem) else {
+    let Some(note) = notebook.lock().unwrap().note(&id) else {
         return StatusCode::NOT_FOUND.into_response();
     };
 
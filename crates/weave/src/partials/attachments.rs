@@ -0,0 +1,240 @@
+use std::path::Path;
+
+use axum::Form;
+use axum::extract::State;
+use axum::http::StatusCode;
+use maud::{Markup, html};
+use serde::Deserialize;
+
+use crate::Notebook;
+use crate::extract::Authenticated;
+use crate::zk::{Attachment, Error, NoteExt, encode_id};
+
+/// Fields of the delete form of an attachment row.
+#[derive(Deserialize)]
+pub(crate) struct DeleteRequest {
+    /// Notebook-relative path of the attachment.
+    path: String,
+}
+
+/// Fields of the form confirming the deletion of unreferenced attachments.
+#[derive(Deserialize)]
+pub(crate) struct CleanupRequest {
+    /// Notebook-relative paths of the listed attachments, one per line.
+    paths: String,
+}
+
+/// Render the attachments in the attachments directory `dir` with the notes
+/// linking to them and forms to delete the unreferenced ones. `status`
+/// reports the result of the last deletion.
+pub(crate) fn attachments(
+    attachments: &[Attachment],
+    dir: Option<&Path>,
+    status: Option<&str>,
+) -> Markup {
+    let unreferenced = attachments.iter().filter(|a| a.notes.is_empty()).count();
+    let total: u64 = attachments.iter().map(|a| a.len).sum();
+
+    html! {
+        section class="attachments" #attachments {
+            header class="note-head" {
+                h1 { "Attachments" }
+            }
+            @if let Some(dir) = dir {
+                p class="attachments-hint" {
+                    code { (dir.display()) "/" } " · "
+                    (count(attachments.len(), "file", "files")) " · "
+                    (unreferenced) " unreferenced · "
+                    (size(total))
+                }
+            } @else {
+                p class="attachments-hint" {
+                    "Set " code { "WEAVE_ATTACHMENTS" }
+                    " to a notebook subdirectory to list its files here."
+                }
+            }
+            @if let Some(status) = status {
+                div class="attachments-status" role="status" { (status) }
+            }
+            @if dir.is_some() && attachments.is_empty() {
+                p class="attachments-hint" { "No files besides notes yet." }
+            }
+            @if unreferenced > 0 {
+                form hx-get="/attachments/cleanup"
+                    hx-target="#attachments"
+                    hx-swap="outerHTML" {
+                    button type="submit" class="btn btn-ghost" { "Delete unreferenced…" }
+                }
+            }
+            ul class="attachment-rows" {
+                @for attachment in attachments {
+                    li class="attachment-row" {
+                        a class="attachment-path" href=(attachment.url) target="_blank" {
+                            (attachment.path)
+                        }
+                        span class="attachment-size" { (size(attachment.len)) }
+                        @if attachment.notes.is_empty() {
+                            span class="attachment-unused" { "unreferenced" }
+                            form class="attachment-form"
+                                hx-post="/attachments/delete"
+                                hx-target="#attachments"
+                                hx-swap="outerHTML"
+                                hx-confirm={ "Delete " (attachment.path) "?" } {
+                                input type="hidden" name="path" value=(attachment.path) {}
+                                button type="submit" class="btn btn-ghost" { "Delete" }
+                            }
+                        } @else {
+                            span class="attachment-notes" {
+                                (count(attachment.notes.len(), "note", "notes")) ": "
+                                @for (i, note) in attachment.notes.iter().enumerate() {
+                                    @if i > 0 { ", " }
+                                    a href={ "/note/" (encode_id(&note.id())) } { (note.title()) }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Delete an attachment no note links to and return the updated list.
+pub(crate) async fn delete(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+    Form(DeleteRequest { path }): Form<DeleteRequest>,
+) -> Result<Markup, StatusCode> {
+    if !authenticated {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    change(notebook, move |notebook| {
+        match notebook.delete_attachment(&path) {
+            Ok(()) => format!("Deleted {path}"),
+            Err(err) => error_status(err),
+        }
+    })
+    .await
+}
+
+/// List the attachments no note links to and ask to confirm their deletion.
+/// Nothing is deleted yet.
+pub(crate) async fn preview(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+) -> Result<Markup, StatusCode> {
+    if !authenticated {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let notebook = notebook.lock().unwrap();
+    let paths = notebook.unreferenced_attachments();
+    if paths.is_empty() {
+        return Ok(attachments(
+            &notebook.attachments(),
+            notebook.attachments_dir(),
+            Some("No unreferenced files"),
+        ));
+    }
+
+    Ok(html! {
+        section class="attachments" #attachments {
+            header class="note-head" {
+                h1 { "Delete unreferenced files" }
+            }
+            p class="attachments-hint" {
+                "No note links to these " (count(paths.len(), "file", "files"))
+                ". They are deleted from disk."
+            }
+            ul class="attachment-rows" {
+                @for path in &paths {
+                    li class="attachment-row" {
+                        code class="attachment-path" { (path) }
+                    }
+                }
+            }
+            form class="attachments-confirm"
+                hx-post="/attachments/cleanup"
+                hx-target="#attachments"
+                hx-swap="outerHTML" {
+                input type="hidden" name="paths" value=(paths.join("\n")) {}
+                button type="submit" class="btn btn-primary" {
+                    "Delete " (count(paths.len(), "file", "files"))
+                }
+                a class="btn btn-ghost" href="/attachments" { "Cancel" }
+            }
+        }
+    })
+}
+
+/// Delete the attachments listed by [`preview`] that no note links to yet
+/// and return the updated list.
+pub(crate) async fn cleanup(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+    Form(CleanupRequest { paths }): Form<CleanupRequest>,
+) -> Result<Markup, StatusCode> {
+    if !authenticated {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let paths: Vec<String> = paths.lines().map(str::to_owned).collect();
+    change(notebook, move |notebook| {
+        match notebook.delete_unreferenced_attachments(&paths) {
+            Ok(deleted) => format!("Deleted {}", count(deleted, "file", "files")),
+            Err(err) => error_status(err),
+        }
+    })
+    .await
+}
+
+/// Apply a deletion returning a status message, off the async runtime, and
+/// render the attachments with the status.
+async fn change(
+    notebook: Notebook,
+    apply: impl FnOnce(&mut crate::zk::Notebook) -> String + Send + 'static,
+) -> Result<Markup, StatusCode> {
+    tokio::task::spawn_blocking(move || {
+        let mut notebook = notebook.lock().unwrap();
+        let status = apply(&mut *notebook);
+        attachments(
+            &notebook.attachments(),
+            notebook.attachments_dir(),
+            Some(&status),
+        )
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Format a file size in bytes for humans, e.g. `1.5 MB`.
+fn size(len: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if len < 1024 {
+        return format!("{len} B");
+    }
+    let mut value = len as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+fn count(count: usize, one: &str, many: &str) -> String {
+    if count == 1 {
+        format!("1 {one}")
+    } else {
+        format!("{count} {many}")
+    }
+}
+
+fn error_status(err: Error) -> String {
+    tracing::warn!(?err, "failed to delete attachment");
+    match err {
+        Error::Zk(err) => err.to_string(),
+        err => err.to_string(),
+    }
+}
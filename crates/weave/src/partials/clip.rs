@@ -9,6 +9,7 @@ use url::Url;
 use crate::Notebook;
 use crate::assets::icons;
 use crate::extract::Authenticated;
+use crate::zk::NewNote;
 
 #[derive(Deserialize)]
 pub(crate) struct ClipRequest {
@@ -110,7 +111,7 @@ async fn clip_url(url: &str, notebook: &Notebook) -> Result<String, ClipError> {
             article.title
         };
 
-        let content = format!("# {title}\n\n{markdown}\n\n---\n\nReference: {url}");
+        let content = format!("{markdown}\n\n---\n\nReference: {url}");
 
         Ok::<_, ClipError>((title, content))
     })
@@ -120,7 +121,11 @@ async fn clip_url(url: &str, notebook: &Notebook) -> Result<String, ClipError> {
     notebook
         .lock()
         .unwrap()
-        .create_note(&content)
+        .create_note(NewNote {
+            title: Some(title.clone()),
+            content,
+            ..NewNote::default()
+        })
         .map_err(|err| ClipError::Save(err.into()))?;
 
     Ok(title)
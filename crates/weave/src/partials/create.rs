@@ -1,28 +1,56 @@
+use axum::Form;
 use axum::extract::State;
 use axum::http::{HeaderName, HeaderValue, StatusCode};
 use axum::response::IntoResponse;
+use serde::Deserialize;
 
 use crate::Notebook;
 use crate::extract::Authenticated;
+use crate::zk::{NewNote, encode_id};
 
 const HX_REDIRECT: HeaderName = HeaderName::from_static("hx-redirect");
 
-/// Create an empty note and tell HTMX to navigate to its `/note/{stem}` page.
+/// Fields of the create drawer. Empty fields fall back to the notebook config.
+#[derive(Deserialize)]
+pub(crate) struct CreateRequest {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    template: String,
+    #[serde(default)]
+    group: String,
+}
+
+fn non_empty(value: String) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+/// Create a note from the chosen template and tell HTMX to navigate to its
+/// `/note/{id}` page.
 pub(crate) async fn create(
     State(notebook): State<Notebook>,
     Authenticated(authenticated): Authenticated,
+    Form(req): Form<CreateRequest>,
 ) -> Result<impl IntoResponse, StatusCode> {
     if !authenticated {
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let stem = notebook
+    let new = NewNote {
+        title: non_empty(req.title),
+        template: non_empty(req.template),
+        group: non_empty(req.group),
+        ..NewNote::default()
+    };
+
+    let id = notebook
         .lock()
         .unwrap()
-        .create_note("# Untitled\n")
+        .create_note(new)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let location = format!("/note/{stem}");
+    let location = format!("/note/{}", encode_id(&id));
     let value = HeaderValue::from_str(&location).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(([(HX_REDIRECT, value)], StatusCode::NO_CONTENT))
 }
@@ -1,21 +1,38 @@
 use axum::extract::{Path, State};
-use axum::http::StatusCode;
+use axum::http::{HeaderValue, StatusCode};
 use maud::{Markup, html};
 
 use crate::extract::Authenticated;
-use crate::partials::note_nav::{NoteNavData, note_nav};
+use crate::partials::frontmatter;
+use crate::partials::note::stem_warning;
+use crate::partials::note_nav::{NoteNavData, note_nav, properties};
+use crate::zk::{Error, NoteExt, encode_id};
 use crate::{Notebook, md};
 
 const HX_TRIGGER: axum::http::HeaderName = axum::http::HeaderName::from_static("hx-trigger");
+const HX_REDIRECT: axum::http::HeaderName = axum::http::HeaderName::from_static("hx-redirect");
 
-fn edit_form(stem: &str, body: &str) -> Markup {
+/// Render the editor for the note `id` with raw content `body`. `status`
+/// reports the result of the last frontmatter panel change.
+pub(crate) fn edit_form(id: &str, body: &str, status: Option<&str>) -> Markup {
     html! {
-        article class="note" data-stem=(stem) data-mode="edit" {
-            form class="editor" {
-                textarea id="editor-textarea" name="body" class="editor-input"
-                    placeholder="Write your note here…" {
-                    (body)
+        article class="note" data-id=(id) data-mode="edit" {
+            form class="rename-row"
+                hx-post={ "/f/" (encode_id(id)) "/rename" }
+                hx-include="#editor-textarea"
+                hx-swap="none" {
+                input type="text" name="name" value=(id) aria-label="Note path"
+                    autocomplete="off" spellcheck="false" {}
+                button type="submit" class="btn btn-ghost" { "Rename" }
+            }
+            div class="edit-body" {
+                form class="editor" {
+                    textarea id="editor-textarea" name="body" class="editor-input"
+                        placeholder="Write your note here…" {
+                        (body)
+                    }
                 }
+                (frontmatter::panel(id, body, status))
             }
         }
     }
@@ -24,67 +41,67 @@ fn edit_form(stem: &str, body: &str) -> Markup {
 pub(crate) async fn edit(
     State(notebook): State<Notebook>,
     Authenticated(authenticated): Authenticated,
-    Path(stem): Path<String>,
+    Path(id): Path<String>,
 ) -> Result<Markup, StatusCode> {
     if !authenticated {
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let content = notebook
+    let note = notebook
         .lock()
         .unwrap()
-        .note(&stem)
-        .ok_or(StatusCode::NOT_FOUND)?
-        .raw_content()
-        .to_owned();
+        .note(&id)
+        .ok_or(StatusCode::NOT_FOUND)?;
 
-    Ok(edit_form(&stem, &content))
+    Ok(edit_form(&note.id(), note.raw_content(), None))
 }
 
 pub(crate) async fn save(
     State(notebook): State<Notebook>,
     Authenticated(authenticated): Authenticated,
-    Path(stem): Path<String>,
+    Path(id): Path<String>,
     axum::extract::Form(Body { body }): axum::extract::Form<Body>,
 ) -> Result<([(axum::http::HeaderName, &'static str); 1], Markup), StatusCode> {
     if !authenticated {
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let stem_clone = stem.clone();
-
-    let (title, rendered, nav_data) = tokio::task::spawn_blocking(move || {
+    let (note, rendered, nav_data, same_stem) = tokio::task::spawn_blocking(move || {
         let mut notebook = notebook.lock().unwrap();
 
         let file_path = notebook
-            .note(&stem_clone)
+            .note(&id)
             .ok_or(StatusCode::NOT_FOUND)?
-            .abs_path()
+            .path()
             .to_owned();
 
-        std::fs::write(&file_path, &body).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
         notebook
-            .reload(&stem_clone)
+            .save(&file_path, &body)
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-        let note = notebook.note(&stem_clone).ok_or(StatusCode::NOT_FOUND)?;
-        let backlinks = notebook.backlinks(&stem_clone, true);
-        let outgoing_links = notebook.outgoing_links(note.outgoing_links(), true);
+        let note = notebook.note(&id).ok_or(StatusCode::NOT_FOUND)?;
+        let backlinks = notebook.backlinks(&id, true);
+        let outgoing_links = notebook.outgoing_links(&id, true);
+        let related = notebook.related(&id, true);
+        let same_stem = notebook.same_stem(&id);
         let tags = note.tags().to_vec();
-        let title = note.title().to_owned();
-        let body = note.body().to_owned();
 
-        let (rendered, headings) = md::markdown_to_html_with_headings(&body);
+        let (rendered, headings) =
+            md::markdown_to_html_with_headings(note.body(), &|link: &str| {
+                notebook.resolve(link, &id)
+            });
 
         let nav_data = NoteNavData {
+            id: id.clone(),
             headings,
             outgoing_links,
             backlinks,
+            related,
             tags,
+            properties: properties(&note),
         };
 
-        Ok::<_, StatusCode>((title, rendered, nav_data))
+        Ok::<_, StatusCode>((note, rendered, nav_data, same_stem))
     })
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
@@ -100,8 +117,11 @@ pub(crate) async fn save(
     Ok((
         [(HX_TRIGGER, "notes-updated")],
         html! {
-            article class=(note_class) data-stem=(stem) data-mode="read" {
-                header class="note-head" { h1 { (title) } }
+            article class=(note_class) data-id=(note.id()) data-mode="read" {
+                header class="note-head" {
+                    h1 { (note.title()) }
+                    (stem_warning(&note, &same_stem))
+                }
                 div class=(body_class) {
                     div class="md" { (rendered) }
                     @if has_rail { (note_nav(&nav_data)) }
@@ -112,7 +132,9 @@ pub(crate) async fn save(
 }
 
 pub(crate) async fn preview(
+    State(notebook): State<Notebook>,
     Authenticated(authenticated): Authenticated,
+    Path(id): Path<String>,
     form: axum::extract::Form<Body>,
 ) -> Result<Markup, StatusCode> {
     if !authenticated {
@@ -120,13 +142,62 @@ pub(crate) async fn preview(
     }
 
     let body = form.0.body;
-    let rendered = tokio::task::spawn_blocking(move || md::markdown_to_html(&body))
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let rendered = tokio::task::spawn_blocking(move || {
+        md::markdown_to_html(&body, &|link: &str| {
+            notebook.lock().unwrap().resolve(link, &id)
+        })
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     Ok(rendered)
 }
 
+/// Save the edited body, rename the note and rewrite the links to it, then
+/// tell HTMX to navigate to its new `/note/{id}` page.
+pub(crate) async fn rename(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+    Path(id): Path<String>,
+    axum::extract::Form(Rename { name, body }): axum::extract::Form<Rename>,
+) -> Result<([(axum::http::HeaderName, HeaderValue); 1], StatusCode), StatusCode> {
+    if !authenticated {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let new_id = tokio::task::spawn_blocking(move || {
+        let mut notebook = notebook.lock().unwrap();
+
+        let file_path = notebook
+            .note(&id)
+            .ok_or(StatusCode::NOT_FOUND)?
+            .path()
+            .to_owned();
+
+        notebook
+            .save(&file_path, &body)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        notebook.rename(&id, &name).map_err(|err| match err {
+            Error::Zk(zk_rs::Error::NoteExists(_)) => StatusCode::CONFLICT,
+            Error::Zk(zk_rs::Error::InvalidPath(_)) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+
+    let location = format!("/note/{}", encode_id(&new_id));
+    let value = HeaderValue::from_str(&location).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(([(HX_REDIRECT, value)], StatusCode::NO_CONTENT))
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct Rename {
+    name: String,
+    body: String,
+}
+
 #[derive(serde::Deserialize)]
 pub(crate) struct Body {
     body: String,
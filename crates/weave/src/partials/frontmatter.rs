@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use axum::Form;
+use axum::extract::{Path, State};
+use axum::http::{HeaderName, StatusCode};
+use axum::response::{IntoResponse, Response};
+use maud::{Markup, html};
+
+use crate::Notebook;
+use crate::extract::Authenticated;
+use crate::partials::edit::edit_form;
+use crate::zk::{Frontmatter, YamlValue, encode_id};
+
+const HX_TRIGGER: HeaderName = HeaderName::from_static("hx-trigger");
+
+/// Frontmatter keys holding tags, in the order zk reads them.
+const TAG_KEYS: [&str; 4] = ["tags", "tag", "keywords", "keyword"];
+
+/// Render the side panel of the editor to change the frontmatter of the note
+/// `id` with raw `content` through form fields.
+pub(crate) fn panel(id: &str, content: &str, status: Option<&str>) -> Markup {
+    let fm = match Frontmatter::parse(content) {
+        Ok(fm) => fm,
+        Err(_) => {
+            return html! {
+                aside class="fm-panel" {
+                    p class="fm-status" { "The frontmatter is not valid YAML, fix it in the text." }
+                }
+            };
+        }
+    };
+
+    let tags = tag_key(&fm)
+        .map(|key| list(fm.get(key)))
+        .unwrap_or_default();
+    let aliases = list(fm.get("aliases"));
+    let fields: Vec<(&str, Option<String>)> = fm
+        .keys()
+        .filter(|key| !is_known(key))
+        .map(|key| (key, fm.get(key).and_then(|value| scalar_text(&value))))
+        .collect();
+
+    html! {
+        aside class="fm-panel" {
+            form class="fm-form"
+                hx-post={ "/f/" (encode_id(id)) "/frontmatter" }
+                hx-include="#editor-textarea"
+                hx-target="closest article"
+                hx-swap="outerHTML" {
+                label {
+                    span { "Title" }
+                    input type="text" name="title" autocomplete="off"
+                        value=(fm.get_str("title").unwrap_or_default()) {}
+                }
+                label {
+                    span { "Tags" }
+                    input type="text" name="tags" autocomplete="off"
+                        placeholder="space separated" value=(tags.join(" ")) {}
+                }
+                label {
+                    span { "Aliases" }
+                    input type="text" name="aliases" autocomplete="off"
+                        placeholder="comma separated" value=(aliases.join(", ")) {}
+                }
+                @for (key, value) in &fields {
+                    label {
+                        span { (key) }
+                        @if let Some(value) = value {
+                            input type="text" name={ "field:" (key) } autocomplete="off"
+                                value=(value) {}
+                        } @else {
+                            code class="fm-complex" { "edit in the text" }
+                        }
+                    }
+                }
+                div class="fm-new" {
+                    input type="text" name="new_key" placeholder="New field"
+                        autocomplete="off" aria-label="New field name" {}
+                    input type="text" name="new_value" placeholder="Value"
+                        autocomplete="off" aria-label="New field value" {}
+                }
+                p class="fm-hint" { "Clear a field to remove it." }
+                button type="submit" class="btn btn-ghost" { "Apply" }
+                @if let Some(status) = status {
+                    p class="fm-status" role="status" { (status) }
+                }
+            }
+        }
+    }
+}
+
+/// Apply the panel fields to the edited note content, save it and return
+/// the editor with the updated text.
+pub(crate) async fn update(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+    Path(id): Path<String>,
+    Form(mut fields): Form<HashMap<String, String>>,
+) -> Result<Response, StatusCode> {
+    if !authenticated {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let body = fields.remove("body").unwrap_or_default();
+    let content = match Frontmatter::parse(&body) {
+        Ok(mut fm) => match apply(&mut fm, &fields) {
+            Ok(()) => fm.to_string(),
+            Err(err) => return Ok(edit_form(&id, &body, Some(&err.to_string())).into_response()),
+        },
+        // The panel explains that the YAML needs fixing.
+        Err(_) => return Ok(edit_form(&id, &body, None).into_response()),
+    };
+
+    let markup = tokio::task::spawn_blocking(move || {
+        let mut notebook = notebook.lock().unwrap();
+        let path = notebook
+            .note(&id)
+            .ok_or(StatusCode::NOT_FOUND)?
+            .path()
+            .to_owned();
+        notebook
+            .save(&path, &content)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        Ok::<_, StatusCode>(edit_form(&id, &content, Some("Saved")))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+
+    Ok(([(HX_TRIGGER, "notes-updated")], markup).into_response())
+}
+
+/// Change `fm` to match the submitted panel `fields`. Unchanged fields are
+/// left alone, so their formatting is kept.
+fn apply(fm: &mut Frontmatter, fields: &HashMap<String, String>) -> Result<(), zk_rs::Error> {
+    let field = |name: &str| fields.get(name).map(|value| value.trim());
+
+    if let Some(title) = field("title")
+        && fm.get_str("title").as_deref().unwrap_or_default() != title
+    {
+        set_or_remove(fm, "title", title, YamlValue::from(title))?;
+    }
+
+    if let Some(tags) = field("tags") {
+        let key = tag_key(fm).unwrap_or("tags").to_owned();
+        let new: Vec<String> = tags
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .map(|tag| tag.trim_start_matches('#'))
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_owned)
+            .collect();
+        if new != list(fm.get(&key)) {
+            set_or_remove(fm, &key, tags, YamlValue::from(new))?;
+        }
+    }
+
+    if let Some(aliases) = field("aliases") {
+        let new: Vec<String> = aliases
+            .split(',')
+            .map(str::trim)
+            .filter(|alias| !alias.is_empty())
+            .map(str::to_owned)
+            .collect();
+        if new != list(fm.get("aliases")) {
+            set_or_remove(fm, "aliases", aliases, YamlValue::from(new))?;
+        }
+    }
+
+    for (name, value) in fields {
+        let Some(key) = name.strip_prefix("field:") else {
+            continue;
+        };
+        let value = value.trim();
+        let old = fm.get(key).and_then(|old| scalar_text(&old));
+        if old.as_deref() != Some(value) {
+            set_or_remove(fm, key, value, scalar(value))?;
+        }
+    }
+
+    if let Some(key) = field("new_key").filter(|key| !key.is_empty()) {
+        fm.set(key, scalar(field("new_value").unwrap_or_default()))?;
+    }
+
+    Ok(())
+}
+
+/// Set `key` to `value`, or remove it if the submitted `text` is empty.
+fn set_or_remove(
+    fm: &mut Frontmatter,
+    key: &str,
+    text: &str,
+    value: YamlValue,
+) -> Result<(), zk_rs::Error> {
+    if text.is_empty() {
+        fm.remove(key);
+        Ok(())
+    } else {
+        fm.set(key, value)
+    }
+}
+
+/// Return the first tag key of `fm`.
+fn tag_key(fm: &Frontmatter) -> Option<&'static str> {
+    TAG_KEYS.into_iter().find(|key| fm.get(key).is_some())
+}
+
+/// Check if `key` is a frontmatter field with its own place in the UI: the
+/// title, the tags or the aliases.
+pub(crate) fn is_known(key: &str) -> bool {
+    let key = key.to_lowercase();
+    key == "title" || key == "aliases" || TAG_KEYS.contains(&key.as_str())
+}
+
+/// Return a list or space-separated string value as strings.
+fn list(value: Option<YamlValue>) -> Vec<String> {
+    match value {
+        Some(YamlValue::Sequence(items)) => items
+            .iter()
+            .filter_map(|item| scalar_text(item).filter(|item| !item.is_empty()))
+            .collect(),
+        Some(YamlValue::String(value)) => value.split_whitespace().map(str::to_owned).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Return a scalar value as shown in a form field, `None` for lists and maps.
+pub(crate) fn scalar_text(value: &YamlValue) -> Option<String> {
+    match value {
+        YamlValue::Null => Some(String::new()),
+        YamlValue::Bool(value) => Some(value.to_string()),
+        YamlValue::Number(value) => Some(value.to_string()),
+        YamlValue::String(value) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+/// Read a form field as a scalar, so `true` and `3` keep their type.
+fn scalar(text: &str) -> YamlValue {
+    if let Ok(value) = text.parse::<bool>() {
+        YamlValue::from(value)
+    } else if let Ok(value) = text.parse::<i64>() {
+        YamlValue::from(value)
+    } else if let Ok(value) = text.parse::<f64>()
+        && value.is_finite()
+    {
+        YamlValue::from(value)
+    } else {
+        YamlValue::from(text)
+    }
+}
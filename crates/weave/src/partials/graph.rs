@@ -0,0 +1,136 @@
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use maud::{Markup, html};
+use serde::Deserialize;
+
+use crate::Notebook;
+use crate::extract::Authenticated;
+use crate::zk::{Graph, encode_id};
+
+/// Links followed from the center note by default.
+const DEFAULT_DEPTH: usize = 2;
+/// Most links followed from the center note, larger graphs get unreadable.
+const MAX_DEPTH: usize = 5;
+
+/// Query parameters of the graph page and its data.
+#[derive(Deserialize)]
+pub(crate) struct GraphParams {
+    /// Id of the center note, all notes are shown without one.
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    depth: Option<usize>,
+    /// Only show notes with this tag or a tag nested in it.
+    #[serde(default)]
+    tag: String,
+}
+
+impl GraphParams {
+    fn note(&self) -> Option<&str> {
+        Some(self.note.trim()).filter(|note| !note.is_empty())
+    }
+
+    fn depth(&self) -> usize {
+        self.depth.unwrap_or(DEFAULT_DEPTH).clamp(1, MAX_DEPTH)
+    }
+
+    fn tag(&self) -> Option<&str> {
+        Some(self.tag.trim().trim_start_matches('#')).filter(|tag| !tag.is_empty())
+    }
+
+    /// Return the URL of the graph data for these parameters.
+    fn data_url(&self) -> String {
+        let mut url = format!("/graph.json?depth={}", self.depth());
+        if let Some(note) = self.note() {
+            url.push_str(&format!("&note={}", encode_id(note)));
+        }
+        if let Some(tag) = self.tag() {
+            url.push_str(&format!("&tag={}", encode_id(tag)));
+        }
+        url
+    }
+}
+
+/// Return the notes and links of the graph view as JSON.
+pub(crate) async fn data(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+    Query(params): Query<GraphParams>,
+) -> Result<Json<Graph>, StatusCode> {
+    tokio::task::spawn_blocking(move || {
+        notebook
+            .lock()
+            .unwrap()
+            .graph(params.note(), params.depth(), params.tag(), authenticated)
+            .map(Json)
+            .ok_or(StatusCode::NOT_FOUND)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+}
+
+/// Render the graph page with controls for the center note, depth and tag.
+/// `tags` are offered as completions.
+pub(crate) fn page(params: &GraphParams, tags: &[String]) -> Markup {
+    html! {
+        section class="graph-page" {
+            header class="note-head" {
+                h1 { "Graph" }
+            }
+            form class="graph-controls" action="/graph" method="get" {
+                @if let Some(note) = params.note() {
+                    input type="hidden" name="note" value=(note) {}
+                    span class="graph-center" { "Around " code { (note) } }
+                    label {
+                        span { "Depth" }
+                        (depth_select(params.depth(), None))
+                    }
+                    a href="/graph" { "Show all notes" }
+                }
+                label {
+                    span { "Tag" }
+                    input type="text" name="tag" value=(params.tag().unwrap_or_default())
+                        list="graph-tags" placeholder="any" autocomplete="off" {}
+                }
+                datalist #graph-tags {
+                    @for tag in tags {
+                        option value=(tag) {}
+                    }
+                }
+                button type="submit" class="btn btn-ghost" { "Apply" }
+            }
+            div class="graph-view" data-src=(params.data_url()) {}
+        }
+    }
+}
+
+/// Render the local graph of the note `id` for the right rail.
+pub(crate) fn local(id: &str) -> Markup {
+    let params = GraphParams {
+        note: id.to_owned(),
+        depth: Some(1),
+        tag: String::new(),
+    };
+
+    html! {
+        div class="graph-view graph-local" data-src=(params.data_url()) {}
+        div class="graph-local-controls" {
+            label {
+                span { "Depth" }
+                (depth_select(1, Some("setGraphDepth(this)")))
+            }
+            a href={ "/graph?note=" (encode_id(id)) "&depth=" (DEFAULT_DEPTH) } { "Open graph" }
+        }
+    }
+}
+
+fn depth_select(depth: usize, onchange: Option<&str>) -> Markup {
+    html! {
+        select name="depth" onchange=[onchange] {
+            @for value in 1..=MAX_DEPTH {
+                option value=(value) selected[value == depth] { (value) }
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use axum::Form;
+use axum::extract::State;
+use axum::http::{HeaderName, StatusCode};
+use maud::{Markup, html};
+use serde::Deserialize;
+
+use crate::Notebook;
+use crate::extract::Authenticated;
+use crate::zk::{Error, Health, Note, NoteExt, encode_id};
+
+const HX_TRIGGER: HeaderName = HeaderName::from_static("hx-trigger");
+
+/// Fields of the form creating the note a dead link points to.
+#[derive(Deserialize)]
+pub(crate) struct CreateRequest {
+    /// Id of the note containing the link.
+    from: String,
+    link: String,
+}
+
+/// Render the dead links, most linked and isolated notes of the notebook.
+/// `status` reports the result of the last created note.
+pub(crate) fn health(health: &Health, status: Option<&str>) -> Markup {
+    html! {
+        section class="health" #health {
+            header class="note-head" {
+                h1 { "Notebook health" }
+            }
+            p class="health-summary" {
+                (count(health.dead_links.len(), "dead link", "dead links")) " · "
+                (count(health.isolated.len(), "isolated note", "isolated notes")) " · "
+                (count(health.clusters, "cluster of linked notes", "clusters of linked notes"))
+            }
+            @if let Some(status) = status {
+                div class="health-status" role="status" { (status) }
+            }
+
+            h2 { "Dead links" }
+            @if health.dead_links.is_empty() {
+                p class="health-hint" { "Every link points to an existing note." }
+            }
+            ul class="health-rows" {
+                @for link in &health.dead_links {
+                    li class="health-row" {
+                        a href={ "/note/" (encode_id(&link.source)) }
+                            hx-get={ "/f/" (encode_id(&link.source)) "/edit" }
+                            hx-target="#note-content"
+                            hx-push-url={ "/note/" (encode_id(&link.source)) }
+                            data-line=(link.line)
+                            title="Edit at the link" {
+                            (link.title)
+                        }
+                        span class="health-line" { "line " (link.line) }
+                        code class="health-target" { (link.target) }
+                        form class="health-form"
+                            hx-post="/health/create"
+                            hx-target="#health"
+                            hx-swap="outerHTML" {
+                            input type="hidden" name="from" value=(link.source) {}
+                            input type="hidden" name="link" value=(link.target) {}
+                            button type="submit" class="btn btn-ghost" { "Create note" }
+                        }
+                    }
+                }
+            }
+
+            h2 { "Most linked" }
+            @if health.hubs.is_empty() {
+                p class="health-hint" { "No note links to another one yet." }
+            }
+            ul class="health-rows" {
+                @for (note, backlinks) in &health.hubs {
+                    li class="health-row" {
+                        (note_link(note))
+                        span class="health-line" {
+                            (count(*backlinks, "backlink", "backlinks"))
+                        }
+                    }
+                }
+            }
+
+            h2 { "Isolated notes" }
+            p class="health-hint" { "Notes without links to or from other notes." }
+            ul class="health-rows" {
+                @for note in &health.isolated {
+                    li class="health-row" { (note_link(note)) }
+                }
+            }
+        }
+    }
+}
+
+/// Create the note a dead link points to and return the updated health page.
+pub(crate) async fn create(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+    Form(CreateRequest { from, link }): Form<CreateRequest>,
+) -> Result<([(HeaderName, &'static str); 1], Markup), StatusCode> {
+    if !authenticated {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let markup = tokio::task::spawn_blocking(move || {
+        let mut notebook = notebook.lock().unwrap();
+        let status = match notebook.create_missing(&link, &from) {
+            Ok(id) => format!("Created {id}"),
+            Err(err) => {
+                tracing::warn!(?err, "failed to create missing note");
+                match err {
+                    Error::Zk(err) => err.to_string(),
+                    err => err.to_string(),
+                }
+            }
+        };
+        health(&notebook.health(), Some(&status))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(([(HX_TRIGGER, "notes-updated")], markup))
+}
+
+fn note_link(note: &Note) -> Markup {
+    html! {
+        a href={ "/note/" (encode_id(&note.id())) }
+            hx-get={ "/f/" (encode_id(&note.id())) }
+            hx-target="#note-content"
+            hx-push-url={ "/note/" (encode_id(&note.id())) }
+        { (note.title()) }
+    }
+}
+
+fn count(count: usize, one: &str, many: &str) -> String {
+    if count == 1 {
+        format!("1 {one}")
+    } else {
+        format!("{count} {many}")
+    }
+}
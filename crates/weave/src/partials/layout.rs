@@ -16,6 +16,16 @@ pub(crate) fn layout(
 ) -> Markup {
     let notebook = notebook.lock().unwrap();
     let notes = notebook.all_notes((!authenticated).then_some("public"));
+    let (templates, templates_error) = match notebook.templates() {
+        Ok(templates) => (templates, None),
+        Err(err) => {
+            tracing::warn!(?err, "failed to list note templates");
+            (Vec::new(), Some(err.to_string()))
+        }
+    };
+    let groups = notebook.groups();
+    let filters = notebook.filters();
+    let tag_tree = notebook.tag_tree(authenticated);
 
     html! {
         (DOCTYPE)
@@ -32,10 +42,11 @@ pub(crate) fn layout(
                             span class="search-icon" { (assets::icons::search()) }
                             input #filter-input type="search"
                                 name="query"
-                                placeholder="Search notes by title, tag, body..."
+                                placeholder="Search notes, #tags, created:>2024-01..."
                                 autocomplete="off"
                                 hx-post="/f/search"
-                                hx-trigger="input changed delay:300ms, keyup[key=='Enter'], notes-updated from:body"
+                                hx-trigger="input changed delay:300ms, keyup[key=='Enter'], notes-updated from:body, view-changed"
+                                hx-include="#view-input"
                                 hx-target="#search-list"
                                 hx-swap="innerHTML"
                                 {}
@@ -60,11 +71,23 @@ pub(crate) fn layout(
                                 (assets::icons::link())
                             }
                             button type="button" class="tb-btn" #new-note
-                                title="New note (N)" aria-label="New note"
-                                hx-post="/note"
-                                hx-swap="none" {
+                                title="New note (N)" aria-label="New note" {
                                 (assets::icons::plus())
                             }
+                            a href="/tags" class="tb-btn" title="Tags" aria-label="Tags" {
+                                (assets::icons::tag())
+                            }
+                            a href="/health" class="tb-btn" title="Notebook health"
+                                aria-label="Notebook health" {
+                                (assets::icons::activity())
+                            }
+                            a href="/attachments" class="tb-btn" title="Attachments"
+                                aria-label="Attachments" {
+                                (assets::icons::paperclip())
+                            }
+                        }
+                        a href="/graph" class="tb-btn" title="Graph" aria-label="Graph" {
+                            (assets::icons::graph())
                         }
                         button type="button" class="tb-btn" #theme-toggle
                             title="Toggle theme (D)" aria-label="Toggle theme" {
@@ -107,10 +130,47 @@ pub(crate) fn layout(
                             div class="clip-status" #clip-status role="status" aria-live="polite" {}
                         }
                     }
+
+                    div class="clip-drawer" #create-drawer {
+                        h3 { "New note" }
+                        form class="clip-row" #create-form
+                            hx-post="/note"
+                            hx-swap="none" {
+                            input #create-title type="text" name="title"
+                                placeholder="Title" autocomplete="off" {}
+                            @if !templates.is_empty() {
+                                select name="template" aria-label="Template" {
+                                    option value="" { "Default template" }
+                                    @for template in &templates {
+                                        option value=(template) { (template) }
+                                    }
+                                }
+                            }
+                            @if !groups.is_empty() {
+                                select name="group" aria-label="Group" {
+                                    option value="" { "No group" }
+                                    @for group in &groups {
+                                        option value=(group) { (group) }
+                                    }
+                                }
+                            }
+                            button type="submit" class="btn btn-primary" { "Create" }
+                            button type="button" class="btn btn-ghost" #create-cancel {
+                                "Cancel"
+                            }
+                        }
+                        @if let Some(err) = &templates_error {
+                            div class="clip-status" role="alert" {
+                                "Templates are unavailable: " (err)
+                            }
+                        }
+                    }
                 }
 
                 div class="body-grid" {
                     aside class="sidebar" {
+                        (partials::views::views(&filters, "", authenticated))
+                        (partials::tags::tree(&tag_tree))
                         div id="search-list" class="note-list" {
                             (partials::note_list::note_list(notes))
                         }
@@ -1,9 +1,15 @@
+pub(crate) mod attachments;
 pub(crate) mod clip;
 pub(crate) mod create;
 pub(crate) mod edit;
+pub(crate) mod frontmatter;
 pub(crate) mod head;
+pub(crate) mod graph;
+pub(crate) mod health;
 pub(crate) mod layout;
 pub(crate) mod note;
 pub(crate) mod note_list;
 pub(crate) mod note_nav;
 pub(crate) mod search;
+pub(crate) mod tags;
+pub(crate) mod views;
@@ -2,46 +2,54 @@ use axum::extract::{Path, State};
 use maud::{Markup, html};
 
 use crate::extract::Authenticated;
-use crate::partials::note_nav::{NoteNavData, note_nav};
+use crate::partials::note_nav::{NoteNavData, note_nav, properties};
+use crate::zk::{Note, NoteExt, encode_id};
 use crate::{Notebook, md};
 
 /// Return note content fragment: <article class="note"> with header + body.
 pub(crate) async fn note(
     State(notebook): State<Notebook>,
     Authenticated(authenticated): Authenticated,
-    Path(stem): Path<String>,
+    Path(id): Path<String>,
 ) -> Markup {
-    let Some(note) = notebook.lock().unwrap().note(&stem) else {
+    let Some(note) = notebook.lock().unwrap().note(&id) else {
         return html! {};
     };
 
     if !authenticated && !note.has("public") {
         return html! {
-            article class="note" data-stem=(stem) data-mode="read" {
+            article class="note" data-id=(note.id()) data-mode="read" {
                 div class="note-empty" { "access denied" }
             }
         };
     }
 
-    let backlinks = notebook.lock().unwrap().backlinks(&stem, authenticated);
-    let outgoing_links = notebook
-        .lock()
-        .unwrap()
-        .outgoing_links(note.outgoing_links(), authenticated);
+    let backlinks = notebook.lock().unwrap().backlinks(&id, authenticated);
+    let outgoing_links = notebook.lock().unwrap().outgoing_links(&id, authenticated);
+    let related = notebook.lock().unwrap().related(&id, authenticated);
+    let same_stem = notebook.lock().unwrap().same_stem(&id);
     let tags = note.tags().to_vec();
     let body = note.body().to_owned();
     let title = note.title().to_owned();
 
-    let (rendered, headings) =
-        tokio::task::spawn_blocking(move || md::markdown_to_html_with_headings(&body))
-            .await
-            .expect("join working");
+    let resolver = notebook.clone();
+    let from = id.clone();
+    let (rendered, headings) = tokio::task::spawn_blocking(move || {
+        md::markdown_to_html_with_headings(&body, &|link: &str| {
+            resolver.lock().unwrap().resolve(link, &from)
+        })
+    })
+    .await
+    .expect("join working");
 
     let nav_data = NoteNavData {
+        id: id.clone(),
         headings,
         outgoing_links,
         backlinks,
+        related,
         tags,
+        properties: properties(&note),
     };
     let has_rail = !nav_data.is_empty();
 
@@ -53,8 +61,11 @@ pub(crate) async fn note(
     let note_class = if has_rail { "note" } else { "note note--no-rail" };
 
     html! {
-        article class=(note_class) data-stem=(stem) data-mode="read" {
-            header class="note-head" { h1 { (title) } }
+        article class=(note_class) data-id=(note.id()) data-mode="read" {
+            header class="note-head" {
+                h1 { (title) }
+                (stem_warning(&note, &same_stem))
+            }
             div class=(body_class) {
                 div class="md" { (rendered) }
                 @if has_rail { (note_nav(&nav_data)) }
@@ -62,3 +73,24 @@ pub(crate) async fn note(
         }
     }
 }
+
+/// Warn that links by filename stem to `note` are ambiguous because the
+/// notes in `same_stem` share it. Renders nothing if the stem is unique.
+pub(crate) fn stem_warning(note: &Note, same_stem: &[Note]) -> Markup {
+    html! {
+        @if !same_stem.is_empty() {
+            div class="note-warning" role="note" {
+                "Other notes are also named “" (note.filename_stem())
+                "”, so links by name are ambiguous: "
+                @for (i, other) in same_stem.iter().enumerate() {
+                    @if i > 0 { ", " }
+                    a href="#"
+                        hx-get={ "/f/" (encode_id(&other.id())) }
+                        hx-target="#note-content"
+                        hx-push-url={ "/note/" (encode_id(&other.id())) }
+                    { (other.path().display()) }
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use maud::{Markup, html};
 
 use crate::assets::icons;
-use crate::zk::{self, NoteExt};
+use crate::zk::{self, NoteExt, Snippet, encode_id};
 
 enum Kind {
     Regular,
@@ -24,22 +24,30 @@ fn classify(note: &zk::Note) -> Kind {
 /// `#pin` notes are grouped at the top, `#archived` at the bottom (greyed out),
 /// regular notes in the middle in modified-date order.
 pub(crate) fn note_list<'a>(notes: impl IntoIterator<Item = &'a zk::Note>) -> Markup {
-    let notes: Vec<&zk::Note> = notes.into_iter().collect();
+    search_results(notes.into_iter().map(|note| (note, None)))
+}
+
+/// Render search results like [`note_list`], showing the matching snippet
+/// instead of the start of the note where there is one.
+pub(crate) fn search_results<'a>(
+    results: impl IntoIterator<Item = (&'a zk::Note, Option<&'a Snippet>)>,
+) -> Markup {
+    let results: Vec<(&zk::Note, Option<&Snippet>)> = results.into_iter().collect();
 
     html! {
-        @for note in notes.iter().filter(|n| matches!(classify(n), Kind::Pinned)) {
-            (note_row(note, Kind::Pinned))
+        @for (note, snippet) in results.iter().filter(|(n, _)| matches!(classify(n), Kind::Pinned)) {
+            (note_row(note, Kind::Pinned, *snippet))
         }
-        @for note in notes.iter().filter(|n| matches!(classify(n), Kind::Regular)) {
-            (note_row(note, Kind::Regular))
+        @for (note, snippet) in results.iter().filter(|(n, _)| matches!(classify(n), Kind::Regular)) {
+            (note_row(note, Kind::Regular, *snippet))
         }
-        @for note in notes.iter().filter(|n| matches!(classify(n), Kind::Archived)) {
-            (note_row(note, Kind::Archived))
+        @for (note, snippet) in results.iter().filter(|(n, _)| matches!(classify(n), Kind::Archived)) {
+            (note_row(note, Kind::Archived, *snippet))
         }
     }
 }
 
-fn note_row(note: &zk::Note, kind: Kind) -> Markup {
+fn note_row(note: &zk::Note, kind: Kind, snippet: Option<&Snippet>) -> Markup {
     let row_class = match kind {
         Kind::Regular => "note-row",
         Kind::Pinned => "note-row note-row--pinned",
@@ -49,10 +57,10 @@ fn note_row(note: &zk::Note, kind: Kind) -> Markup {
 
     html! {
         div class=(row_class)
-            data-stem=(note.filename_stem())
-            hx-get={ "/f/" (note.filename_stem()) }
+            data-id=(note.id())
+            hx-get={ "/f/" (encode_id(&note.id())) }
             hx-target="#note-content"
-            hx-push-url={ "/note/" (note.filename_stem()) }
+            hx-push-url={ "/note/" (encode_id(&note.id())) }
             onclick="showNote(event)" {
             div class="nr-top" {
                 span class="nr-title" { (note.title()) }
@@ -60,7 +68,15 @@ fn note_row(note: &zk::Note, kind: Kind) -> Markup {
                     span class="nr-pin" { (icons::pin()) }
                 }
             }
-            p class="nr-preview" { (note.snippet()) }
+            @if let Some(snippet) = snippet {
+                p class="nr-preview nr-preview--match" {
+                    @for (text, marked) in snippet.fragments() {
+                        @if marked { mark { (text) } } @else { (text) }
+                    }
+                }
+            } @else {
+                p class="nr-preview" { (note.snippet()) }
+            }
         }
     }
 }
@@ -1,13 +1,18 @@
 use maud::{Markup, html};
 
 use crate::md::Heading;
-use crate::zk::Note;
+use crate::partials::frontmatter::{is_known, scalar_text};
+use crate::partials::graph;
+use crate::zk::{Note, NoteExt, Related, YamlValue, encode_id};
 
 pub(crate) struct NoteNavData {
+    pub id: String,
     pub headings: Vec<Heading>,
     pub outgoing_links: Vec<Note>,
     pub backlinks: Vec<Note>,
+    pub related: Vec<Related>,
     pub tags: Vec<String>,
+    pub properties: Vec<(String, YamlValue)>,
 }
 
 impl NoteNavData {
@@ -15,10 +20,21 @@ impl NoteNavData {
         self.headings.is_empty()
             && self.outgoing_links.is_empty()
             && self.backlinks.is_empty()
+            && self.related.is_empty()
             && self.tags.is_empty()
+            && self.properties.is_empty()
     }
 }
 
+/// Return the frontmatter fields of `note` shown in the properties table.
+pub(crate) fn properties(note: &Note) -> Vec<(String, YamlValue)> {
+    note.metadata()
+        .iter()
+        .filter(|(key, _)| !is_known(key))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
 pub(crate) fn note_nav(data: &NoteNavData) -> Markup {
     if data.is_empty() {
         return html! {};
@@ -44,9 +60,9 @@ pub(crate) fn note_nav(data: &NoteNavData) -> Markup {
                         @for note in &data.outgoing_links {
                             li {
                                 a href="#"
-                                    hx-get={ "/f/" (note.filename_stem()) }
+                                    hx-get={ "/f/" (encode_id(&note.id())) }
                                     hx-target="#note-content"
-                                    hx-push-url={ "/note/" (note.filename_stem()) }
+                                    hx-push-url={ "/note/" (encode_id(&note.id())) }
                                 { (note.title()) }
                             }
                         }
@@ -61,9 +77,9 @@ pub(crate) fn note_nav(data: &NoteNavData) -> Markup {
                         @for note in &data.backlinks {
                             li {
                                 a href="#"
-                                    hx-get={ "/f/" (note.filename_stem()) }
+                                    hx-get={ "/f/" (encode_id(&note.id())) }
                                     hx-target="#note-content"
-                                    hx-push-url={ "/note/" (note.filename_stem()) }
+                                    hx-push-url={ "/note/" (encode_id(&note.id())) }
                                 { (note.title()) }
                             }
                         }
@@ -71,6 +87,45 @@ pub(crate) fn note_nav(data: &NoteNavData) -> Markup {
                 }
             }
 
+            @if !data.outgoing_links.is_empty() || !data.backlinks.is_empty() {
+                section class="note-nav-section" {
+                    h4 class="note-nav-title" { "Graph" }
+                    (graph::local(&data.id))
+                }
+            }
+
+            @if !data.related.is_empty() {
+                section class="note-nav-section" {
+                    h4 class="note-nav-title" { "Related" }
+                    ul class="note-nav-list" {
+                        @for related in &data.related {
+                            li {
+                                a href="#"
+                                    hx-get={ "/f/" (encode_id(&related.note.id())) }
+                                    hx-target="#note-content"
+                                    hx-push-url={ "/note/" (encode_id(&related.note.id())) }
+                                    title=(reason(related))
+                                { (related.note.title()) }
+                            }
+                        }
+                    }
+                }
+            }
+
+            @if !data.properties.is_empty() {
+                section class="note-nav-section" {
+                    h4 class="note-nav-title" { "Properties" }
+                    table class="note-props" {
+                        @for (key, value) in &data.properties {
+                            tr {
+                                th scope="row" { (key) }
+                                td { (property_value(key, value)) }
+                            }
+                        }
+                    }
+                }
+            }
+
             @if !data.tags.is_empty() {
                 section class="note-nav-section" {
                     h4 class="note-nav-title" { "Tags" }
@@ -90,6 +145,62 @@ pub(crate) fn note_nav(data: &NoteNavData) -> Markup {
     }
 }
 
+/// Describe why a note is related, strongest relation first.
+fn reason(related: &Related) -> String {
+    let mut reasons = [
+        (
+            related.tags,
+            format!("shared tags #{}", related.shared_tags.join(", #")),
+        ),
+        (related.co_citation, "linked from the same notes".to_owned()),
+        (related.coupling, "links to the same notes".to_owned()),
+        (related.similarity, "similar words".to_owned()),
+    ];
+    reasons.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let reasons: Vec<String> = reasons
+        .into_iter()
+        .filter(|(score, _)| *score > 0.0)
+        .map(|(_, reason)| reason)
+        .collect();
+    format!("Related by {}", reasons.join(", "))
+}
+
+/// Render a property value. Scalars link to a search for notes with the same
+/// value, nested values are shown as JSON.
+fn property_value(key: &str, value: &YamlValue) -> Markup {
+    match value {
+        YamlValue::Sequence(items) if items.iter().all(|item| scalar_text(item).is_some()) => {
+            html! {
+                @for (i, item) in items.iter().enumerate() {
+                    @if i > 0 { ", " }
+                    (property_value(key, item))
+                }
+            }
+        }
+        YamlValue::Tagged(tagged) => property_value(key, &tagged.value),
+        value => match scalar_text(value) {
+            Some(text) if text.is_empty() => html! {},
+            Some(text) if !text.contains('"') => {
+                let query = format!("{key}:\"{text}\"");
+                html! {
+                    a href="#" class="note-prop-link"
+                        hx-post="/f/search"
+                        hx-vals=(serde_json::json!({ "query": query }))
+                        hx-target="#search-list"
+                        onclick="showList()"
+                    { (text) }
+                }
+            }
+            Some(text) => html! { (text) },
+            None => html! {
+                code class="note-prop-nested" {
+                    (serde_json::to_string(value).unwrap_or_default())
+                }
+            },
+        },
+    }
+}
+
 fn toc_item(h: &Heading, min_level: u8) -> Markup {
     let depth = h.level.saturating_sub(min_level);
     html! {
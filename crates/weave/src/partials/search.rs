@@ -1,37 +1,88 @@
 use axum::Form;
 use axum::extract::State;
-use maud::Markup;
+use maud::{Markup, html};
 use serde::Deserialize;
 
 use crate::extract::Authenticated;
+use crate::zk::{NoteQuery, Snippet};
 use crate::{Notebook, partials};
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct Search {
     query: String,
+    /// Name of the selected saved filter, empty for all notes.
+    #[serde(default)]
+    view: String,
 }
 
 /// Return fragment for the sidebar search results (filters notes list).
+///
+/// The query uses zk's filters, see [`NoteQuery::parse`], and is narrowed
+/// down by the selected view. Without authentication only public notes are
+/// found, and link filters may only name public notes.
 #[tracing::instrument(skip(notebook))]
 pub(crate) async fn search(
     State(notebook): State<Notebook>,
     Authenticated(authenticated): Authenticated,
     Form(search): Form<Search>,
 ) -> Markup {
-    let query = search.query.trim();
-    let notebook = notebook.lock().unwrap();
-    let tag_filter = (!authenticated).then_some("public");
-
-    let notes = if query.is_empty() {
-        // Return all authorized notes when query is empty
-        notebook.all_notes(tag_filter)
-    } else if let Some(tag) = query.strip_prefix('#') {
-        notebook.search_tag(tag, tag_filter)
-    } else {
-        notebook.search_titles(query, tag_filter)
+    let mut query = match NoteQuery::parse(search.query.trim()) {
+        Ok(query) => query,
+        Err(err) => {
+            tracing::info!(%err, "invalid search");
+            return html! { div class="search-error" { (err.to_string()) } };
+        }
     };
 
+    if !search.view.is_empty() {
+        // Views are not shown without authentication.
+        if !authenticated {
+            return html! { div class="search-error" { "unknown view: " (search.view) } };
+        }
+        match notebook.lock().unwrap().filter(&search.view) {
+            Ok(view) => query = view.and(query),
+            Err(err) => {
+                tracing::info!(%err, "invalid view");
+                return html! { div class="search-error" { (err.to_string()) } };
+            }
+        }
+    }
+    if let Some(text) = query
+        .match_text()
+        .and_then(|text| as_you_type(&search.query, text))
+    {
+        query = query.matching(text);
+    }
+
+    let notebook = notebook.lock().unwrap();
+    if !authenticated {
+        // Results of link filters would reveal which notes link to hidden ones.
+        let hidden = query
+            .link_ids()
+            .find(|id| !notebook.note(id).is_some_and(|note| note.has("public")));
+        if let Some(id) = hidden {
+            tracing::info!(id, "link filter on hidden note");
+            return html! { div class="search-error" { "unknown note: " (id) } };
+        }
+        // Before the query's limit, which would count hidden notes otherwise.
+        query = query.with_tag("public");
+    }
+    let notes = notebook.query(&query);
+
     tracing::info!(number = notes.len(), "search results");
 
-    partials::note_list::note_list(notes)
+    let snippets: Vec<Option<Snippet>> = notes
+        .iter()
+        .map(|note| query.match_text().map(|text| notebook.snippet(note, text)))
+        .collect();
+
+    partials::note_list::search_results(notes.into_iter().zip(snippets.iter().map(Option::as_ref)))
+}
+
+/// Treat the last word of the full-text part as a prefix while it is being
+/// typed, so results show up before the word is complete.
+fn as_you_type(raw: &str, text: &str) -> Option<String> {
+    let typing = raw.ends_with(char::is_alphanumeric)
+        && raw.split_whitespace().next_back() == text.split_whitespace().next_back();
+    typing.then(|| format!("{text}*"))
 }
@@ -0,0 +1,205 @@
+use axum::Form;
+use axum::extract::State;
+use axum::http::{HeaderName, StatusCode};
+use maud::{Markup, html};
+use serde::Deserialize;
+
+use crate::Notebook;
+use crate::extract::Authenticated;
+use crate::zk::{Error, TagNode};
+
+const HX_TRIGGER: HeaderName = HeaderName::from_static("hx-trigger");
+
+/// Fields of the rename and delete forms of a tag row.
+#[derive(Deserialize)]
+pub(crate) struct TagRequest {
+    tag: String,
+    #[serde(default)]
+    to: String,
+}
+
+/// Render all tags with their note counts and forms to rename, merge or
+/// delete them. `status` reports the result of the last change.
+pub(crate) fn tags(tags: &[(String, usize)], status: Option<&str>) -> Markup {
+    html! {
+        section class="tags" #tags {
+            header class="note-head" {
+                h1 { "Tags" }
+            }
+            p class="tags-hint" {
+                "Changes apply to every note, in frontmatter and inline tags. "
+                "Renaming a tag to an existing one merges them."
+            }
+            @if let Some(status) = status {
+                div class="tags-status" role="status" { (status) }
+            }
+            @if tags.is_empty() {
+                p class="tags-hint" { "No tags yet." }
+            }
+            datalist #tag-names {
+                @for (tag, _) in tags {
+                    option value=(tag) {}
+                }
+            }
+            ul class="tag-rows" {
+                @for (tag, count) in tags {
+                    li class="tag-row" {
+                        a href="#" class="tag-chip"
+                            hx-post="/f/search"
+                            hx-vals={ "{\"query\": \"#" (tag) "\"}" }
+                            hx-target="#search-list"
+                            onclick="showList()"
+                        { "#" (tag) }
+                        span class="tag-count" {
+                            (count) @if *count == 1 { " note" } @else { " notes" }
+                        }
+                        form class="tag-form"
+                            hx-post="/tags/rename"
+                            hx-target="#tags"
+                            hx-swap="outerHTML" {
+                            input type="hidden" name="tag" value=(tag) {}
+                            input type="text" name="to" placeholder="New name"
+                                list="tag-names" autocomplete="off"
+                                aria-label={ "New name for #" (tag) } {}
+                            button type="submit" class="btn btn-ghost" { "Rename" }
+                            button type="button" class="btn btn-ghost"
+                                hx-post="/tags/delete"
+                                hx-confirm={ "Remove #" (tag) " from " (count) " notes?" } {
+                                "Delete"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Render the nested tags as a collapsible tree for the sidebar. Each tag
+/// searches for its notes, including those with tags nested in it.
+pub(crate) fn tree(nodes: &[TagNode]) -> Markup {
+    html! {
+        @if !nodes.is_empty() {
+            details class="tag-tree" {
+                summary class="tag-tree-title" { "Tags" }
+                (tree_list(nodes))
+            }
+        }
+    }
+}
+
+fn tree_list(nodes: &[TagNode]) -> Markup {
+    html! {
+        ul class="tag-tree-list" {
+            @for node in nodes {
+                li {
+                    @if node.children.is_empty() {
+                        (tree_link(node))
+                    } @else {
+                        details {
+                            summary { (tree_link(node)) }
+                            (tree_list(&node.children))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn tree_link(node: &TagNode) -> Markup {
+    html! {
+        a href="#" class="tag-tree-link"
+            title={ "#" (node.tag) }
+            hx-post="/f/search"
+            hx-vals=(serde_json::json!({ "query": format!("#{}", node.tag) }))
+            hx-target="#search-list"
+            onclick="showList()"
+        {
+            (node.name)
+            span class="tag-tree-count" { (node.count) }
+        }
+    }
+}
+
+/// Rename a tag in all notes, merging it if the new name exists, and return
+/// the updated tag list.
+pub(crate) async fn rename(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+    Form(TagRequest { tag, to }): Form<TagRequest>,
+) -> Result<([(HeaderName, &'static str); 1], Markup), StatusCode> {
+    if !authenticated {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    change(notebook, move |notebook| {
+        let to = to.trim();
+        if to.is_empty() {
+            return format!("Enter a new name for #{tag}");
+        }
+
+        let to_lower = to.to_lowercase();
+        let merge = notebook
+            .tag_counts()
+            .iter()
+            .any(|(other, _)| *other == to_lower)
+            && tag.to_lowercase() != to_lower;
+        match notebook.rename_tag(&tag, to) {
+            Ok(count) if merge => format!("Merged #{tag} into #{to} in {}", notes(count)),
+            Ok(count) => format!("Renamed #{tag} to #{to} in {}", notes(count)),
+            Err(err) => error_status(err),
+        }
+    })
+    .await
+}
+
+/// Remove a tag from all notes and return the updated tag list.
+pub(crate) async fn delete(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+    Form(TagRequest { tag, .. }): Form<TagRequest>,
+) -> Result<([(HeaderName, &'static str); 1], Markup), StatusCode> {
+    if !authenticated {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    change(notebook, move |notebook| match notebook.delete_tag(&tag) {
+        Ok(count) => format!("Removed #{tag} from {}", notes(count)),
+        Err(err) => error_status(err),
+    })
+    .await
+}
+
+/// Apply a tag change returning a status message, off the async runtime since
+/// it may rewrite many notes, and render the tag list with the status.
+async fn change(
+    notebook: Notebook,
+    apply: impl FnOnce(&mut crate::zk::Notebook) -> String + Send + 'static,
+) -> Result<([(HeaderName, &'static str); 1], Markup), StatusCode> {
+    let markup = tokio::task::spawn_blocking(move || {
+        let mut notebook = notebook.lock().unwrap();
+        let status = apply(&mut *notebook);
+        tags(&notebook.tag_counts(), Some(&status))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(([(HX_TRIGGER, "notes-updated")], markup))
+}
+
+fn notes(count: usize) -> String {
+    if count == 1 {
+        "1 note".to_owned()
+    } else {
+        format!("{count} notes")
+    }
+}
+
+fn error_status(err: Error) -> String {
+    tracing::warn!(?err, "failed to change tag");
+    match err {
+        Error::Zk(err) => err.to_string(),
+        err => err.to_string(),
+    }
+}
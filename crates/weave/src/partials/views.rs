@@ -0,0 +1,90 @@
+use axum::Form;
+use axum::extract::State;
+use axum::http::StatusCode;
+use maud::{Markup, html};
+use serde::Deserialize;
+
+use crate::Notebook;
+use crate::extract::Authenticated;
+use crate::zk::NoteQuery;
+
+/// Render the saved filters from the `[filter]` config section as views above
+/// the note list. `selected` is the active filter, empty for all notes.
+///
+/// The hidden `#view-input` is included in every search request, so the list
+/// keeps showing the selected view when it is refreshed.
+///
+/// Views are only shown when `authenticated`, their names may describe
+/// private notes.
+pub(crate) fn views(filters: &[String], selected: &str, authenticated: bool) -> Markup {
+    let chip = |on: bool| if on { "view-chip is-on" } else { "view-chip" };
+
+    html! {
+        div #views class="views" hidden[!authenticated] {
+            input #view-input type="hidden" name="view" value=(selected);
+            @if authenticated {
+                button type="button" class=(chip(selected.is_empty())) data-view="" {
+                    "All"
+                }
+                @for name in filters {
+                    button type="button" class=(chip(name == selected)) data-view=(name) {
+                        (name)
+                    }
+                }
+                button type="button" #save-view class="view-chip view-save" title="Save search as view" {
+                    "+"
+                }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SaveRequest {
+    name: String,
+    #[serde(default)]
+    query: String,
+    #[serde(default)]
+    view: String,
+}
+
+/// Save the current search, narrowed by the selected view, as a named filter
+/// in the notebook config and return the views with the new one selected.
+pub(crate) async fn save(
+    State(notebook): State<Notebook>,
+    Authenticated(authenticated): Authenticated,
+    Form(req): Form<SaveRequest>,
+) -> Result<Markup, (StatusCode, String)> {
+    if !authenticated {
+        return Err((StatusCode::FORBIDDEN, "Not logged in".into()));
+    }
+
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Enter a name for the view".into()));
+    }
+
+    let mut notebook = notebook.lock().unwrap();
+    let bad_request = |err: crate::zk::Error| (StatusCode::BAD_REQUEST, err.to_string());
+
+    let mut query = NoteQuery::parse(req.query.trim()).map_err(|err| bad_request(err.into()))?;
+    if !req.view.is_empty() {
+        query = notebook.filter(&req.view).map_err(bad_request)?.and(query);
+    }
+
+    notebook
+        .save_filter(name, &query)
+        .map_err(|err| match err {
+            // Frontmatter field filters have no `zk list` equivalent.
+            crate::zk::Error::Zk(zk_rs::Error::Query(_)) => bad_request(err),
+            err => {
+                tracing::error!(?err, "failed to save filter");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Could not save the view".into(),
+                )
+            }
+        })?;
+
+    Ok(views(&notebook.filters(), name, authenticated))
+}
@@ -1,10 +1,15 @@
 //! Interface with the zk notebook via zk-rs.
 
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
 use pulldown_cmark::{Event, Parser, TagEnd};
+use serde::Serialize;
 
-pub use zk_rs::Note;
+pub use zk_rs::{
+    FileChange, Frontmatter, NewNote, Note, NoteQuery, NotebookEvent, NotebookWatcher, Snippet,
+    YamlValue,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -12,6 +17,8 @@ pub enum Error {
     Var(#[from] std::env::VarError),
     #[error("attachment path is not a subdirectory of the notebook")]
     InvalidAttachmentPath,
+    #[error("attachment is still linked from notes")]
+    AttachmentInUse,
     #[error("zk error: {0}")]
     Zk(#[from] zk_rs::Error),
 }
@@ -20,119 +27,605 @@ pub struct Notebook {
     /// Path to the notebook.
     pub path: PathBuf,
     inner: zk_rs::Notebook,
+    /// Notebook subdirectory served as attachments, see
+    /// [`serve_attachments`](Self::serve_attachments).
+    attachments_dir: Option<PathBuf>,
 }
 
 impl Notebook {
     pub fn load() -> Result<Self, Error> {
         let path = PathBuf::from(std::env::var("ZK_NOTEBOOK_DIR")?);
-        let inner = zk_rs::Notebook::load(&path)?;
-        Ok(Self { path, inner })
+
+        // Keep zk's index up to date if the notebook has one, so the zk CLI
+        // sees changes made here. Otherwise only parse what changed since the
+        // last start.
+        let inner = if path.join(".zk").join("notebook.db").is_file() {
+            zk_rs::Notebook::load_with_index(&path)?
+        } else {
+            zk_rs::Notebook::load_cached(&path)?
+        };
+        for err in inner.config_errors() {
+            tracing::warn!(?err, "invalid notebook config");
+        }
+        Ok(Self {
+            path,
+            inner,
+            attachments_dir: None,
+        })
     }
 
-    /// Get the path to the attachments based on the `ZK_NOTEBOOK_DIR` and the given `subdir`.
+    /// Get the path to the attachments based on the `ZK_NOTEBOOK_DIR` and the given `subdir`
+    /// and list the files in it on the attachments page.
     /// Returns an error if subdir is not actually a subdir (i.e. ../../../foo).
-    pub fn attachments(&self, subdir: &Path) -> Result<PathBuf, Error> {
+    pub fn serve_attachments(&mut self, subdir: &Path) -> Result<PathBuf, Error> {
         let path = self.path.join(subdir);
 
         if !path.starts_with(&self.path) {
             return Err(Error::InvalidAttachmentPath);
         }
 
+        self.inner
+            .set_attachments_dir(subdir)
+            .map_err(|err| match err {
+                zk_rs::Error::InvalidPath(_) => Error::InvalidAttachmentPath,
+                err => err.into(),
+            })?;
+        self.attachments_dir = Some(subdir.to_path_buf());
         Ok(path)
     }
 
-    /// Reload the note with the given stem.
-    pub fn reload(&mut self, stem: &str) -> Result<(), Error> {
-        self.inner.reload(stem)?;
+    /// Return the notebook subdirectory served as attachments, if any.
+    pub fn attachments_dir(&self) -> Option<&Path> {
+        self.attachments_dir.as_deref()
+    }
+
+    /// Return the files in the attachments directory that are not notes with
+    /// the notes linking to them, sorted by path.
+    pub fn attachments(&self) -> Vec<Attachment> {
+        self.inner
+            .attachments()
+            .map(|attachment| {
+                let path = attachment.path();
+                let url = path
+                    .components()
+                    .map(|c| format!("/{}", encode_id(&c.as_os_str().to_string_lossy())))
+                    .collect();
+                Attachment {
+                    path: path_string(path),
+                    len: attachment.len(),
+                    url,
+                    notes: self
+                        .inner
+                        .attachment_references(path)
+                        .into_iter()
+                        .cloned()
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Delete the attachment at the notebook-relative `path` unless a note
+    /// links to it.
+    pub fn delete_attachment(&mut self, path: &str) -> Result<(), Error> {
+        if !self.inner.attachment_references(path).is_empty() {
+            return Err(Error::AttachmentInUse);
+        }
+        Ok(self.inner.delete_attachment(path)?)
+    }
+
+    /// Return the notebook-relative paths of the attachments no note links
+    /// to, the files [`delete_unreferenced_attachments`] would delete.
+    ///
+    /// [`delete_unreferenced_attachments`]: Self::delete_unreferenced_attachments
+    pub fn unreferenced_attachments(&self) -> Vec<String> {
+        self.inner
+            .unreferenced_attachments()
+            .into_iter()
+            .map(|attachment| path_string(attachment.path()))
+            .collect()
+    }
+
+    /// Delete the attachments at `paths` that no note links to, as listed by
+    /// [`unreferenced_attachments`](Self::unreferenced_attachments), and
+    /// return how many were deleted. Paths linked to since are kept.
+    pub fn delete_unreferenced_attachments(&mut self, paths: &[String]) -> Result<usize, Error> {
+        let unreferenced = self.unreferenced_attachments();
+        let paths: Vec<&String> = paths
+            .iter()
+            .filter(|path| unreferenced.contains(*path))
+            .collect();
+        for path in &paths {
+            self.inner.delete_attachment(path.as_str())?;
+        }
+        Ok(paths.len())
+    }
+
+    /// Bring the notebook up to date with a `change` of its files and return
+    /// how its notes changed, `None` for files that are not notes.
+    pub fn apply(&mut self, change: &FileChange) -> Result<Option<NotebookEvent>, Error> {
+        Ok(self.inner.apply(change)?)
+    }
+
+    /// Turn notes removed and written again with the same content at another
+    /// path in `changes` into renames.
+    pub fn match_renames(&self, changes: Vec<FileChange>) -> Vec<FileChange> {
+        self.inner.match_renames(changes)
+    }
+
+    /// Write `content` to the note at `path`, absolute or relative to the
+    /// notebook, and reload it.
+    pub fn save(&mut self, path: &Path, content: &str) -> Result<(), Error> {
+        self.inner.save(path, content)?;
         Ok(())
     }
 
-    /// Remove the note with the given stem from the in-memory notebook.
-    pub fn remove(&mut self, stem: &str) {
-        self.inner.remove(stem);
+    /// Rename the note `id` to the stem or notebook-relative path `to`,
+    /// rewriting the links to it. Returns the new id.
+    pub fn rename(&mut self, id: &str, to: &str) -> Result<String, Error> {
+        Ok(self.inner.rename(id, to)?.id())
     }
 
-    /// Return note with the given filename stem or `None`.
-    pub fn note(&self, stem: &str) -> Option<Note> {
-        self.inner.note(stem).cloned()
+    /// Return the id of the note at `path`, absolute or relative to the
+    /// notebook. The note does not need to exist.
+    pub fn id_for(&self, path: &Path) -> String {
+        path_id(path.strip_prefix(&self.path).unwrap_or(path))
     }
 
-    /// Return all notes, optionally filtered by a required tag, sorted by last modified (most recent first).
-    pub fn all_notes(&self, with_tag: Option<&str>) -> Vec<&Note> {
-        let mut notes: Vec<&Note> = self.inner.all_notes(with_tag).collect();
-        notes.sort_by_key(|note| std::cmp::Reverse(note.modified()));
-        notes
+    /// Return note with the given id (or unique filename stem) or `None`.
+    pub fn note(&self, id: &str) -> Option<Note> {
+        self.inner.note(id).cloned()
     }
 
-    /// Return notes for given tag, optionally also requiring `with_tag`,
-    /// sorted by last modified (most recent first).
-    pub fn search_tag(&self, tag: &str, with_tag: Option<&str>) -> Vec<&Note> {
+    /// Return the other notes sharing the filename stem of the note `id`.
+    /// Links by stem to any of them are ambiguous.
+    pub fn same_stem(&self, id: &str) -> Vec<Note> {
+        let Some(note) = self.inner.note(id) else {
+            return Vec::new();
+        };
+
+        self.inner
+            .notes_with_stem(note.filename_stem())
+            .into_iter()
+            .filter(|other| other.path() != note.path())
+            .cloned()
+            .collect()
+    }
+
+    /// Return all tags with the number of notes having them, most used first.
+    pub fn tag_counts(&self) -> Vec<(String, usize)> {
+        let mut tags: Vec<(String, usize)> = self
+            .inner
+            .tag_counts()
+            .map(|(tag, count)| (tag.to_owned(), count))
+            .collect();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        tags
+    }
+
+    /// Return the tags as a tree of nested tags, sorted by name. If
+    /// `authenticated` is false, only tags of public-tagged notes are included.
+    pub fn tag_tree(&self, authenticated: bool) -> Vec<TagNode> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for note in self.all_notes((!authenticated).then_some("public")) {
+            let tags: BTreeSet<String> = note
+                .tags()
+                .iter()
+                .map(|tag| tag.to_lowercase())
+                .flat_map(|tag| {
+                    let parents: Vec<String> = tag
+                        .match_indices('/')
+                        .map(|(i, _)| tag[..i].to_owned())
+                        .collect();
+                    parents.into_iter().chain([tag])
+                })
+                .collect();
+            for tag in tags {
+                *counts.entry(tag).or_default() += 1;
+            }
+        }
+        tag_nodes(&counts, None)
+    }
+
+    /// Rename the tag `tag` to `to` in all notes, merging it into `to` if
+    /// that tag exists. Returns the number of changed notes.
+    pub fn rename_tag(&mut self, tag: &str, to: &str) -> Result<usize, Error> {
+        Ok(self.inner.rename_tag(tag, to)?)
+    }
+
+    /// Remove the tag `tag` from all notes. Returns the number of changed
+    /// notes.
+    pub fn delete_tag(&mut self, tag: &str) -> Result<usize, Error> {
+        Ok(self.inner.delete_tag(tag)?)
+    }
+
+    /// Return all notes, optionally filtered by a required tag, sorted by last modified (most recent first).
+    /// Tags nested in the required tag do not count, `public/draft` is not `public`.
+    pub fn all_notes(&self, with_tag: Option<&str>) -> Vec<&Note> {
         let mut notes: Vec<&Note> = self
             .inner
-            .all_notes(Some(tag))
-            .filter(|n| with_tag.is_none_or(|t| n.has(t)))
+            .all_notes(None)
+            .filter(|note| with_tag.is_none_or(|tag| note.has(tag)))
             .collect();
         notes.sort_by_key(|note| std::cmp::Reverse(note.modified()));
         notes
     }
 
-    /// Fuzzy search for `query` inside titles and return matching [`Note`]s.
-    pub fn search_titles(&self, query: &str, with_tag: Option<&str>) -> Vec<&Note> {
-        self.inner.search_titles(query, with_tag).collect()
+    /// Return the notes matching `query`, see [`NoteQuery::parse`] for the
+    /// search syntax.
+    pub fn query(&self, query: &NoteQuery) -> Vec<&Note> {
+        self.inner.query(query)
     }
 
-    /// Return notes that wiki-link to `stem`.
+    /// Return the names of the saved filters in the `[filter]` config section.
+    pub fn filters(&self) -> Vec<String> {
+        self.inner.config().filters.keys().cloned().collect()
+    }
+
+    /// Return the query of the saved filter `name`.
+    pub fn filter(&self, name: &str) -> Result<NoteQuery, Error> {
+        Ok(self.inner.filter(name)?)
+    }
+
+    /// Save `query` as the filter `name` in the notebook config.
+    pub fn save_filter(&mut self, name: &str, query: &NoteQuery) -> Result<(), Error> {
+        Ok(self.inner.save_filter(name, query)?)
+    }
+
+    /// Return an excerpt of `note` around the words matching `text`.
+    pub fn snippet(&self, note: &Note, text: &str) -> Snippet {
+        self.inner.snippet(note, text)
+    }
+
+    /// Return notes that link to the note `id`.
     /// If `authenticated` is false, only public-tagged notes are returned.
-    pub fn backlinks(&self, stem: &str, authenticated: bool) -> Vec<Note> {
+    pub fn backlinks(&self, id: &str, authenticated: bool) -> Vec<Note> {
+        let Some(note) = self.inner.note(id) else {
+            return Vec::new();
+        };
+
         self.inner
-            .backlinks(stem)
+            .backlinks(note)
             .into_iter()
             .filter(|n| authenticated || n.has("public"))
             .cloned()
             .collect()
     }
 
-    /// Resolve outgoing wiki-link stems to notes.
+    /// Resolve the outgoing links of the note `id` to notes.
     /// If `authenticated` is false, only public-tagged notes are returned.
-    pub fn outgoing_links(&self, stems: &[String], authenticated: bool) -> Vec<Note> {
-        stems
-            .iter()
-            .filter_map(|stem| self.inner.note(stem))
+    pub fn outgoing_links(&self, id: &str, authenticated: bool) -> Vec<Note> {
+        let Some(note) = self.inner.note(id) else {
+            return Vec::new();
+        };
+
+        self.inner
+            .outgoing_links(note)
+            .into_iter()
             .filter(|n| authenticated || n.has("public"))
             .cloned()
             .collect()
     }
 
-    /// Create a new note with a random 4-character zk-style ID and write it to disk.
-    /// Returns the generated filename stem.
-    pub fn create_note(&self, content: &str) -> std::io::Result<String> {
-        use rand::RngExt;
+    /// Return the notes most related to the note `id`, most related first.
+    /// If `authenticated` is false, only public-tagged notes are returned.
+    pub fn related(&self, id: &str, authenticated: bool) -> Vec<Related> {
+        let Some(note) = self.inner.note(id) else {
+            return Vec::new();
+        };
 
-        const CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
-        let mut rng = rand::rng();
+        self.inner
+            .related(note)
+            .into_iter()
+            .filter(|related| authenticated || related.note.has("public"))
+            .take(RELATED)
+            .map(|related| Related {
+                note: related.note.clone(),
+                shared_tags: related.shared_tags,
+                tags: related.tags,
+                co_citation: related.co_citation,
+                coupling: related.coupling,
+                similarity: related.similarity,
+            })
+            .collect()
+    }
 
-        let stem = loop {
-            let id: String = (0..4)
-                .map(|_| CHARS[rng.random_range(0..CHARS.len())] as char)
-                .collect();
-            let path = self.path.join(format!("{id}.md"));
-            if !path.exists() {
-                break id;
+    /// Return the dead links, the most linked and the isolated notes of the
+    /// notebook.
+    pub fn health(&self) -> Health {
+        let dead_links = self
+            .inner
+            .dead_links()
+            .into_iter()
+            .map(|link| DeadLink {
+                source: link.source.id(),
+                title: link.source.title().to_owned(),
+                target: link.target,
+                line: link.line,
+            })
+            .collect();
+
+        let dead_ends: HashSet<&Path> =
+            self.inner.dead_ends().into_iter().map(Note::path).collect();
+        let isolated = self
+            .inner
+            .orphans()
+            .into_iter()
+            .filter(|note| dead_ends.contains(note.path()))
+            .cloned()
+            .collect();
+
+        Health {
+            dead_links,
+            isolated,
+            hubs: self
+                .inner
+                .hubs(HUBS)
+                .into_iter()
+                .map(|(note, count)| (note.clone(), count))
+                .collect(),
+            clusters: self
+                .inner
+                .components()
+                .iter()
+                .filter(|group| group.len() > 1)
+                .count(),
+        }
+    }
+
+    /// Return the notes and links of the graph view: the notes at most
+    /// `depth` links away from the note `center`, or all notes without
+    /// center. Other notes only show up if they have the tag `tag`.
+    /// If `authenticated` is false, only public-tagged notes are shown.
+    ///
+    /// Returns `None` if `center` does not exist or is hidden.
+    pub fn graph(
+        &self,
+        center: Option<&str>,
+        depth: usize,
+        tag: Option<&str>,
+        authenticated: bool,
+    ) -> Option<Graph> {
+        let visible = |note: &Note| authenticated || note.has("public");
+        let shown = |note: &Note| visible(note) && tag.is_none_or(|tag| note.has_within(tag));
+
+        let notes: Vec<(&Note, usize)> = match center {
+            Some(id) => {
+                let center = self.inner.note(id).filter(|note| visible(note))?;
+                self.inner.neighborhood(center, depth, shown)
+            }
+            None => {
+                let mut notes: Vec<&Note> =
+                    self.inner.all_notes(None).filter(|n| shown(n)).collect();
+                notes.sort_by(|a, b| a.path().cmp(b.path()));
+                notes.into_iter().map(|note| (note, 0)).collect()
             }
         };
 
-        std::fs::write(self.path.join(format!("{stem}.md")), content)?;
-        Ok(stem)
+        let positions: HashMap<&Path, usize> = notes
+            .iter()
+            .enumerate()
+            .map(|(i, (note, _))| (note.path(), i))
+            .collect();
+        let links = self
+            .inner
+            .links()
+            .into_iter()
+            .filter_map(|(source, target)| {
+                Some((
+                    *positions.get(source.path())?,
+                    *positions.get(target.path())?,
+                ))
+            })
+            .collect();
+        let nodes = notes
+            .into_iter()
+            .map(|(note, depth)| GraphNode {
+                id: note.id(),
+                title: note.title().to_owned(),
+                depth,
+            })
+            .collect();
+
+        Some(Graph { nodes, links })
+    }
+
+    /// Create the note the dead link `link` in the note `from` points to and
+    /// return its id.
+    pub fn create_missing(&mut self, link: &str, from: &str) -> Result<String, Error> {
+        let from = self
+            .inner
+            .note(from)
+            .ok_or_else(|| zk_rs::Error::NoteNotFound(from.to_owned()))?
+            .path()
+            .to_owned();
+        Ok(self.inner.create_missing(link, from)?.id())
+    }
+
+    /// Resolve a link written in the note `from` to the id of the note it
+    /// points to. Dead and ambiguous links return `None`.
+    pub fn resolve(&self, link: &str, from: &str) -> Option<String> {
+        let from = self.inner.note(from)?;
+        self.inner.resolve(link, from).note().map(NoteExt::id)
+    }
+
+    /// Create a new note following the notebook's zk settings (filename
+    /// template, ID options, groups, body template) and return its id.
+    ///
+    /// Without a body template the content is written as is, so it is prefixed
+    /// with a `# title` heading to keep the title.
+    pub fn create_note(&mut self, mut new: NewNote) -> Result<String, Error> {
+        let note_config = self.inner.config().note_config(new.group.as_deref());
+
+        if new.template.is_none() && note_config.template.is_none() {
+            let title = new.title.as_deref().unwrap_or(&note_config.default_title);
+            new.content = format!("# {title}\n\n{}", new.content)
+                .trim_end()
+                .to_owned();
+            new.content.push('\n');
+        }
+
+        let note = self.inner.create(new)?;
+        Ok(note.id())
+    }
+
+    /// Return the names of the note templates in `.zk/templates/`.
+    pub fn templates(&self) -> Result<Vec<String>, Error> {
+        Ok(self.inner.templates()?)
+    }
+
+    /// Return the names of the groups configured in `.zk/config.toml`.
+    pub fn groups(&self) -> Vec<String> {
+        self.inner.config().groups.keys().cloned().collect()
     }
 }
 
+/// A tag in the tag tree, see [`Notebook::tag_tree`].
+pub struct TagNode {
+    /// The whole tag, e.g. `project/weave`.
+    pub tag: String,
+    /// The last level of the tag, e.g. `weave`.
+    pub name: String,
+    /// Number of notes with the tag or a tag nested in it.
+    pub count: usize,
+    pub children: Vec<TagNode>,
+}
+
+/// Build the nodes of the tags directly nested in `parent`, or of the top
+/// level tags without one.
+fn tag_nodes(counts: &BTreeMap<String, usize>, parent: Option<&str>) -> Vec<TagNode> {
+    counts
+        .iter()
+        .filter_map(|(tag, &count)| {
+            let name = match parent {
+                Some(parent) => tag.strip_prefix(parent)?.strip_prefix('/')?,
+                None => tag,
+            };
+            (!name.contains('/')).then(|| TagNode {
+                tag: tag.clone(),
+                name: name.to_owned(),
+                count,
+                children: tag_nodes(counts, Some(tag)),
+            })
+        })
+        .collect()
+}
+
+/// Number of most linked notes on the health page.
+const HUBS: usize = 10;
+
+/// Number of related notes shown next to a note.
+const RELATED: usize = 8;
+
+/// A note related to another one, see [`Notebook::related`].
+pub struct Related {
+    pub note: Note,
+    /// Tags both notes have.
+    pub shared_tags: Vec<String>,
+    /// How much the tags of both notes overlap, from 0 to 1.
+    pub tags: f64,
+    /// How much both notes are linked from the same notes, from 0 to 1.
+    pub co_citation: f64,
+    /// How much both notes link to the same notes, from 0 to 1.
+    pub coupling: f64,
+    /// How similar the words of both notes are, from 0 to 1.
+    pub similarity: f64,
+}
+
+/// Link structure of the notebook, see [`Notebook::health`].
+pub struct Health {
+    pub dead_links: Vec<DeadLink>,
+    /// Notes without links in either direction.
+    pub isolated: Vec<Note>,
+    /// The most linked notes with their number of backlinks.
+    pub hubs: Vec<(Note, usize)>,
+    /// Number of groups of notes linked to each other.
+    pub clusters: usize,
+}
+
+/// Notes and links drawn by the graph view, see [`Notebook::graph`].
+#[derive(Serialize)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    /// Links as positions in `nodes`, from source to target.
+    pub links: Vec<(usize, usize)>,
+}
+
+#[derive(Serialize)]
+pub struct GraphNode {
+    pub id: String,
+    pub title: String,
+    /// Number of links from the center note, 0 without center.
+    pub depth: usize,
+}
+
+/// A file of the notebook besides its notes, see [`Notebook::attachments`].
+pub struct Attachment {
+    /// Path relative to the notebook, with `/` separators.
+    pub path: String,
+    /// Size in bytes.
+    pub len: u64,
+    /// URL the file is served at.
+    pub url: String,
+    /// Notes linking to the file.
+    pub notes: Vec<Note>,
+}
+
+/// A link to a note that does not exist.
+pub struct DeadLink {
+    /// Id of the note containing the link.
+    pub source: String,
+    /// Title of the note containing the link.
+    pub title: String,
+    /// The link target, as written.
+    pub target: String,
+    /// Line of the link in the note file, starting at 1.
+    pub line: usize,
+}
+
+/// Return the id of the note at the notebook-relative `path`: the path
+/// without extension, with `/` separators.
+fn path_id(path: &Path) -> String {
+    path_string(&path.with_extension(""))
+}
+
+/// Return the notebook-relative `path` with `/` separators.
+fn path_string(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Percent-encode a note id for use as a single URL path segment.
+pub fn encode_id(id: &str) -> String {
+    let mut encoded = String::with_capacity(id.len());
+    for byte in id.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char);
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
 /// Extension trait for weave-specific [`Note`] methods.
 pub trait NoteExt {
+    /// Return the id weave addresses the note by: its notebook-relative path
+    /// without extension. Equal to the filename stem for notes in the root.
+    fn id(&self) -> String;
+
     /// Return a truncated snippet version of the note with ellipsis at the end.
     fn snippet(&self) -> String;
 }
 
 impl NoteExt for Note {
+    fn id(&self) -> String {
+        path_id(self.path())
+    }
+
     fn snippet(&self) -> String {
         Parser::new(self.body())
             .scan(0, |len, event| {
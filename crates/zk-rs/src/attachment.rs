@@ -0,0 +1,100 @@
+//! Files of a notebook besides its notes, like images and PDFs.
+
+use std::path::{Path, PathBuf};
+
+use crate::link;
+use crate::storage::FileMetadata;
+
+/// A file in the notebook that is not a note, e.g. an image linked from a
+/// note. Hidden files and directories are not attachments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub(crate) path: PathBuf,
+    pub(crate) abs_path: PathBuf,
+    pub(crate) len: u64,
+    pub(crate) modified: jiff::Timestamp,
+}
+
+impl Attachment {
+    pub(crate) fn new(root: &Path, path: PathBuf, meta: &FileMetadata) -> Self {
+        Self {
+            abs_path: root.join(&path),
+            path,
+            len: meta.len,
+            modified: meta.modified,
+        }
+    }
+
+    /// Path relative to the notebook root.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn abs_path(&self) -> &Path {
+        &self.abs_path
+    }
+
+    /// Size in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Return `true` if the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn modified(&self) -> jiff::Timestamp {
+        self.modified
+    }
+}
+
+/// Return the notebook-relative paths the attachment linked as `target` from
+/// the note at `note_path` may be at: relative to the note's directory and,
+/// like wiki-links and links with a leading `/`, relative to the notebook
+/// root. Paths leaving the notebook are left out.
+pub(crate) fn resolve(target: &str, note_path: &Path) -> Vec<PathBuf> {
+    let target = link::percent_decode(target);
+    let dir = note_path.parent().unwrap_or(Path::new(""));
+    let mut paths: Vec<PathBuf> = [dir.join(target.as_ref()), PathBuf::from(target.as_ref())]
+        .iter()
+        .filter_map(|path| link::normalize(path))
+        .collect();
+    paths.dedup();
+    paths
+}
+
+/// Return the file name of `target` if it has no directory. Such links may
+/// point to a file of that name anywhere in the notebook, like
+/// `![[image.png]]` does in Obsidian.
+pub(crate) fn bare_name(target: &str) -> Option<String> {
+    let target = link::percent_decode(target);
+    (!target.contains('/')).then(|| target.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let note = Path::new("journal/day.md");
+        assert_eq!(
+            resolve("img/cat.png", note),
+            [Path::new("journal/img/cat.png"), Path::new("img/cat.png")]
+        );
+        assert_eq!(
+            resolve("../files/my%20paper.pdf", note),
+            [Path::new("files/my paper.pdf")]
+        );
+        assert_eq!(resolve("/files/a.pdf", note), [Path::new("files/a.pdf")]);
+        assert!(resolve("../../a.pdf", note).is_empty());
+        assert_eq!(resolve("a.pdf", Path::new("root.md")), [Path::new("a.pdf")]);
+    }
+
+    #[test]
+    fn test_bare_name() {
+        assert_eq!(bare_name("my%20cat.png").as_deref(), Some("my cat.png"));
+        assert_eq!(bare_name("img/cat.png"), None);
+    }
+}
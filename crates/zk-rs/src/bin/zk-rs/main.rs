@@ -0,0 +1,339 @@
+//! `zk-rs`, a command-line tool to query and update zk notebooks without the
+//! `zk` binary.
+
+mod output;
+
+use std::error::Error;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use zk_rs::{NewNote, Note, NoteQuery, Notebook};
+
+const USAGE: &str = "\
+Usage: zk-rs [--notebook-dir DIR] <COMMAND> [OPTIONS]
+
+Commands:
+  list [FILTERS]    List the notes matching `zk list` filters, e.g.
+                    `--tag rust --sort created- -n 10 journal`
+      -f, --format FORMAT     oneline (default), short, medium, long, full,
+                              path, link, json, jsonl or a Handlebars template
+      -d, --delimiter TEXT    Printed between notes
+  tag list          List the tags with their number of notes
+      -f, --format FORMAT     full (default), name, json or jsonl
+  graph [FILTERS]   Print the notes matching the filters and their links
+      -f, --format FORMAT     json (default) or dot
+  new [DIR]         Create a note in DIR, relative to the notebook, and print
+                    its path
+      -t, --title TITLE       Title of the note
+      -g, --group GROUP       Group whose settings apply
+          --template NAME     Body template in .zk/templates/
+      -i, --interactive       Read the content of the note from stdin
+  index             Update zk's .zk/notebook.db, or zk-rs's parse cache
+  check             Report dead links and config errors, failing if there are
+                    any
+  lsp               Start a language server on stdin and stdout, if built with
+                    the lsp feature
+
+The notebook is --notebook-dir, the first directory containing .zk/ from the
+current one up, or ZK_NOTEBOOK_DIR.
+
+Exits with 1 if `check` found problems and 2 on errors.
+";
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("zk-rs: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(args: Vec<String>) -> Result<ExitCode> {
+    let mut args = Args::new(args);
+    let mut notebook_dir = None;
+
+    let command = loop {
+        let Some(arg) = args.next() else {
+            return Err(format!("missing command\n\n{USAGE}").into());
+        };
+        match arg.as_str() {
+            "--notebook-dir" => notebook_dir = Some(PathBuf::from(args.value(&arg)?)),
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            "-V" | "--version" => {
+                println!("zk-rs {}", env!("CARGO_PKG_VERSION"));
+                return Ok(ExitCode::SUCCESS);
+            }
+            _ => break arg,
+        }
+    };
+
+    let root = notebook_root(notebook_dir)?;
+    match command.as_str() {
+        "list" | "ls" => list(&root, args),
+        "tag" => match args.next().as_deref() {
+            Some("list" | "ls") => tag_list(&root, args),
+            _ => Err("usage: zk-rs tag list [--format FORMAT]".into()),
+        },
+        "graph" => graph(&root, args),
+        "new" => new(&root, args),
+        "index" => index(&root, args),
+        "check" => check(&root, args),
+        "lsp" => lsp(&root, args),
+        command => Err(format!("unknown command: {command}\n\n{USAGE}").into()),
+    }
+}
+
+/// Command-line arguments, with `--flag=value` split into the flag and its
+/// value.
+struct Args {
+    args: std::vec::IntoIter<String>,
+    /// Value given with `=` to the last flag.
+    inline: Option<String>,
+}
+
+impl Args {
+    fn new(args: Vec<String>) -> Self {
+        Self {
+            args: args.into_iter(),
+            inline: None,
+        }
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let arg = self.args.next()?;
+        match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                self.inline = Some(value.to_owned());
+                Some(flag.to_owned())
+            }
+            _ => {
+                self.inline = None;
+                Some(arg)
+            }
+        }
+    }
+
+    /// Return the value of `flag`, given with `=` or as the next argument.
+    fn value(&mut self, flag: &str) -> Result<String> {
+        self.inline
+            .take()
+            .or_else(|| self.args.next())
+            .ok_or_else(|| format!("missing value for {flag}").into())
+    }
+
+    /// Return `arg` as it was written, with the value given with `=`.
+    fn written(&mut self, arg: String) -> String {
+        match self.inline.take() {
+            Some(value) => format!("{arg}={value}"),
+            None => arg,
+        }
+    }
+
+    /// Fail if `arg`, an unexpected argument, is left.
+    fn unexpected(&mut self, arg: String) -> Result<()> {
+        Err(format!("unexpected argument: {}", self.written(arg)).into())
+    }
+}
+
+/// Find the notebook like zk: `dir`, the first directory containing `.zk/`
+/// from the current one up, or `ZK_NOTEBOOK_DIR`.
+fn notebook_root(dir: Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(dir) = dir {
+        return Ok(dir);
+    }
+
+    let cwd = std::env::current_dir()?;
+    if let Some(root) = cwd.ancestors().find(|dir| dir.join(".zk").is_dir()) {
+        return Ok(root.to_path_buf());
+    }
+    std::env::var_os("ZK_NOTEBOOK_DIR")
+        .map(PathBuf::from)
+        .ok_or_else(|| "no notebook found, use --notebook-dir or ZK_NOTEBOOK_DIR".into())
+}
+
+/// Load the notebook, keeping zk's index up to date if it has one.
+fn load(root: &Path) -> Result<Notebook> {
+    #[cfg(feature = "sqlite")]
+    if has_index(root) {
+        return Ok(Notebook::load_with_index(root)?);
+    }
+    Ok(Notebook::load_cached(root)?)
+}
+
+fn has_index(root: &Path) -> bool {
+    root.join(".zk").join("notebook.db").is_file()
+}
+
+/// Split the `--format` and `--delimiter` options off the `zk list` filters
+/// in `args`.
+fn format_and_filters(mut args: Args) -> Result<(Option<String>, Option<String>, NoteQuery)> {
+    let mut format = None;
+    let mut delimiter = None;
+    let mut filters = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--format" => format = Some(args.value(&arg)?),
+            "-d" | "--delimiter" => delimiter = Some(args.value(&arg)?),
+            _ => filters.push(args.written(arg)),
+        }
+    }
+
+    Ok((format, delimiter, NoteQuery::from_arg_list(filters)?))
+}
+
+fn list(root: &Path, args: Args) -> Result<ExitCode> {
+    let (format, delimiter, query) = format_and_filters(args)?;
+    let notebook = load(root)?;
+    let notes = notebook.query(&query);
+
+    let format = format.as_deref().unwrap_or("oneline");
+    print!("{}", output::notes(&notes, format, delimiter.as_deref())?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn tag_list(root: &Path, mut args: Args) -> Result<ExitCode> {
+    let mut format = "full".to_owned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--format" => format = args.value(&arg)?,
+            _ => args.unexpected(arg)?,
+        }
+    }
+
+    let notebook = load(root)?;
+    let mut tags: Vec<(&str, usize)> = notebook.tag_counts().collect();
+    tags.sort();
+    print!("{}", output::tags(&tags, &format)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn graph(root: &Path, args: Args) -> Result<ExitCode> {
+    let (format, delimiter, query) = format_and_filters(args)?;
+    if delimiter.is_some() {
+        return Err("graph has no --delimiter".into());
+    }
+    let notebook = load(root)?;
+    let notes = notebook.query(&query);
+
+    let shown: std::collections::HashSet<&Path> = notes.iter().map(|note| note.path()).collect();
+    let links: Vec<(&Note, &Note)> = notebook
+        .links()
+        .into_iter()
+        .filter(|(source, target)| shown.contains(source.path()) && shown.contains(target.path()))
+        .collect();
+
+    let format = format.as_deref().unwrap_or("json");
+    print!("{}", output::graph(&notes, &links, format)?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn new(root: &Path, mut args: Args) -> Result<ExitCode> {
+    let mut new = NewNote::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-t" | "--title" => new.title = Some(args.value(&arg)?),
+            "-g" | "--group" => new.group = Some(args.value(&arg)?),
+            "--template" => new.template = Some(args.value(&arg)?),
+            "-i" | "--interactive" => {
+                std::io::stdin().read_to_string(&mut new.content)?;
+            }
+            // zk only prints the path with this flag, zk-rs always does.
+            "-p" | "--print-path" => {}
+            _ if arg.starts_with('-') || new.dir.is_some() => args.unexpected(arg)?,
+            _ => new.dir = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let mut notebook = load(root)?;
+    let note = notebook.create(new)?;
+    println!("{}", note.abs_path().display());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn index(root: &Path, mut args: Args) -> Result<ExitCode> {
+    if let Some(arg) = args.next() {
+        args.unexpected(arg)?;
+    }
+
+    let notebook = load(root)?;
+    let count = notebook.all_notes(None).count();
+    let target = if cfg!(feature = "sqlite") && has_index(root) {
+        ".zk/notebook.db"
+    } else {
+        "the parse cache"
+    };
+    eprintln!("Indexed {count} notes in {target}");
+    Ok(ExitCode::SUCCESS)
+}
+
+fn check(root: &Path, mut args: Args) -> Result<ExitCode> {
+    if let Some(arg) = args.next() {
+        args.unexpected(arg)?;
+    }
+
+    let notebook = load(root)?;
+    let config_errors = notebook.config_errors();
+    for err in &config_errors {
+        match err.source() {
+            Some(source) => println!(".zk/config.toml: {err}: {source}"),
+            None => println!(".zk/config.toml: {err}"),
+        }
+    }
+
+    let dead_links = notebook.dead_links();
+    for link in &dead_links {
+        println!(
+            "{}:{}: dead link to {}",
+            link.source.path().display(),
+            link.line,
+            link.target
+        );
+    }
+
+    if dead_links.is_empty() {
+        eprintln!("No dead links");
+        if config_errors.is_empty() {
+            Ok(ExitCode::SUCCESS)
+        } else {
+            Ok(ExitCode::from(1))
+        }
+    } else {
+        match dead_links.len() {
+            1 => eprintln!("1 dead link"),
+            count => eprintln!("{count} dead links"),
+        }
+        Ok(ExitCode::from(1))
+    }
+}
+
+#[cfg(feature = "lsp")]
+fn lsp(root: &Path, mut args: Args) -> Result<ExitCode> {
+    // zk's editor integrations pass `--log`, there is nothing to log here.
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log" => {
+                args.value(&arg)?;
+            }
+            _ => args.unexpected(arg)?,
+        }
+    }
+
+    // Clients identify documents by absolute paths.
+    let root = root.canonicalize()?;
+    zk_rs::LanguageServer::new(load(&root)?).run_stdio()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(not(feature = "lsp"))]
+fn lsp(_root: &Path, _args: Args) -> Result<ExitCode> {
+    Err("zk-rs was built without the lsp feature".into())
+}
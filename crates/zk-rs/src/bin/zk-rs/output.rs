@@ -0,0 +1,292 @@
+//! Output formats of notes, tags and link graphs.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+use zk_rs::{Note, YamlValue};
+
+use crate::Result;
+
+/// A note in JSON output, with the same keys as `zk list --format json`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NoteJson<'a> {
+    filename: &'a str,
+    filename_stem: &'a str,
+    path: &'a Path,
+    abs_path: &'a Path,
+    title: &'a str,
+    link: &'a str,
+    lead: &'a str,
+    body: &'a str,
+    raw_content: &'a str,
+    word_count: usize,
+    tags: &'a [String],
+    metadata: &'a BTreeMap<String, YamlValue>,
+    created: jiff::Timestamp,
+    modified: jiff::Timestamp,
+}
+
+impl<'a> NoteJson<'a> {
+    fn new(note: &'a Note) -> Self {
+        NoteJson {
+            filename: note.filename(),
+            filename_stem: note.filename_stem(),
+            path: note.path(),
+            abs_path: note.abs_path(),
+            title: note.title(),
+            link: note.link(),
+            lead: note.lead(),
+            body: note.body(),
+            raw_content: note.raw_content(),
+            word_count: note.word_count(),
+            tags: note.tags(),
+            metadata: note.metadata(),
+            created: note.created(),
+            modified: note.modified(),
+        }
+    }
+}
+
+/// A tag in JSON output, with the same keys as `zk tag list --format json`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TagJson<'a> {
+    name: &'a str,
+    note_count: usize,
+}
+
+/// A link in JSON output of the graph.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LinkJson<'a> {
+    source_path: &'a Path,
+    target_path: &'a Path,
+}
+
+/// Return the template of zk's named note `format`s. The lead paragraph
+/// stands in for zk's search snippets.
+fn named_template(format: &str) -> Option<&'static str> {
+    let template = match format {
+        "path" => "{{path}}",
+        "link" => "{{link}}",
+        "oneline" => r#"{{title}} {{path}} ({{format-date created "elapsed"}})"#,
+        "short" => concat!(
+            r#"{{title}} {{path}} ({{format-date created "elapsed"}})"#,
+            "\n\n{{lead}}"
+        ),
+        "medium" => concat!(
+            "{{title}} {{path}}\n",
+            r#"Created: {{format-date created "short"}}"#,
+            "\n\n{{lead}}"
+        ),
+        "long" => concat!(
+            "{{title}} {{path}}\n",
+            r#"Created: {{format-date created "short"}}"#,
+            "\n",
+            r#"Modified: {{format-date modified "short"}}"#,
+            "\n\n{{lead}}"
+        ),
+        "full" => concat!(
+            "{{title}} {{path}}\n",
+            r#"Created: {{format-date created "short"}}"#,
+            "\n",
+            r#"Modified: {{format-date modified "short"}}"#,
+            "\n",
+            r#"Tags: {{join tags ", "}}"#,
+            "\n\n{{body}}"
+        ),
+        _ => return None,
+    };
+    Some(template)
+}
+
+/// Render `notes` in `format`: `json`, `jsonl`, one of zk's named formats or
+/// a Handlebars template. Notes are separated by `delimiter`, by default a
+/// blank line for formats spanning several lines and a newline otherwise.
+pub(crate) fn notes(notes: &[&Note], format: &str, delimiter: Option<&str>) -> Result<String> {
+    match format {
+        "json" => {
+            let notes: Vec<NoteJson> = notes.iter().map(|note| NoteJson::new(note)).collect();
+            Ok(format!("{}\n", serde_json::to_string(&notes)?))
+        }
+        "jsonl" => json_lines(notes.iter().map(|note| NoteJson::new(note))),
+        format => {
+            let template = named_template(format).unwrap_or(format);
+            let default_delimiter = if template.contains('\n') {
+                "\n\n"
+            } else {
+                "\n"
+            };
+            let rendered = notes
+                .iter()
+                .map(|note| note.format(template))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Ok(lines(&rendered, delimiter.unwrap_or(default_delimiter)))
+        }
+    }
+}
+
+/// Render `tags` with their note counts in `format`: `full`, `name`, `json`
+/// or `jsonl`.
+pub(crate) fn tags(tags: &[(&str, usize)], format: &str) -> Result<String> {
+    let json = tags
+        .iter()
+        .map(|&(name, note_count)| TagJson { name, note_count });
+    match format {
+        "full" => {
+            let rendered: Vec<String> = tags
+                .iter()
+                .map(|(name, count)| format!("{name} ({count})"))
+                .collect();
+            Ok(lines(&rendered, "\n"))
+        }
+        "name" => {
+            let names: Vec<String> = tags.iter().map(|(name, _)| name.to_string()).collect();
+            Ok(lines(&names, "\n"))
+        }
+        "json" => Ok(format!(
+            "{}\n",
+            serde_json::to_string(&json.collect::<Vec<_>>())?
+        )),
+        "jsonl" => json_lines(json),
+        format => Err(format!("unknown tag format: {format}").into()),
+    }
+}
+
+/// Render `notes` and the `links` between them in `format`: `json` or
+/// Graphviz's `dot`.
+pub(crate) fn graph(notes: &[&Note], links: &[(&Note, &Note)], format: &str) -> Result<String> {
+    match format {
+        "json" => {
+            #[derive(Serialize)]
+            struct Graph<'a> {
+                notes: Vec<NoteJson<'a>>,
+                links: Vec<LinkJson<'a>>,
+            }
+
+            let graph = Graph {
+                notes: notes.iter().map(|note| NoteJson::new(note)).collect(),
+                links: links
+                    .iter()
+                    .map(|(source, target)| LinkJson {
+                        source_path: source.path(),
+                        target_path: target.path(),
+                    })
+                    .collect(),
+            };
+            Ok(format!("{}\n", serde_json::to_string(&graph)?))
+        }
+        "dot" => {
+            let id = |note: &Note| dot_string(&note.path().to_string_lossy());
+            let mut dot = String::from("digraph notebook {\n");
+            for note in notes {
+                dot.push_str(&format!(
+                    "  {} [label={}];\n",
+                    id(note),
+                    dot_string(note.title())
+                ));
+            }
+            for (source, target) in links {
+                dot.push_str(&format!("  {} -> {};\n", id(source), id(target)));
+            }
+            dot.push_str("}\n");
+            Ok(dot)
+        }
+        format => Err(format!("unknown graph format: {format}").into()),
+    }
+}
+
+/// Quote `text` as a DOT string.
+fn dot_string(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+/// Join `items` with `delimiter`, ending with a newline unless empty.
+fn lines(items: &[String], delimiter: &str) -> String {
+    if items.is_empty() {
+        return String::new();
+    }
+    format!("{}\n", items.join(delimiter))
+}
+
+fn json_lines<T: Serialize>(items: impl Iterator<Item = T>) -> Result<String> {
+    let mut out = String::new();
+    for item in items {
+        out.push_str(&serde_json::to_string(&item)?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zk_rs::{MemoryStorage, Notebook};
+
+    fn notebook() -> Notebook {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "")
+            .with_file(
+                "a.md",
+                "---\ntags: [x, y]\n---\n# A \"quoted\"\n\nLead of a. [[b]]",
+            )
+            .with_file("b.md", "# B");
+        Notebook::load_from("/notebook", storage).unwrap()
+    }
+
+    #[test]
+    fn test_notes() {
+        let nb = notebook();
+        let mut notes: Vec<&Note> = nb.all_notes(None).collect();
+        notes.sort_by_key(|note| note.path());
+
+        assert_eq!(notes_as(&notes, "path", None), "a.md\nb.md\n");
+        assert_eq!(
+            notes_as(&notes, "{{title}}", Some(", ")),
+            "A \"quoted\", B\n"
+        );
+        assert!(notes_as(&notes, "full", None).contains("Tags: x, y\n\nLead of a."));
+        assert_eq!(notes_as(&[], "path", None), "");
+
+        let json: serde_json::Value =
+            serde_json::from_str(&notes_as(&notes, "json", None)).unwrap();
+        assert_eq!(json[0]["filenameStem"], "a");
+        assert_eq!(json[0]["tags"][1], "y");
+        assert_eq!(notes_as(&notes, "jsonl", None).lines().count(), 2);
+    }
+
+    fn notes_as(notes: &[&Note], format: &str, delimiter: Option<&str>) -> String {
+        super::notes(notes, format, delimiter).unwrap()
+    }
+
+    #[test]
+    fn test_tags() {
+        let tags = [("a", 2), ("b/c", 1)];
+        assert_eq!(super::tags(&tags, "full").unwrap(), "a (2)\nb/c (1)\n");
+        assert_eq!(
+            super::tags(&tags, "jsonl").unwrap(),
+            "{\"name\":\"a\",\"noteCount\":2}\n{\"name\":\"b/c\",\"noteCount\":1}\n"
+        );
+        assert!(super::tags(&tags, "{{name}}").is_err());
+    }
+
+    #[test]
+    fn test_graph_dot() {
+        let nb = notebook();
+        let mut notes: Vec<&Note> = nb.all_notes(None).collect();
+        notes.sort_by_key(|note| note.path());
+
+        let dot = graph(&notes, &nb.links(), "dot").unwrap();
+        assert_eq!(
+            dot,
+            "digraph notebook {\n  \"a.md\" [label=\"A \\\"quoted\\\"\"];\n  \
+             \"b.md\" [label=\"B\"];\n  \"a.md\" -> \"b.md\";\n}\n"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Persistent cache of parsed notes in `.zk/zk-rs.cache`.
+//!
+//! Each note is stored with the size and checksum of its file. A cached note
+//! is used as is if size and modification time are unchanged, and after
+//! comparing the checksum otherwise. The whole cache is dropped if it was
+//! written by another version or with different `[format.markdown]` settings.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{MarkdownConfig, NotebookConfig};
+use crate::error::Error;
+use crate::note::Note;
+use crate::parse;
+use crate::storage::NotebookStorage;
+
+/// Bump whenever `Note` or parsing changes in a way the cache cannot tell.
+const VERSION: u32 = 4;
+
+/// Location of the cache, relative to the notebook root.
+const FILE_NAME: &str = ".zk/zk-rs.cache";
+
+/// Size and checksum of the file a note was parsed from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct FileStamp {
+    size: u64,
+    checksum: String,
+}
+
+#[derive(Deserialize)]
+struct CacheFile {
+    version: u32,
+    markdown: MarkdownConfig,
+    entries: Vec<(FileStamp, Note)>,
+}
+
+/// Same layout as [`CacheFile`], borrowing from the notebook.
+#[derive(Serialize)]
+struct CacheFileRef<'a> {
+    version: u32,
+    markdown: &'a MarkdownConfig,
+    entries: Vec<(&'a FileStamp, &'a Note)>,
+}
+
+#[derive(Default)]
+pub(crate) struct Cache {
+    entries: HashMap<PathBuf, (FileStamp, Note)>,
+}
+
+impl Cache {
+    /// Read the cache from `storage`. A missing, unreadable or outdated cache
+    /// is empty.
+    pub(crate) fn read(storage: &dyn NotebookStorage, markdown: &MarkdownConfig) -> Self {
+        let Ok(bytes) = storage.read(Path::new(FILE_NAME)) else {
+            return Self::default();
+        };
+
+        match bincode::deserialize::<CacheFile>(&bytes) {
+            Ok(file) if file.version == VERSION && file.markdown == *markdown => Self {
+                entries: file
+                    .entries
+                    .into_iter()
+                    .map(|(stamp, note)| (note.path.clone(), (stamp, note)))
+                    .collect(),
+            },
+            _ => Self::default(),
+        }
+    }
+
+    /// Remove and return the entry of the note at the notebook-relative `path`.
+    pub(crate) fn take(&mut self, path: &Path) -> Option<(FileStamp, Note)> {
+        self.entries.remove(path)
+    }
+
+    /// Return `true` if all entries were taken, i.e. no cached file was deleted.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Write the cache with `entries` of notes and the stamps of their files to
+/// `storage`.
+pub(crate) fn write<'a>(
+    storage: &dyn NotebookStorage,
+    markdown: &MarkdownConfig,
+    entries: impl IntoIterator<Item = (&'a FileStamp, &'a Note)>,
+) -> Result<(), Error> {
+    let file = CacheFileRef {
+        version: VERSION,
+        markdown,
+        entries: entries.into_iter().collect(),
+    };
+    let bytes = bincode::serialize(&file).map_err(std::io::Error::other)?;
+
+    // Write to a temporary file first, so a crash never leaves half a cache.
+    let tmp = PathBuf::from(format!("{FILE_NAME}.tmp"));
+    storage.write(&tmp, &bytes)?;
+    storage.rename(&tmp, Path::new(FILE_NAME))?;
+    Ok(())
+}
+
+/// Load the note at `abs_path` from its `cached` entry if the file is
+/// unchanged, parse it otherwise. Returns `true` if the entry changed.
+pub(crate) fn load_note(
+    storage: &dyn NotebookStorage,
+    abs_path: PathBuf,
+    rel_path: PathBuf,
+    cached: Option<(FileStamp, Note)>,
+    config: &NotebookConfig,
+) -> Result<(FileStamp, Note, bool), Error> {
+    let meta = storage.metadata(&rel_path)?;
+    let modified = meta.modified;
+
+    let cached = match cached {
+        Some((stamp, mut note)) if stamp.size == meta.len && note.modified == modified => {
+            note.abs_path = abs_path;
+            return Ok((stamp, note, false));
+        }
+        cached => cached,
+    };
+
+    let content = storage.read_to_string(&rel_path)?;
+    let stamp = FileStamp {
+        size: meta.len,
+        checksum: parse::checksum(&content),
+    };
+
+    // Touched but not edited, e.g. by a checkout or a sync tool.
+    if let Some((cached_stamp, mut note)) = cached
+        && cached_stamp.checksum == stamp.checksum
+    {
+        note.abs_path = abs_path;
+        note.modified = modified;
+        return Ok((stamp, note, true));
+    }
+
+    let markdown = &config.format.markdown;
+    let note = parse::parse_note(&content, rel_path, abs_path, &meta, markdown)?;
+    Ok((stamp, note, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Notebook;
+    use std::fs;
+    use std::time::SystemTime;
+    use tempfile::TempDir;
+
+    fn setup_notebook() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(".zk")).unwrap();
+        fs::write(dir.path().join("a.md"), "# Alpha\n\n#rust").unwrap();
+        fs::write(dir.path().join("b.md"), "# Beta\n\nSee [a](a).").unwrap();
+        dir
+    }
+
+    /// Replace the content of `name` without changing its size or mtime, so
+    /// only a fresh parse sees the change.
+    fn write_unnoticed(dir: &TempDir, name: &str, content: &str) {
+        let path = dir.path().join(name);
+        let modified: SystemTime = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(fs::read(&path).unwrap().len(), content.len());
+
+        fs::write(&path, content).unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+    }
+
+    #[test]
+    fn test_cache_reuses_unchanged_notes() {
+        let dir = setup_notebook();
+        Notebook::load_cached(dir.path()).unwrap();
+        assert!(dir.path().join(".zk").join("zk-rs.cache").is_file());
+
+        write_unnoticed(&dir, "a.md", "# Gamma\n\n#rust");
+        let nb = Notebook::load_cached(dir.path()).unwrap();
+        let a = nb.note("a").unwrap();
+        assert_eq!(a.title(), "Alpha");
+        assert_eq!(a.abs_path(), dir.path().join("a.md"));
+        assert_eq!(a.tags(), ["rust"]);
+        assert_eq!(nb.backlinks(a).len(), 1);
+
+        // A plain load always parses.
+        let nb = Notebook::load(dir.path()).unwrap();
+        assert_eq!(nb.note("a").unwrap().title(), "Gamma");
+    }
+
+    #[test]
+    fn test_cache_reparses_changed_notes() {
+        let dir = setup_notebook();
+        Notebook::load_cached(dir.path()).unwrap();
+
+        fs::write(dir.path().join("a.md"), "# Changed").unwrap();
+        fs::remove_file(dir.path().join("b.md")).unwrap();
+        fs::write(dir.path().join("c.md"), "# New").unwrap();
+
+        let nb = Notebook::load_cached(dir.path()).unwrap();
+        assert_eq!(nb.note("a").unwrap().title(), "Changed");
+        assert!(nb.note("b").is_none());
+        assert_eq!(nb.note("c").unwrap().title(), "New");
+        assert_eq!(nb.all_notes(None).count(), 2);
+    }
+
+    #[test]
+    fn test_cache_invalidated_by_markdown_config() {
+        let dir = setup_notebook();
+        Notebook::load_cached(dir.path()).unwrap();
+
+        write_unnoticed(&dir, "a.md", "# Gamma\n\n#rust");
+        fs::write(
+            dir.path().join(".zk").join("config.toml"),
+            "[format.markdown]\nhashtags = false\n",
+        )
+        .unwrap();
+
+        let nb = Notebook::load_cached(dir.path()).unwrap();
+        let a = nb.note("a").unwrap();
+        assert_eq!(a.title(), "Gamma");
+        assert!(a.tags().is_empty());
+    }
+
+    #[test]
+    fn test_cache_keeps_metadata() {
+        let dir = setup_notebook();
+        fs::write(
+            dir.path().join("c.md"),
+            "---\nstatus: draft\nreview:\n  due: 2024-06-01\n---\n# Gamma",
+        )
+        .unwrap();
+        let parsed = Notebook::load_cached(dir.path()).unwrap();
+        let cached = Notebook::load_cached(dir.path()).unwrap();
+        assert_eq!(
+            cached.note("c").unwrap().metadata(),
+            parsed.note("c").unwrap().metadata()
+        );
+        assert_eq!(cached.note("c").unwrap().metadata().len(), 2);
+    }
+
+    #[test]
+    fn test_corrupt_cache() {
+        let dir = setup_notebook();
+        fs::write(dir.path().join(".zk").join("zk-rs.cache"), "garbage").unwrap();
+
+        let nb = Notebook::load_cached(dir.path()).unwrap();
+        assert_eq!(nb.all_notes(None).count(), 2);
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::query::glob_match;
+
+/// Typed representation of a notebook's `.zk/config.toml`.
+///
+/// Missing keys fall back to the same defaults the `zk` CLI uses, so a
+/// notebook without a config file behaves like a freshly initialized one.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NotebookConfig {
+    /// Default options used when generating new notes (`[note]`).
+    pub note: NoteConfig,
+    /// Custom template variables (`[extra]`).
+    pub extra: HashMap<String, String>,
+    /// Per-directory overrides (`[group."<name>"]`).
+    #[serde(rename = "group")]
+    pub groups: BTreeMap<String, GroupConfig>,
+    /// Parsing and link formatting options (`[format]`).
+    pub format: FormatConfig,
+    /// Named filters (`[filter]`), mapping a name to `zk list` arguments.
+    #[serde(rename = "filter")]
+    pub filters: BTreeMap<String, String>,
+    /// Command aliases (`[alias]`), mapping a name to a shell command.
+    #[serde(rename = "alias")]
+    pub aliases: BTreeMap<String, String>,
+    /// Language server options (`[lsp]`).
+    pub lsp: LspConfig,
+}
+
+impl NotebookConfig {
+    /// Load the config from `.zk/config.toml` inside `zk_dir`.
+    /// Returns the default config if the file does not exist.
+    pub fn load(zk_dir: &Path) -> Result<Self, Error> {
+        let path = zk_dir.join("config.toml");
+
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        Self::parse(&content, &path)
+    }
+
+    /// Parse config TOML. `path` is only used for error reporting.
+    pub(crate) fn parse(content: &str, path: &Path) -> Result<Self, Error> {
+        toml::from_str(content).map_err(|source| Error::Config {
+            path: path.to_string_lossy().into_owned(),
+            source,
+        })
+    }
+
+    /// Return the name of the group a notebook-relative directory belongs to.
+    ///
+    /// A group without `paths` applies to the directory of the same name. The
+    /// first group with a path matching `dir` or one of its ancestors wins.
+    pub fn group_for(&self, dir: &Path) -> Option<&str> {
+        self.groups.iter().find_map(|(name, group)| {
+            let matches = if group.paths.is_empty() {
+                dir.starts_with(name)
+            } else {
+                group.paths.iter().any(|p| dir.starts_with(p))
+            };
+            matches.then_some(name.as_str())
+        })
+    }
+
+    /// Return the notebook-relative directory new notes of `group` are created in.
+    pub fn group_dir(&self, group: &str) -> PathBuf {
+        self.groups
+            .get(group)
+            .and_then(|g| g.paths.first())
+            .map_or_else(|| PathBuf::from(group), PathBuf::from)
+    }
+
+    /// Return the note options for `group`, with the group's overrides applied
+    /// on top of the global `[note]` section.
+    pub fn note_config(&self, group: Option<&str>) -> NoteConfig {
+        let mut note = self.note.clone();
+
+        if let Some(group) = group.and_then(|name| self.groups.get(name)) {
+            group.note.apply(&mut note);
+        }
+
+        note
+    }
+
+    /// Return `true` if the notebook-relative `path` matches one of the
+    /// `exclude` globs of its group or of the `[note]` section.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let exclude = self
+            .group_for(dir)
+            .and_then(|name| self.groups.get(name)?.note.exclude.as_ref())
+            .unwrap_or(&self.note.exclude);
+        exclude.iter().any(|glob| path_matches(glob, path))
+    }
+
+    /// Return the `[extra]` variables for `group`, with the group's values
+    /// taking precedence over the global ones.
+    pub fn extra(&self, group: Option<&str>) -> HashMap<String, String> {
+        let mut extra = self.extra.clone();
+
+        if let Some(group) = group.and_then(|name| self.groups.get(name)) {
+            extra.extend(group.extra.clone());
+        }
+
+        extra
+    }
+}
+
+/// Options from the `[note]` section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NoteConfig {
+    /// Language used for slugs and date formats.
+    pub language: String,
+    /// Title used for new notes without an explicit one.
+    pub default_title: String,
+    /// Handlebars template for the filename, without extension.
+    pub filename: String,
+    /// File extension of notes.
+    pub extension: String,
+    /// Body template, relative to `.zk/templates/` unless absolute.
+    pub template: Option<String>,
+    /// Path globs ignored while indexing.
+    pub exclude: Vec<String>,
+    /// Characters used for random IDs.
+    pub id_charset: IdCharset,
+    /// Length of random IDs.
+    pub id_length: usize,
+    /// Letter case of random IDs.
+    pub id_case: IdCase,
+}
+
+impl Default for NoteConfig {
+    fn default() -> Self {
+        Self {
+            language: "en".into(),
+            default_title: "Untitled".into(),
+            filename: "{{id}}".into(),
+            extension: "md".into(),
+            template: None,
+            exclude: Vec::new(),
+            id_charset: IdCharset::Alphanum,
+            id_length: 4,
+            id_case: IdCase::Lower,
+        }
+    }
+}
+
+/// Partial `[note]` section of a group. Only set keys override the global ones.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NoteOverrides {
+    pub language: Option<String>,
+    pub default_title: Option<String>,
+    pub filename: Option<String>,
+    pub extension: Option<String>,
+    pub template: Option<String>,
+    pub exclude: Option<Vec<String>>,
+    pub id_charset: Option<IdCharset>,
+    pub id_length: Option<usize>,
+    pub id_case: Option<IdCase>,
+}
+
+impl NoteOverrides {
+    fn apply(&self, note: &mut NoteConfig) {
+        if let Some(language) = &self.language {
+            note.language = language.clone();
+        }
+        if let Some(default_title) = &self.default_title {
+            note.default_title = default_title.clone();
+        }
+        if let Some(filename) = &self.filename {
+            note.filename = filename.clone();
+        }
+        if let Some(extension) = &self.extension {
+            note.extension = extension.clone();
+        }
+        if let Some(template) = &self.template {
+            note.template = Some(template.clone());
+        }
+        if let Some(exclude) = &self.exclude {
+            note.exclude = exclude.clone();
+        }
+        if let Some(id_charset) = &self.id_charset {
+            note.id_charset = id_charset.clone();
+        }
+        if let Some(id_length) = self.id_length {
+            note.id_length = id_length;
+        }
+        if let Some(id_case) = self.id_case {
+            note.id_case = id_case;
+        }
+    }
+}
+
+/// Character set for random note IDs.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum IdCharset {
+    /// `a` to `z`.
+    Letters,
+    /// `0` to `9`.
+    Numbers,
+    /// Letters and numbers.
+    Alphanum,
+    /// `0` to `9` and `a` to `f`.
+    Hex,
+    /// Any character from the given string.
+    Custom(String),
+}
+
+impl From<String> for IdCharset {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "letters" => IdCharset::Letters,
+            "numbers" => IdCharset::Numbers,
+            "alphanum" => IdCharset::Alphanum,
+            "hex" => IdCharset::Hex,
+            _ => IdCharset::Custom(value),
+        }
+    }
+}
+
+/// Letter case for random note IDs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdCase {
+    Lower,
+    Upper,
+    Mixed,
+}
+
+/// A `[group."<name>"]` section.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GroupConfig {
+    /// Directories belonging to the group. Empty means the group's own name.
+    pub paths: Vec<String>,
+    /// Overrides for the `[note]` section.
+    pub note: NoteOverrides,
+    /// Overrides for the `[extra]` section.
+    pub extra: HashMap<String, String>,
+}
+
+/// The `[format]` section.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FormatConfig {
+    pub markdown: MarkdownConfig,
+}
+
+/// The `[format.markdown]` section.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct MarkdownConfig {
+    /// Extract `#hashtags` from note bodies.
+    pub hashtags: bool,
+    /// Extract `:colon:separated:tags:` from note bodies.
+    pub colon_tags: bool,
+    /// Extract Bear-style `#multi-word tags#`. Requires `hashtags`.
+    pub multiword_tags: bool,
+    /// Link format used between notes: `markdown`, `wiki` or a custom template.
+    pub link_format: String,
+    /// Whether link paths are percent-encoded. Defaults depend on `link_format`.
+    pub link_encode_path: Option<bool>,
+    /// Whether the file extension is removed from link paths.
+    pub link_drop_extension: bool,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        Self {
+            hashtags: true,
+            colon_tags: false,
+            multiword_tags: false,
+            link_format: "markdown".into(),
+            link_encode_path: None,
+            link_drop_extension: true,
+        }
+    }
+}
+
+/// The `[lsp]` section.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LspConfig {
+    pub diagnostics: LspDiagnostics,
+    pub completion: LspCompletion,
+}
+
+/// The `[lsp.diagnostics]` section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LspDiagnostics {
+    /// Severity of links to notes that do not exist.
+    pub dead_link: DiagnosticLevel,
+    /// Severity of links of a note to itself.
+    pub self_link: DiagnosticLevel,
+}
+
+impl Default for LspDiagnostics {
+    fn default() -> Self {
+        Self {
+            dead_link: DiagnosticLevel::Error,
+            self_link: DiagnosticLevel::None,
+        }
+    }
+}
+
+/// Severity of a language server diagnostic, `none` turning it off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticLevel {
+    None,
+    Hint,
+    Info,
+    Warning,
+    Error,
+}
+
+/// The `[lsp.completion]` section. The templates are rendered with the
+/// variables of `zk list --format`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LspCompletion {
+    /// Label of a note in the completion pop-up.
+    pub note_label: String,
+    /// Text the completion pop-up filters notes by.
+    pub note_filter_text: String,
+    /// Detail shown next to a note, none if unset.
+    pub note_detail: Option<String>,
+}
+
+impl Default for LspCompletion {
+    fn default() -> Self {
+        Self {
+            note_label: "{{title-or-path}}".into(),
+            note_filter_text: "{{title}} {{path}}".into(),
+            note_detail: None,
+        }
+    }
+}
+
+/// Match the notebook-relative `path` against `glob`, where `*` stands for
+/// any characters within a path component and `**` for any number of
+/// components, e.g. `drafts/*` or `**/log.md`.
+fn path_matches(glob: &str, path: &Path) -> bool {
+    let glob: Vec<&str> = glob.trim_matches('/').split('/').collect();
+    let parts: Vec<String> = path
+        .iter()
+        .map(|part| part.to_string_lossy().into_owned())
+        .collect();
+    components_match(&glob, &parts)
+}
+
+fn components_match(glob: &[&str], parts: &[String]) -> bool {
+    match glob.split_first() {
+        None => parts.is_empty(),
+        Some((&"**", rest)) => (0..=parts.len()).any(|i| components_match(rest, &parts[i..])),
+        Some((pattern, rest)) => parts.split_first().is_some_and(|(part, parts)| {
+            glob_match(pattern, part) && components_match(rest, parts)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> NotebookConfig {
+        NotebookConfig::parse(content, Path::new("config.toml")).unwrap()
+    }
+
+    #[test]
+    fn test_is_excluded() {
+        let config = parse(
+            "[note]\nexclude = [\"drafts/*\", \"log.md\", \"**/*.tmp.md\"]\n\
+             [group.journal]\npaths = [\"journal\"]\n\
+             [group.journal.note]\nexclude = [\"journal/old\"]",
+        );
+        assert!(config.is_excluded(Path::new("drafts/idea.md")));
+        assert!(config.is_excluded(Path::new("log.md")));
+        assert!(config.is_excluded(Path::new("a/b/scratch.tmp.md")));
+        assert!(!config.is_excluded(Path::new("drafts/deep/idea.md")));
+        assert!(!config.is_excluded(Path::new("sub/log.md")));
+        assert!(!config.is_excluded(Path::new("note.md")));
+
+        // The group's exclude replaces the global one.
+        assert!(config.is_excluded(Path::new("journal/old")));
+        assert!(!config.is_excluded(Path::new("journal/log.md")));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = parse("");
+        assert_eq!(config.note.filename, "{{id}}");
+        assert_eq!(config.note.id_length, 4);
+        assert_eq!(config.note.id_charset, IdCharset::Alphanum);
+        assert_eq!(config.note.id_case, IdCase::Lower);
+        assert!(config.format.markdown.hashtags);
+        assert!(!config.format.markdown.colon_tags);
+        assert!(!config.format.markdown.multiword_tags);
+    }
+
+    #[test]
+    fn test_note_section() {
+        let config = parse(
+            "[note]\nfilename = \"{{ slug title }}\"\nid-length = 8\nid-charset = \"hex\"\nid-case = \"upper\"\ntemplate = \"default.md\"",
+        );
+        assert_eq!(config.note.filename, "{{ slug title }}");
+        assert_eq!(config.note.id_length, 8);
+        assert_eq!(config.note.id_charset, IdCharset::Hex);
+        assert_eq!(config.note.id_case, IdCase::Upper);
+        assert_eq!(config.note.template.as_deref(), Some("default.md"));
+    }
+
+    #[test]
+    fn test_custom_charset() {
+        let config = parse("[note]\nid-charset = \"xyz\"");
+        assert_eq!(config.note.id_charset, IdCharset::Custom("xyz".into()));
+    }
+
+    #[test]
+    fn test_markdown_flags() {
+        let config =
+            parse("[format.markdown]\nhashtags = false\ncolon-tags = true\nlink-format = \"wiki\"");
+        assert!(!config.format.markdown.hashtags);
+        assert!(config.format.markdown.colon_tags);
+        assert_eq!(config.format.markdown.link_format, "wiki");
+    }
+
+    #[test]
+    fn test_extra_filter_alias() {
+        let config = parse(
+            "[extra]\nauthor = \"me\"\n[filter]\nrecents = \"--sort created-\"\n[alias]\nls = \"zk list $@\"",
+        );
+        assert_eq!(config.extra["author"], "me");
+        assert_eq!(config.filters["recents"], "--sort created-");
+        assert_eq!(config.aliases["ls"], "zk list $@");
+    }
+
+    #[test]
+    fn test_lsp_section() {
+        let config = parse("");
+        assert_eq!(config.lsp.diagnostics.dead_link, DiagnosticLevel::Error);
+        assert_eq!(config.lsp.diagnostics.self_link, DiagnosticLevel::None);
+        assert_eq!(config.lsp.completion.note_label, "{{title-or-path}}");
+
+        let config = parse(
+            "[lsp.diagnostics]\ndead-link = \"warning\"\n\
+             missing-backlink = { level = \"hint\", position = \"bottom\" }\n\
+             [lsp.completion]\nnote-detail = \"{{filename-stem}}\"",
+        );
+        assert_eq!(config.lsp.diagnostics.dead_link, DiagnosticLevel::Warning);
+        assert_eq!(
+            config.lsp.completion.note_detail.as_deref(),
+            Some("{{filename-stem}}")
+        );
+    }
+
+    #[test]
+    fn test_group_overrides() {
+        let config = parse(
+            "[note]\nid-length = 4\n[extra]\nauthor = \"me\"\n\
+             [group.journal]\npaths = [\"journal/daily\"]\n\
+             [group.journal.note]\nfilename = \"{{format-date now}}\"\n\
+             [group.journal.extra]\nauthor = \"diary\"",
+        );
+
+        assert_eq!(
+            config.group_for(Path::new("journal/daily/2024")),
+            Some("journal")
+        );
+        assert_eq!(config.group_for(Path::new("journal")), None);
+
+        let note = config.note_config(Some("journal"));
+        assert_eq!(note.filename, "{{format-date now}}");
+        assert_eq!(note.id_length, 4);
+        assert_eq!(config.extra(Some("journal"))["author"], "diary");
+        assert_eq!(config.extra(None)["author"], "me");
+        assert_eq!(config.group_dir("journal"), Path::new("journal/daily"));
+    }
+
+    #[test]
+    fn test_group_without_paths() {
+        let config = parse("[group.books]");
+        assert_eq!(config.group_for(Path::new("books")), Some("books"));
+        assert_eq!(config.group_for(Path::new("other")), None);
+        assert_eq!(config.group_dir("books"), Path::new("books"));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let result = NotebookConfig::parse("[note\n", Path::new("config.toml"));
+        assert!(matches!(result, Err(Error::Config { .. })));
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use rand::RngExt;
+use serde::Serialize;
+
+use crate::config::{IdCase, IdCharset, NoteConfig};
+
+/// Options for [`Notebook::create`](crate::Notebook::create).
+#[derive(Clone, Debug, Default)]
+pub struct NewNote {
+    /// Title of the note. Falls back to the configured `default-title`.
+    pub title: Option<String>,
+    /// Content of the note, available as `{{content}}` in the body template.
+    /// Written as is if no template applies.
+    pub content: String,
+    /// Notebook-relative directory for the note. Defaults to the directory of
+    /// `group`, or the notebook root.
+    pub dir: Option<PathBuf>,
+    /// Group whose settings apply. Defaults to the group `dir` belongs to.
+    pub group: Option<String>,
+    /// Body template in `.zk/templates/` used instead of the configured one.
+    pub template: Option<String>,
+}
+
+/// Variables available in the `filename` template.
+#[derive(Serialize)]
+pub(crate) struct FilenameContext<'a> {
+    pub id: String,
+    pub title: &'a str,
+    pub content: &'a str,
+    pub dir: String,
+    pub now: String,
+    pub extra: &'a HashMap<String, String>,
+    pub env: &'a HashMap<String, String>,
+}
+
+/// Variables available in body templates.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct BodyContext<'a> {
+    pub id: &'a str,
+    pub title: &'a str,
+    pub content: &'a str,
+    pub dir: String,
+    pub filename: String,
+    pub filename_stem: &'a str,
+    pub now: String,
+    pub extra: &'a HashMap<String, String>,
+    pub env: &'a HashMap<String, String>,
+}
+
+/// Generate a random ID following the `id-charset`, `id-length` and `id-case` options.
+pub(crate) fn generate_id(config: &NoteConfig) -> String {
+    let chars = id_chars(&config.id_charset, config.id_case);
+    if chars.is_empty() {
+        return String::new();
+    }
+
+    let mut rng = rand::rng();
+    (0..config.id_length)
+        .map(|_| chars[rng.random_range(0..chars.len())])
+        .collect()
+}
+
+fn id_chars(charset: &IdCharset, case: IdCase) -> Vec<char> {
+    const LETTERS: &str = "abcdefghijklmnopqrstuvwxyz";
+    const NUMBERS: &str = "0123456789";
+
+    let base: String = match charset {
+        IdCharset::Letters => LETTERS.into(),
+        IdCharset::Numbers => NUMBERS.into(),
+        IdCharset::Alphanum => format!("{NUMBERS}{LETTERS}"),
+        IdCharset::Hex => format!("{NUMBERS}abcdef"),
+        IdCharset::Custom(chars) => chars.clone(),
+    };
+
+    let mut chars: Vec<char> = match case {
+        IdCase::Lower => base.chars().flat_map(char::to_lowercase).collect(),
+        IdCase::Upper => base.chars().flat_map(char::to_uppercase).collect(),
+        IdCase::Mixed => base
+            .chars()
+            .flat_map(|c| c.to_lowercase().chain(c.to_uppercase()))
+            .collect(),
+    };
+
+    chars.sort_unstable();
+    chars.dedup();
+    chars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_id_defaults() {
+        let id = generate_id(&NoteConfig::default());
+        assert_eq!(id.len(), 4);
+        assert!(
+            id.chars()
+                .all(|c| c.is_ascii_digit() || c.is_ascii_lowercase())
+        );
+    }
+
+    #[test]
+    fn test_generate_id_hex_upper() {
+        let config = NoteConfig {
+            id_charset: IdCharset::Hex,
+            id_case: IdCase::Upper,
+            id_length: 12,
+            ..NoteConfig::default()
+        };
+        let id = generate_id(&config);
+        assert_eq!(id.len(), 12);
+        assert!(id.chars().all(|c| "0123456789ABCDEF".contains(c)));
+    }
+
+    #[test]
+    fn test_generate_id_custom() {
+        let config = NoteConfig {
+            id_charset: IdCharset::Custom("xy".into()),
+            ..NoteConfig::default()
+        };
+        assert!(generate_id(&config).chars().all(|c| c == 'x' || c == 'y'));
+    }
+
+    #[test]
+    fn test_id_chars_mixed() {
+        let chars = id_chars(&IdCharset::Letters, IdCase::Mixed);
+        assert_eq!(chars.len(), 52);
+    }
+}
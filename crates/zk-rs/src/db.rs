@@ -0,0 +1,627 @@
+//! Read and write zk's `.zk/notebook.db` SQLite index.
+//!
+//! Notes whose checksum and modification time match their row are taken from
+//! the index instead of being parsed again. Notes that were parsed are written
+//! back in zk's schema, so the `zk` CLI sees the same notebook.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, OpenFlags, Transaction, params};
+
+use crate::config::MarkdownConfig;
+use crate::error::Error;
+use crate::note::Note;
+use crate::parse;
+use crate::storage::FileMetadata;
+
+/// Oldest `user_version` of zk's schema with all columns written here.
+const SCHEMA_VERSION: i64 = 8;
+
+/// Format of the `created` and `modified` columns, as written by zk.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f%:z";
+
+pub(crate) struct Database {
+    conn: Connection,
+}
+
+/// A note as stored in the index, without the parts derived from its path.
+pub(crate) struct IndexedNote {
+    title: String,
+    lead: String,
+    body: String,
+    checksum: String,
+    created: jiff::Timestamp,
+    modified: jiff::Timestamp,
+    tags: Vec<String>,
+    aliases: Vec<String>,
+    metadata: BTreeMap<String, serde_yaml::Value>,
+    links: Vec<String>,
+}
+
+impl Database {
+    /// Open an existing index. The database is never created here, zk owns its
+    /// schema and migrations.
+    pub(crate) fn open(path: &Path) -> Result<Self, Error> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version < SCHEMA_VERSION {
+            return Err(Error::IndexVersion(version));
+        }
+
+        // Removing a note cascades to its links and tags like in zk.
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        Ok(Self { conn })
+    }
+
+    /// Return all indexed notes by notebook-relative path.
+    pub(crate) fn notes(&self) -> Result<HashMap<PathBuf, IndexedNote>, Error> {
+        let mut notes = HashMap::new();
+
+        let mut stmt = self.conn.prepare(
+            "SELECT id, path, title, lead, body, checksum, created, modified, metadata FROM notes",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let path: String = row.get(1)?;
+            let metadata: String = row.get(8)?;
+
+            let note = IndexedNote {
+                title: row.get(2)?,
+                lead: row.get(3)?,
+                body: row.get(4)?,
+                checksum: row.get(5)?,
+                created: parse_timestamp(&row.get::<_, String>(6)?),
+                modified: parse_timestamp(&row.get::<_, String>(7)?),
+                tags: Vec::new(),
+                aliases: aliases(&metadata),
+                metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+                links: Vec::new(),
+            };
+            notes.insert(id, (PathBuf::from(path), note));
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT nc.note_id, c.name FROM notes_collections nc
+             JOIN collections c ON c.id = nc.collection_id
+             WHERE c.kind = 'tag' ORDER BY nc.id",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            if let Some((_, note)) = notes.get_mut(&row.get::<_, i64>(0)?) {
+                note.tags.push(row.get(1)?);
+            }
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT source_id, href, type FROM links WHERE external = 0 ORDER BY id")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let href: String = row.get(1)?;
+            let kind: String = row.get(2)?;
+            if let Some((_, note)) = notes.get_mut(&row.get::<_, i64>(0)?)
+                && let Some(target) = link_target(&href, &kind)
+                && !note.links.iter().any(|link| link == target)
+            {
+                note.links.push(target.to_owned());
+            }
+        }
+
+        Ok(notes.into_values().collect())
+    }
+
+    /// Write `written` notes, delete the rows of `removed` paths and resolve
+    /// all links without a target, in a single transaction.
+    ///
+    /// `resolve` returns the path of the note a link from the note at the
+    /// given path points to.
+    pub(crate) fn sync<'a>(
+        &mut self,
+        written: impl IntoIterator<Item = &'a Note>,
+        removed: impl IntoIterator<Item = &'a Path>,
+        resolve: impl Fn(&str, &Path) -> Option<&'a Path>,
+    ) -> Result<(), Error> {
+        let tx = self.conn.transaction()?;
+
+        for path in removed {
+            tx.execute(
+                "DELETE FROM notes WHERE path = ?1",
+                [path.to_string_lossy()],
+            )?;
+        }
+        for note in written {
+            write_note(&tx, note)?;
+        }
+        resolve_links(&tx, resolve)?;
+
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl IndexedNote {
+    /// Check if the row still describes the file with `checksum` and `meta`.
+    pub(crate) fn is_current(&self, checksum: &str, meta: &FileMetadata) -> bool {
+        self.checksum == checksum && self.modified == meta.modified
+    }
+
+    /// Turn the row into a note for the file at `path` with `content`.
+    pub(crate) fn into_note(
+        self,
+        content: &str,
+        path: PathBuf,
+        abs_path: PathBuf,
+        markdown: &MarkdownConfig,
+    ) -> Result<Note, Error> {
+        let filename = path
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let filename_stem = path
+            .file_stem()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let link = parse::note_link(&path, &self.title, markdown);
+        // zk only indexes links to notes.
+        let attachment_links = parse::extract_attachment_targets(&self.body);
+
+        Ok(Note {
+            filename,
+            filename_stem,
+            path,
+            abs_path,
+            title: self.title,
+            link,
+            lead: self.lead,
+            body: self.body,
+            raw_content: content.to_owned(),
+            // zk counts the words of the body only, keep the count comparable
+            // to parsed notes.
+            word_count: content.split_whitespace().count(),
+            tags: self.tags,
+            aliases: self.aliases,
+            metadata: self.metadata,
+            outgoing_links: self.links,
+            attachment_links,
+            created: self.created,
+            modified: self.modified,
+        })
+    }
+}
+
+/// Insert or update the row of `note` and replace its tags and links.
+fn write_note(tx: &Transaction, note: &Note) -> Result<(), Error> {
+    let path = note.path().to_string_lossy();
+    let content = note.raw_content();
+
+    tx.execute(
+        "INSERT INTO notes (path, sortable_path, title, lead, body, raw_content,
+                            word_count, checksum, created, modified, metadata, filename)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+         ON CONFLICT(path) DO UPDATE SET
+             sortable_path = excluded.sortable_path, title = excluded.title,
+             lead = excluded.lead, body = excluded.body,
+             raw_content = excluded.raw_content, word_count = excluded.word_count,
+             checksum = excluded.checksum, created = excluded.created,
+             modified = excluded.modified, metadata = excluded.metadata,
+             filename = excluded.filename",
+        params![
+            path,
+            // zk sorts paths with separators before any other character.
+            path.replace('/', "\x01"),
+            note.title(),
+            note.lead(),
+            note.body(),
+            content,
+            note.word_count() as i64,
+            parse::checksum(content),
+            format_timestamp(note.created()),
+            format_timestamp(note.modified()),
+            metadata(content),
+            note.filename(),
+        ],
+    )?;
+    let id: i64 = tx.query_row("SELECT id FROM notes WHERE path = ?1", [&path], |row| {
+        row.get(0)
+    })?;
+
+    tx.execute("DELETE FROM notes_collections WHERE note_id = ?1", [id])?;
+    for tag in note.tags() {
+        tx.execute(
+            "INSERT OR IGNORE INTO collections (kind, name) VALUES ('tag', ?1)",
+            [tag],
+        )?;
+        tx.execute(
+            "INSERT INTO notes_collections (note_id, collection_id)
+             SELECT ?1, id FROM collections WHERE kind = 'tag' AND name = ?2",
+            params![id, tag],
+        )?;
+    }
+
+    // Only links to other notes are tracked, external links of a rewritten
+    // note are dropped. Targets are filled in by `resolve_links`.
+    tx.execute("DELETE FROM links WHERE source_id = ?1", [id])?;
+    for link in parse::extract_links(note.body()) {
+        tx.execute(
+            "INSERT INTO links (source_id, title, href, external, type) VALUES (?1, ?2, ?3, 0, ?4)",
+            params![id, link.title, link.href, link.kind.as_str()],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Set the target of all links to other notes that have none yet.
+fn resolve_links<'a>(
+    tx: &Transaction,
+    resolve: impl Fn(&str, &Path) -> Option<&'a Path>,
+) -> Result<(), Error> {
+    let mut stmt = tx.prepare(
+        "SELECT l.id, l.href, l.type, s.path FROM links l
+         JOIN notes s ON s.id = l.source_id
+         WHERE l.target_id IS NULL AND l.external = 0",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut resolved = Vec::new();
+    while let Some(row) = rows.next()? {
+        let href: String = row.get(1)?;
+        let kind: String = row.get(2)?;
+        let source: String = row.get(3)?;
+        if let Some(target) = link_target(&href, &kind)
+            && let Some(path) = resolve(target, Path::new(&source))
+        {
+            resolved.push((row.get::<_, i64>(0)?, path));
+        }
+    }
+
+    for (id, path) in resolved {
+        tx.execute(
+            "UPDATE links SET target_id = (SELECT id FROM notes WHERE path = ?1) WHERE id = ?2",
+            params![path.to_string_lossy(), id],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Return the note a link points to, like the targets of parsed notes. The
+/// href is stored as written, links written by older versions have no type.
+fn link_target<'a>(href: &'a str, kind: &str) -> Option<&'a str> {
+    match kind {
+        "wiki-link" => parse::double_bracket_target(href),
+        "markdown" | "implicit" => parse::markdown_link_target(href),
+        _ => Some(href).filter(|href| !href.is_empty()),
+    }
+}
+
+/// Return the frontmatter of `content` as JSON object with lowercase keys,
+/// `{}` if there is none.
+fn metadata(content: &str) -> String {
+    let object = parse::extract_frontmatter(content)
+        .0
+        .and_then(|yaml| serde_yaml::from_str::<serde_yaml::Mapping>(yaml).ok())
+        .map(|mapping| {
+            mapping
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let key = key.as_str()?.to_lowercase();
+                    Some((key, serde_json::to_value(value).ok()?))
+                })
+                .collect::<serde_json::Map<_, _>>()
+        })
+        .unwrap_or_default();
+
+    serde_json::Value::Object(object).to_string()
+}
+
+/// Return the `aliases` of the JSON `metadata` column.
+fn aliases(metadata: &str) -> Vec<String> {
+    let Ok(serde_json::Value::Object(object)) = serde_json::from_str(metadata) else {
+        return Vec::new();
+    };
+
+    match object.get("aliases") {
+        Some(serde_json::Value::String(alias)) => vec![alias.clone()],
+        Some(serde_json::Value::Array(aliases)) => aliases
+            .iter()
+            .filter_map(|alias| alias.as_str().map(str::to_owned))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn format_timestamp(ts: jiff::Timestamp) -> String {
+    ts.strftime(TIMESTAMP_FORMAT).to_string()
+}
+
+/// Parse a timestamp column. Rows that cannot be parsed never match a file and
+/// are written again.
+fn parse_timestamp(s: &str) -> jiff::Timestamp {
+    jiff::fmt::strtime::parse(TIMESTAMP_FORMAT, s)
+        .and_then(|tm| tm.to_timestamp())
+        .ok()
+        .or_else(|| parse::parse_date_string(s))
+        .unwrap_or(jiff::Timestamp::UNIX_EPOCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Notebook;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// The parts of zk's schema read and written here, including the
+    /// full-text triggers that fire on every write.
+    const SCHEMA: &str = "
+        CREATE TABLE notes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+            path TEXT NOT NULL,
+            sortable_path TEXT NOT NULL,
+            title TEXT DEFAULT('') NOT NULL,
+            lead TEXT DEFAULT('') NOT NULL,
+            body TEXT DEFAULT('') NOT NULL,
+            raw_content TEXT DEFAULT('') NOT NULL,
+            word_count INTEGER DEFAULT(0) NOT NULL,
+            checksum TEXT NOT NULL,
+            created DATETIME DEFAULT(CURRENT_TIMESTAMP) NOT NULL,
+            modified DATETIME DEFAULT(CURRENT_TIMESTAMP) NOT NULL,
+            metadata TEXT DEFAULT('{}') NOT NULL,
+            filename TEXT DEFAULT('') NOT NULL,
+            UNIQUE(path)
+        );
+        CREATE TABLE links (
+            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+            source_id INTEGER NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+            target_id INTEGER REFERENCES notes(id) ON DELETE SET NULL,
+            title TEXT DEFAULT('') NOT NULL,
+            href TEXT NOT NULL,
+            external INT DEFAULT(0) NOT NULL,
+            rels TEXT DEFAULT('') NOT NULL,
+            snippet TEXT DEFAULT('') NOT NULL,
+            snippet_start INTEGER DEFAULT(0) NOT NULL,
+            snippet_end INTEGER DEFAULT(0) NOT NULL,
+            type TEXT DEFAULT('') NOT NULL
+        );
+        CREATE TABLE collections (
+            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+            kind TEXT NO NULL,
+            name TEXT NOT NULL,
+            UNIQUE(kind, name)
+        );
+        CREATE TABLE notes_collections (
+            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+            note_id INTEGER NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+            collection_id INTEGER NOT NULL REFERENCES collections(id) ON DELETE CASCADE
+        );
+        CREATE VIRTUAL TABLE notes_fts USING fts5(
+            path, title, body, content = notes, content_rowid = id
+        );
+        CREATE TRIGGER trigger_notes_ai AFTER INSERT ON notes BEGIN
+            INSERT INTO notes_fts(rowid, path, title, body) VALUES (new.id, new.path, new.title, new.body);
+        END;
+        CREATE TRIGGER trigger_notes_ad AFTER DELETE ON notes BEGIN
+            INSERT INTO notes_fts(notes_fts, rowid, path, title, body) VALUES('delete', old.id, old.path, old.title, old.body);
+        END;
+        CREATE TRIGGER trigger_notes_au AFTER UPDATE ON notes BEGIN
+            INSERT INTO notes_fts(notes_fts, rowid, path, title, body) VALUES('delete', old.id, old.path, old.title, old.body);
+            INSERT INTO notes_fts(rowid, path, title, body) VALUES (new.id, new.path, new.title, new.body);
+        END;
+        PRAGMA user_version = 8;
+    ";
+
+    fn setup_notebook() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(".zk")).unwrap();
+        Connection::open(db_path(&dir))
+            .unwrap()
+            .execute_batch(SCHEMA)
+            .unwrap();
+
+        fs::write(
+            dir.path().join("source.md"),
+            "---\ntitle: Source\ntags: [rust]\naliases: [origin]\n---\nSee [[target]].",
+        )
+        .unwrap();
+        fs::write(dir.path().join("target.md"), "# Target\n\nNo links.").unwrap();
+        dir
+    }
+
+    fn db_path(dir: &TempDir) -> PathBuf {
+        dir.path().join(".zk").join("notebook.db")
+    }
+
+    /// Return the path of the note the link from `source` points to.
+    fn link_target_path(conn: &Connection, source: &str) -> Option<String> {
+        conn.query_row(
+            "SELECT t.path FROM links l
+             JOIN notes s ON s.id = l.source_id
+             LEFT JOIN notes t ON t.id = l.target_id
+             WHERE s.path = ?1",
+            [source],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_load_writes_index() {
+        let dir = setup_notebook();
+        Notebook::load_with_index(dir.path()).unwrap();
+
+        let conn = Connection::open(db_path(&dir)).unwrap();
+        let (title, metadata, checksum): (String, String, String) = conn
+            .query_row(
+                "SELECT title, metadata, checksum FROM notes WHERE path = 'source.md'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(title, "Source");
+        assert!(metadata.contains(r#""aliases":["origin"]"#));
+        let content = fs::read_to_string(dir.path().join("source.md")).unwrap();
+        assert_eq!(checksum, parse::checksum(&content));
+
+        let tag: String = conn
+            .query_row(
+                "SELECT c.name FROM collections c
+                 JOIN notes_collections nc ON nc.collection_id = c.id
+                 JOIN notes n ON n.id = nc.note_id
+                 WHERE n.path = 'source.md' AND c.kind = 'tag'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tag, "rust");
+        assert_eq!(
+            link_target_path(&conn, "source.md").as_deref(),
+            Some("target.md")
+        );
+    }
+
+    #[test]
+    fn test_links_keep_label_and_type() {
+        let dir = setup_notebook();
+        fs::write(
+            dir.path().join("source.md"),
+            "# Source\n\nSee [the target](target.md#top \"Title\") and [[target|again]].",
+        )
+        .unwrap();
+        Notebook::load_with_index(dir.path()).unwrap();
+
+        let conn = Connection::open(db_path(&dir)).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT title, href, type, target_id IS NOT NULL FROM links ORDER BY id")
+            .unwrap();
+        let links: Vec<(String, String, String, bool)> = stmt
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            links,
+            [
+                (
+                    "the target".into(),
+                    "target.md#top".into(),
+                    "markdown".into(),
+                    true
+                ),
+                ("again".into(), "target".into(), "wiki-link".into(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_reuses_current_rows() {
+        let dir = setup_notebook();
+        Notebook::load_with_index(dir.path()).unwrap();
+
+        // A changed row is only visible if the note is not parsed again.
+        let conn = Connection::open(db_path(&dir)).unwrap();
+        conn.execute(
+            "UPDATE notes SET title = 'Indexed' WHERE path = 'target.md'",
+            [],
+        )
+        .unwrap();
+
+        let nb = Notebook::load_with_index(dir.path()).unwrap();
+        let source = nb.note("source").unwrap();
+        assert_eq!(source.tags(), ["rust"]);
+        assert_eq!(source.aliases(), ["origin"]);
+        assert_eq!(source.outgoing_links(), ["target"]);
+        assert_eq!(nb.note("target").unwrap().title(), "Indexed");
+        assert_eq!(nb.backlinks(nb.note("target").unwrap()).len(), 1);
+
+        fs::write(dir.path().join("target.md"), "# Changed\n").unwrap();
+        let nb = Notebook::load_with_index(dir.path()).unwrap();
+        assert_eq!(nb.note("target").unwrap().title(), "Changed");
+        let title: String = conn
+            .query_row(
+                "SELECT title FROM notes WHERE path = 'target.md'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(title, "Changed");
+    }
+
+    #[test]
+    fn test_index_follows_changes() {
+        let dir = setup_notebook();
+        let mut nb = Notebook::load_with_index(dir.path()).unwrap();
+        let conn = Connection::open(db_path(&dir)).unwrap();
+        let count = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT count(*) FROM notes", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        fs::remove_file(dir.path().join("target.md")).unwrap();
+        nb.remove("target.md").unwrap();
+        assert_eq!(count(&conn), 1);
+        assert_eq!(link_target_path(&conn, "source.md"), None);
+
+        // Recreating the note resolves the dangling link again.
+        fs::write(dir.path().join("target.md"), "# Target\n").unwrap();
+        nb.reload("target.md").unwrap();
+        assert_eq!(count(&conn), 2);
+        assert_eq!(
+            link_target_path(&conn, "source.md").as_deref(),
+            Some("target.md")
+        );
+
+        // Files deleted while the notebook was not loaded are dropped.
+        drop(nb);
+        fs::remove_file(dir.path().join("target.md")).unwrap();
+        Notebook::load_with_index(dir.path()).unwrap();
+        assert_eq!(count(&conn), 1);
+    }
+
+    #[test]
+    fn test_zk_rows() {
+        let dir = setup_notebook();
+        Notebook::load_with_index(dir.path()).unwrap();
+
+        // Rows written by zk keep the link as written, with its type.
+        let conn = Connection::open(db_path(&dir)).unwrap();
+        conn.execute_batch(
+            "UPDATE links SET href = 'target#heading', type = 'markdown';
+             INSERT INTO links (source_id, href, external, type)
+             SELECT id, 'https://example.com', 1, 'markdown' FROM notes WHERE path = 'source.md';",
+        )
+        .unwrap();
+
+        let nb = Notebook::load_with_index(dir.path()).unwrap();
+        assert_eq!(nb.note("source").unwrap().outgoing_links(), ["target"]);
+    }
+
+    #[test]
+    fn test_old_schema() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(".zk")).unwrap();
+        Connection::open(db_path(&dir)).unwrap();
+
+        let err = Notebook::load_with_index(dir.path()).err().unwrap();
+        assert!(matches!(err, Error::IndexVersion(0)));
+    }
+
+    #[test]
+    fn test_missing_index() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(".zk")).unwrap();
+
+        let err = Notebook::load_with_index(dir.path()).err().unwrap();
+        assert!(matches!(err, Error::Database(_)));
+        assert!(!db_path(&dir).exists());
+    }
+}
@@ -13,4 +13,58 @@ pub enum Error {
         path: String,
         source: serde_yaml::Error,
     },
+
+    #[error("invalid YAML frontmatter")]
+    Frontmatter(#[source] serde_yaml::Error),
+
+    #[error("failed to render template {name}")]
+    Template {
+        name: String,
+        source: Box<handlebars::RenderError>,
+    },
+
+    #[error("path is outside of the notebook: {0}")]
+    InvalidPath(PathBuf),
+
+    #[error("note already exists: {0}")]
+    NoteExists(PathBuf),
+
+    #[error("note not found: {0}")]
+    NoteNotFound(String),
+
+    #[error("attachment not found: {0}")]
+    AttachmentNotFound(PathBuf),
+
+    #[error("invalid tag name: {0:?}")]
+    InvalidTag(String),
+
+    #[error("tag {tag:?} cannot be written in the tag syntax used in {path}")]
+    TagSyntax { tag: String, path: PathBuf },
+
+    #[error("invalid query: {0}")]
+    Query(String),
+
+    #[error("failed to edit notebook config {path}: {reason}")]
+    ConfigEdit { path: String, reason: String },
+
+    #[cfg(feature = "sqlite")]
+    #[error("notebook index error")]
+    Database(#[from] rusqlite::Error),
+
+    #[cfg(feature = "watch")]
+    #[error("failed to watch the notebook")]
+    Watch(#[from] notify::Error),
+
+    #[cfg(feature = "lsp")]
+    #[error("language server protocol error: {0}")]
+    Lsp(String),
+
+    #[error("unsupported notebook.db schema version {0}, run `zk index` to migrate it")]
+    IndexVersion(i64),
+
+    #[error("failed to parse notebook config {path}")]
+    Config {
+        path: String,
+        source: toml::de::Error,
+    },
 }
@@ -0,0 +1,70 @@
+//! Typed changes of a notebook's notes and files.
+
+use std::path::{Path, PathBuf};
+
+use crate::note::Note;
+
+/// How the notes of a notebook changed, as returned by
+/// [`Notebook::apply`](crate::Notebook::apply) and
+/// [`Notebook::reload`](crate::Notebook::reload). Paths are relative to the
+/// notebook root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotebookEvent {
+    /// A note was added.
+    Created { path: PathBuf },
+    /// A known note was read again.
+    Modified { path: PathBuf, changes: NoteChanges },
+    /// A note was removed.
+    Removed { path: PathBuf },
+    /// A note moved from `from` to `to`. Its content may have changed too.
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+impl NotebookEvent {
+    /// Return the path of the note after the event.
+    pub fn path(&self) -> &Path {
+        match self {
+            NotebookEvent::Created { path }
+            | NotebookEvent::Modified { path, .. }
+            | NotebookEvent::Removed { path }
+            | NotebookEvent::Renamed { to: path, .. } => path,
+        }
+    }
+}
+
+/// What changed in a modified note besides its body.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoteChanges {
+    pub title: bool,
+    pub tags: bool,
+    /// The outgoing links, as written.
+    pub links: bool,
+}
+
+impl NoteChanges {
+    pub(crate) fn between(old: &Note, new: &Note) -> Self {
+        Self {
+            title: old.title() != new.title(),
+            tags: old.tags() != new.tags(),
+            links: old.outgoing_links() != new.outgoing_links(),
+        }
+    }
+
+    /// Return `true` if neither title, tags nor links changed.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A change of a file in the notebook directory, e.g. reported by a
+/// [`NotebookWatcher`](crate::NotebookWatcher). Paths are absolute or
+/// relative to the notebook root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileChange {
+    /// The file was created or written.
+    Written(PathBuf),
+    /// The file was deleted.
+    Removed(PathBuf),
+    /// The file was moved from `from` to `to`.
+    Renamed { from: PathBuf, to: PathBuf },
+}
@@ -0,0 +1,373 @@
+//! Editing the YAML frontmatter of a note without losing its formatting.
+//!
+//! The frontmatter is kept as lines, grouped by top-level key. Setting a key
+//! only rewrites the lines of that key, so unknown fields, key order and
+//! comments survive a round trip. Values are read with `serde_yaml`.
+
+use std::fmt;
+
+use serde_yaml::{Mapping, Value};
+
+use crate::error::Error;
+use crate::parse;
+
+/// The frontmatter of a note, together with the note body.
+///
+/// `to_string()` returns the full note content with the edited frontmatter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frontmatter {
+    /// Opening delimiter line, empty if the note has no frontmatter.
+    open: String,
+    segments: Vec<Segment>,
+    /// Closing delimiter line.
+    close: String,
+    body: String,
+}
+
+/// A top-level key with its value lines, or lines between keys such as
+/// comments and blank lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Segment {
+    key: Option<String>,
+    /// Lines of the segment, each ending with a newline.
+    text: String,
+}
+
+impl Frontmatter {
+    /// Split note `content` into its frontmatter and body. A note without
+    /// frontmatter gets an empty one.
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let (Some(yaml), body_start) = parse::extract_frontmatter(content) else {
+            return Ok(Self {
+                open: String::new(),
+                segments: Vec::new(),
+                close: String::new(),
+                body: content.to_owned(),
+            });
+        };
+
+        serde_yaml::from_str::<Value>(yaml).map_err(Error::Frontmatter)?;
+
+        let yaml_start = yaml.as_ptr() as usize - content.as_ptr() as usize;
+        let yaml_end = yaml_start + yaml.len();
+        // The newline before the closing delimiter ends the last line.
+        let close = content[yaml_end..body_start].trim_start_matches(['\r', '\n']);
+
+        let mut segments: Vec<Segment> = Vec::new();
+        for line in yaml.split_inclusive('\n') {
+            let mut line = line.to_owned();
+            if !line.ends_with('\n') {
+                line.push('\n');
+            }
+
+            let continues = line.starts_with([' ', '\t', '-']) && !line.trim().is_empty();
+            match segments.last_mut() {
+                Some(segment) if continues && segment.key.is_some() => {
+                    segment.text.push_str(&line);
+                    continue;
+                }
+                _ => {}
+            }
+
+            segments.push(Segment {
+                key: top_level_key(&line),
+                text: line,
+            });
+        }
+
+        Ok(Self {
+            open: content[..yaml_start].to_owned(),
+            segments,
+            close: close.to_owned(),
+            body: content[body_start..].to_owned(),
+        })
+    }
+
+    /// Return the keys in order, as written.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.segments
+            .iter()
+            .filter_map(|segment| segment.key.as_deref())
+    }
+
+    /// Return the value of `key`, compared case-insensitively like zk does.
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let key = self.segments[self.position(key)?].key.as_deref()?;
+        match self.mapping().remove(key)? {
+            Value::Tagged(tagged) => Some(tagged.value),
+            value => Some(value),
+        }
+    }
+
+    /// Return the value of `key` if it is a string.
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        match self.get(key)? {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Set `key` to `value`, keeping the position and spelling of an existing
+    /// key and the flow or block style of an existing list. New keys are
+    /// added at the end, new lists are written as `[a, b]`.
+    pub fn set(&mut self, key: &str, value: impl Into<Value>) -> Result<(), Error> {
+        let value = value.into();
+        match self.position(key) {
+            Some(idx) => {
+                let segment = &mut self.segments[idx];
+                let key = segment.key.clone().unwrap_or_else(|| key.to_owned());
+                segment.text = render_entry(&key, &value, Some(&segment.text))?;
+            }
+            None => {
+                // Keep trailing comments and blank lines after the new key.
+                let idx = self
+                    .segments
+                    .iter()
+                    .rposition(|segment| segment.key.is_some())
+                    .map_or(0, |idx| idx + 1);
+                self.segments.insert(
+                    idx,
+                    Segment {
+                        key: Some(key.to_owned()),
+                        text: render_entry(key, &value, None)?,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove `key`. Returns `false` if there was no such key.
+    pub fn remove(&mut self, key: &str) -> bool {
+        match self.position(key) {
+            Some(idx) => {
+                self.segments.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return the note body after the frontmatter.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.segments.iter().position(|segment| {
+            segment
+                .key
+                .as_deref()
+                .is_some_and(|other| other.eq_ignore_ascii_case(key))
+        })
+    }
+
+    fn mapping(&self) -> Mapping {
+        let yaml: String = self.segments.iter().map(|s| s.text.as_str()).collect();
+        match serde_yaml::from_str(&yaml) {
+            Ok(Value::Mapping(mapping)) => mapping,
+            _ => Mapping::new(),
+        }
+    }
+}
+
+impl fmt::Display for Frontmatter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // An empty `---` block is not frontmatter for zk, drop it.
+        if self.segments.is_empty() {
+            return f.write_str(&self.body);
+        }
+
+        let open = if self.open.is_empty() {
+            "---\n"
+        } else {
+            &self.open
+        };
+        let close = if self.close.is_empty() {
+            "---\n"
+        } else {
+            &self.close
+        };
+        f.write_str(open)?;
+        for segment in &self.segments {
+            f.write_str(&segment.text)?;
+        }
+        f.write_str(close)?;
+        f.write_str(&self.body)
+    }
+}
+
+/// Return the key of a `key: value` line starting a top-level entry.
+fn top_level_key(line: &str) -> Option<String> {
+    if line.starts_with([' ', '\t', '#', '-']) {
+        return None;
+    }
+    let (key, _) = line.split_once(':')?;
+    let key = key.trim();
+    let key = key
+        .strip_prefix(['"', '\''])
+        .and_then(|key| key.strip_suffix(['"', '\'']))
+        .unwrap_or(key);
+    (!key.is_empty()).then(|| key.to_owned())
+}
+
+/// Render the lines of the entry `key: value`. With the `previous` lines of
+/// the entry, a list keeps its flow or block style and indentation.
+fn render_entry(key: &str, value: &Value, previous: Option<&str>) -> Result<String, Error> {
+    let first_line = previous.and_then(|text| text.lines().next());
+    let previous_value = first_line.and_then(|line| line.split_once(':'));
+    let block = previous_value.is_some_and(|(_, value)| value.trim().is_empty());
+
+    if let Value::Sequence(items) = value
+        && !block
+        && let Some(items) = items.iter().map(flow_scalar).collect::<Option<Vec<_>>>()
+    {
+        let key = serde_yaml::to_string(&Value::from(key)).map_err(Error::Frontmatter)?;
+        return Ok(format!("{}: [{}]\n", key.trim_end(), items.join(", ")));
+    }
+
+    let mut mapping = Mapping::new();
+    mapping.insert(Value::from(key), value.clone());
+    let text = serde_yaml::to_string(&mapping).map_err(Error::Frontmatter)?;
+
+    // serde_yaml does not indent list items, use the indentation they had.
+    let indent = previous
+        .and_then(|text| text.lines().nth(1))
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .filter(|indent| !indent.is_empty());
+    match indent {
+        Some(indent) if block => Ok(text
+            .split_inclusive('\n')
+            .enumerate()
+            .map(|(i, line)| {
+                if i == 0 {
+                    line.to_owned()
+                } else {
+                    format!("{indent}{line}")
+                }
+            })
+            .collect()),
+        _ => Ok(text),
+    }
+}
+
+/// Return a scalar as written in a flow list, `None` for nested values.
+fn flow_scalar(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::Sequence(_) | Value::Mapping(_) | Value::Tagged(_) => return None,
+        value => serde_yaml::to_string(value).ok()?,
+    };
+    let text = text.trim_end();
+
+    let quoted = text.starts_with(['"', '\'']);
+    if !quoted && (text.contains([',', '[', ']', '{', '}', '\n']) || text.starts_with('|')) {
+        let Value::String(s) = value else {
+            return None;
+        };
+        let escaped = s
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        return Some(format!("\"{escaped}\""));
+    }
+    Some(text.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTE: &str = "---\n\
+        title: Old title # keep me\n\
+        # A comment\n\
+        Tags: [a, b]\n\
+        aliases:\n  - one\n  - two\n\
+        custom:\n  nested: true\n\
+        ---\n\
+        # Heading\n\nBody\n";
+
+    #[test]
+    fn test_round_trip() {
+        let fm = Frontmatter::parse(NOTE).unwrap();
+        assert_eq!(fm.to_string(), NOTE);
+        assert_eq!(
+            fm.keys().collect::<Vec<_>>(),
+            ["title", "Tags", "aliases", "custom"]
+        );
+        assert_eq!(fm.body(), "# Heading\n\nBody\n");
+
+        let fm = Frontmatter::parse("# No frontmatter\n").unwrap();
+        assert_eq!(fm.to_string(), "# No frontmatter\n");
+        assert!(fm.keys().next().is_none());
+    }
+
+    #[test]
+    fn test_get() {
+        let fm = Frontmatter::parse(NOTE).unwrap();
+        assert_eq!(fm.get_str("TITLE").unwrap(), "Old title");
+        assert_eq!(
+            fm.get("tags").unwrap(),
+            Value::Sequence(vec!["a".into(), "b".into()])
+        );
+        assert!(fm.get("custom").unwrap().is_mapping());
+        assert!(fm.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_set_keeps_style() {
+        let mut fm = Frontmatter::parse(NOTE).unwrap();
+        fm.set("title", "New: title").unwrap();
+        fm.set("tags", vec!["a", "c, d"]).unwrap();
+        fm.set("aliases", vec!["three"]).unwrap();
+        fm.set("status", "draft").unwrap();
+        fm.set("due", vec![1, 2]).unwrap();
+
+        assert_eq!(
+            fm.to_string(),
+            "---\n\
+            title: 'New: title'\n\
+            # A comment\n\
+            Tags: [a, \"c, d\"]\n\
+            aliases:\n  - three\n\
+            custom:\n  nested: true\n\
+            status: draft\n\
+            due: [1, 2]\n\
+            ---\n\
+            # Heading\n\nBody\n"
+        );
+        assert_eq!(fm.get_str("title").unwrap(), "New: title");
+        assert_eq!(fm.get("tags").unwrap().as_sequence().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut fm = Frontmatter::parse(NOTE).unwrap();
+        assert!(fm.remove("custom"));
+        assert!(fm.remove("Aliases"));
+        assert!(!fm.remove("missing"));
+        assert_eq!(
+            fm.to_string(),
+            "---\ntitle: Old title # keep me\n# A comment\nTags: [a, b]\n---\n# Heading\n\nBody\n"
+        );
+    }
+
+    #[test]
+    fn test_add_frontmatter() {
+        let mut fm = Frontmatter::parse("Body").unwrap();
+        fm.set("title", "Title").unwrap();
+        assert_eq!(fm.to_string(), "---\ntitle: Title\n---\nBody");
+
+        fm.remove("title");
+        assert_eq!(fm.to_string(), "Body");
+
+        let mut fm = Frontmatter::parse("---\ntitle: Title\n---\nBody").unwrap();
+        fm.remove("title");
+        assert_eq!(fm.to_string(), "Body");
+    }
+
+    #[test]
+    fn test_invalid_yaml() {
+        let err = Frontmatter::parse("---\ntitle: [unclosed\n---\n").unwrap_err();
+        assert!(matches!(err, Error::Frontmatter(_)));
+    }
+}
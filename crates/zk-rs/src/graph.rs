@@ -0,0 +1,77 @@
+//! Analysis of the links between notes.
+
+use crate::note::Note;
+
+/// A link to a note that does not exist, see
+/// [`Notebook::dead_links`](crate::Notebook::dead_links).
+#[derive(Clone, Debug)]
+pub struct DeadLink<'a> {
+    /// The note containing the link.
+    pub source: &'a Note,
+    /// The link target, as written.
+    pub target: String,
+    /// Line of the link in the note file, starting at 1.
+    pub line: usize,
+}
+
+/// Group the nodes `0..len` connected by `edges` in either direction.
+///
+/// Groups are ordered by their smallest node, nodes within a group ascending.
+pub(crate) fn components(
+    len: usize,
+    edges: impl IntoIterator<Item = (usize, usize)>,
+) -> Vec<Vec<usize>> {
+    let mut parents: Vec<usize> = (0..len).collect();
+
+    fn root(parents: &mut [usize], mut node: usize) -> usize {
+        while parents[node] != node {
+            // Halve the path on the way up to keep later lookups short.
+            parents[node] = parents[parents[node]];
+            node = parents[node];
+        }
+        node
+    }
+
+    for (a, b) in edges {
+        let (a, b) = (root(&mut parents, a), root(&mut parents, b));
+        if a != b {
+            parents[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of = vec![usize::MAX; len];
+    for node in 0..len {
+        let root = root(&mut parents, node);
+        if group_of[root] == usize::MAX {
+            group_of[root] = groups.len();
+            groups.push(Vec::new());
+        }
+        groups[group_of[root]].push(node);
+    }
+    groups
+}
+
+/// Return the line number, starting at 1, of the byte `offset` in `content`.
+pub(crate) fn line_at(content: &str, offset: usize) -> usize {
+    content[..offset].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_components() {
+        let groups = components(6, [(3, 1), (4, 3), (5, 5), (2, 0)]);
+        assert_eq!(groups, [vec![0, 2], vec![1, 3, 4], vec![5]]);
+        assert!(components(0, []).is_empty());
+    }
+
+    #[test]
+    fn test_line_at() {
+        let content = "a\nb [[x]]\n";
+        assert_eq!(line_at(content, 0), 1);
+        assert_eq!(line_at(content, 4), 2);
+    }
+}
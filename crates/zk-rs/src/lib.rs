@@ -1,20 +1,90 @@
+mod attachment;
+mod cache;
+mod config;
+mod create;
+#[cfg(feature = "sqlite")]
+mod db;
 mod error;
+mod event;
+mod frontmatter;
+mod graph;
+mod link;
+#[cfg(feature = "lsp")]
+mod lsp;
 mod note;
 mod parse;
-
+mod query;
+mod related;
+mod search;
+mod storage;
+mod tag;
+mod template;
+#[cfg(feature = "watch")]
+mod watch;
+
+pub use attachment::Attachment;
+pub use config::{
+    DiagnosticLevel, FormatConfig, GroupConfig, IdCase, IdCharset, LspCompletion, LspConfig,
+    LspDiagnostics, MarkdownConfig, NoteConfig, NoteOverrides, NotebookConfig,
+};
+pub use create::NewNote;
 pub use error::Error;
+pub use event::{FileChange, NoteChanges, NotebookEvent};
+pub use frontmatter::Frontmatter;
+pub use graph::DeadLink;
+pub use link::Resolution;
+#[cfg(feature = "lsp")]
+pub use lsp::LanguageServer;
 pub use note::Note;
+pub use query::{NoteQuery, Sort, SortField};
+pub use related::Related;
+pub use search::{SearchHit, Snippet};
+pub use storage::{DirEntry, FileMetadata, LocalStorage, MemoryStorage, NotebookStorage};
+#[cfg(feature = "watch")]
+pub use watch::NotebookWatcher;
+
+/// A YAML value, as read from frontmatter.
+pub use serde_yaml::Value as YamlValue;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+use rand::seq::SliceRandom;
+use rayon::prelude::*;
 
-use std::collections::{HashMap, HashSet};
-use std::fs;
-use std::path::{Path, PathBuf};
+/// How often a new note's filename is re-rendered before giving up on collisions.
+const MAX_CREATE_ATTEMPTS: usize = 50;
 
 pub struct Notebook {
     root: PathBuf,
+    storage: Box<dyn NotebookStorage>,
+    config: NotebookConfig,
     notes: Vec<Note>,
-    stems: HashMap<String, usize>,
-    tags: HashMap<String, Vec<String>>,
-    backlinks: HashMap<String, Vec<String>>,
+    /// Notebook-relative path -> note. Paths identify notes uniquely.
+    paths: HashMap<PathBuf, usize>,
+    /// Filename stem -> notes. Notes in different directories may share a stem.
+    stems: HashMap<String, Vec<usize>>,
+    tags: HashMap<String, Vec<usize>>,
+    /// Note -> notes linking to it, sorted.
+    backlinks: HashMap<usize, Vec<usize>>,
+    /// Note -> notes its links resolve to, the reverse of `backlinks`.
+    targets: HashMap<usize, Vec<usize>>,
+    links: link::LinkIndex,
+    search: search::SearchIndex,
+    /// Notebook subdirectory attachments are found in, see
+    /// [`Notebook::set_attachments_dir`].
+    attachments_dir: Option<PathBuf>,
+    /// Notebook-relative path -> file that is not a note.
+    attachments: BTreeMap<PathBuf, Attachment>,
+    /// Attachment path -> notes linking to it, whether it exists or not.
+    attachment_refs: HashMap<PathBuf, Vec<usize>>,
+    /// File name -> notes linking to an attachment by its name alone.
+    attachment_names: HashMap<String, Vec<usize>>,
+    /// zk's `.zk/notebook.db`, kept in sync if loaded with
+    /// [`Notebook::load_with_index`].
+    #[cfg(feature = "sqlite")]
+    db: Option<db::Database>,
 }
 
 impl Notebook {
@@ -22,551 +92,2786 @@ impl Notebook {
     /// Returns `Error::NotANotebook` if `root/.zk/` does not exist.
     pub fn load(root: impl Into<PathBuf>) -> Result<Self, Error> {
         let root = root.into();
-        let zk_dir = root.join(".zk");
-        if !zk_dir.is_dir() {
-            return Err(Error::NotANotebook(root));
-        }
+        let storage = LocalStorage::new(&root);
+        Self::load_from(root, storage)
+    }
 
-        let md_files = discover_md_files(&root)?;
-        let mut notes = Vec::new();
-        let mut stems = HashMap::new();
-        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    /// Load all notes from `storage`. `root` is only used to build the
+    /// absolute paths of notes, see [`Note::abs_path`].
+    pub fn load_from(
+        root: impl Into<PathBuf>,
+        storage: impl NotebookStorage + 'static,
+    ) -> Result<Self, Error> {
+        let root = root.into();
+        let config = load_config(&root, &storage)?;
 
-        for abs_path in md_files {
-            let rel_path = abs_path
-                .strip_prefix(&root)
-                .unwrap_or(&abs_path)
-                .to_path_buf();
+        let (files, _) = discover_files(&storage, &config, Path::new(""))?;
+        let notes = files
+            .into_par_iter()
+            .map(|rel_path| load_single_note(&storage, &root, rel_path, &config))
+            .collect::<Result<Vec<_>, _>>()?;
 
-            let note = load_single_note(abs_path, rel_path)?;
-            let idx = notes.len();
-            let stem = note.filename_stem().to_owned();
-            for tag in note.tags() {
-                tags.entry(tag.to_lowercase())
-                    .or_default()
-                    .push(stem.clone());
-            }
-            stems.insert(stem, idx);
-            notes.push(note);
+        Ok(Self::new(root, Box::new(storage), config, notes))
+    }
+
+    /// Load all notes like [`Notebook::load`], taking unchanged notes from
+    /// the parse cache in `.zk/zk-rs.cache` and parsing only the others.
+    ///
+    /// The cache is rewritten if any note was parsed or deleted. Failing to
+    /// write it is not an error, it only speeds up the next load.
+    pub fn load_cached(root: impl Into<PathBuf>) -> Result<Self, Error> {
+        let root = root.into();
+        let storage = LocalStorage::new(&root);
+        let config = load_config(&root, &storage)?;
+        let mut cache = cache::Cache::read(&storage, &config.format.markdown);
+
+        let (files, _) = discover_files(&storage, &config, Path::new(""))?;
+        let files = files
+            .into_iter()
+            .map(|rel_path| {
+                let cached = cache.take(&rel_path);
+                (rel_path, cached)
+            })
+            .collect::<Vec<_>>();
+
+        let loaded = files
+            .into_par_iter()
+            .map(|(rel_path, cached)| {
+                let abs_path = root.join(&rel_path);
+                cache::load_note(&storage, abs_path, rel_path, cached, &config)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let changed = !cache.is_empty() || loaded.iter().any(|(_, _, changed)| *changed);
+        let (stamps, notes): (Vec<_>, Vec<_>) = loaded
+            .into_iter()
+            .map(|(stamp, note, _)| (stamp, note))
+            .unzip();
+        let notebook = Self::new(root, Box::new(storage), config, notes);
+
+        if changed {
+            let markdown = &notebook.config.format.markdown;
+            let entries = stamps.iter().zip(&notebook.notes);
+            let _ = cache::write(notebook.storage.as_ref(), markdown, entries);
         }
 
-        let backlinks = build_backlinks(&notes);
+        Ok(notebook)
+    }
+
+    /// Load all notes like [`Notebook::load`], taking notes whose checksum
+    /// and modification time are unchanged from zk's `.zk/notebook.db`
+    /// instead of parsing them.
+    ///
+    /// Notes that were parsed, added or deleted since zk last indexed the
+    /// notebook are written to the index right away, and later on every
+    /// [`reload`](Notebook::reload), [`create`](Notebook::create) and
+    /// [`remove`](Notebook::remove). The database must exist, it is created
+    /// and migrated by zk.
+    #[cfg(feature = "sqlite")]
+    pub fn load_with_index(root: impl Into<PathBuf>) -> Result<Self, Error> {
+        let root = root.into();
+        let storage = LocalStorage::new(&root);
+        let config = load_config(&root, &storage)?;
+        let db = db::Database::open(&root.join(".zk").join("notebook.db"))?;
+        let mut indexed = db.notes()?;
+
+        let (files, _) = discover_files(&storage, &config, Path::new(""))?;
+        let files = files
+            .into_iter()
+            .map(|rel_path| {
+                let row = indexed.remove(&rel_path);
+                (rel_path, row)
+            })
+            .collect::<Vec<_>>();
+
+        let loaded = files
+            .into_par_iter()
+            .map(|(rel_path, row)| {
+                let abs_path = root.join(&rel_path);
+                let content = storage.read_to_string(&rel_path)?;
+                let meta = storage.metadata(&rel_path)?;
+                let markdown = &config.format.markdown;
+
+                match row {
+                    Some(row) if row.is_current(&parse::checksum(&content), &meta) => row
+                        .into_note(&content, rel_path, abs_path, markdown)
+                        .map(|note| (note, false)),
+                    _ => parse::parse_note(&content, rel_path, abs_path, &meta, markdown)
+                        .map(|note| (note, true)),
+                }
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let parsed = loaded
+            .iter()
+            .filter(|(_, parsed)| *parsed)
+            .map(|(note, _)| note.path().to_path_buf())
+            .collect::<Vec<_>>();
+        let notes = loaded.into_iter().map(|(note, _)| note).collect();
+
+        // Rows left over belong to files deleted while zk-rs was not running.
+        let deleted = indexed.into_keys().collect::<Vec<_>>();
+        let mut notebook = Self::new(root, Box::new(storage), config, notes);
+        notebook.db = Some(db);
+        notebook.sync_index(&parsed, &deleted)?;
+
+        Ok(notebook)
+    }
 
-        Ok(Notebook {
+    fn new(
+        root: PathBuf,
+        storage: Box<dyn NotebookStorage>,
+        config: NotebookConfig,
+        notes: Vec<Note>,
+    ) -> Self {
+        let mut notebook = Notebook {
             root,
+            storage,
+            config,
             notes,
-            stems,
-            tags,
-            backlinks,
-        })
+            paths: HashMap::new(),
+            stems: HashMap::new(),
+            tags: HashMap::new(),
+            backlinks: HashMap::new(),
+            targets: HashMap::new(),
+            links: link::LinkIndex::default(),
+            search: search::SearchIndex::default(),
+            attachments_dir: None,
+            attachments: BTreeMap::new(),
+            attachment_refs: HashMap::new(),
+            attachment_names: HashMap::new(),
+            #[cfg(feature = "sqlite")]
+            db: None,
+        };
+        for note in &notebook.notes {
+            notebook.search.insert(note);
+        }
+        notebook.reindex();
+        notebook
     }
 
-    /// Reload a single note from disk by its stem.
-    /// If the stem is unknown, the notebook is re-walked to find new files.
-    pub fn reload(&mut self, stem: &str) -> Result<(), Error> {
-        if let Some(&idx) = self.stems.get(stem) {
-            // Re-read existing note
-            let abs_path = self.notes[idx].abs_path().to_path_buf();
-            let rel_path = abs_path
-                .strip_prefix(&self.root)
-                .unwrap_or(&abs_path)
-                .to_path_buf();
-
-            let new_note = load_single_note(abs_path, rel_path)?;
-
-            // Remove old tags
-            let old_tags: Vec<String> = self.notes[idx].tags().to_vec();
-            for tag in &old_tags {
-                if let Some(stems) = self.tags.get_mut(&tag.to_lowercase()) {
-                    stems.retain(|s| s != stem);
-                    if stems.is_empty() {
-                        self.tags.remove(&tag.to_lowercase());
-                    }
+    /// Reload a single note from disk. `path` is either absolute or relative
+    /// to the notebook root. Notes not known yet are added.
+    pub fn reload(&mut self, path: impl AsRef<Path>) -> Result<NotebookEvent, Error> {
+        let rel_path = self.relative(path.as_ref());
+        let note = load_single_note(
+            self.storage.as_ref(),
+            &self.root,
+            rel_path.clone(),
+            &self.config,
+        )?;
+
+        let event = match self.paths.get(note.path()) {
+            Some(&idx) => {
+                let changes = NoteChanges::between(&self.notes[idx], &note);
+                self.search.insert(&note);
+                self.unindex(idx);
+                self.notes[idx] = note;
+                self.index(idx);
+                NotebookEvent::Modified {
+                    path: rel_path.clone(),
+                    changes,
                 }
             }
-
-            // Add new tags
-            for tag in new_note.tags() {
-                self.tags
-                    .entry(tag.to_lowercase())
-                    .or_default()
-                    .push(stem.to_owned());
+            None => {
+                self.insert(note);
+                NotebookEvent::Created {
+                    path: rel_path.clone(),
+                }
             }
+        };
+
+        self.sync_index(&[rel_path], &[])?;
+        Ok(event)
+    }
 
-            // Remove old backlinks contributed by this note, then re-add from new version
-            remove_outgoing_backlinks(&mut self.backlinks, stem);
-            let new_outgoing: Vec<String> = new_note.outgoing_links().to_vec();
+    /// Move the note at `from` to the file `to` it was renamed to on disk,
+    /// both absolute or relative to the notebook root, and read it again.
+    /// Without a note at `from` this is a [`reload`](Self::reload) of `to`.
+    pub fn reload_renamed(
+        &mut self,
+        from: impl AsRef<Path>,
+        to: impl AsRef<Path>,
+    ) -> Result<NotebookEvent, Error> {
+        let from = self.relative(from.as_ref());
+        let to = self.relative(to.as_ref());
+        if !self.paths.contains_key(&from) {
+            return self.reload(&to);
+        }
 
-            self.notes[idx] = new_note;
+        let note = load_single_note(self.storage.as_ref(), &self.root, to.clone(), &self.config)?;
+        // A note replaced by the rename is gone.
+        if self.paths.contains_key(&to) {
+            self.remove(&to)?;
+        }
+        let idx = self.paths[&from];
+        self.search.remove(&from);
+        self.search.insert(&note);
+        self.unindex(idx);
+        self.notes[idx] = note;
+        self.index(idx);
+
+        self.sync_index(std::slice::from_ref(&to), std::slice::from_ref(&from))?;
+        Ok(NotebookEvent::Renamed { from, to })
+    }
 
-            let from = stem.to_owned();
-            for target in new_outgoing {
-                self.backlinks.entry(target).or_default().push(from.clone());
+    /// Bring the notebook up to date with a `change` of its files, e.g. from a
+    /// `NotebookWatcher`, and return how its notes changed. Changes of other
+    /// files only update the [attachments](Self::attachments).
+    pub fn apply(&mut self, change: &FileChange) -> Result<Option<NotebookEvent>, Error> {
+        self.apply_to_attachments(change)?;
+
+        match change {
+            FileChange::Written(path) if self.is_note(path) => match self.reload(path) {
+                // Deleted again before the change was applied.
+                Err(Error::Io(err)) if err.kind() == ErrorKind::NotFound => self.remove(path),
+                result => result.map(Some),
+            },
+            FileChange::Removed(path) if self.is_note(path) => self.remove(path),
+            FileChange::Renamed { from, to } if self.is_note(to) => {
+                self.reload_renamed(from, to).map(Some)
             }
-        } else {
-            // Walk to find the new file
-            let md_files = discover_md_files(&self.root)?;
-            for abs_path in md_files {
-                let file_stem = abs_path
-                    .file_stem()
-                    .map(|s| s.to_string_lossy().into_owned())
-                    .unwrap_or_default();
-                if file_stem == stem {
-                    let rel_path = abs_path
-                        .strip_prefix(&self.root)
-                        .unwrap_or(&abs_path)
-                        .to_path_buf();
-
-                    let note = load_single_note(abs_path, rel_path)?;
-                    let idx = self.notes.len();
-                    for tag in note.tags() {
-                        self.tags
-                            .entry(tag.to_lowercase())
-                            .or_default()
-                            .push(stem.to_owned());
-                    }
-                    self.stems.insert(stem.to_owned(), idx);
-                    self.notes.push(note);
-
-                    // Add outgoing links for the new note
-                    let outgoing: Vec<String> = self.notes[idx].outgoing_links().to_vec();
-                    let from = stem.to_owned();
-                    for target in outgoing {
-                        self.backlinks.entry(target).or_default().push(from.clone());
-                    }
+            FileChange::Renamed { from, .. } if self.is_note(from) => self.remove(from),
+            _ => Ok(None),
+        }
+    }
 
-                    break;
-                }
+    /// Turn a removed note and a new note with the same content in `changes`
+    /// into a rename, as tools moving files by copying and deleting them
+    /// report them. Call this before applying the changes.
+    pub fn match_renames(&self, changes: Vec<FileChange>) -> Vec<FileChange> {
+        // Index of the removal for each matched write.
+        let mut copies: HashMap<usize, usize> = HashMap::new();
+        for (i, change) in changes.iter().enumerate() {
+            let FileChange::Removed(from) = change else {
+                continue;
+            };
+            let Some(&idx) = self.paths.get(&self.relative(from)) else {
+                continue;
+            };
+            let content = self.notes[idx].raw_content();
+
+            let copy = changes
+                .iter()
+                .enumerate()
+                .position(|(j, change)| match change {
+                    FileChange::Written(to) if !copies.contains_key(&j) && self.is_note(to) => {
+                        let to = self.relative(to);
+                        !self.paths.contains_key(&to)
+                            && self
+                                .storage
+                                .read_to_string(&to)
+                                .is_ok_and(|copied| copied == content)
+                    }
+                    _ => false,
+                });
+            if let Some(j) = copy {
+                copies.insert(j, i);
             }
         }
 
-        Ok(())
+        let mut renamed_to: HashMap<usize, PathBuf> = HashMap::new();
+        let mut changes: Vec<Option<FileChange>> = changes.into_iter().map(Some).collect();
+        for (&j, &i) in &copies {
+            if let Some(FileChange::Written(to)) = changes[j].take() {
+                renamed_to.insert(i, to);
+            }
+        }
+        changes
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, change)| match (change?, renamed_to.remove(&i)) {
+                (FileChange::Removed(from), Some(to)) => Some(FileChange::Renamed { from, to }),
+                (change, _) => Some(change),
+            })
+            .collect()
     }
 
-    /// Remove the note with the given stem from the notebook.
-    /// No-op if the stem is not known.
-    pub fn remove(&mut self, stem: &str) {
-        let Some(&idx) = self.stems.get(stem) else {
-            return;
+    /// Add, update or remove the attachments changed by `change`.
+    fn apply_to_attachments(&mut self, change: &FileChange) -> Result<(), Error> {
+        let (removed, written) = match change {
+            FileChange::Written(path) => (None, Some(path)),
+            FileChange::Removed(path) => (Some(path), None),
+            FileChange::Renamed { from, to } => (Some(from), Some(to)),
         };
 
-        // Clean up tag index
-        let old_tags: Vec<String> = self.notes[idx].tags().to_vec();
-        for tag in &old_tags {
-            if let Some(stems) = self.tags.get_mut(&tag.to_lowercase()) {
-                stems.retain(|s| s != stem);
-                if stems.is_empty() {
-                    self.tags.remove(&tag.to_lowercase());
+        if let Some(path) = removed {
+            self.attachments.remove(&self.relative(path));
+        }
+        if let Some(path) = written.filter(|path| self.is_attachment(path)) {
+            let path = self.relative(path);
+            match self.storage.metadata(&path) {
+                Ok(meta) if !meta.is_dir => {
+                    let attachment = Attachment::new(&self.root, path.clone(), &meta);
+                    self.attachments.insert(path, attachment);
+                }
+                Ok(_) => {}
+                // Deleted again before the change was applied.
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    self.attachments.remove(&path);
                 }
+                Err(err) => return Err(err.into()),
             }
         }
-
-        // Clean up backlinks: remove outgoing links contributed by this note.
-        // Keep the target entry (backlinks[stem]) so that if the note is
-        // recreated (common with editors that delete+write), incoming
-        // backlinks from other notes are preserved.
-        remove_outgoing_backlinks(&mut self.backlinks, stem);
-
-        self.stems.remove(stem);
-
-        // swap_remove is O(1) but moves the last element to `idx`.
-        // Update the stems index for whichever note lands there.
-        self.notes.swap_remove(idx);
-        if idx < self.notes.len() {
-            let swapped_stem = self.notes[idx].filename_stem().to_owned();
-            self.stems.insert(swapped_stem, idx);
-        }
+        Ok(())
     }
 
-    /// Look up a note by its filename stem.
-    pub fn note(&self, stem: &str) -> Option<&Note> {
-        self.stems.get(stem).map(|&idx| &self.notes[idx])
+    /// Return `true` if `path` is a note: a Markdown file outside hidden
+    /// directories and not excluded by the config.
+    fn is_note(&self, path: &Path) -> bool {
+        let path = self.relative(path);
+        path.extension().is_some_and(|ext| ext == "md")
+            && !is_hidden(&path)
+            && !self.is_excluded(&path)
     }
 
-    /// Return all notes, optionally filtered by tag.
-    pub fn all_notes(&self, with_tag: Option<&str>) -> impl Iterator<Item = &Note> {
-        let allowed: Option<HashSet<&str>> = with_tag.map(|tag| {
-            self.tags
-                .get(&tag.to_lowercase())
-                .map(|v| v.iter().map(String::as_str).collect())
-                .unwrap_or_default()
-        });
-        self.notes.iter().filter(move |note| {
-            allowed
-                .as_ref()
-                .is_none_or(|s| s.contains(note.filename_stem()))
-        })
+    /// Return `true` if `path` may be an attachment: a file besides notes in
+    /// the attachments directory, outside hidden directories.
+    fn is_attachment(&self, path: &Path) -> bool {
+        let path = self.relative(path);
+        self.attachments_dir
+            .as_deref()
+            .is_some_and(|dir| path.starts_with(dir) && path != dir)
+            && path.extension().is_none_or(|ext| ext != "md")
+            && !is_hidden(&path)
+            && !self.is_excluded(&path)
     }
 
-    /// Return all unique tags across all notes.
-    pub fn all_tags(&self) -> impl Iterator<Item = &str> {
-        self.tags.keys().map(String::as_str)
+    /// Return `true` if the notebook-relative `path` or one of its parent
+    /// directories is excluded by the config.
+    fn is_excluded(&self, path: &Path) -> bool {
+        path.ancestors()
+            .take_while(|dir| !dir.as_os_str().is_empty())
+            .any(|dir| self.config.is_excluded(dir))
     }
 
-    /// Return notes that have ALL of the given tags.
-    pub fn notes_with_tags<'a>(&'a self, tags: &'a [&str]) -> impl Iterator<Item = &'a Note> {
-        self.notes
-            .iter()
-            .filter(move |note| tags.is_empty() || tags.iter().all(|tag| note.has(tag)))
+    /// Turn `path` into a notebook-relative path.
+    fn relative(&self, path: &Path) -> PathBuf {
+        relative_to(&self.root, path)
     }
 
-    /// Return notes that wiki-link to `stem`.
-    pub fn backlinks(&self, stem: &str) -> Vec<&Note> {
-        self.backlinks
-            .get(stem)
-            .map(|sources| sources.iter().filter_map(|s| self.note(s)).collect())
-            .unwrap_or_default()
-    }
+    /// Create a new note on disk following the notebook's `[note]` settings
+    /// and add it to the index.
+    ///
+    /// The filename is rendered from the configured `filename` template. If the
+    /// resulting file already exists, a new ID is generated, or a numeric
+    /// suffix is appended for templates that do not depend on the ID.
+    pub fn create(&mut self, new: NewNote) -> Result<&Note, Error> {
+        let group = new.group.clone().or_else(|| {
+            new.dir
+                .as_deref()
+                .and_then(|dir| self.config.group_for(dir))
+                .map(str::to_owned)
+        });
 
-    /// Fuzzy search note titles, returning matches ranked by score.
-    pub fn search_titles(
-        &self,
-        query: &str,
-        with_tag: Option<&str>,
-    ) -> impl Iterator<Item = &Note> {
-        if query.is_empty() {
-            return self.all_notes(with_tag).collect::<Vec<_>>().into_iter();
-        }
+        let dir = new
+            .dir
+            .clone()
+            .or_else(|| group.as_deref().map(|g| self.config.group_dir(g)))
+            .unwrap_or_default();
+
+        let note_config = self.config.note_config(group.as_deref());
+        let extra = self.config.extra(group.as_deref());
+        let env: HashMap<String, String> = std::env::vars().collect();
+
+        let title = new
+            .title
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .unwrap_or(&note_config.default_title);
+
+        // Templates requested by the caller must stay inside `.zk/templates/`,
+        // only the notebook config may point elsewhere.
+        let template_name = match &new.template {
+            Some(name) if !is_relative_subpath(Path::new(name)) => {
+                return Err(Error::InvalidPath(PathBuf::from(name)));
+            }
+            Some(name) => Some(name.as_str()),
+            None => note_config.template.as_deref(),
+        };
+        let template = template_name
+            .map(|name| self.storage.read_to_string(&self.template_path(name)))
+            .transpose()?;
+
+        let mut first_stem = None;
+
+        for attempt in 1..=MAX_CREATE_ATTEMPTS {
+            let id = create::generate_id(&note_config);
+            let context = create::FilenameContext {
+                id: id.clone(),
+                title,
+                content: &new.content,
+                dir: dir.to_string_lossy().into_owned(),
+                now: jiff::Timestamp::now().to_string(),
+                extra: &extra,
+                env: &env,
+            };
+
+            let rendered = template::render(&note_config.filename, &context, "filename")?;
+            let mut stem = match rendered.trim() {
+                "" => id.clone(),
+                rendered => rendered.to_owned(),
+            };
+
+            // Templates without an ID render the same name on every attempt.
+            if attempt > 1 && first_stem.as_ref() == Some(&stem) {
+                stem = format!("{stem}-{attempt}");
+            }
+            first_stem.get_or_insert_with(|| stem.clone());
 
-        let mut matcher = nucleo::Matcher::new(nucleo::Config::DEFAULT);
-        let needle = nucleo::pattern::Pattern::parse(
-            query,
-            nucleo::pattern::CaseMatching::Ignore,
-            nucleo::pattern::Normalization::Smart,
-        );
+            let rel_path = dir.join(format!("{stem}.{}", note_config.extension));
+            if !is_relative_subpath(&rel_path) {
+                return Err(Error::InvalidPath(rel_path));
+            }
 
-        let mut scored: Vec<(u32, &Note)> = self
-            .all_notes(with_tag)
-            .filter_map(|note| {
-                let mut buf = Vec::new();
-                let haystack = nucleo::Utf32Str::new(note.title(), &mut buf);
-                let score = needle.score(haystack, &mut matcher)?;
-                Some((score, note))
-            })
-            .collect();
+            let file_stem = rel_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            // Avoid paths that exist and stems that would make links ambiguous.
+            if self.paths.contains_key(&rel_path) || self.stems.contains_key(&file_stem) {
+                continue;
+            }
 
-        scored.sort_by_key(|b| std::cmp::Reverse(b.0));
+            let content = match (&template, template_name) {
+                (Some(template), Some(name)) => {
+                    let context = create::BodyContext {
+                        id: &id,
+                        title,
+                        content: &new.content,
+                        dir: dir.to_string_lossy().into_owned(),
+                        filename: rel_path
+                            .file_name()
+                            .map(|s| s.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                        filename_stem: &file_stem,
+                        now: jiff::Timestamp::now().to_string(),
+                        extra: &extra,
+                        env: &env,
+                    };
+                    template::render(template, &context, name)?
+                }
+                _ => new.content.clone(),
+            };
 
-        scored
-            .into_iter()
-            .map(|(_, note)| note)
-            .collect::<Vec<_>>()
-            .into_iter()
-    }
-}
+            match self.storage.create(&rel_path, content.as_bytes()) {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err.into()),
+            }
 
-/// Build a backlinks index: target_stem -> [source_stems].
-fn build_backlinks(notes: &[Note]) -> HashMap<String, Vec<String>> {
-    let mut index: HashMap<String, Vec<String>> = HashMap::new();
-    for note in notes {
-        let from = note.filename_stem().to_owned();
-        for target in note.outgoing_links() {
-            index.entry(target.clone()).or_default().push(from.clone());
+            let note = load_single_note(
+                self.storage.as_ref(),
+                &self.root,
+                rel_path.clone(),
+                &self.config,
+            )?;
+            let idx = self.insert(note);
+            self.sync_index(&[rel_path], &[])?;
+            return Ok(&self.notes[idx]);
         }
-    }
-    index
-}
 
-/// Remove all backlink entries where `from_stem` is a source.
-fn remove_outgoing_backlinks(backlinks: &mut HashMap<String, Vec<String>>, from_stem: &str) {
-    for v in backlinks.values_mut() {
-        v.retain(|s| s != from_stem);
+        Err(Error::NoteExists(self.root.join(dir)))
     }
-    backlinks.retain(|_, v| !v.is_empty());
-}
 
-/// Read a single markdown file and parse it into a Note.
-fn load_single_note(abs_path: PathBuf, rel_path: PathBuf) -> Result<Note, Error> {
-    let content = fs::read_to_string(&abs_path)?;
-    let meta = fs::metadata(&abs_path)?;
-    parse::parse_note(&content, rel_path, abs_path, &meta)
-}
+    /// Rename the note `id` to `to` and rewrite the links pointing to it.
+    ///
+    /// `to` is either a new filename stem, keeping the note in its directory,
+    /// or a notebook-relative path with or without the `.md` extension. Links
+    /// keep their label, `#fragment` and style (stem, relative or root path);
+    /// the note's own relative links are adjusted if it changes directory.
+    pub fn rename(&mut self, id: &str, to: &str) -> Result<&Note, Error> {
+        let old_path = self
+            .note(id)
+            .ok_or_else(|| Error::NoteNotFound(id.to_owned()))?
+            .path()
+            .to_path_buf();
+        let old_idx = self.paths[&old_path];
+
+        let to = to.trim();
+        let to = to.strip_suffix(".md").unwrap_or(to);
+        let new_path = if to.contains('/') {
+            PathBuf::from(format!("{to}.md"))
+        } else {
+            old_path.with_file_name(format!("{to}.md"))
+        };
+        if to.is_empty() || !is_relative_subpath(&new_path) {
+            return Err(Error::InvalidPath(new_path));
+        }
+        if new_path == old_path {
+            return Ok(&self.notes[old_idx]);
+        }
+        if self.paths.contains_key(&new_path) || self.storage.metadata(&new_path).is_ok() {
+            return Err(Error::NoteExists(self.root.join(new_path)));
+        }
 
-/// Recursively discover all `.md` files under `root`, skipping hidden directories.
-fn discover_md_files(root: &Path) -> Result<Vec<PathBuf>, Error> {
-    let mut files = Vec::new();
-    walk_dir(root, &mut files)?;
-    files.sort();
-    Ok(files)
-}
+        // Remember where the affected links point to, they may not resolve
+        // anymore once the note has moved.
+        let mut sources = self.backlinks.get(&old_idx).cloned().unwrap_or_default();
+        if !sources.contains(&old_idx) {
+            sources.push(old_idx);
+        }
 
-fn walk_dir(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
-    let entries = fs::read_dir(dir)?;
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
-        let file_name = entry.file_name();
-        let name = file_name.to_string_lossy();
+        let mut rewrites = Vec::new();
+        for idx in sources {
+            let source = self.notes[idx].path();
+            let content = self.storage.read_to_string(source)?;
+            let body_start = parse::extract_frontmatter(&content).1;
+
+            let mut links = Vec::new();
+            for span in parse::link_spans(&content[body_start..]) {
+                let span = span.start + body_start..span.end + body_start;
+                let [target] = self.links.resolve(&content[span.clone()], source) else {
+                    continue;
+                };
+                if *target == old_idx || idx == old_idx {
+                    let old_target = self.notes[*target].path().to_path_buf();
+                    links.push((span, old_target));
+                }
+            }
 
-        // Skip hidden dirs/files
-        if name.starts_with('.') {
-            continue;
+            if !links.is_empty() {
+                rewrites.push((source.to_path_buf(), content, links));
+            }
         }
 
-        if path.is_dir() {
-            walk_dir(&path, files)?;
-        } else if path.extension().is_some_and(|ext| ext == "md") {
-            files.push(path);
+        self.storage.rename(&old_path, &new_path)?;
+        self.reload_renamed(&old_path, &new_path)?;
+
+        let moved = |path: PathBuf| {
+            if path == old_path {
+                new_path.clone()
+            } else {
+                path
+            }
+        };
+        for (old_source, mut content, mut links) in rewrites {
+            let source = moved(old_source.clone());
+            let mut changed = false;
+
+            // Replace from the end, so earlier spans stay valid.
+            links.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+            for (span, old_target) in links {
+                let target = moved(old_target.clone());
+                let link = LinkRewrite {
+                    text: &content[span.clone()],
+                    wiki: content[..span.start].trim_end().ends_with("[["),
+                    old_source: &old_source,
+                    old_target: &old_target,
+                };
+                if let Some(text) = self.rewrite_link(link, &source, &target) {
+                    content.replace_range(span, &text);
+                    changed = true;
+                }
+            }
+
+            if changed {
+                self.save(&source, &content)?;
+            }
         }
+
+        Ok(&self.notes[self.paths[&new_path]])
     }
-    Ok(())
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use tempfile::TempDir;
+    /// Return the new text of a link in the note at `source` that has to
+    /// point to the note at `target`, or `None` if it still does.
+    ///
+    /// The link is written in its old style if that resolves unambiguously,
+    /// falling back to a path from the notebook root.
+    fn rewrite_link(&self, link: LinkRewrite, source: &Path, target: &Path) -> Option<String> {
+        let resolves = |text: &str| {
+            let found = self.links.resolve(text, source);
+            matches!(found, [idx] if self.notes[*idx].path() == target)
+        };
 
-    fn setup_notebook() -> TempDir {
-        let dir = TempDir::new().unwrap();
-        // Create .zk directory
-        fs::create_dir(dir.path().join(".zk")).unwrap();
+        let decoded = link::percent_decode(link.text);
+        let decoded = decoded.trim();
+        let without_extension = |path: &Path| path.with_extension("");
+        let old_target = without_extension(link.old_target);
+        let old_dir = link.old_source.parent().unwrap_or(Path::new(""));
+        let was_relative = link::normalize(&old_dir.join(decoded))
+            .is_some_and(|path| without_extension(&path) == old_target);
+        let was_path = link::normalize(Path::new(decoded))
+            .is_some_and(|path| without_extension(&path) == old_target);
+        let was_stem = !decoded.contains('/')
+            && old_target.file_name() == Some(decoded.trim_end_matches(".md").as_ref());
+
+        // Links by title or alias are left alone as long as they resolve.
+        if !(was_relative || was_path || was_stem) && resolves(link.text) {
+            return None;
+        }
 
-        // Create a note with frontmatter
-        fs::write(
-            dir.path().join("note1.md"),
-            "---\ntitle: First Note\ntags: [rust, testing]\ndate: 2024-06-15\n---\n# First Note\n\nThis is the body.\n\nSecond paragraph.",
-        )
-        .unwrap();
+        let keep_extension = decoded.ends_with(".md");
+        let format = |path: &Path| {
+            let path = if keep_extension {
+                path.to_path_buf()
+            } else {
+                without_extension(path)
+            };
+            let path = path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if link.text.contains('%') {
+                parse::encode_path(&path)
+            } else {
+                path
+            }
+        };
 
-        // Create a note with just a heading
-        fs::write(
-            dir.path().join("note2.md"),
-            "# Second Note\n\nAnother body here.",
-        )
-        .unwrap();
+        let source_dir = source.parent().unwrap_or(Path::new(""));
+        let relative = format(&link::relative_path(source_dir, target));
+        let from_root = format(target);
+        let rooted = format!("/{from_root}");
+
+        let candidates = if decoded.starts_with('/') {
+            vec![rooted]
+        } else if link.wiki && !decoded.contains('/') {
+            let stem = format(Path::new(target.file_name()?));
+            vec![stem, relative, from_root, rooted]
+        } else if was_relative {
+            vec![relative, from_root, rooted]
+        } else {
+            vec![from_root, relative, rooted]
+        };
 
-        // Create a subdirectory with a note
-        fs::create_dir(dir.path().join("subdir")).unwrap();
-        fs::write(
-            dir.path().join("subdir").join("note3.md"),
-            "---\ntitle: Sub Note\ntags: [rust]\n---\nContent of sub note.",
-        )
-        .unwrap();
+        let text = candidates.into_iter().find(|text| resolves(text))?;
+        (text != link.text).then_some(text)
+    }
 
-        // Create a hidden directory that should be skipped
-        fs::create_dir(dir.path().join(".hidden")).unwrap();
-        fs::write(
-            dir.path().join(".hidden").join("secret.md"),
-            "# Secret\nShould not appear.",
-        )
-        .unwrap();
+    /// Remove the note at `path`, absolute or relative to the notebook root.
+    /// No-op returning `None` if the path is not known.
+    pub fn remove(&mut self, path: impl AsRef<Path>) -> Result<Option<NotebookEvent>, Error> {
+        let rel_path = self.relative(path.as_ref());
+        let Some(&idx) = self.paths.get(&rel_path) else {
+            return Ok(None);
+        };
 
-        dir
+        // swap_remove is O(1) but moves the last element to `idx`, so that
+        // note is indexed again at its new position. Links are stored
+        // unresolved, so incoming links from other notes resolve again if the
+        // note is recreated (common with editors that delete+write).
+        let last = self.notes.len() - 1;
+        self.unindex(idx);
+        if idx != last {
+            self.unindex(last);
+        }
+        self.notes.swap_remove(idx);
+        if idx != last {
+            self.index(idx);
+        }
+        self.search.remove(&rel_path);
+        self.sync_index(&[], std::slice::from_ref(&rel_path))?;
+        Ok(Some(NotebookEvent::Removed { path: rel_path }))
     }
 
-    #[test]
-    fn test_not_a_notebook() {
-        let dir = TempDir::new().unwrap();
-        let result = Notebook::load(dir.path());
-        assert!(result.is_err());
-        let err = result.err().unwrap();
-        assert!(matches!(err, Error::NotANotebook(_)));
+    /// Write `content` to the note at `path`, absolute or relative to the
+    /// notebook root, and reload it. Notes not known yet are added.
+    pub fn save(&mut self, path: impl AsRef<Path>, content: &str) -> Result<(), Error> {
+        let rel_path = self.relative(path.as_ref());
+        if !is_relative_subpath(&rel_path) {
+            return Err(Error::InvalidPath(rel_path));
+        }
+
+        self.storage.write(&rel_path, content.as_bytes())?;
+        self.reload(rel_path)?;
+        Ok(())
     }
 
-    #[test]
-    fn test_load_notebook() {
-        let dir = setup_notebook();
-        let nb = Notebook::load(dir.path()).unwrap();
-        assert_eq!(nb.all_notes(None).count(), 3);
+    /// Delete the attachment at `path`, absolute or relative to the notebook
+    /// root, from disk. Links to it from notes are left as they are.
+    pub fn delete_attachment(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let rel_path = self.relative(path.as_ref());
+        if !self.attachments.contains_key(&rel_path) {
+            return Err(Error::AttachmentNotFound(rel_path));
+        }
+
+        self.storage.delete(&rel_path)?;
+        self.attachments.remove(&rel_path);
+        Ok(())
     }
 
-    #[test]
-    fn test_note_lookup() {
-        let dir = setup_notebook();
-        let nb = Notebook::load(dir.path()).unwrap();
-        let note = nb.note("note1").unwrap();
-        assert_eq!(note.title(), "First Note");
-        assert_eq!(note.filename(), "note1.md");
-        assert_eq!(note.filename_stem(), "note1");
+    /// Read the frontmatter of the note at `path`, absolute or relative to the
+    /// notebook root. Save the edited note with [`Notebook::save`] and
+    /// [`Frontmatter::to_string`].
+    pub fn frontmatter(&self, path: impl AsRef<Path>) -> Result<Frontmatter, Error> {
+        let rel_path = self.relative(path.as_ref());
+        let content = self.storage.read_to_string(&rel_path)?;
+        Frontmatter::parse(&content).map_err(|err| match err {
+            Error::Frontmatter(source) => Error::Yaml {
+                path: self.root.join(&rel_path).to_string_lossy().into_owned(),
+                source,
+            },
+            err => err,
+        })
     }
 
-    #[test]
-    fn test_tags() {
-        let dir = setup_notebook();
-        let nb = Notebook::load(dir.path()).unwrap();
+    /// Return the names of the templates in `.zk/templates/`, sorted.
+    pub fn templates(&self) -> Result<Vec<String>, Error> {
+        let entries = match self.storage.list(&Path::new(".zk").join("templates")) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
 
-        let all_tags: Vec<&str> = nb.all_tags().collect();
-        assert!(all_tags.contains(&"rust"));
-        assert!(all_tags.contains(&"testing"));
+        let mut names = entries
+            .into_iter()
+            .filter(|entry| !entry.is_dir)
+            .filter_map(|entry| Some(entry.path.file_name()?.to_string_lossy().into_owned()))
+            .filter(|name| !name.starts_with('.'))
+            .collect::<Vec<_>>();
+        names.sort();
+        Ok(names)
+    }
 
-        assert_eq!(nb.all_notes(Some("rust")).count(), 2);
-        assert_eq!(nb.all_notes(Some("testing")).count(), 1);
+    /// Return the storage the notebook's files are read from and written to.
+    pub fn storage(&self) -> &dyn NotebookStorage {
+        self.storage.as_ref()
     }
 
-    #[test]
-    fn test_notes_with_tags() {
-        let dir = setup_notebook();
-        let nb = Notebook::load(dir.path()).unwrap();
+    /// Resolve a template name from the config: absolute and `~/` paths are
+    /// used as is, everything else is relative to `.zk/templates/`.
+    fn template_path(&self, name: &str) -> PathBuf {
+        if let Some(rest) = name.strip_prefix("~/")
+            && let Some(home) = std::env::var_os("HOME")
+        {
+            return PathBuf::from(home).join(rest);
+        }
 
-        let both: Vec<&Note> = nb.notes_with_tags(&["rust", "testing"]).collect();
-        assert_eq!(both.len(), 1);
-        assert_eq!(both[0].title(), "First Note");
+        let path = Path::new(name);
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            Path::new(".zk").join("templates").join(path)
+        }
     }
 
-    #[test]
-    fn test_hidden_dir_skipped() {
-        let dir = setup_notebook();
-        let nb = Notebook::load(dir.path()).unwrap();
-        assert!(nb.note("secret").is_none());
+    /// Add a freshly loaded note to the indices and return its position.
+    fn insert(&mut self, note: Note) -> usize {
+        let idx = self.notes.len();
+        self.search.insert(&note);
+        self.notes.push(note);
+        self.index(idx);
+        idx
     }
 
-    #[test]
-    fn test_note_fields() {
-        let dir = setup_notebook();
-        let nb = Notebook::load(dir.path()).unwrap();
-        let note = nb.note("note1").unwrap();
-
-        assert!(note.word_count() > 0);
-        assert!(!note.link().is_empty());
-        assert!(note.link().contains("note1.md"));
-        assert_eq!(note.lead(), "# First Note");
-        assert!(note.raw_content().contains("First Note"));
+    /// Rebuild the path, stem, tag, link and attachment indices from all
+    /// notes.
+    fn reindex(&mut self) {
+        self.paths.clear();
+        self.stems.clear();
+        self.tags.clear();
+        self.backlinks.clear();
+        self.targets.clear();
+        self.attachment_refs.clear();
+        self.attachment_names.clear();
+
+        self.links = link::LinkIndex::new(&self.notes);
+        for idx in 0..self.notes.len() {
+            self.index_keys(idx);
+        }
+        for idx in 0..self.notes.len() {
+            self.resolve_links(idx);
+        }
+    }
+
+    /// Add the note at position `idx` to the indices and resolve the links
+    /// that may point to it again.
+    fn index(&mut self, idx: usize) {
+        let note = &self.notes[idx];
+        self.links.insert(idx, note);
+        let sources = self.links.linking_to(note);
+        self.index_keys(idx);
+
+        for source in sources {
+            self.resolve_links(source);
+        }
+        self.resolve_links(idx);
+    }
+
+    /// Remove the note at position `idx` from the indices, as it was added by
+    /// [`index`](Self::index), and resolve the links that pointed to it again.
+    fn unindex(&mut self, idx: usize) {
+        let note = &self.notes[idx];
+        self.links.remove(idx, note);
+        let sources = self.links.linking_to(note);
+
+        self.paths.remove(note.path());
+        link::remove_position(&mut self.stems, note.filename_stem(), idx);
+        for tag in note.tags() {
+            link::remove_position(&mut self.tags, &tag.to_lowercase(), idx);
+        }
+        for path in attachment_paths(note) {
+            link::remove_position(&mut self.attachment_refs, &path, idx);
+        }
+        for name in attachment_names(note) {
+            link::remove_position(&mut self.attachment_names, &name, idx);
+        }
+
+        self.unresolve_links(idx);
+        for source in sources {
+            self.resolve_links(source);
+        }
+    }
+
+    /// Add the path, stem, tags and attachment links of the note at `idx`.
+    fn index_keys(&mut self, idx: usize) {
+        let note = &self.notes[idx];
+        self.paths.insert(note.path().to_path_buf(), idx);
+        link::insert_position(&mut self.stems, note.filename_stem().to_owned(), idx);
+        for tag in note.tags() {
+            link::insert_position(&mut self.tags, tag.to_lowercase(), idx);
+        }
+        for path in attachment_paths(note) {
+            link::insert_position(&mut self.attachment_refs, path, idx);
+        }
+        for name in attachment_names(note) {
+            link::insert_position(&mut self.attachment_names, name, idx);
+        }
+    }
+
+    /// Resolve the links of the note at `source` and update the backlinks of
+    /// the notes they point to.
+    fn resolve_links(&mut self, source: usize) {
+        self.unresolve_links(source);
+
+        let note = &self.notes[source];
+        let mut targets = Vec::new();
+        for link in note.outgoing_links() {
+            if let [target] = self.links.resolve(link, note.path())
+                && !targets.contains(target)
+            {
+                targets.push(*target);
+            }
+        }
+
+        for &target in &targets {
+            link::insert_position(&mut self.backlinks, target, source);
+        }
+        if !targets.is_empty() {
+            self.targets.insert(source, targets);
+        }
+    }
+
+    /// Drop the resolved links of the note at `source` from the backlinks.
+    fn unresolve_links(&mut self, source: usize) {
+        for target in self.targets.remove(&source).unwrap_or_default() {
+            link::remove_position(&mut self.backlinks, &target, source);
+        }
+    }
+
+    /// Write the notes at the `written` paths to the index and delete the rows
+    /// of `removed` paths, if the notebook was loaded with an index.
+    #[cfg(feature = "sqlite")]
+    fn sync_index(&mut self, written: &[PathBuf], removed: &[PathBuf]) -> Result<(), Error> {
+        let Some(db) = &mut self.db else {
+            return Ok(());
+        };
+
+        let (notes, links) = (&self.notes, &self.links);
+        let written = written
+            .iter()
+            .filter_map(|path| self.paths.get(path))
+            .map(|&idx| &notes[idx]);
+        let removed = removed.iter().map(PathBuf::as_path);
+        let resolve = |target: &str, from: &Path| match links.resolve(target, from) {
+            [idx] => Some(notes[*idx].path()),
+            _ => None,
+        };
+
+        db.sync(written, removed, resolve)
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn sync_index(&mut self, _written: &[PathBuf], _removed: &[PathBuf]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Return the notebook configuration loaded from `.zk/config.toml`.
+    pub fn config(&self) -> &NotebookConfig {
+        &self.config
+    }
+
+    /// Return the problems of the configuration that did not prevent loading
+    /// the notebook, like a `link-format` template failing to render. Notes
+    /// are linked in the markdown format instead.
+    pub fn config_errors(&self) -> Vec<Error> {
+        let markdown = &self.config.format.markdown;
+        match parse::format_link(Path::new("note.md"), "Title", markdown) {
+            Ok(_) => Vec::new(),
+            Err(err) => vec![err],
+        }
+    }
+
+    /// Return the query of the named filter from the `[filter]` section.
+    pub fn filter(&self, name: &str) -> Result<NoteQuery, Error> {
+        let args = self
+            .config
+            .filters
+            .get(name)
+            .ok_or_else(|| Error::Query(format!("unknown filter: {name}")))?;
+        NoteQuery::from_args(args)
+    }
+
+    /// Save `query` as the named filter `name` in `.zk/config.toml`,
+    /// replacing a filter of the same name. The rest of the file, including
+    /// comments and formatting, is kept. Queries with frontmatter field
+    /// filters cannot be saved, `zk list` has no equivalent.
+    pub fn save_filter(&mut self, name: &str, query: &NoteQuery) -> Result<(), Error> {
+        if !query.metadata.is_empty() {
+            return Err(Error::Query(
+                "frontmatter field filters cannot be saved as zk filter".into(),
+            ));
+        }
+        let path = Path::new(".zk").join("config.toml");
+        let edit_error = |reason: String| Error::ConfigEdit {
+            path: path.to_string_lossy().into_owned(),
+            reason,
+        };
+
+        let content = match self.storage.read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut doc: toml_edit::DocumentMut = content
+            .parse()
+            .map_err(|err: toml_edit::TomlError| edit_error(err.to_string()))?;
+
+        let args = query.to_args();
+        doc.entry("filter")
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or_else(|| edit_error("`filter` is not a table".into()))?
+            .insert(name, toml_edit::value(args.as_str()));
+
+        self.storage.write(&path, doc.to_string().as_bytes())?;
+        self.config.filters.insert(name.to_owned(), args);
+        Ok(())
+    }
+
+    /// Look up a note by its notebook-relative path, with or without the
+    /// `.md` extension, or by its filename stem if no other note shares it.
+    pub fn note(&self, id: &str) -> Option<&Note> {
+        let idx = self
+            .paths
+            .get(Path::new(id))
+            .or_else(|| self.paths.get(Path::new(&format!("{id}.md"))))
+            .or_else(|| match self.stems.get(id).map(Vec::as_slice) {
+                Some([idx]) => Some(idx),
+                _ => None,
+            })?;
+
+        Some(&self.notes[*idx])
+    }
+
+    /// Return all notes with the filename stem `stem`, sorted by path. More
+    /// than one note means links by stem are ambiguous.
+    pub fn notes_with_stem(&self, stem: &str) -> Vec<&Note> {
+        let mut notes: Vec<&Note> = self
+            .stems
+            .get(stem)
+            .map(|indices| indices.iter().map(|&idx| &self.notes[idx]).collect())
+            .unwrap_or_default();
+        notes.sort_by_key(|note| note.path());
+        notes
+    }
+
+    /// Return all notes, optionally filtered by tag. Notes with a tag nested
+    /// in `with_tag` are included.
+    pub fn all_notes(&self, with_tag: Option<&str>) -> impl Iterator<Item = &Note> {
+        let allowed: Option<HashSet<usize>> = with_tag.map(|with_tag| {
+            self.tags
+                .iter()
+                .filter(|(tag, _)| tag::is_within(tag, with_tag))
+                .flat_map(|(_, notes)| notes.iter().copied())
+                .collect()
+        });
+        self.notes
+            .iter()
+            .enumerate()
+            .filter(move |(idx, _)| allowed.as_ref().is_none_or(|s| s.contains(idx)))
+            .map(|(_, note)| note)
+    }
+
+    /// Return all unique tags across all notes.
+    pub fn all_tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.keys().map(String::as_str)
+    }
+
+    /// Return all unique tags with the number of notes having them.
+    pub fn tag_counts(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.tags
+            .iter()
+            .map(|(tag, notes)| (tag.as_str(), notes.len()))
+    }
+
+    /// Rename the tag `tag` to `to` in all notes, in every tag syntax.
+    /// Renaming to an existing tag merges both. Returns the number of
+    /// changed notes.
+    pub fn rename_tag(&mut self, tag: &str, to: &str) -> Result<usize, Error> {
+        self.merge_tags(&[tag], to)
+    }
+
+    /// Replace the tags `tags` by `into` in all notes. Returns the number of
+    /// changed notes.
+    ///
+    /// Nothing is written if `into` cannot be used in one of the notes, e.g.
+    /// a tag with spaces replacing a `:colon:tag:`.
+    pub fn merge_tags(&mut self, tags: &[&str], into: &str) -> Result<usize, Error> {
+        let into = into.trim();
+        if !tag::is_valid_tag(into) {
+            return Err(Error::InvalidTag(into.to_owned()));
+        }
+        self.edit_tags(tags, Some(into))
+    }
+
+    /// Remove the tag `tag` from all notes. Returns the number of changed
+    /// notes.
+    pub fn delete_tag(&mut self, tag: &str) -> Result<usize, Error> {
+        self.edit_tags(&[tag], None)
+    }
+
+    fn edit_tags(&mut self, tags: &[&str], to: Option<&str>) -> Result<usize, Error> {
+        let from: Vec<String> = tags
+            .iter()
+            .map(|tag| tag.trim().trim_start_matches('#').to_lowercase())
+            .collect();
+        let mut indices: Vec<usize> = from
+            .iter()
+            .filter_map(|tag| self.tags.get(tag))
+            .flatten()
+            .copied()
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+
+        // Rewrite all notes before saving any, so an unsupported tag name
+        // leaves the notebook untouched.
+        let markdown = &self.config.format.markdown;
+        let mut changes = Vec::new();
+        for idx in indices {
+            let path = self.notes[idx].path();
+            let content = self.storage.read_to_string(path)?;
+            if let Some(content) = tag::rewrite_tags(&content, path, &from, to, markdown)? {
+                changes.push((path.to_path_buf(), content));
+            }
+        }
+
+        for (path, content) in &changes {
+            self.save(path, content)?;
+        }
+        Ok(changes.len())
+    }
+
+    /// Return notes that have ALL of the given tags, or tags nested in them.
+    pub fn notes_with_tags<'a>(&'a self, tags: &'a [&str]) -> impl Iterator<Item = &'a Note> {
+        self.notes
+            .iter()
+            .filter(move |note| tags.is_empty() || tags.iter().all(|tag| note.has_within(tag)))
+    }
+
+    /// Resolve a link written in `from` to the note it points to.
+    ///
+    /// Like zk, the link is tried as a path relative to `from`, a path relative
+    /// to the notebook root, a filename stem, an alias and finally a
+    /// case-insensitive title.
+    pub fn resolve(&self, link: &str, from: &Note) -> Resolution<'_> {
+        match self.links.resolve(link, from.path()) {
+            [] => Resolution::NotFound,
+            [idx] => Resolution::Found(&self.notes[*idx]),
+            candidates => {
+                Resolution::Ambiguous(candidates.iter().map(|&idx| &self.notes[idx]).collect())
+            }
+        }
+    }
+
+    /// Return the notes `note` links to, skipping dead and ambiguous links.
+    pub fn outgoing_links(&self, note: &Note) -> Vec<&Note> {
+        let mut seen = HashSet::new();
+        note.outgoing_links()
+            .iter()
+            .filter_map(|link| self.resolve(link, note).note())
+            .filter(|target| seen.insert(target.path()))
+            .collect()
+    }
+
+    /// Return notes that link to `note`.
+    pub fn backlinks(&self, note: &Note) -> Vec<&Note> {
+        self.paths
+            .get(note.path())
+            .and_then(|idx| self.backlinks.get(idx))
+            .map(|sources| sources.iter().map(|&idx| &self.notes[idx]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Return all resolved links between notes as `(source, target)` pairs,
+    /// once per pair and without links of a note to itself.
+    pub fn links(&self) -> Vec<(&Note, &Note)> {
+        let mut links: Vec<(&Note, &Note)> = self
+            .backlinks
+            .iter()
+            .flat_map(|(&target, sources)| {
+                sources
+                    .iter()
+                    .filter(move |&&source| source != target)
+                    .map(move |&source| (&self.notes[source], &self.notes[target]))
+            })
+            .collect();
+        links.sort_by(|a, b| (a.0.path(), a.1.path()).cmp(&(b.0.path(), b.1.path())));
+        links
+    }
+
+    /// Return the notes at most `depth` links away from `note`, following
+    /// links in either direction, nearest first. `note` comes first and has
+    /// depth 0.
+    ///
+    /// Other notes are only returned and followed if `visit` returns `true`,
+    /// so hidden notes do not connect the notes around them.
+    pub fn neighborhood(
+        &self,
+        note: &Note,
+        depth: usize,
+        visit: impl Fn(&Note) -> bool,
+    ) -> Vec<(&Note, usize)> {
+        let Some(&start) = self.paths.get(note.path()) else {
+            return Vec::new();
+        };
+
+        let mut neighbors: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (&target, sources) in &self.backlinks {
+            for &source in sources {
+                neighbors.entry(source).or_default().push(target);
+                neighbors.entry(target).or_default().push(source);
+            }
+        }
+
+        let mut found = vec![(start, 0)];
+        let mut seen = HashSet::from([start]);
+        let mut next = 0;
+        while let Some(&(idx, distance)) = found.get(next) {
+            next += 1;
+            if distance == depth {
+                continue;
+            }
+            let mut around: Vec<usize> = neighbors
+                .get(&idx)
+                .into_iter()
+                .flatten()
+                .copied()
+                .filter(|&other| visit(&self.notes[other]) && seen.insert(other))
+                .collect();
+            around.sort_by(|&a, &b| self.notes[a].path().cmp(self.notes[b].path()));
+            found.extend(around.into_iter().map(|other| (other, distance + 1)));
+        }
+
+        found
+            .into_iter()
+            .map(|(idx, distance)| (&self.notes[idx], distance))
+            .collect()
+    }
+
+    /// Return the notes no other note links to, like `zk list --orphan`,
+    /// sorted by path.
+    pub fn orphans(&self) -> Vec<&Note> {
+        self.sorted_notes(|idx, _| !self.backlinks.contains_key(&idx))
+    }
+
+    /// Return the notes without links to existing notes, sorted by path.
+    pub fn dead_ends(&self) -> Vec<&Note> {
+        self.sorted_notes(|_, note| {
+            note.outgoing_links()
+                .iter()
+                .all(|link| self.links.resolve(link, note.path()).is_empty())
+        })
+    }
+
+    /// Return the links that do not resolve to any note, by source path and
+    /// line. zk's LSP reports these as dead links; ambiguous links are not
+    /// dead.
+    pub fn dead_links(&self) -> Vec<DeadLink<'_>> {
+        let mut dead = Vec::new();
+        for note in self.sorted_notes(|_, _| true) {
+            let content = note.raw_content();
+            let body_start = parse::extract_frontmatter(content).1;
+            for span in parse::link_spans(&content[body_start..]) {
+                let target = &content[body_start + span.start..body_start + span.end];
+                if self.links.resolve(target, note.path()).is_empty() {
+                    dead.push(DeadLink {
+                        source: note,
+                        target: target.to_owned(),
+                        line: graph::line_at(content, body_start + span.start),
+                    });
+                }
+            }
+        }
+        dead
+    }
+
+    /// Find the attachments in the notebook subdirectory `dir`, absolute or
+    /// relative to the notebook root, and watch for changes there. Without a
+    /// directory the notebook has no attachments.
+    ///
+    /// Files outside of `dir` are never listed or deleted as attachments.
+    pub fn set_attachments_dir(&mut self, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let dir = self.relative(dir.as_ref());
+        if dir.as_os_str().is_empty() || !is_relative_subpath(&dir) {
+            return Err(Error::InvalidPath(dir));
+        }
+
+        let others = match discover_files(self.storage.as_ref(), &self.config, &dir) {
+            Ok(_) if self.is_excluded(&dir) => Vec::new(),
+            Ok((_, others)) => others,
+            Err(Error::Io(err)) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        self.attachments = load_attachments(self.storage.as_ref(), &self.root, others)?
+            .into_iter()
+            .map(|attachment| (attachment.path.clone(), attachment))
+            .collect();
+        self.attachments_dir = Some(dir);
+        Ok(())
+    }
+
+    /// Return the files in the [attachments directory](Self::set_attachments_dir)
+    /// that are not notes, sorted by path.
+    pub fn attachments(&self) -> impl Iterator<Item = &Attachment> {
+        self.attachments.values()
+    }
+
+    /// Return the attachment at `path`, absolute or relative to the notebook
+    /// root.
+    pub fn attachment(&self, path: impl AsRef<Path>) -> Option<&Attachment> {
+        self.attachments.get(&self.relative(path.as_ref()))
+    }
+
+    /// Return the notes linking to the attachment at `path`, absolute or
+    /// relative to the notebook root, sorted by path. The attachment does not
+    /// need to exist.
+    ///
+    /// Links count if they may point to `path`: relative to the linking note
+    /// or the notebook root, or by the file name alone.
+    pub fn attachment_references(&self, path: impl AsRef<Path>) -> Vec<&Note> {
+        let (by_path, by_name) = self.attachment_sources(&self.relative(path.as_ref()));
+        if by_path.is_empty() && by_name.is_empty() {
+            return Vec::new();
+        }
+        self.sorted_notes(|idx, _| by_path.contains(&idx) || by_name.contains(&idx))
+    }
+
+    /// Return the attachments no note links to, sorted by path. See
+    /// [`attachment_references`](Self::attachment_references) for the links
+    /// that count.
+    pub fn unreferenced_attachments(&self) -> Vec<&Attachment> {
+        self.attachments
+            .values()
+            .filter(|attachment| {
+                let (by_path, by_name) = self.attachment_sources(attachment.path());
+                by_path.is_empty() && by_name.is_empty()
+            })
+            .collect()
+    }
+
+    /// Return the notes linking to the attachment at the notebook-relative
+    /// `path`, and the notes linking to its file name alone.
+    fn attachment_sources(&self, path: &Path) -> (&[usize], &[usize]) {
+        let by_path = self.attachment_refs.get(path);
+        let by_name = path
+            .file_name()
+            .and_then(|name| self.attachment_names.get(name.to_string_lossy().as_ref()));
+        (
+            by_path.map_or(&[], Vec::as_slice),
+            by_name.map_or(&[], Vec::as_slice),
+        )
+    }
+
+    /// Return up to `limit` notes with the most backlinks and their count,
+    /// most linked first. Notes without backlinks are left out.
+    pub fn hubs(&self, limit: usize) -> Vec<(&Note, usize)> {
+        let mut hubs: Vec<(&Note, usize)> = self
+            .backlinks
+            .iter()
+            .map(|(&idx, sources)| (&self.notes[idx], sources.len()))
+            .collect();
+        hubs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.path().cmp(b.0.path())));
+        hubs.truncate(limit);
+        hubs
+    }
+
+    /// Group the notes connected by links in either direction, largest group
+    /// first. A note without links is a group of its own. Notes within a group
+    /// are sorted by path.
+    pub fn components(&self) -> Vec<Vec<&Note>> {
+        let edges = self
+            .backlinks
+            .iter()
+            .flat_map(|(&target, sources)| sources.iter().map(move |&source| (source, target)));
+
+        let mut groups: Vec<Vec<&Note>> = graph::components(self.notes.len(), edges)
+            .into_iter()
+            .map(|group| {
+                let mut notes: Vec<&Note> = group.into_iter().map(|idx| &self.notes[idx]).collect();
+                notes.sort_by(|a, b| a.path().cmp(b.path()));
+                notes
+            })
+            .collect();
+        groups.sort_by(|a, b| {
+            b.len()
+                .cmp(&a.len())
+                .then_with(|| a[0].path().cmp(b[0].path()))
+        });
+        groups
+    }
+
+    /// Return the notes related to `note`, most related first.
+    ///
+    /// Notes are related by shared tags, by being linked from the same notes
+    /// (co-citation), by linking to the same notes (bibliographic coupling)
+    /// and by the words of their title and body. Notes related in none of
+    /// these ways are left out.
+    pub fn related(&self, note: &Note) -> Vec<Related<'_>> {
+        let Some(&idx) = self.paths.get(note.path()) else {
+            return Vec::new();
+        };
+        let mut related: HashMap<usize, Related> = HashMap::new();
+
+        let n = self.notes.len() as f64;
+        let tag_weights = |note: &Note| -> BTreeMap<String, f64> {
+            note.tags()
+                .iter()
+                .map(|tag| tag.to_lowercase())
+                .map(|tag| {
+                    let weight = (1.0 + n / self.tags[&tag].len() as f64).ln();
+                    (tag, weight)
+                })
+                .collect()
+        };
+        let norm = |weights: &BTreeMap<String, f64>| -> f64 {
+            weights
+                .values()
+                .map(|weight| weight * weight)
+                .sum::<f64>()
+                .sqrt()
+        };
+        let own_tags = tag_weights(note);
+        let others: BTreeSet<usize> = own_tags
+            .keys()
+            .flat_map(|tag| self.tags[tag].iter().copied())
+            .filter(|&other| other != idx)
+            .collect();
+        for other in others {
+            let other_tags = tag_weights(&self.notes[other]);
+            let shared: Vec<String> = own_tags
+                .keys()
+                .filter(|tag| other_tags.contains_key(*tag))
+                .cloned()
+                .collect();
+            let dot: f64 = shared.iter().map(|tag| own_tags[tag].powi(2)).sum();
+            let entry = related
+                .entry(other)
+                .or_insert_with(|| Related::new(&self.notes[other]));
+            entry.tags = dot / (norm(&own_tags) * norm(&other_tags));
+            entry.shared_tags = shared;
+        }
+
+        let mut cited: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut citing: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (&target, sources) in &self.backlinks {
+            for &source in sources.iter().filter(|&&source| source != target) {
+                cited.entry(source).or_default().push(target);
+                citing.entry(target).or_default().push(source);
+            }
+        }
+        // Count the notes reached from `idx` through one note of `via` and
+        // back through `back`.
+        let shared = |via: &HashMap<usize, Vec<usize>>, back: &HashMap<usize, Vec<usize>>| {
+            let mut shared: HashMap<usize, usize> = HashMap::new();
+            for middle in via.get(&idx).into_iter().flatten() {
+                for &other in back[middle].iter().filter(|&&other| other != idx) {
+                    *shared.entry(other).or_default() += 1;
+                }
+            }
+            shared
+        };
+        let count =
+            |map: &HashMap<usize, Vec<usize>>, idx: usize| map.get(&idx).map_or(0, Vec::len);
+        for (other, shared) in shared(&citing, &cited) {
+            related
+                .entry(other)
+                .or_insert_with(|| Related::new(&self.notes[other]))
+                .co_citation = related::overlap(shared, count(&citing, idx), count(&citing, other));
+        }
+        for (other, shared) in shared(&cited, &citing) {
+            related
+                .entry(other)
+                .or_insert_with(|| Related::new(&self.notes[other]))
+                .coupling = related::overlap(shared, count(&cited, idx), count(&cited, other));
+        }
+
+        for (path, similarity) in self.search.similar(note.path()) {
+            let other = self.paths[path];
+            related
+                .entry(other)
+                .or_insert_with(|| Related::new(&self.notes[other]))
+                .similarity = similarity;
+        }
+
+        let mut related: Vec<Related> = related
+            .into_values()
+            .map(|mut related| {
+                related.score =
+                    related.tags + related.co_citation + related.coupling + related.similarity;
+                related
+            })
+            .collect();
+        related.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.note.path().cmp(b.note.path()))
+        });
+        related
+    }
+
+    /// Create the note the dead link `link` in the note at `from` points to,
+    /// with the link target as title.
+    ///
+    /// The note is created where the link looks first: relative to `from`'s
+    /// directory, or to the notebook root for links starting with `/`. The
+    /// `[note]` filename and template settings do not apply.
+    pub fn create_missing(&mut self, link: &str, from: impl AsRef<Path>) -> Result<&Note, Error> {
+        let from = self.relative(from.as_ref());
+        let target = link::percent_decode(link);
+        let target = target.trim();
+        let target = target.strip_suffix(".md").unwrap_or(target);
+
+        let dir = from.parent().unwrap_or(Path::new(""));
+        let path = link::normalize(&dir.join(target))
+            .filter(|_| !target.ends_with('/'))
+            .map(|path| PathBuf::from(format!("{}.md", path.to_string_lossy())))
+            .ok_or_else(|| Error::InvalidPath(PathBuf::from(link)))?;
+        if self.paths.contains_key(&path) {
+            return Err(Error::NoteExists(self.root.join(path)));
+        }
+
+        let title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        let content = format!("# {title}\n");
+        match self.storage.create(&path, content.as_bytes()) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                return Err(Error::NoteExists(self.root.join(path)));
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let note = load_single_note(
+            self.storage.as_ref(),
+            &self.root,
+            path.clone(),
+            &self.config,
+        )?;
+        let idx = self.insert(note);
+        self.sync_index(&[path], &[])?;
+        Ok(&self.notes[idx])
+    }
+
+    /// Return the notes for which `keep` returns `true`, sorted by path.
+    fn sorted_notes(&self, keep: impl Fn(usize, &Note) -> bool) -> Vec<&Note> {
+        let mut notes: Vec<&Note> = self
+            .notes
+            .iter()
+            .enumerate()
+            .filter(|(idx, note)| keep(*idx, note))
+            .map(|(_, note)| note)
+            .collect();
+        notes.sort_by(|a, b| a.path().cmp(b.path()));
+        notes
+    }
+
+    /// Search note titles and bodies for `query`, returning matches ranked by
+    /// relevance.
+    ///
+    /// The query consists of words that must all occur, `prefix*` words and
+    /// `"quoted phrases"`. Matching is case-insensitive. `with_tag` keeps
+    /// notes with that tag or a tag nested in it.
+    pub fn search(&self, query: &str, with_tag: Option<&str>) -> Vec<SearchHit<'_>> {
+        let clauses = search::parse_query(query);
+
+        let mut hits: Vec<SearchHit> = self
+            .search
+            .search(&clauses)
+            .into_iter()
+            .filter_map(|(path, score)| {
+                let note = &self.notes[*self.paths.get(path)?];
+                with_tag
+                    .is_none_or(|tag| note.has_within(tag))
+                    .then(|| SearchHit {
+                        note,
+                        score,
+                        snippet: search::snippet(note.body(), &clauses),
+                    })
+            })
+            .collect();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.note.path().cmp(b.note.path()))
+        });
+        hits
+    }
+
+    /// Return the notes matching `query`, sorted and limited as requested.
+    ///
+    /// Without explicit sort keys, notes are ordered by relevance if the query
+    /// has a full-text part and by last modification (most recent first)
+    /// otherwise. Notes referenced by `linked_by`/`link_to` that do not exist
+    /// match nothing.
+    pub fn query(&self, query: &NoteQuery) -> Vec<&Note> {
+        let relevance: Option<HashMap<&Path, f64>> = query.match_text.as_deref().map(|text| {
+            self.search
+                .search(&search::parse_query(text))
+                .into_iter()
+                .collect()
+        });
+
+        let linked_by = self.link_targets(&query.linked_by, Self::outgoing_links);
+        let link_to = self.link_targets(&query.link_to, Self::backlinks);
+
+        let mut notes: Vec<&Note> = self
+            .notes
+            .iter()
+            .filter(|note| query.matches(note))
+            .filter(|note| {
+                relevance
+                    .as_ref()
+                    .is_none_or(|scores| scores.contains_key(note.path()))
+            })
+            .filter(|note| {
+                linked_by
+                    .as_ref()
+                    .is_none_or(|paths| paths.contains(note.path()))
+            })
+            .filter(|note| {
+                link_to
+                    .as_ref()
+                    .is_none_or(|paths| paths.contains(note.path()))
+            })
+            .filter(|note| !query.orphan || self.backlinks(note).is_empty())
+            .collect();
+
+        let score = |note: &Note| relevance.as_ref().map_or(0.0, |scores| scores[note.path()]);
+
+        if query
+            .sort
+            .first()
+            .is_some_and(|sort| sort.field == SortField::Random)
+        {
+            notes.shuffle(&mut rand::rng());
+        } else if query.sort.is_empty() && relevance.is_some() {
+            notes.sort_by(|a, b| {
+                score(b)
+                    .total_cmp(&score(a))
+                    .then_with(|| a.path().cmp(b.path()))
+            });
+        } else {
+            let default = [Sort {
+                field: SortField::Modified,
+                ascending: false,
+            }];
+            let keys = if query.sort.is_empty() {
+                &default[..]
+            } else {
+                &query.sort
+            };
+            notes.sort_by(|a, b| {
+                keys.iter()
+                    .fold(std::cmp::Ordering::Equal, |ordering, key| {
+                        ordering.then_with(|| key.compare(a, b))
+                    })
+                    .then_with(|| a.path().cmp(b.path()))
+            });
+        }
+
+        if let Some(limit) = query.limit {
+            notes.truncate(limit);
+        }
+        notes
+    }
+
+    /// Collect the paths of the notes `related` returns for each of `ids`.
+    /// Returns `None` if `ids` is empty, meaning no restriction.
+    fn link_targets<'a>(
+        &'a self,
+        ids: &[String],
+        related: fn(&'a Self, &Note) -> Vec<&'a Note>,
+    ) -> Option<HashSet<&'a Path>> {
+        if ids.is_empty() {
+            return None;
+        }
+
+        Some(
+            ids.iter()
+                .filter_map(|id| self.note(id))
+                .flat_map(|note| related(self, note))
+                .map(Note::path)
+                .collect(),
+        )
+    }
+
+    /// Cut an excerpt of `note`'s body around the words matching the
+    /// full-text query `text`, as in [`SearchHit::snippet`].
+    pub fn snippet(&self, note: &Note, text: &str) -> Snippet {
+        search::snippet(note.body(), &search::parse_query(text))
+    }
+
+    /// Fuzzy search note titles, returning matches ranked by score.
+    pub fn search_titles(
+        &self,
+        query: &str,
+        with_tag: Option<&str>,
+    ) -> impl Iterator<Item = &Note> {
+        if query.is_empty() {
+            return self.all_notes(with_tag).collect::<Vec<_>>().into_iter();
+        }
+
+        let mut matcher = nucleo::Matcher::new(nucleo::Config::DEFAULT);
+        let needle = nucleo::pattern::Pattern::parse(
+            query,
+            nucleo::pattern::CaseMatching::Ignore,
+            nucleo::pattern::Normalization::Smart,
+        );
+
+        let mut scored: Vec<(u32, &Note)> = self
+            .all_notes(with_tag)
+            .filter_map(|note| {
+                let mut buf = Vec::new();
+                let haystack = nucleo::Utf32Str::new(note.title(), &mut buf);
+                let score = needle.score(haystack, &mut matcher)?;
+                Some((score, note))
+            })
+            .collect();
+
+        scored.sort_by_key(|b| std::cmp::Reverse(b.0));
+
+        scored
+            .into_iter()
+            .map(|(_, note)| note)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+/// A link to rewrite in [`Notebook::rename`], as found before the rename.
+struct LinkRewrite<'a> {
+    /// The link target as written.
+    text: &'a str,
+    /// `true` for a `[[wiki-link]]`, `false` for a Markdown link.
+    wiki: bool,
+    old_source: &'a Path,
+    old_target: &'a Path,
+}
+
+/// Check that `path` is relative and does not escape its base directory.
+fn is_relative_subpath(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Load the configuration of the notebook in `storage`.
+/// Returns `Error::NotANotebook` if `.zk/` does not exist.
+fn load_config(root: &Path, storage: &dyn NotebookStorage) -> Result<NotebookConfig, Error> {
+    if !storage
+        .metadata(Path::new(".zk"))
+        .is_ok_and(|meta| meta.is_dir)
+    {
+        return Err(Error::NotANotebook(root.to_path_buf()));
+    }
+
+    let path = Path::new(".zk").join("config.toml");
+    match storage.read_to_string(&path) {
+        Ok(content) => NotebookConfig::parse(&content, &root.join(path)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(NotebookConfig::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Return `path` relative to the notebook `root`.
+fn relative_to(root: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
+
+/// Read a single markdown file and parse it into a Note.
+fn load_single_note(
+    storage: &dyn NotebookStorage,
+    root: &Path,
+    rel_path: PathBuf,
+    config: &NotebookConfig,
+) -> Result<Note, Error> {
+    let content = storage.read_to_string(&rel_path)?;
+    let meta = storage.metadata(&rel_path)?;
+    let abs_path = root.join(&rel_path);
+    parse::parse_note(&content, rel_path, abs_path, &meta, &config.format.markdown)
+}
+
+/// Return the paths of the notes and of the other files in the notebook
+/// subdirectory `dir`, skipping hidden files and directories and the paths
+/// excluded by `config`.
+fn discover_files(
+    storage: &dyn NotebookStorage,
+    config: &NotebookConfig,
+    dir: &Path,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>), Error> {
+    let mut files = Vec::new();
+    let mut others = Vec::new();
+    walk_dir(storage, config, dir, &mut files, &mut others)?;
+    files.sort();
+    Ok((files, others))
+}
+
+fn walk_dir(
+    storage: &dyn NotebookStorage,
+    config: &NotebookConfig,
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+    others: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    for entry in storage.list(dir)? {
+        let name = entry
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+
+        // Skip hidden dirs/files
+        if name.starts_with('.') || config.is_excluded(&entry.path) {
+            continue;
+        }
+
+        if entry.is_dir {
+            walk_dir(storage, config, &entry.path, files, others)?;
+        } else if entry.path.extension().is_some_and(|ext| ext == "md") {
+            files.push(entry.path);
+        } else {
+            others.push(entry.path);
+        }
+    }
+    Ok(())
+}
+
+fn load_attachments(
+    storage: &dyn NotebookStorage,
+    root: &Path,
+    paths: Vec<PathBuf>,
+) -> Result<Vec<Attachment>, Error> {
+    paths
+        .into_iter()
+        .map(|path| {
+            let meta = storage.metadata(&path)?;
+            Ok(Attachment::new(root, path, &meta))
+        })
+        .collect()
+}
+
+/// Return `true` if a component of the notebook-relative `path` is hidden.
+fn is_hidden(path: &Path) -> bool {
+    path.iter()
+        .any(|part| part.to_string_lossy().starts_with('.'))
+}
+
+/// Return the notebook-relative paths of the attachments `note` may link to.
+fn attachment_paths(note: &Note) -> impl Iterator<Item = PathBuf> + '_ {
+    note.attachment_links()
+        .iter()
+        .flat_map(|target| attachment::resolve(target, note.path()))
+}
+
+/// Return the file names of the attachments `note` links to by name alone.
+fn attachment_names(note: &Note) -> impl Iterator<Item = String> + '_ {
+    note.attachment_links()
+        .iter()
+        .filter_map(|target| attachment::bare_name(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn setup_notebook() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        // Create .zk directory
+        fs::create_dir(dir.path().join(".zk")).unwrap();
+
+        // Create a note with frontmatter
+        fs::write(
+            dir.path().join("note1.md"),
+            "---\ntitle: First Note\ntags: [rust, testing]\ndate: 2024-06-15\n---\n# First Note\n\nThis is the body.\n\nSecond paragraph.",
+        )
+        .unwrap();
+
+        // Create a note with just a heading
+        fs::write(
+            dir.path().join("note2.md"),
+            "# Second Note\n\nAnother body here.",
+        )
+        .unwrap();
+
+        // Create a subdirectory with a note
+        fs::create_dir(dir.path().join("subdir")).unwrap();
+        fs::write(
+            dir.path().join("subdir").join("note3.md"),
+            "---\ntitle: Sub Note\ntags: [rust]\n---\nContent of sub note.",
+        )
+        .unwrap();
+
+        // Create a hidden directory that should be skipped
+        fs::create_dir(dir.path().join(".hidden")).unwrap();
+        fs::write(
+            dir.path().join(".hidden").join("secret.md"),
+            "# Secret\nShould not appear.",
+        )
+        .unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn test_not_a_notebook() {
+        let dir = TempDir::new().unwrap();
+        let result = Notebook::load(dir.path());
+        assert!(result.is_err());
+        let err = result.err().unwrap();
+        assert!(matches!(err, Error::NotANotebook(_)));
+    }
+
+    #[test]
+    fn test_load_notebook() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        assert_eq!(nb.all_notes(None).count(), 3);
+    }
+
+    #[test]
+    fn test_note_lookup() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        let note = nb.note("note1").unwrap();
+        assert_eq!(note.title(), "First Note");
+        assert_eq!(note.filename(), "note1.md");
+        assert_eq!(note.filename_stem(), "note1");
+    }
+
+    #[test]
+    fn test_tags() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+
+        let all_tags: Vec<&str> = nb.all_tags().collect();
+        assert!(all_tags.contains(&"rust"));
+        assert!(all_tags.contains(&"testing"));
+
+        assert_eq!(nb.all_notes(Some("rust")).count(), 2);
+        assert_eq!(nb.all_notes(Some("testing")).count(), 1);
+    }
+
+    #[test]
+    fn test_nested_tags() {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "[format.markdown]\nhashtags = true\n")
+            .with_file("a.md", "---\ntags: [Project/Weave/UI]\n---\n# A")
+            .with_file("b.md", "# B\n\n#project/weave")
+            .with_file("c.md", "---\ntags: \"/projects/ \"\n---\n# C")
+            .with_file("d.md", "# D\n\n#project");
+        let nb = Notebook::load_from("/notebook", storage).unwrap();
+
+        assert_eq!(nb.note("c").unwrap().tags(), ["projects"]);
+        let titles = |notes: Vec<&Note>| -> Vec<String> {
+            let mut titles: Vec<String> = notes.iter().map(|n| n.title().to_owned()).collect();
+            titles.sort();
+            titles
+        };
+
+        assert_eq!(
+            titles(nb.all_notes(Some("project")).collect()),
+            ["A", "B", "D"]
+        );
+        assert_eq!(
+            titles(nb.all_notes(Some("project/weave")).collect()),
+            ["A", "B"]
+        );
+        assert_eq!(
+            titles(nb.notes_with_tags(&["PROJECT/weave/ui"]).collect()),
+            ["A"]
+        );
+        assert!(!nb.note("a").unwrap().has("project"));
+        assert!(nb.note("a").unwrap().has_within("project"));
+
+        let query = |expr: &str| titles(nb.query(&NoteQuery::parse(expr).unwrap()));
+        assert_eq!(query("#project/weave"), ["A", "B"]);
+        assert_eq!(query("#project -#project/weave/ui"), ["B", "D"]);
+        assert_eq!(query("#proj*"), ["A", "B", "C", "D"]);
+    }
+
+    #[test]
+    fn test_notes_with_tags() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+
+        let both: Vec<&Note> = nb.notes_with_tags(&["rust", "testing"]).collect();
+        assert_eq!(both.len(), 1);
+        assert_eq!(both[0].title(), "First Note");
+    }
+
+    #[test]
+    fn test_hidden_dir_skipped() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        assert!(nb.note("secret").is_none());
+    }
+
+    #[test]
+    fn test_note_fields() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        let note = nb.note("note1").unwrap();
+
+        assert!(note.word_count() > 0);
+        assert_eq!(note.link(), "[First Note](note1)");
+        assert_eq!(note.lead(), "# First Note");
+        assert!(note.raw_content().contains("First Note"));
+    }
+
+    #[test]
+    fn test_heading_title() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        let note = nb.note("note2").unwrap();
+        assert_eq!(note.title(), "Second Note");
+        assert_eq!(note.body(), "Another body here.");
+    }
+
+    #[test]
+    fn test_subdirectory_note() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        let note = nb.note("note3").unwrap();
+        assert_eq!(note.title(), "Sub Note");
+        assert!(note.path().to_string_lossy().contains("subdir"));
+    }
+
+    #[test]
+    fn test_reload_existing() {
+        let dir = setup_notebook();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+
+        // Modify the file
+        fs::write(
+            dir.path().join("note1.md"),
+            "---\ntitle: Updated Title\ntags: [updated]\ndate: 2024-06-15\n---\nNew body.",
+        )
+        .unwrap();
+
+        nb.reload("note1.md").unwrap();
+        let note = nb.note("note1").unwrap();
+        assert_eq!(note.title(), "Updated Title");
+        assert_eq!(note.tags(), &["updated"]);
+    }
+
+    #[test]
+    fn test_reload_new_note() {
+        let dir = setup_notebook();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+        assert_eq!(nb.all_notes(None).count(), 3);
+
+        // Add a new file
+        fs::write(
+            dir.path().join("note4.md"),
+            "---\ntitle: Brand New\n---\nNew note content.",
+        )
+        .unwrap();
+
+        nb.reload("note4.md").unwrap();
+        assert_eq!(nb.all_notes(None).count(), 4);
+        let note = nb.note("note4").unwrap();
+        assert_eq!(note.title(), "Brand New");
+    }
+
+    #[test]
+    fn test_search_titles() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+
+        let first = nb.search_titles("First", None).next();
+        assert!(first.is_some());
+        assert_eq!(first.unwrap().title(), "First Note");
+    }
+
+    #[test]
+    fn test_search_titles_with_tag() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+
+        let results: Vec<&Note> = nb.search_titles("Note", Some("testing")).collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title(), "First Note");
     }
 
     #[test]
-    fn test_heading_title() {
+    fn test_search_empty_query() {
         let dir = setup_notebook();
         let nb = Notebook::load(dir.path()).unwrap();
-        let note = nb.note("note2").unwrap();
-        assert_eq!(note.title(), "Second Note");
-        assert_eq!(note.body(), "Another body here.");
+
+        assert_eq!(nb.search_titles("", None).count(), 3);
+    }
+
+    fn search_paths(nb: &Notebook, query: &str, with_tag: Option<&str>) -> Vec<PathBuf> {
+        nb.search(query, with_tag)
+            .iter()
+            .map(|hit| hit.note.path().to_path_buf())
+            .collect()
     }
 
     #[test]
-    fn test_subdirectory_note() {
+    fn test_search_body() {
         let dir = setup_notebook();
         let nb = Notebook::load(dir.path()).unwrap();
-        let note = nb.note("note3").unwrap();
-        assert_eq!(note.title(), "Sub Note");
-        assert!(note.path().to_string_lossy().contains("subdir"));
+
+        // The shorter note ranks first.
+        assert_eq!(
+            search_paths(&nb, "BODY", None),
+            [PathBuf::from("note2.md"), PathBuf::from("note1.md")]
+        );
+        assert_eq!(
+            search_paths(&nb, "body here", None),
+            [PathBuf::from("note2.md")]
+        );
+        assert_eq!(
+            search_paths(&nb, "body", Some("testing")),
+            [PathBuf::from("note1.md")]
+        );
+        assert!(search_paths(&nb, "missing", None).is_empty());
+        assert!(search_paths(&nb, "", None).is_empty());
     }
 
     #[test]
-    fn test_reload_existing() {
+    fn test_search_phrase_and_prefix() {
         let dir = setup_notebook();
-        let mut nb = Notebook::load(dir.path()).unwrap();
+        let nb = Notebook::load(dir.path()).unwrap();
 
-        // Modify the file
+        assert_eq!(
+            search_paths(&nb, "\"second paragraph\"", None),
+            [PathBuf::from("note1.md")]
+        );
+        assert!(search_paths(&nb, "\"paragraph second\"", None).is_empty());
+        assert_eq!(
+            search_paths(&nb, "para*", None),
+            [PathBuf::from("note1.md")]
+        );
+        assert!(search_paths(&nb, "para", None).is_empty());
+    }
+
+    #[test]
+    fn test_search_ranking_and_snippet() {
+        let dir = setup_notebook();
+        fs::write(dir.path().join("a.md"), "# A\n\nrust rust rust").unwrap();
         fs::write(
-            dir.path().join("note1.md"),
-            "---\ntitle: Updated Title\ntags: [updated]\ndate: 2024-06-15\n---\nNew body.",
+            dir.path().join("b.md"),
+            "# B\n\nrust and many other words here",
         )
         .unwrap();
+        let nb = Notebook::load(dir.path()).unwrap();
+
+        let hits = nb.search("rust", None);
+        assert_eq!(hits[0].note.path(), Path::new("a.md"));
+        assert_eq!(hits[1].note.path(), Path::new("b.md"));
+        assert_eq!(hits[1].snippet.highlights.len(), 1);
+        assert_eq!(
+            &hits[1].snippet.text[hits[1].snippet.highlights[0].clone()],
+            "rust"
+        );
+    }
+
+    #[test]
+    fn test_search_follows_reload_and_remove() {
+        let dir = setup_notebook();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+
+        fs::write(dir.path().join("note2.md"), "# Second Note\n\nNew words.").unwrap();
+        nb.reload("note2.md").unwrap();
+        assert_eq!(search_paths(&nb, "body", None), [PathBuf::from("note1.md")]);
+        assert_eq!(
+            search_paths(&nb, "words", None),
+            [PathBuf::from("note2.md")]
+        );
+
+        fs::write(dir.path().join("note4.md"), "More words.").unwrap();
+        nb.reload("note4.md").unwrap();
+        assert_eq!(search_paths(&nb, "words", None).len(), 2);
+
+        nb.remove("note2.md").unwrap();
+        assert_eq!(
+            search_paths(&nb, "words", None),
+            [PathBuf::from("note4.md")]
+        );
+    }
 
-        nb.reload("note1").unwrap();
+    #[test]
+    fn test_has_tag() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
         let note = nb.note("note1").unwrap();
-        assert_eq!(note.title(), "Updated Title");
-        assert_eq!(note.tags(), &["updated"]);
+        assert!(note.has("rust"));
+        assert!(note.has("Rust")); // case-insensitive
+        assert!(!note.has("nonexistent"));
     }
 
     #[test]
-    fn test_reload_new_note() {
+    fn test_format_note() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        let note = nb.note("note1").unwrap();
+
+        let line = note
+            .format("{{title}} {{path}} {{join tags}} {{format-date created \"year\"}}")
+            .unwrap();
+        assert_eq!(line, "First Note note1.md rust, testing 2024");
+        assert_eq!(
+            note.format("{{word-count}}").unwrap(),
+            note.word_count().to_string()
+        );
+        assert!(note.format("{{#if}}").is_err());
+    }
+
+    #[test]
+    fn test_all_notes_no_filter() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        assert_eq!(nb.all_notes(None).count(), 3);
+    }
+
+    #[test]
+    fn test_remove_note() {
         let dir = setup_notebook();
         let mut nb = Notebook::load(dir.path()).unwrap();
         assert_eq!(nb.all_notes(None).count(), 3);
 
-        // Add a new file
+        nb.remove("note1.md").unwrap();
+
+        assert_eq!(nb.all_notes(None).count(), 2);
+        assert!(nb.note("note1").is_none());
+        // Tag index is cleaned up
+        assert_eq!(nb.all_notes(Some("testing")).count(), 0);
+        // Other notes are still accessible
+        assert!(nb.note("note2").is_some());
+    }
+
+    #[test]
+    fn test_remove_nonexistent() {
+        let dir = setup_notebook();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+        // Should be a no-op
+        nb.remove("doesnotexist.md").unwrap();
+        assert_eq!(nb.all_notes(None).count(), 3);
+    }
+
+    #[test]
+    fn test_change_events() {
+        let dir = setup_notebook();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+
+        fs::write(dir.path().join("note2.md"), "# Second Note\n\nNew body.").unwrap();
+        assert_eq!(
+            nb.reload("note2.md").unwrap(),
+            NotebookEvent::Modified {
+                path: PathBuf::from("note2.md"),
+                changes: NoteChanges::default(),
+            }
+        );
+        fs::write(dir.path().join("note2.md"), "# Renamed\n\n#tag [[note1]]").unwrap();
+        let Ok(NotebookEvent::Modified { changes, .. }) = nb.reload("note2.md") else {
+            panic!("not modified");
+        };
+        assert!(changes.title && changes.tags && changes.links);
+
+        fs::write(dir.path().join("note4.md"), "# Fourth").unwrap();
+        let written = FileChange::Written(dir.path().join("note4.md"));
+        assert_eq!(
+            nb.apply(&written).unwrap(),
+            Some(NotebookEvent::Created {
+                path: PathBuf::from("note4.md")
+            })
+        );
+
+        // The note keeps its backlinks when renamed.
+        fs::rename(dir.path().join("note1.md"), dir.path().join("first.md")).unwrap();
+        let renamed = FileChange::Renamed {
+            from: PathBuf::from("note1.md"),
+            to: PathBuf::from("first.md"),
+        };
+        assert_eq!(
+            nb.apply(&renamed).unwrap(),
+            Some(NotebookEvent::Renamed {
+                from: PathBuf::from("note1.md"),
+                to: PathBuf::from("first.md"),
+            })
+        );
+        assert!(nb.note("note1").is_none());
+        assert_eq!(nb.all_notes(None).count(), 4);
+        fs::write(dir.path().join("note1.md"), "# Again").unwrap();
+        nb.reload("note1.md").unwrap();
+        let first = nb.note("first").unwrap();
+        assert!(nb.backlinks(first).is_empty());
+
+        let removed = FileChange::Removed(PathBuf::from("note4.md"));
+        assert!(matches!(
+            nb.apply(&removed).unwrap(),
+            Some(NotebookEvent::Removed { .. })
+        ));
+        assert_eq!(nb.apply(&removed).unwrap(), None);
+        let hidden = FileChange::Written(dir.path().join(".hidden/secret.md"));
+        assert_eq!(nb.apply(&hidden).unwrap(), None);
+        let vanished = FileChange::Written(PathBuf::from("note2.md"));
+        fs::remove_file(dir.path().join("note2.md")).unwrap();
+        assert!(matches!(
+            nb.apply(&vanished).unwrap(),
+            Some(NotebookEvent::Removed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_attachments() {
+        let dir = setup_notebook();
+        fs::create_dir(dir.path().join("files")).unwrap();
+        fs::write(dir.path().join("files/cat.png"), "png").unwrap();
+        fs::write(dir.path().join("files/unused.pdf"), "pdf").unwrap();
+        fs::write(dir.path().join(".zk/hidden.png"), "png").unwrap();
+        fs::write(
+            dir.path().join("subdir/pics.md"),
+            "# Pics\n\n![cat](../files/cat.png) ![dog](/files/dog.png)",
+        )
+        .unwrap();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+        assert_eq!(nb.attachments().count(), 0);
+        nb.set_attachments_dir("files").unwrap();
+
+        let paths: Vec<&Path> = nb.attachments().map(Attachment::path).collect();
+        assert_eq!(
+            paths,
+            [Path::new("files/cat.png"), Path::new("files/unused.pdf")]
+        );
+        assert_eq!(nb.attachment("files/cat.png").unwrap().len(), 3);
+        let sources = nb.attachment_references("files/cat.png");
+        assert_eq!(sources[0].path(), Path::new("subdir/pics.md"));
+        // Missing attachments are referenced too.
+        assert_eq!(nb.attachment_references("files/dog.png").len(), 1);
+        let unreferenced: Vec<&Path> = nb
+            .unreferenced_attachments()
+            .into_iter()
+            .map(Attachment::path)
+            .collect();
+        assert_eq!(unreferenced, [Path::new("files/unused.pdf")]);
+
+        fs::write(dir.path().join("files/dog.png"), "png").unwrap();
+        let written = FileChange::Written(dir.path().join("files/dog.png"));
+        assert_eq!(nb.apply(&written).unwrap(), None);
+        assert!(nb.attachment("files/dog.png").is_some());
+        fs::rename(dir.path().join("files/cat.png"), dir.path().join("cat.png")).unwrap();
+        let renamed = FileChange::Renamed {
+            from: dir.path().join("files/cat.png"),
+            to: dir.path().join("cat.png"),
+        };
+        nb.apply(&renamed).unwrap();
+        assert!(nb.attachment("files/cat.png").is_none());
+        // Moved out of the attachments directory.
+        assert!(nb.attachment("cat.png").is_none());
+        assert_eq!(nb.unreferenced_attachments().len(), 1);
+
+        nb.delete_attachment("files/unused.pdf").unwrap();
+        assert!(!dir.path().join("files/unused.pdf").exists());
+        assert!(nb.attachment("files/unused.pdf").is_none());
+        assert!(matches!(
+            nb.delete_attachment("note1.md"),
+            Err(Error::AttachmentNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_unreferenced_attachments_link_syntaxes() {
+        let dir = setup_notebook();
+        fs::create_dir_all(dir.path().join("files/img")).unwrap();
+        for file in [
+            "files/wiki.png",
+            "files/doc.pdf",
+            "files/img/ref.jpg",
+            "files/img/html.png",
+            "files/unused.txt",
+            "script.sh",
+        ] {
+            fs::write(dir.path().join(file), "data").unwrap();
+        }
+        fs::write(
+            dir.path().join("subdir/refs.md"),
+            "# Refs\n\n![[wiki.png]] and [[doc.pdf|the doc]]\n\n\
+             [pic]: /files/img/ref.jpg\n\n\
+             <img src=\"../files/img/html.png\" width=\"100\">",
+        )
+        .unwrap();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+        nb.set_attachments_dir("files").unwrap();
+
+        for path in [
+            "files/wiki.png",
+            "files/doc.pdf",
+            "files/img/ref.jpg",
+            "files/img/html.png",
+        ] {
+            assert_eq!(nb.attachment_references(path).len(), 1, "{path}");
+        }
+        let unreferenced: Vec<PathBuf> = nb
+            .unreferenced_attachments()
+            .into_iter()
+            .map(|attachment| attachment.path().to_path_buf())
+            .collect();
+        assert_eq!(unreferenced, [PathBuf::from("files/unused.txt")]);
+
+        for path in &unreferenced {
+            nb.delete_attachment(path).unwrap();
+        }
+        for file in [
+            "files/wiki.png",
+            "files/doc.pdf",
+            "files/img/ref.jpg",
+            "files/img/html.png",
+            "script.sh",
+            "note1.md",
+        ] {
+            assert!(dir.path().join(file).exists(), "{file} was deleted");
+        }
+        assert!(!dir.path().join("files/unused.txt").exists());
+        assert!(matches!(
+            nb.delete_attachment("script.sh"),
+            Err(Error::AttachmentNotFound(_))
+        ));
+        assert!(matches!(
+            nb.set_attachments_dir("../outside"),
+            Err(Error::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_match_renames() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+
+        // Copied and deleted, in either order, next to an unrelated change.
+        fs::copy(dir.path().join("note2.md"), dir.path().join("second.md")).unwrap();
+        fs::remove_file(dir.path().join("note2.md")).unwrap();
+        fs::write(dir.path().join("other.md"), "# Other").unwrap();
+        let changes = nb.match_renames(vec![
+            FileChange::Written(dir.path().join("other.md")),
+            FileChange::Written(dir.path().join("second.md")),
+            FileChange::Removed(dir.path().join("note2.md")),
+        ]);
+        assert_eq!(
+            changes,
+            [
+                FileChange::Written(dir.path().join("other.md")),
+                FileChange::Renamed {
+                    from: dir.path().join("note2.md"),
+                    to: dir.path().join("second.md"),
+                },
+            ]
+        );
+
+        // A new note with other content is not the removed one.
+        fs::remove_file(dir.path().join("note1.md")).unwrap();
+        let changes = vec![
+            FileChange::Removed(dir.path().join("note1.md")),
+            FileChange::Written(dir.path().join("other.md")),
+        ];
+        assert_eq!(nb.match_renames(changes.clone()), changes);
+    }
+
+    #[test]
+    fn test_remove_preserves_remaining_indices() {
+        // Verify that swap_remove correctly updates the stems index for the
+        // note that gets moved.
+        let dir = setup_notebook();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+
+        nb.remove("note1.md").unwrap();
+
+        // All remaining notes must still be reachable by stem.
+        for note in nb.all_notes(None) {
+            let stem = note.filename_stem().to_owned();
+            assert!(
+                nb.note(&stem).is_some(),
+                "stem {stem} unreachable after remove"
+            );
+        }
+    }
+
+    /// Assert that the indices of `nb` equal the ones rebuilt from its notes.
+    fn assert_indices_rebuilt(nb: &mut Notebook, step: &str) {
+        let indices = |nb: &Notebook| {
+            (
+                nb.paths.clone(),
+                nb.stems.clone(),
+                nb.tags.clone(),
+                nb.backlinks.clone(),
+                nb.targets.clone(),
+                nb.links.clone(),
+                nb.attachment_refs.clone(),
+                nb.attachment_names.clone(),
+            )
+        };
+        let updated = indices(nb);
+        nb.reindex();
+        assert_eq!(updated, indices(nb), "indices differ after {step}");
+    }
+
+    #[test]
+    fn test_incremental_indices_match_rebuild() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join(".zk")).unwrap();
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        fs::write(
+            dir.path().join("a.md"),
+            "# A\n\n[[b]] [[sub/c]] [[Later]] [[dup]] ![img](img.png) #one",
+        )
+        .unwrap();
+        fs::write(dir.path().join("b.md"), "# B\n\n[[a]] [[Alias C]] #two").unwrap();
+        fs::write(
+            dir.path().join("sub/c.md"),
+            "---\naliases: [Alias C]\n---\n# C\n\n[[../a]] [[c]] #one",
+        )
+        .unwrap();
+        fs::write(dir.path().join("dup.md"), "# Dup\n\n[[b]]").unwrap();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+        assert_indices_rebuilt(&mut nb, "load");
+
+        nb.save("later.md", "# Later\n\n[[a]] ![img](img.png)")
+            .unwrap();
+        assert_indices_rebuilt(&mut nb, "creating a linked note");
+        assert_eq!(
+            nb.backlinks(&nb.notes[nb.paths[Path::new("later.md")]])
+                .len(),
+            1
+        );
+
+        nb.save("sub/dup.md", "# Other dup").unwrap();
+        assert_indices_rebuilt(&mut nb, "creating an ambiguous stem");
+
+        nb.save("b.md", "# Renamed B\n\n[[Later]] #three").unwrap();
+        assert_indices_rebuilt(&mut nb, "changing links and tags");
+
+        nb.save("sub/c.md", "---\naliases: [Other]\n---\n# C")
+            .unwrap();
+        assert_indices_rebuilt(&mut nb, "changing an alias");
+
+        fs::rename(dir.path().join("dup.md"), dir.path().join("moved.md")).unwrap();
+        nb.reload_renamed("dup.md", "moved.md").unwrap();
+        assert_indices_rebuilt(&mut nb, "a rename");
+
+        nb.remove("a.md").unwrap();
+        assert_indices_rebuilt(&mut nb, "removing a note");
+
+        let last = nb.notes.last().unwrap().path().to_path_buf();
+        nb.remove(&last).unwrap();
+        assert_indices_rebuilt(&mut nb, "removing the last note");
+    }
+
+    #[test]
+    fn test_config_loaded() {
+        let dir = setup_notebook();
+        fs::write(
+            dir.path().join(".zk").join("config.toml"),
+            "[note]\nid-length = 6\n[filter]\nrecents = \"--sort created-\"",
+        )
+        .unwrap();
+
+        let nb = Notebook::load(dir.path()).unwrap();
+        assert_eq!(nb.config().note.id_length, 6);
+        assert!(nb.config().filters.contains_key("recents"));
+    }
+
+    #[test]
+    fn test_config_missing_uses_defaults() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        assert_eq!(nb.config().note.filename, "{{id}}");
+        assert!(nb.config().format.markdown.hashtags);
+    }
+
+    #[test]
+    fn test_demo_notebook_tags() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../notebook");
+        let nb = Notebook::load(root).unwrap();
+
+        let public: Vec<&str> = nb
+            .all_notes(Some("public"))
+            .map(|note| note.filename_stem())
+            .collect();
+        assert!(public.contains(&"installation"), "{public:?}");
+        assert!(
+            nb.all_notes(Some("archived"))
+                .any(|note| note.filename_stem() == "archived")
+        );
+    }
+
+    #[test]
+    fn test_demo_notebook_links() {
+        // The syntax docs show links in code spans and images.
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../notebook");
+        let nb = Notebook::load(root).unwrap();
+        let dead: Vec<String> = nb
+            .dead_links()
+            .into_iter()
+            .map(|link| link.target)
+            .collect();
+        assert!(dead.is_empty(), "{dead:?}");
+    }
+
+    #[test]
+    fn test_config_exclude() {
+        let dir = setup_notebook();
+        fs::write(
+            dir.path().join(".zk/config.toml"),
+            "[note]\nexclude = [\"drafts\", \"log.md\"]",
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("drafts")).unwrap();
+        fs::write(dir.path().join("drafts/idea.md"), "# Idea").unwrap();
+        fs::write(dir.path().join("log.md"), "# Log").unwrap();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+
+        assert!(nb.note("idea").is_none());
+        assert!(nb.note("log").is_none());
+        assert!(nb.note("note1").is_some());
+
+        fs::write(dir.path().join("drafts/later.md"), "# Later").unwrap();
+        let written = FileChange::Written(dir.path().join("drafts/later.md"));
+        assert_eq!(nb.apply(&written).unwrap(), None);
+        assert!(nb.note("later").is_none());
+    }
+
+    #[test]
+    fn test_config_markdown_flags() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(".zk")).unwrap();
+        fs::write(dir.path().join("note.md"), "# Note\n\n#hash :colon:").unwrap();
+
+        let nb = Notebook::load(dir.path()).unwrap();
+        assert_eq!(nb.note("note").unwrap().tags(), &["hash"]);
+
+        fs::write(
+            dir.path().join(".zk").join("config.toml"),
+            "[format.markdown]\nhashtags = false\ncolon-tags = true",
+        )
+        .unwrap();
+
+        let nb = Notebook::load(dir.path()).unwrap();
+        assert_eq!(nb.note("note").unwrap().tags(), &["colon"]);
+    }
+
+    #[test]
+    fn test_create_default_id() {
+        let dir = setup_notebook();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+
+        let note = nb
+            .create(NewNote {
+                content: "# Created\n".into(),
+                ..NewNote::default()
+            })
+            .unwrap();
+
+        assert_eq!(note.title(), "Created");
+        assert_eq!(note.filename_stem().len(), 4);
+        let stem = note.filename_stem().to_owned();
+        assert!(dir.path().join(format!("{stem}.md")).is_file());
+        assert!(nb.note(&stem).is_some());
+        assert_eq!(nb.all_notes(None).count(), 4);
+    }
+
+    #[test]
+    fn test_create_slug_filename_collision() {
+        let dir = setup_notebook();
+        fs::write(
+            dir.path().join(".zk").join("config.toml"),
+            "[note]\nfilename = \"{{ slug title }}\"",
+        )
+        .unwrap();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+
+        let new = NewNote {
+            title: Some("Hello World".into()),
+            content: "# Hello World\n".into(),
+            ..NewNote::default()
+        };
+
+        let first = nb.create(new.clone()).unwrap().filename_stem().to_owned();
+        let second = nb.create(new).unwrap().filename_stem().to_owned();
+
+        assert_eq!(first, "hello-world");
+        assert_eq!(second, "hello-world-2");
+    }
+
+    #[test]
+    fn test_create_id_options() {
+        let dir = setup_notebook();
         fs::write(
-            dir.path().join("note4.md"),
-            "---\ntitle: Brand New\n---\nNew note content.",
+            dir.path().join(".zk").join("config.toml"),
+            "[note]\nid-charset = \"numbers\"\nid-length = 8",
         )
         .unwrap();
+        let mut nb = Notebook::load(dir.path()).unwrap();
 
-        nb.reload("note4").unwrap();
-        assert_eq!(nb.all_notes(None).count(), 4);
-        let note = nb.note("note4").unwrap();
-        assert_eq!(note.title(), "Brand New");
+        let note = nb.create(NewNote::default()).unwrap();
+        assert_eq!(note.filename_stem().len(), 8);
+        assert!(note.filename_stem().chars().all(|c| c.is_ascii_digit()));
     }
 
     #[test]
-    fn test_search_titles() {
+    fn test_create_in_group() {
         let dir = setup_notebook();
-        let nb = Notebook::load(dir.path()).unwrap();
+        fs::write(
+            dir.path().join(".zk").join("config.toml"),
+            "[group.journal]\npaths = [\"journal\"]\n[group.journal.note]\nfilename = \"{{format-date now 'year'}}-{{id}}\"",
+        )
+        .unwrap();
+        let mut nb = Notebook::load(dir.path()).unwrap();
 
-        let first = nb.search_titles("First", None).next();
-        assert!(first.is_some());
-        assert_eq!(first.unwrap().title(), "First Note");
+        let note = nb
+            .create(NewNote {
+                group: Some("journal".into()),
+                ..NewNote::default()
+            })
+            .unwrap();
+
+        assert!(note.path().starts_with("journal"));
+        let year = jiff::Zoned::now().year().to_string();
+        assert!(note.filename_stem().starts_with(&year));
     }
 
     #[test]
-    fn test_search_titles_with_tag() {
+    fn test_create_with_invalid_date_format() {
         let dir = setup_notebook();
-        let nb = Notebook::load(dir.path()).unwrap();
+        fs::write(
+            dir.path().join(".zk").join("config.toml"),
+            "[note]\nfilename = \"{{format-date now '%E'}}-{{id}}\"",
+        )
+        .unwrap();
+        let mut nb = Notebook::load(dir.path()).unwrap();
 
-        let results: Vec<&Note> = nb.search_titles("Note", Some("testing")).collect();
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0].title(), "First Note");
+        let result = nb.create(NewNote::default());
+        assert!(matches!(result, Err(Error::Template { .. })));
     }
 
     #[test]
-    fn test_search_empty_query() {
+    fn test_invalid_link_format() {
         let dir = setup_notebook();
+        fs::write(
+            dir.path().join(".zk").join("config.toml"),
+            "[format.markdown]\nlink-format = \"{{#if}}\"",
+        )
+        .unwrap();
         let nb = Notebook::load(dir.path()).unwrap();
 
-        assert_eq!(nb.search_titles("", None).count(), 3);
-    }
+        let note = nb.note("note2").unwrap();
+        assert_eq!(note.link(), "[Second Note](note2)");
+        assert!(matches!(
+            nb.config_errors().as_slice(),
+            [Error::Template { name, .. }] if name == "link-format"
+        ));
 
-    #[test]
-    fn test_has_tag() {
         let dir = setup_notebook();
-        let nb = Notebook::load(dir.path()).unwrap();
-        let note = nb.note("note1").unwrap();
-        assert!(note.has("rust"));
-        assert!(note.has("Rust")); // case-insensitive
-        assert!(!note.has("nonexistent"));
+        assert!(
+            Notebook::load(dir.path())
+                .unwrap()
+                .config_errors()
+                .is_empty()
+        );
     }
 
     #[test]
-    fn test_all_notes_no_filter() {
+    fn test_create_rejects_escaping_dir() {
         let dir = setup_notebook();
-        let nb = Notebook::load(dir.path()).unwrap();
-        assert_eq!(nb.all_notes(None).count(), 3);
+        let mut nb = Notebook::load(dir.path()).unwrap();
+
+        let result = nb.create(NewNote {
+            dir: Some(PathBuf::from("../outside")),
+            ..NewNote::default()
+        });
+        assert!(matches!(result, Err(Error::InvalidPath(_))));
     }
 
     #[test]
-    fn test_remove_note() {
+    fn test_create_with_configured_template() {
         let dir = setup_notebook();
+        let zk_dir = dir.path().join(".zk");
+        fs::create_dir(zk_dir.join("templates")).unwrap();
+        fs::write(
+            zk_dir.join("templates").join("default.md"),
+            "---\nauthor: {{extra.author}}\n---\n# {{title}}\n\n{{content}}\n",
+        )
+        .unwrap();
+        fs::write(
+            zk_dir.join("config.toml"),
+            "[note]\ntemplate = \"default.md\"\n[extra]\nauthor = \"me\"",
+        )
+        .unwrap();
         let mut nb = Notebook::load(dir.path()).unwrap();
-        assert_eq!(nb.all_notes(None).count(), 3);
 
-        nb.remove("note1");
+        let note = nb
+            .create(NewNote {
+                title: Some("From Template".into()),
+                content: "Some <content> & more.".into(),
+                ..NewNote::default()
+            })
+            .unwrap();
 
-        assert_eq!(nb.all_notes(None).count(), 2);
-        assert!(nb.note("note1").is_none());
-        // Tag index is cleaned up
-        assert_eq!(nb.all_notes(Some("testing")).count(), 0);
-        // Other notes are still accessible
-        assert!(nb.note("note2").is_some());
+        assert_eq!(note.title(), "From Template");
+        assert_eq!(note.body(), "Some <content> & more.");
+        assert!(note.raw_content().contains("author: me"));
     }
 
     #[test]
-    fn test_remove_nonexistent() {
+    fn test_create_with_explicit_template() {
         let dir = setup_notebook();
+        let templates = dir.path().join(".zk").join("templates");
+        fs::create_dir(&templates).unwrap();
+        fs::write(templates.join("daily.md"), "# Daily {{filename-stem}}\n").unwrap();
         let mut nb = Notebook::load(dir.path()).unwrap();
-        // Should be a no-op
-        nb.remove("doesnotexist");
-        assert_eq!(nb.all_notes(None).count(), 3);
+
+        assert_eq!(nb.templates().unwrap(), vec!["daily.md"]);
+
+        let note = nb
+            .create(NewNote {
+                template: Some("daily.md".into()),
+                ..NewNote::default()
+            })
+            .unwrap();
+
+        let stem = note.filename_stem().to_owned();
+        assert_eq!(note.title(), format!("Daily {stem}"));
     }
 
     #[test]
-    fn test_remove_preserves_remaining_indices() {
-        // Verify that swap_remove correctly updates the stems index for the
-        // note that gets moved.
+    fn test_create_rejects_escaping_template() {
         let dir = setup_notebook();
         let mut nb = Notebook::load(dir.path()).unwrap();
 
-        nb.remove("note1");
-
-        // All remaining notes must still be reachable by stem.
-        for note in nb.all_notes(None) {
-            let stem = note.filename_stem().to_owned();
-            assert!(
-                nb.note(&stem).is_some(),
-                "stem {stem} unreachable after remove"
-            );
-        }
+        let result = nb.create(NewNote {
+            template: Some("../config.toml".into()),
+            ..NewNote::default()
+        });
+        assert!(matches!(result, Err(Error::InvalidPath(_))));
     }
 
     #[test]
@@ -587,7 +2892,7 @@ mod tests {
         .unwrap();
 
         let nb = Notebook::load(dir.path()).unwrap();
-        let backlinks = nb.backlinks("target");
+        let backlinks = nb.backlinks(nb.note("target").unwrap());
         assert_eq!(backlinks.len(), 1);
         assert_eq!(backlinks[0].filename_stem(), "source");
     }
@@ -597,7 +2902,7 @@ mod tests {
         let dir = setup_notebook();
         let nb = Notebook::load(dir.path()).unwrap();
         // note1 doesn't link to note2, so note2 should have no backlinks
-        assert!(nb.backlinks("note2").is_empty());
+        assert!(nb.backlinks(nb.note("note2").unwrap()).is_empty());
     }
 
     #[test]
@@ -614,10 +2919,10 @@ mod tests {
         fs::write(dir.path().join("target.md"), "# Target\n\nBody.").unwrap();
 
         let mut nb = Notebook::load(dir.path()).unwrap();
-        assert_eq!(nb.backlinks("target").len(), 1);
+        assert_eq!(nb.backlinks(nb.note("target").unwrap()).len(), 1);
 
-        nb.remove("source");
-        assert!(nb.backlinks("target").is_empty());
+        nb.remove("source.md").unwrap();
+        assert!(nb.backlinks(nb.note("target").unwrap()).is_empty());
     }
 
     #[test]
@@ -636,10 +2941,10 @@ mod tests {
         fs::write(dir.path().join("target.md"), "# Target\n\nBody.").unwrap();
 
         let mut nb = Notebook::load(dir.path()).unwrap();
-        assert_eq!(nb.backlinks("target").len(), 1);
+        assert_eq!(nb.backlinks(nb.note("target").unwrap()).len(), 1);
 
         // Simulate editor delete+recreate cycle
-        nb.remove("target");
+        nb.remove("target.md").unwrap();
         assert!(nb.note("target").is_none());
 
         fs::write(
@@ -647,11 +2952,686 @@ mod tests {
             "# Target Updated\n\nNew body.",
         )
         .unwrap();
-        nb.reload("target").unwrap();
+        nb.reload("target.md").unwrap();
 
         // Backlink from source must still be present
-        let backlinks = nb.backlinks("target");
+        let backlinks = nb.backlinks(nb.note("target").unwrap());
         assert_eq!(backlinks.len(), 1);
         assert_eq!(backlinks[0].filename_stem(), "source");
     }
+
+    fn setup_linked_notebook() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(".zk")).unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+
+        fs::write(
+            dir.path().join("a/source.md"),
+            "# Source\n\n[rel](b/deep) [root](top) [[Deep Thoughts]] [[shortcut]] [[Twin]]",
+        )
+        .unwrap();
+        fs::write(dir.path().join("a/b/deep.md"), "# Deep Thoughts\n").unwrap();
+        fs::write(
+            dir.path().join("top.md"),
+            "---\naliases: [Shortcut]\n---\n# Top\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("twin1.md"), "# Twin\n").unwrap();
+        fs::write(dir.path().join("a/twin2.md"), "# twin\n").unwrap();
+
+        dir
+    }
+
+    fn query_paths(nb: &Notebook, query: &NoteQuery) -> Vec<PathBuf> {
+        nb.query(query)
+            .iter()
+            .map(|note| note.path().to_path_buf())
+            .collect()
+    }
+
+    #[test]
+    fn test_query_tags_and_sort() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        let by_path = Sort::parse("path").unwrap();
+
+        let query = NoteQuery::new().tag("rust").sort(by_path);
+        assert_eq!(
+            query_paths(&nb, &query),
+            [PathBuf::from("note1.md"), PathBuf::from("subdir/note3.md")]
+        );
+
+        let query = NoteQuery::new().tag("rust, NOT testing");
+        assert_eq!(query_paths(&nb, &query), [PathBuf::from("subdir/note3.md")]);
+
+        let query = NoteQuery::new().tag("testing OR missing");
+        assert_eq!(query_paths(&nb, &query), [PathBuf::from("note1.md")]);
+
+        let query = NoteQuery::new()
+            .tag("r*")
+            .sort(Sort::parse("path-").unwrap())
+            .limit(1);
+        assert_eq!(query_paths(&nb, &query), [PathBuf::from("subdir/note3.md")]);
+    }
+
+    #[test]
+    fn test_query_with_tag() {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "")
+            .with_file("a.md", "# A\n\n#public")
+            .with_file("b.md", "# B\n\n#public/draft")
+            .with_file("c.md", "# C\n\n#Public");
+        let nb = Notebook::load_from("/notebook", storage).unwrap();
+        let by_path = Sort::parse("path").unwrap();
+
+        let query = NoteQuery::new().with_tag("public").sort(by_path);
+        assert_eq!(
+            query_paths(&nb, &query),
+            [PathBuf::from("a.md"), PathBuf::from("c.md")]
+        );
+        // The limit applies to the matching notes only.
+        let query = NoteQuery::new()
+            .with_tag("public")
+            .sort(Sort::parse("path-").unwrap())
+            .limit(2);
+        assert_eq!(
+            query_paths(&nb, &query),
+            [PathBuf::from("c.md"), PathBuf::from("a.md")]
+        );
+    }
+
+    #[test]
+    fn test_query_dates_and_match() {
+        let dir = setup_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+
+        let query = NoteQuery::parse("created:2024-06").unwrap();
+        assert_eq!(query_paths(&nb, &query), [PathBuf::from("note1.md")]);
+
+        let query = NoteQuery::parse("created:<2024").unwrap();
+        assert!(query_paths(&nb, &query).is_empty());
+
+        let query = NoteQuery::parse("#rust body").unwrap();
+        assert_eq!(query_paths(&nb, &query), [PathBuf::from("note1.md")]);
+
+        let query = NoteQuery::parse("-#rust body").unwrap();
+        assert_eq!(query_paths(&nb, &query), [PathBuf::from("note2.md")]);
+    }
+
+    #[test]
+    fn test_query_metadata() {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "")
+            .with_file(
+                "draft.md",
+                "---\nStatus: Draft\npriority: 2\nsources: [web, book]\n---\n# Draft",
+            )
+            .with_file("done.md", "---\nstatus: done\n---\n# Done")
+            .with_file("plain.md", "# Plain");
+        let nb = Notebook::load_from("/notebook", storage).unwrap();
+        let by_path = Sort::parse("path").unwrap();
+
+        let draft = nb.note("draft").unwrap();
+        assert_eq!(draft.metadata()["status"], YamlValue::from("Draft"));
+        assert_eq!(draft.metadata()["priority"], YamlValue::from(2));
+
+        let query = NoteQuery::parse("status:draft").unwrap();
+        assert_eq!(query_paths(&nb, &query), [PathBuf::from("draft.md")]);
+
+        let query = NoteQuery::parse("priority:2 sources:book").unwrap();
+        assert_eq!(query_paths(&nb, &query), [PathBuf::from("draft.md")]);
+
+        let query = NoteQuery::parse("status:*").unwrap().sort(by_path);
+        assert_eq!(
+            query_paths(&nb, &query),
+            [PathBuf::from("done.md"), PathBuf::from("draft.md")]
+        );
+
+        let query = NoteQuery::new()
+            .without_metadata("status", "d*")
+            .sort(by_path);
+        assert_eq!(query_paths(&nb, &query), [PathBuf::from("plain.md")]);
+    }
+
+    #[test]
+    fn test_query_links() {
+        let dir = setup_linked_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        let by_path = Sort::parse("path").unwrap();
+
+        let query = NoteQuery::new().linked_by("a/source").sort(by_path);
+        assert_eq!(
+            query_paths(&nb, &query),
+            [PathBuf::from("a/b/deep.md"), PathBuf::from("top.md")]
+        );
+
+        let query = NoteQuery::new().link_to("top");
+        assert_eq!(query_paths(&nb, &query), [PathBuf::from("a/source.md")]);
+
+        let query = NoteQuery::parse("is:orphan sort:path").unwrap();
+        assert_eq!(
+            query_paths(&nb, &query),
+            [
+                PathBuf::from("a/source.md"),
+                PathBuf::from("a/twin2.md"),
+                PathBuf::from("twin1.md")
+            ]
+        );
+
+        let query = NoteQuery::new().linked_by("missing");
+        assert!(query_paths(&nb, &query).is_empty());
+    }
+
+    #[test]
+    fn test_link_analysis() {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "")
+            .with_file(
+                "index.md",
+                "# Index\n\n[[a]] [[b]]\n\n[gone](missing) and [[sub/nowhere]]",
+            )
+            .with_file("a.md", "# A\n\n[[b]]")
+            .with_file("b.md", "# B")
+            .with_file("lone.md", "# Lone")
+            .with_file("sub/c.md", "# C\n\n```\n[[ignored]]\n```\n");
+        let mut nb = Notebook::load_from("/notebook", storage).unwrap();
+        let paths = |notes: Vec<&Note>| -> Vec<PathBuf> {
+            notes.iter().map(|note| note.path().to_path_buf()).collect()
+        };
+
+        assert_eq!(
+            paths(nb.orphans()),
+            [
+                PathBuf::from("index.md"),
+                PathBuf::from("lone.md"),
+                PathBuf::from("sub/c.md")
+            ]
+        );
+        assert_eq!(
+            paths(nb.dead_ends()),
+            [
+                PathBuf::from("b.md"),
+                PathBuf::from("lone.md"),
+                PathBuf::from("sub/c.md")
+            ]
+        );
+
+        let dead: Vec<(String, usize)> = nb
+            .dead_links()
+            .into_iter()
+            .map(|link| (link.target, link.line))
+            .collect();
+        assert_eq!(
+            dead,
+            [("missing".to_owned(), 5), ("sub/nowhere".to_owned(), 5)]
+        );
+
+        let hubs: Vec<(&str, usize)> = nb
+            .hubs(10)
+            .into_iter()
+            .map(|(note, count)| (note.title(), count))
+            .collect();
+        assert_eq!(hubs, [("B", 2), ("A", 1)]);
+        assert_eq!(nb.hubs(1).len(), 1);
+
+        let components: Vec<Vec<PathBuf>> = nb.components().into_iter().map(paths).collect();
+        assert_eq!(
+            components,
+            [
+                vec![
+                    PathBuf::from("a.md"),
+                    PathBuf::from("b.md"),
+                    PathBuf::from("index.md")
+                ],
+                vec![PathBuf::from("lone.md")],
+                vec![PathBuf::from("sub/c.md")],
+            ]
+        );
+
+        let created = nb.create_missing("sub/nowhere", "index.md").unwrap();
+        assert_eq!(created.path(), Path::new("sub/nowhere.md"));
+        assert_eq!(created.title(), "nowhere");
+        assert_eq!(nb.dead_links().len(), 1);
+        assert!(matches!(
+            nb.create_missing("sub/nowhere", "index.md"),
+            Err(Error::NoteExists(_))
+        ));
+        assert!(matches!(
+            nb.create_missing("../outside", "index.md"),
+            Err(Error::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_links_and_neighborhood() {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "")
+            .with_file("a.md", "# A\n\n[[b]] [[a]]")
+            .with_file("b.md", "# B\n\n[[c]] [[secret]]")
+            .with_file("c.md", "# C\n\n[[d]] [[b]]")
+            .with_file("d.md", "# D")
+            .with_file("secret.md", "# Secret\n\n[[e]]")
+            .with_file("e.md", "# E");
+        let nb = Notebook::load_from("/notebook", storage).unwrap();
+
+        let links: Vec<(&str, &str)> = nb
+            .links()
+            .into_iter()
+            .map(|(source, target)| (source.title(), target.title()))
+            .collect();
+        assert_eq!(
+            links,
+            [
+                ("A", "B"),
+                ("B", "C"),
+                ("B", "Secret"),
+                ("C", "B"),
+                ("C", "D"),
+                ("Secret", "E")
+            ]
+        );
+
+        let b = nb.note("b").unwrap();
+        let around = |depth, visit: &dyn Fn(&Note) -> bool| -> Vec<(String, usize)> {
+            nb.neighborhood(b, depth, visit)
+                .into_iter()
+                .map(|(note, depth)| (note.title().to_owned(), depth))
+                .collect()
+        };
+        let all = |_: &Note| true;
+        assert_eq!(around(0, &all), [("B".to_owned(), 0)]);
+        assert_eq!(
+            around(1, &all),
+            [
+                ("B".to_owned(), 0),
+                ("A".to_owned(), 1),
+                ("C".to_owned(), 1),
+                ("Secret".to_owned(), 1)
+            ]
+        );
+        let public = |note: &Note| note.title() != "Secret";
+        let titles: Vec<String> = around(5, &public).into_iter().map(|(t, _)| t).collect();
+        assert_eq!(titles, ["B", "A", "C", "D"]);
+    }
+
+    #[test]
+    fn test_related() {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "")
+            .with_file(
+                "a.md",
+                "---\ntags: [rust, common]\n---\n# A\n\nBorrow checker. [[x]] [[y]]",
+            )
+            .with_file("b.md", "---\ntags: [Rust, common]\n---\n# B\n\n[[x]]")
+            .with_file("c.md", "---\ntags: [common]\n---\n# C")
+            .with_file("d.md", "# D\n\nThe borrow checker again.")
+            .with_file("hub.md", "# Hub\n\n[[a]] [[d]]")
+            .with_file("x.md", "# X")
+            .with_file("y.md", "# Y")
+            .with_file("other.md", "# Other\n\nSomething else.");
+        let nb = Notebook::load_from("/notebook", storage).unwrap();
+
+        let related = nb.related(nb.note("a").unwrap());
+        let titles: Vec<&str> = related.iter().map(|r| r.note.title()).collect();
+        // Link targets are words of the body too, so X, Y and Hub follow.
+        assert_eq!(titles[..3], ["B", "D", "C"]);
+        assert!(!titles.contains(&"Other"));
+
+        let b = &related[0];
+        assert_eq!(b.shared_tags, ["common", "rust"]);
+        assert!((b.tags - 1.0).abs() < 1e-9);
+        assert!((b.coupling - 1.0 / 2f64.sqrt()).abs() < 1e-9);
+        assert_eq!(b.co_citation, 0.0);
+
+        let d = &related[1];
+        assert_eq!(d.co_citation, 1.0);
+        assert!(d.similarity > 0.0);
+        assert!(d.shared_tags.is_empty());
+
+        let c = &related[2];
+        assert_eq!(c.shared_tags, ["common"]);
+        assert!(c.tags < b.tags);
+
+        assert!(nb.related(nb.note("other").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_filters() {
+        let dir = setup_notebook();
+        fs::write(
+            dir.path().join(".zk/config.toml"),
+            "# my notebook\n[filter]\nrusty = \"--tag rust --sort path\"\n",
+        )
+        .unwrap();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+
+        let query = nb.filter("rusty").unwrap();
+        assert_eq!(
+            query_paths(&nb, &query),
+            [PathBuf::from("note1.md"), PathBuf::from("subdir/note3.md")]
+        );
+        assert!(nb.filter("missing").is_err());
+
+        let query = NoteQuery::parse("#testing").unwrap();
+        nb.save_filter("testing", &query).unwrap();
+        assert_eq!(nb.config().filters["testing"], "--tag testing");
+
+        let query = NoteQuery::parse("status:draft").unwrap();
+        assert!(matches!(
+            nb.save_filter("drafts", &query),
+            Err(Error::Query(_))
+        ));
+
+        let config = fs::read_to_string(dir.path().join(".zk/config.toml")).unwrap();
+        assert!(config.starts_with("# my notebook\n"), "{config}");
+        let reloaded = Notebook::load(dir.path()).unwrap();
+        assert_eq!(
+            query_paths(&reloaded, &reloaded.filter("testing").unwrap()),
+            [PathBuf::from("note1.md")]
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = setup_linked_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+        let source = nb.note("source").unwrap();
+
+        let stem = |link: &str| {
+            nb.resolve(link, source)
+                .note()
+                .map(|n| n.filename_stem().to_owned())
+        };
+
+        assert_eq!(stem("b/deep").as_deref(), Some("deep"));
+        assert_eq!(stem("./b/deep.md").as_deref(), Some("deep"));
+        assert_eq!(stem("../top").as_deref(), Some("top"));
+        assert_eq!(stem("top").as_deref(), Some("top"));
+        assert_eq!(stem("a/b/deep").as_deref(), Some("deep"));
+        assert_eq!(stem("deep").as_deref(), Some("deep"));
+        assert_eq!(stem("deep thoughts").as_deref(), Some("deep"));
+        assert_eq!(stem("SHORTCUT").as_deref(), Some("top"));
+        assert_eq!(stem("b/deep%20thoughts"), None);
+        assert!(matches!(
+            nb.resolve("missing", source),
+            Resolution::NotFound
+        ));
+        assert!(matches!(
+            nb.resolve("../../outside", source),
+            Resolution::NotFound
+        ));
+
+        match nb.resolve("Twin", source) {
+            Resolution::Ambiguous(candidates) => assert_eq!(candidates.len(), 2),
+            other => panic!("expected ambiguity, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_resolved_backlinks() {
+        let dir = setup_linked_notebook();
+        let nb = Notebook::load(dir.path()).unwrap();
+
+        assert_eq!(nb.backlinks(nb.note("deep").unwrap()).len(), 1);
+        assert_eq!(nb.backlinks(nb.note("top").unwrap()).len(), 1);
+        assert!(nb.backlinks(nb.note("twin1").unwrap()).is_empty());
+
+        let source = nb.note("source").unwrap();
+        let outgoing: Vec<_> = nb
+            .outgoing_links(source)
+            .iter()
+            .map(|n| n.filename_stem())
+            .collect();
+        assert_eq!(outgoing, vec!["deep", "top"]);
+    }
+
+    #[test]
+    fn test_backlinks_follow_new_titles() {
+        let dir = setup_linked_notebook();
+        let mut nb = Notebook::load(dir.path()).unwrap();
+        assert!(nb.note("later").is_none());
+
+        fs::write(dir.path().join("later.md"), "# Later\n").unwrap();
+        fs::write(
+            dir.path().join("linker.md"),
+            "# Linker\n\n[[later]] and [[Later]]",
+        )
+        .unwrap();
+        nb.reload("linker.md").unwrap();
+        assert!(nb.note("later").is_none());
+
+        nb.reload("later.md").unwrap();
+        assert_eq!(nb.backlinks(nb.note("later").unwrap()).len(), 1);
+    }
+
+    #[test]
+    fn test_duplicate_stems() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(".zk")).unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        fs::create_dir(dir.path().join("b")).unwrap();
+        fs::write(dir.path().join("a/todo.md"), "# Work\n").unwrap();
+        fs::write(dir.path().join("b/todo.md"), "# Home\n").unwrap();
+        fs::write(
+            dir.path().join("index.md"),
+            "# Index\n\n[[todo]] [work](a/todo)",
+        )
+        .unwrap();
+
+        let mut nb = Notebook::load(dir.path()).unwrap();
+        assert_eq!(nb.all_notes(None).count(), 3);
+        assert!(nb.note("todo").is_none());
+        assert_eq!(nb.note("a/todo").unwrap().title(), "Work");
+        assert_eq!(nb.note("b/todo.md").unwrap().title(), "Home");
+
+        let twins: Vec<_> = nb
+            .notes_with_stem("todo")
+            .iter()
+            .map(|n| n.title())
+            .collect();
+        assert_eq!(twins, vec!["Work", "Home"]);
+
+        let index = nb.note("index").unwrap();
+        assert!(matches!(
+            nb.resolve("todo", index),
+            Resolution::Ambiguous(_)
+        ));
+        assert_eq!(nb.backlinks(nb.note("a/todo").unwrap()).len(), 1);
+        assert!(nb.backlinks(nb.note("b/todo").unwrap()).is_empty());
+
+        fs::write(dir.path().join("b/todo.md"), "# Chores\n").unwrap();
+        nb.reload(dir.path().join("b/todo.md")).unwrap();
+        assert_eq!(nb.note("a/todo").unwrap().title(), "Work");
+        assert_eq!(nb.note("b/todo").unwrap().title(), "Chores");
+
+        nb.remove("a/todo.md").unwrap();
+        assert_eq!(nb.note("todo").unwrap().title(), "Chores");
+        assert_eq!(nb.backlinks(nb.note("todo").unwrap()).len(), 1);
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "[note]\nfilename = \"{{slug title}}\"\n")
+            .with_file(".zk/templates/daily.md", "# {{title}}\n")
+            .with_file(".hidden/secret.md", "# Secret")
+            .with_file("index.md", "# Index\n\nSee [[sub/note]].")
+            .with_file("sub/note.md", "---\ntags: [rust]\n---\n# Note");
+
+        let mut nb = Notebook::load_from("/notebook", storage).unwrap();
+        assert_eq!(nb.all_notes(None).count(), 2);
+        let note = nb.note("sub/note").unwrap();
+        assert_eq!(note.abs_path(), Path::new("/notebook/sub/note.md"));
+        assert_eq!(note.tags(), ["rust"]);
+        assert_eq!(nb.backlinks(note).len(), 1);
+        assert_eq!(nb.templates().unwrap(), ["daily.md"]);
+
+        let created = nb
+            .create(NewNote {
+                title: Some("Hello World".into()),
+                template: Some("daily.md".into()),
+                ..NewNote::default()
+            })
+            .unwrap();
+        assert_eq!(created.path(), Path::new("hello-world.md"));
+        let content = nb.storage().read_to_string(Path::new("hello-world.md"));
+        assert_eq!(content.unwrap(), "# Hello World\n");
+
+        nb.save("sub/note.md", "# Renamed\n").unwrap();
+        assert_eq!(nb.note("sub/note").unwrap().title(), "Renamed");
+        assert!(nb.note("sub/note").unwrap().tags().is_empty());
+        assert!(nb.save("../escape.md", "").is_err());
+    }
+
+    fn setup_rename_notebook() -> Notebook {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "")
+            .with_file(
+                "index.md",
+                "# Index\n\n[[target|Label]], [t](target.md#part).",
+            )
+            .with_file(
+                "sub/rel.md",
+                "# Rel\n\nSee [up](../target) and [[/target]].",
+            )
+            .with_file("sub/sibling.md", "# Sibling")
+            .with_file(
+                "target.md",
+                "# Target\n\nNext to [[index]] and [s](sub/sibling).",
+            )
+            .with_file("code.md", "# Code\n\n```\n[[target]]\n```\n");
+        Notebook::load_from("/notebook", storage).unwrap()
+    }
+
+    fn read(nb: &Notebook, path: &str) -> String {
+        nb.storage().read_to_string(Path::new(path)).unwrap()
+    }
+
+    #[test]
+    fn test_rename_stem() {
+        let mut nb = setup_rename_notebook();
+        let note = nb.rename("target", "renamed").unwrap();
+        assert_eq!(note.path(), Path::new("renamed.md"));
+        assert!(nb.note("target").is_none());
+
+        assert_eq!(
+            read(&nb, "index.md"),
+            "# Index\n\n[[renamed|Label]], [t](renamed.md#part)."
+        );
+        assert_eq!(
+            read(&nb, "sub/rel.md"),
+            "# Rel\n\nSee [up](../renamed) and [[/renamed]]."
+        );
+        assert_eq!(read(&nb, "code.md"), "# Code\n\n```\n[[target]]\n```\n");
+        let renamed = nb.note("renamed").unwrap();
+        assert_eq!(nb.backlinks(renamed).len(), 2);
+    }
+
+    #[test]
+    fn test_rename_to_directory() {
+        let mut nb = setup_rename_notebook();
+        nb.rename("target", "sub/deep/moved.md").unwrap();
+
+        assert_eq!(
+            read(&nb, "index.md"),
+            "# Index\n\n[[moved|Label]], [t](sub/deep/moved.md#part)."
+        );
+        assert_eq!(
+            read(&nb, "sub/rel.md"),
+            "# Rel\n\nSee [up](deep/moved) and [[/sub/deep/moved]]."
+        );
+        // The note's own links still point to the same notes.
+        assert_eq!(
+            read(&nb, "sub/deep/moved.md"),
+            "# Target\n\nNext to [[index]] and [s](../sibling)."
+        );
+        let moved = nb.note("sub/deep/moved").unwrap();
+        assert_eq!(nb.backlinks(moved).len(), 2);
+        assert_eq!(nb.backlinks(nb.note("sub/sibling").unwrap()).len(), 1);
+    }
+
+    #[test]
+    fn test_rename_errors() {
+        let mut nb = setup_rename_notebook();
+        let err = nb.rename("missing", "other").err().unwrap();
+        assert!(matches!(err, Error::NoteNotFound(_)));
+        let err = nb.rename("target", "index").err().unwrap();
+        assert!(matches!(err, Error::NoteExists(_)));
+        let err = nb.rename("target", "../escape").err().unwrap();
+        assert!(matches!(err, Error::InvalidPath(_)));
+        assert!(nb.note("target").is_some());
+    }
+
+    #[test]
+    fn test_edit_tags() {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "[format.markdown]\ncolon-tags = true\n")
+            .with_file("a.md", "---\ntags: [rust, lang]\n---\n# A\n\n#Rust :lang:")
+            .with_file("b.md", "# B\n\n#rustlang")
+            .with_file("c.md", "# C\n\n```\n#rust\n```\n");
+        let mut nb = Notebook::load_from("/notebook", storage).unwrap();
+        let read = |nb: &Notebook, path: &str| nb.storage().read_to_string(Path::new(path));
+
+        assert_eq!(nb.rename_tag("RUST", "oxide").unwrap(), 1);
+        assert_eq!(
+            read(&nb, "a.md").unwrap(),
+            "---\ntags: [oxide, lang]\n---\n# A\n\n#oxide :lang:"
+        );
+        assert_eq!(nb.note("a").unwrap().tags(), ["oxide", "lang"]);
+        assert_eq!(read(&nb, "c.md").unwrap(), "# C\n\n```\n#rust\n```\n");
+
+        assert_eq!(nb.merge_tags(&["rustlang", "lang"], "oxide").unwrap(), 2);
+        assert_eq!(
+            read(&nb, "a.md").unwrap(),
+            "---\ntags: [oxide]\n---\n# A\n\n#oxide :oxide:"
+        );
+        assert_eq!(nb.tag_counts().collect::<Vec<_>>(), [("oxide", 2)]);
+
+        let err = nb.rename_tag("oxide", "two words").err().unwrap();
+        assert!(matches!(err, Error::TagSyntax { .. }));
+        assert!(matches!(
+            nb.rename_tag("oxide", "a, b"),
+            Err(Error::InvalidTag(_))
+        ));
+
+        assert_eq!(nb.delete_tag("#oxide").unwrap(), 2);
+        assert_eq!(read(&nb, "b.md").unwrap(), "# B\n\n");
+        assert_eq!(nb.all_tags().count(), 0);
+    }
+
+    #[test]
+    fn test_frontmatter_edit() {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "")
+            .with_file("note.md", "---\ntitle: Old\nstatus: draft\n---\nBody");
+        let mut nb = Notebook::load_from("/notebook", storage).unwrap();
+
+        let mut fm = nb.frontmatter("note.md").unwrap();
+        assert_eq!(fm.get_str("status").unwrap(), "draft");
+        fm.set("title", "New").unwrap();
+        fm.set("tags", vec!["rust"]).unwrap();
+        nb.save("note.md", &fm.to_string()).unwrap();
+
+        let note = nb.note("note").unwrap();
+        assert_eq!(note.title(), "New");
+        assert_eq!(note.tags(), ["rust"]);
+        assert_eq!(
+            note.raw_content(),
+            "---\ntitle: New\nstatus: draft\ntags: [rust]\n---\nBody"
+        );
+
+        let bad = Path::new("bad.md");
+        nb.storage()
+            .write(bad, b"---\ntitle: [\n---\nBody")
+            .unwrap();
+        let err = nb.frontmatter(bad).unwrap_err();
+        assert!(matches!(err, Error::Yaml { .. }));
+    }
+
+    #[test]
+    fn test_memory_storage_not_a_notebook() {
+        let storage = MemoryStorage::new().with_file("note.md", "# Note");
+        let err = Notebook::load_from("/notebook", storage).err().unwrap();
+        assert!(matches!(err, Error::NotANotebook(_)));
+    }
 }
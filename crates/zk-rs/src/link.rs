@@ -0,0 +1,314 @@
+//! Resolve links between notes the way zk does.
+
+use std::borrow::{Borrow, Cow};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::{Component, Path, PathBuf};
+
+use crate::note::Note;
+
+/// Outcome of [`Notebook::resolve`](crate::Notebook::resolve).
+#[derive(Debug)]
+pub enum Resolution<'a> {
+    /// Exactly one note matches the link.
+    Found(&'a Note),
+    /// Several notes match equally well.
+    Ambiguous(Vec<&'a Note>),
+    /// No note matches the link.
+    NotFound,
+}
+
+impl<'a> Resolution<'a> {
+    /// Return the note if the link resolved unambiguously.
+    pub fn note(self) -> Option<&'a Note> {
+        match self {
+            Resolution::Found(note) => Some(note),
+            Resolution::Ambiguous(_) | Resolution::NotFound => None,
+        }
+    }
+}
+
+/// Lookup tables from paths, filename stems, aliases and titles to note
+/// positions, and from the same keys to the notes with links looked up by
+/// them. Updated note by note.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct LinkIndex {
+    paths: HashMap<PathBuf, Vec<usize>>,
+    stems: HashMap<String, Vec<usize>>,
+    aliases: HashMap<String, Vec<usize>>,
+    titles: HashMap<String, Vec<usize>>,
+    /// Path a link is looked up by -> notes with such links.
+    linking_paths: HashMap<PathBuf, Vec<usize>>,
+    /// Stem a link is looked up by -> notes with such links.
+    linking_stems: HashMap<String, Vec<usize>>,
+    /// Lowercase alias or title a link is looked up by -> notes with such
+    /// links.
+    linking_names: HashMap<String, Vec<usize>>,
+}
+
+/// Keys a link is looked up by, in the order tried by
+/// [`LinkIndex::resolve`].
+struct LinkKeys<'a> {
+    /// Paths relative to the linking note's directory and to the root.
+    paths: [Option<PathBuf>; 2],
+    stem: Option<&'a str>,
+    /// Lowercase link, compared to aliases and titles.
+    name: String,
+}
+
+impl<'a> LinkKeys<'a> {
+    /// Return the keys of the percent-decoded `link` from the note at the
+    /// notebook-relative path `from`, or `None` for an empty link.
+    fn new(link: &'a str, from: &Path) -> Option<Self> {
+        let link = link.trim();
+        if link.is_empty() {
+            return None;
+        }
+
+        let target = link.strip_suffix(".md").unwrap_or(link);
+        let dir = from.parent().unwrap_or(Path::new(""));
+        Some(Self {
+            paths: [normalize(&dir.join(target)), normalize(Path::new(target))],
+            stem: (!target.contains('/')).then_some(target),
+            name: link.to_lowercase(),
+        })
+    }
+}
+
+impl LinkIndex {
+    pub(crate) fn new(notes: &[Note]) -> Self {
+        let mut index = Self::default();
+        for (idx, note) in notes.iter().enumerate() {
+            index.insert(idx, note);
+        }
+        index
+    }
+
+    /// Add the note at position `idx` and its links.
+    pub(crate) fn insert(&mut self, idx: usize, note: &Note) {
+        insert_position(&mut self.paths, note.path().with_extension(""), idx);
+        insert_position(&mut self.stems, note.filename_stem().to_owned(), idx);
+        for alias in note.aliases() {
+            insert_position(&mut self.aliases, alias.to_lowercase(), idx);
+        }
+        if !note.title().is_empty() {
+            insert_position(&mut self.titles, note.title().to_lowercase(), idx);
+        }
+
+        for link in note.outgoing_links() {
+            let link = percent_decode(link);
+            let Some(keys) = LinkKeys::new(&link, note.path()) else {
+                continue;
+            };
+            for path in keys.paths.into_iter().flatten() {
+                insert_position(&mut self.linking_paths, path, idx);
+            }
+            if let Some(stem) = keys.stem {
+                insert_position(&mut self.linking_stems, stem.to_owned(), idx);
+            }
+            insert_position(&mut self.linking_names, keys.name, idx);
+        }
+    }
+
+    /// Remove the note at position `idx` and its links, as they were added.
+    pub(crate) fn remove(&mut self, idx: usize, note: &Note) {
+        remove_position(&mut self.paths, &note.path().with_extension(""), idx);
+        remove_position(&mut self.stems, note.filename_stem(), idx);
+        for alias in note.aliases() {
+            remove_position(&mut self.aliases, &alias.to_lowercase(), idx);
+        }
+        remove_position(&mut self.titles, &note.title().to_lowercase(), idx);
+
+        for link in note.outgoing_links() {
+            let link = percent_decode(link);
+            let Some(keys) = LinkKeys::new(&link, note.path()) else {
+                continue;
+            };
+            for path in keys.paths.iter().flatten() {
+                remove_position(&mut self.linking_paths, path, idx);
+            }
+            if let Some(stem) = keys.stem {
+                remove_position(&mut self.linking_stems, stem, idx);
+            }
+            remove_position(&mut self.linking_names, &keys.name, idx);
+        }
+    }
+
+    /// Return the positions of the notes with links that may resolve to
+    /// `note`, sorted. Only their links change when `note` is added or
+    /// removed.
+    pub(crate) fn linking_to(&self, note: &Note) -> Vec<usize> {
+        let names = note
+            .aliases()
+            .iter()
+            .chain([&note.title])
+            .map(|name| self.linking_names.get(&name.to_lowercase()));
+        let mut sources: Vec<usize> = [
+            self.linking_paths.get(&note.path().with_extension("")),
+            self.linking_stems.get(note.filename_stem()),
+        ]
+        .into_iter()
+        .chain(names)
+        .flatten()
+        .flatten()
+        .copied()
+        .collect();
+        sources.sort_unstable();
+        sources.dedup();
+        sources
+    }
+
+    /// Return the positions of the notes `link` points to from the note at the
+    /// notebook-relative path `from`.
+    ///
+    /// Candidates are tried in order: a path relative to `from`'s directory, a
+    /// path relative to the notebook root, a filename stem, an alias and
+    /// finally a case-insensitive title. The first step with a match wins.
+    pub(crate) fn resolve(&self, link: &str, from: &Path) -> &[usize] {
+        let link = percent_decode(link);
+        let Some(keys) = LinkKeys::new(&link, from) else {
+            return &[];
+        };
+
+        for path in keys.paths.iter().flatten() {
+            if let Some(found) = self.paths.get(path) {
+                return found;
+            }
+        }
+
+        if let Some(stem) = keys.stem
+            && let Some(found) = self.stems.get(stem)
+        {
+            return found;
+        }
+
+        self.aliases
+            .get(&keys.name)
+            .or_else(|| self.titles.get(&keys.name))
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Add the note position `idx` to the sorted positions of `key`.
+pub(crate) fn insert_position<K: Hash + Eq>(map: &mut HashMap<K, Vec<usize>>, key: K, idx: usize) {
+    let positions = map.entry(key).or_default();
+    let pos = positions.partition_point(|&other| other < idx);
+    if positions.get(pos) != Some(&idx) {
+        positions.insert(pos, idx);
+    }
+}
+
+/// Remove the note position `idx` from the positions of `key`, and `key`
+/// once no position is left.
+pub(crate) fn remove_position<K, Q>(map: &mut HashMap<K, Vec<usize>>, key: &Q, idx: usize)
+where
+    K: Borrow<Q> + Hash + Eq,
+    Q: Hash + Eq + ?Sized,
+{
+    if let Some(positions) = map.get_mut(key) {
+        positions.retain(|&other| other != idx);
+        if positions.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+/// Collapse `.` and `..` components. Returns `None` if the path leaves the
+/// notebook; a leading `/` refers to the notebook root.
+pub(crate) fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => normalized.clear(),
+        }
+    }
+
+    (!normalized.as_os_str().is_empty()).then_some(normalized)
+}
+
+/// Return the path from the directory `from_dir` to `to`, both relative to
+/// the notebook root, e.g. `../b/note.md` from `a` to `b/note.md`.
+pub(crate) fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from = from_dir.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    path.extend(&to[common..]);
+    path
+}
+
+/// Decode `%XX` escapes, as written by `link-encode-path`. Invalid escapes are
+/// kept verbatim.
+pub(crate) fn percent_decode(link: &str) -> Cow<'_, str> {
+    if !link.contains('%') {
+        return Cow::Borrowed(link);
+    }
+
+    let bytes = link.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(Path::new("a/b/../c/./d")),
+            Some(PathBuf::from("a/c/d"))
+        );
+        assert_eq!(normalize(Path::new("a/../../b")), None);
+        assert_eq!(normalize(Path::new("/b/x")), Some(PathBuf::from("b/x")));
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let path = |from: &str, to: &str| relative_path(Path::new(from), Path::new(to));
+        assert_eq!(path("", "a/b.md"), PathBuf::from("a/b.md"));
+        assert_eq!(path("a", "a/b.md"), PathBuf::from("b.md"));
+        assert_eq!(path("a/x", "b/c.md"), PathBuf::from("../../b/c.md"));
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("my%20note"), "my note");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%C3%BCber"), "über");
+        assert_eq!(percent_decode("plain"), "plain");
+    }
+}
@@ -0,0 +1,946 @@
+//! A Language Server Protocol server for notebooks, like `zk lsp`.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument, Exit,
+    LogMessage, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, References, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    CompletionTextEdit, Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, InitializeResult, Location, LogMessageParams, MarkupContent,
+    MarkupKind, MessageType, OneOf, Position, PublishDiagnosticsParams, ReferenceParams,
+    ServerCapabilities, ServerInfo, TextDocumentPositionParams, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextDocumentSyncOptions, TextDocumentSyncSaveOptions, TextEdit, Uri,
+};
+use serde::de::DeserializeOwned;
+
+use crate::config::DiagnosticLevel;
+use crate::error::Error;
+use crate::note::Note;
+use crate::{Notebook, link, parse};
+
+/// A language server for a notebook, speaking the Language Server Protocol
+/// over an [`lsp_server::Connection`].
+///
+/// It completes links by note title and tags, goes to the note a link points
+/// to, finds the links to a note, previews linked notes on hover and reports
+/// dead links as configured in `[lsp]`. Open documents are analyzed as they
+/// are edited; the notebook is reloaded when they are saved.
+pub struct LanguageServer {
+    notebook: Notebook,
+    /// Open documents by notebook-relative path.
+    documents: HashMap<PathBuf, Document>,
+}
+
+/// A document open in the client.
+struct Document {
+    uri: Uri,
+    version: i32,
+    text: String,
+}
+
+impl LanguageServer {
+    pub fn new(notebook: Notebook) -> Self {
+        Self {
+            notebook,
+            documents: HashMap::new(),
+        }
+    }
+
+    /// Serve the notebook on stdin and stdout until the client exits.
+    pub fn run_stdio(self) -> Result<(), Error> {
+        let (connection, io_threads) = Connection::stdio();
+        self.run(&connection)?;
+        drop(connection);
+        io_threads.join()?;
+        Ok(())
+    }
+
+    /// Serve the notebook on `connection` until the client exits.
+    pub fn run(mut self, connection: &Connection) -> Result<(), Error> {
+        let (id, _) = connection.initialize_start().map_err(lsp_error)?;
+        let result = InitializeResult {
+            capabilities: capabilities(),
+            server_info: Some(ServerInfo {
+                name: "zk-rs".into(),
+                version: Some(env!("CARGO_PKG_VERSION").into()),
+            }),
+        };
+        let result = serde_json::to_value(result).map_err(lsp_error)?;
+        connection
+            .initialize_finish(id, result)
+            .map_err(lsp_error)?;
+
+        for message in &connection.receiver {
+            let replies = match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request).map_err(lsp_error)? {
+                        return Ok(());
+                    }
+                    vec![self.request(request).into()]
+                }
+                Message::Notification(notification) if notification.method == Exit::METHOD => {
+                    return Ok(());
+                }
+                Message::Notification(notification) => self.notification(notification),
+                Message::Response(_) => Vec::new(),
+            };
+            for reply in replies {
+                connection.sender.send(reply).map_err(lsp_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            Completion::METHOD => handle::<Completion>(request, |params| self.completion(params)),
+            GotoDefinition::METHOD => {
+                handle::<GotoDefinition>(request, |params| self.definition(params))
+            }
+            References::METHOD => handle::<References>(request, |params| self.references(params)),
+            HoverRequest::METHOD => handle::<HoverRequest>(request, |params| self.hover(params)),
+            method => Response::new_err(
+                request.id.clone(),
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request: {method}"),
+            ),
+        }
+    }
+
+    /// Handle `notification`, returning the messages to send in reply.
+    fn notification(&mut self, notification: Notification) -> Vec<Message> {
+        let params = notification.params;
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => from_params(params).map(|params| self.did_open(params)),
+            DidChangeTextDocument::METHOD => {
+                from_params(params).map(|params| self.did_change(params))
+            }
+            DidSaveTextDocument::METHOD => from_params(params).map(|params| self.did_save(params)),
+            DidCloseTextDocument::METHOD => {
+                from_params(params).map(|params| self.did_close(params))
+            }
+            _ => None,
+        }
+        .unwrap_or_default()
+    }
+
+    fn did_open(&mut self, params: DidOpenTextDocumentParams) -> Vec<Message> {
+        let document = params.text_document;
+        let Some(path) = self.path(&document.uri) else {
+            return Vec::new();
+        };
+        self.documents.insert(
+            path.clone(),
+            Document {
+                uri: document.uri,
+                version: document.version,
+                text: document.text,
+            },
+        );
+        vec![self.publish_diagnostics(&path)]
+    }
+
+    fn did_change(&mut self, mut params: DidChangeTextDocumentParams) -> Vec<Message> {
+        let Some(path) = self.path(&params.text_document.uri) else {
+            return Vec::new();
+        };
+        // Documents are synced in full, the last change has the whole text.
+        let (Some(document), Some(change)) =
+            (self.documents.get_mut(&path), params.content_changes.pop())
+        else {
+            return Vec::new();
+        };
+        document.version = params.text_document.version;
+        document.text = change.text;
+        vec![self.publish_diagnostics(&path)]
+    }
+
+    /// Reload the saved note and report the diagnostics of all open documents
+    /// again, links to the note may have changed.
+    fn did_save(&mut self, params: DidSaveTextDocumentParams) -> Vec<Message> {
+        let Some(path) = self.path(&params.text_document.uri) else {
+            return Vec::new();
+        };
+        let mut messages = Vec::new();
+        if let Err(err) = self.notebook.reload(&path) {
+            let params = LogMessageParams {
+                typ: MessageType::ERROR,
+                message: format!("failed to reload {}: {err}", path.display()),
+            };
+            messages.push(Notification::new(LogMessage::METHOD.into(), params).into());
+        }
+
+        let mut open: Vec<&PathBuf> = self.documents.keys().collect();
+        open.sort();
+        messages.extend(open.into_iter().map(|path| self.publish_diagnostics(path)));
+        messages
+    }
+
+    fn did_close(&mut self, params: DidCloseTextDocumentParams) -> Vec<Message> {
+        let uri = params.text_document.uri;
+        if let Some(path) = self.path(&uri) {
+            self.documents.remove(&path);
+        }
+        let params = PublishDiagnosticsParams::new(uri, Vec::new(), None);
+        vec![Notification::new(PublishDiagnostics::METHOD.into(), params).into()]
+    }
+
+    /// Return the notification publishing the diagnostics of the open
+    /// document at `path`.
+    fn publish_diagnostics(&self, path: &Path) -> Message {
+        let document = &self.documents[path];
+        let params = PublishDiagnosticsParams::new(
+            document.uri.clone(),
+            self.diagnostics(path, &document.text),
+            Some(document.version),
+        );
+        Notification::new(PublishDiagnostics::METHOD.into(), params).into()
+    }
+
+    /// Report the dead links and links to itself in the note `text` at `path`.
+    fn diagnostics(&self, path: &Path, text: &str) -> Vec<Diagnostic> {
+        let levels = &self.notebook.config.lsp.diagnostics;
+        let mut diagnostics = Vec::new();
+
+        for span in link_spans(text) {
+            let target = &text[span.clone()];
+            let (level, message) = match self.notebook.links.resolve(target, path) {
+                [] => (levels.dead_link, format!("dead link to {target}")),
+                [idx] if self.notebook.notes[*idx].path() == path => {
+                    (levels.self_link, "link to the note itself".to_owned())
+                }
+                _ => continue,
+            };
+            let Some(severity) = severity(level) else {
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                range: range(text, span),
+                severity: Some(severity),
+                source: Some("zk-rs".into()),
+                message,
+                ..Default::default()
+            });
+        }
+        diagnostics
+    }
+
+    /// Complete links to notes after `[[` and tags after `#` or in the `tags`
+    /// of the frontmatter.
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let (path, text, offset) = self.locate(&params.text_document_position)?;
+        let line_start = text[..offset].rfind('\n').map_or(0, |pos| pos + 1);
+        let before = &text[line_start..offset];
+
+        if let Some(open) = before.rfind("[[")
+            && !before[open..].contains("]]")
+        {
+            // Replace the `]]` closed by the editor too.
+            let end = offset
+                + if text[offset..].starts_with("]]") {
+                    2
+                } else {
+                    0
+                };
+            let range = range(text, line_start + open..end);
+            return Some(CompletionResponse::Array(
+                self.note_completions(&path, range),
+            ));
+        }
+
+        let word = before
+            .rsplit(|c: char| c.is_whitespace() || matches!(c, '[' | ',' | ':'))
+            .next()
+            .unwrap_or_default();
+        let word_start = offset - word.len();
+        let body_start = parse::extract_frontmatter(text).1;
+
+        if offset < body_start {
+            let in_tags = is_tags_field(&text[..line_start], &text[line_start..offset]);
+            return in_tags.then(|| {
+                let range = range(text, word_start..offset);
+                CompletionResponse::Array(self.tag_completions(range, false))
+            });
+        }
+
+        let partial_tag = word.strip_prefix('#')?;
+        let is_partial_tag = partial_tag
+            .bytes()
+            .all(|b| parse::is_tag_char(b) || b == b'/');
+        (self.notebook.config.format.markdown.hashtags && is_partial_tag).then(|| {
+            let range = range(text, word_start + 1..offset);
+            CompletionResponse::Array(self.tag_completions(range, true))
+        })
+    }
+
+    /// Offer links to all notes but the one at `path`, replacing `range`.
+    fn note_completions(&self, path: &Path, range: lsp_types::Range) -> Vec<CompletionItem> {
+        let config = &self.notebook.config;
+        let completion = &config.lsp.completion;
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        let mut notes: Vec<&Note> = self
+            .notebook
+            .notes
+            .iter()
+            .filter(|note| note.path() != path)
+            .collect();
+        notes.sort_by_key(|note| note.path());
+
+        notes
+            .into_iter()
+            .map(|note| {
+                let target = link::relative_path(dir, note.path());
+                let link = parse::note_link(&target, note.title(), &config.format.markdown);
+                let label = note
+                    .format(&completion.note_label)
+                    .ok()
+                    .filter(|label| !label.is_empty())
+                    .unwrap_or_else(|| note.path().to_string_lossy().into_owned());
+                CompletionItem {
+                    label,
+                    kind: Some(CompletionItemKind::REFERENCE),
+                    detail: completion
+                        .note_detail
+                        .as_ref()
+                        .and_then(|detail| note.format(detail).ok()),
+                    // Clients filter by the replaced text, which starts with `[[`.
+                    filter_text: note
+                        .format(&completion.note_filter_text)
+                        .ok()
+                        .map(|text| format!("[[{text}")),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, link))),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    /// Offer all tags of the notebook, replacing `range`. Tags that cannot be
+    /// written as `#hashtag` are left out for `hashtag`s.
+    fn tag_completions(&self, range: lsp_types::Range, hashtag: bool) -> Vec<CompletionItem> {
+        let mut tags: Vec<(&str, usize)> = self
+            .notebook
+            .tag_counts()
+            .filter(|(tag, _)| !hashtag || parse::is_tag_name(tag))
+            .collect();
+        tags.sort();
+
+        tags.into_iter()
+            .map(|(tag, count)| CompletionItem {
+                label: tag.to_owned(),
+                kind: Some(CompletionItemKind::CONSTANT),
+                detail: Some(match count {
+                    1 => "1 note".to_owned(),
+                    count => format!("{count} notes"),
+                }),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                    range,
+                    tag.to_owned(),
+                ))),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Go to the note the link at the cursor points to, or to all candidates
+    /// of an ambiguous link.
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let (path, text, offset) = self.locate(&params.text_document_position_params)?;
+        let (target, _) = link_at(text, offset)?;
+
+        let mut locations: Vec<Location> = self
+            .notebook
+            .links
+            .resolve(&text[target], &path)
+            .iter()
+            .map(|&idx| {
+                Location::new(
+                    self.uri(self.notebook.notes[idx].path()),
+                    Default::default(),
+                )
+            })
+            .collect();
+        match locations.len() {
+            0 => None,
+            1 => locations.pop().map(GotoDefinitionResponse::Scalar),
+            _ => Some(GotoDefinitionResponse::Array(locations)),
+        }
+    }
+
+    /// Find the links to the note linked at the cursor, or else to the note of
+    /// the document, in the notes known to link to it.
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let (path, text, offset) = self.locate(&params.text_document_position)?;
+        let target = match link_at(text, offset) {
+            Some((span, _)) => match self.notebook.links.resolve(&text[span], &path) {
+                [idx] => *idx,
+                _ => return None,
+            },
+            None => *self.notebook.paths.get(&path)?,
+        };
+        let note = &self.notebook.notes[target];
+
+        let mut locations = Vec::new();
+        if params.context.include_declaration {
+            locations.push(Location::new(self.uri(note.path()), Default::default()));
+        }
+
+        let mut sources = self.notebook.backlinks(note);
+        sources.sort_by_key(|source| source.path());
+        for source in sources {
+            let Some(text) = self.text(source.path()) else {
+                continue;
+            };
+            for span in link_spans(text) {
+                if self
+                    .notebook
+                    .links
+                    .resolve(&text[span.clone()], source.path())
+                    == [target]
+                {
+                    let range = range(text, link_extent(text, span));
+                    locations.push(Location::new(self.uri(source.path()), range));
+                }
+            }
+        }
+        Some(locations)
+    }
+
+    /// Preview the note the link at the cursor points to.
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let (path, text, offset) = self.locate(&params.text_document_position_params)?;
+        let (target, extent) = link_at(text, offset)?;
+        let [idx] = self.notebook.links.resolve(&text[target], &path) else {
+            return None;
+        };
+
+        let note = &self.notebook.notes[*idx];
+        let preview = match note.title() {
+            "" => note.body().to_owned(),
+            title => format!("# {title}\n\n{}", note.body()),
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: preview,
+            }),
+            range: Some(range(text, extent)),
+        })
+    }
+
+    /// Return the notebook-relative path of the note at `uri`, if it is a note
+    /// of the notebook.
+    fn path(&self, uri: &Uri) -> Option<PathBuf> {
+        let path = uri.as_str().strip_prefix("file://")?;
+        let path = link::percent_decode(path);
+        let path = Path::new(path.as_ref())
+            .strip_prefix(&self.notebook.root)
+            .ok()?;
+
+        let hidden = path
+            .iter()
+            .any(|part| part.to_string_lossy().starts_with('.'));
+        let is_note = path.extension().is_some_and(|ext| ext == "md");
+        (is_note && !hidden).then(|| path.to_path_buf())
+    }
+
+    /// Return the `file://` URI of the notebook-relative `path`.
+    fn uri(&self, path: &Path) -> Uri {
+        let path = self.notebook.root.join(path);
+        let uri = format!("file://{}", parse::encode_path(&path.to_string_lossy()));
+        Uri::from_str(&uri).expect("percent-encoded paths are valid URIs")
+    }
+
+    /// Return the text of the note at `path`, as edited if it is open.
+    fn text(&self, path: &Path) -> Option<&str> {
+        match self.documents.get(path) {
+            Some(document) => Some(&document.text),
+            None => self
+                .notebook
+                .paths
+                .get(path)
+                .map(|&idx| self.notebook.notes[idx].raw_content()),
+        }
+    }
+
+    /// Return the path and text of the document at `position` and the byte
+    /// offset of the position in the text.
+    fn locate(&self, position: &TextDocumentPositionParams) -> Option<(PathBuf, &str, usize)> {
+        let path = self.path(&position.text_document.uri)?;
+        let text = self.text(&path)?;
+        let offset = offset(text, position.position);
+        Some((path, text, offset))
+    }
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..Default::default()
+            },
+        )),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["[".into(), "#".into()]),
+            ..Default::default()
+        }),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        ..Default::default()
+    }
+}
+
+/// Answer `request` with the result of `handler`, or an error if its params
+/// are invalid.
+fn handle<R: lsp_types::request::Request>(
+    request: Request,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    match serde_json::from_value(request.params) {
+        Ok(params) => Response::new_ok(request.id, handler(params)),
+        Err(err) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, err.to_string()),
+    }
+}
+
+fn from_params<P: DeserializeOwned>(params: serde_json::Value) -> Option<P> {
+    serde_json::from_value(params).ok()
+}
+
+fn lsp_error(err: impl std::fmt::Display) -> Error {
+    Error::Lsp(err.to_string())
+}
+
+fn severity(level: DiagnosticLevel) -> Option<DiagnosticSeverity> {
+    match level {
+        DiagnosticLevel::None => None,
+        DiagnosticLevel::Hint => Some(DiagnosticSeverity::HINT),
+        DiagnosticLevel::Info => Some(DiagnosticSeverity::INFORMATION),
+        DiagnosticLevel::Warning => Some(DiagnosticSeverity::WARNING),
+        DiagnosticLevel::Error => Some(DiagnosticSeverity::ERROR),
+    }
+}
+
+/// Return `true` if the frontmatter line ending in `line` continues the
+/// `tags` or `keywords` field, after the frontmatter lines `above`.
+fn is_tags_field(above: &str, line: &str) -> bool {
+    let is_field = |line: &str| line.starts_with("tags:") || line.starts_with("keywords:");
+    if is_field(line) {
+        return true;
+    }
+    // Items of a YAML block sequence below the field.
+    line.trim_start().starts_with('-')
+        && above
+            .lines()
+            .rev()
+            .find(|line| !line.trim_start().starts_with('-'))
+            .is_some_and(is_field)
+}
+
+/// Return the byte ranges of the link targets in the note `text` in order,
+/// leaving out its frontmatter.
+fn link_spans(text: &str) -> Vec<Range<usize>> {
+    let body_start = parse::extract_frontmatter(text).1;
+    let mut spans: Vec<Range<usize>> = parse::link_spans(&text[body_start..])
+        .into_iter()
+        .map(|span| body_start + span.start..body_start + span.end)
+        .collect();
+    spans.sort_by_key(|span| span.start);
+    spans
+}
+
+/// Return the target and the extent of the link at the byte `offset`.
+fn link_at(text: &str, offset: usize) -> Option<(Range<usize>, Range<usize>)> {
+    link_spans(text)
+        .into_iter()
+        .map(|span| (span.clone(), link_extent(text, span)))
+        .find(|(_, extent)| extent.contains(&offset) || extent.end == offset)
+}
+
+/// Widen the `span` of a link target in `text` to the whole `[[link]]` or
+/// `[label](link)`.
+fn link_extent(text: &str, span: Range<usize>) -> Range<usize> {
+    let line_start = text[..span.start].rfind('\n').map_or(0, |pos| pos + 1);
+    let before = &text[line_start..span.start];
+    let after = text[span.end..].split('\n').next().unwrap_or_default();
+
+    if let Some(open) = before.rfind("[[")
+        && !before[open..].contains("]]")
+    {
+        let end = after.find("]]").map_or(span.end, |end| span.end + end + 2);
+        return line_start + open..end;
+    }
+    if let Some(open) = before.rfind("](") {
+        let start = before[..open].rfind('[').unwrap_or(open);
+        let end = after.find(')').map_or(span.end, |end| span.end + end + 1);
+        return line_start + start..end;
+    }
+    span
+}
+
+/// Return the byte offset of the UTF-16 `position` in `text`, clamped to
+/// the end of its line.
+fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(end) => line_start += end + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut units = 0;
+    for (idx, ch) in text[line_start..].char_indices() {
+        if units >= position.character || ch == '\n' {
+            return line_start + idx;
+        }
+        units += ch.len_utf16() as u32;
+    }
+    text.len()
+}
+
+/// Return the UTF-16 position of the byte `offset` in `text`.
+fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+fn range(text: &str, span: Range<usize>) -> lsp_types::Range {
+    lsp_types::Range::new(position(text, span.start), position(text, span.end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStorage;
+    use lsp_server::RequestId;
+    use lsp_types::notification::Initialized;
+    use lsp_types::request::{Initialize, Shutdown};
+    use lsp_types::{
+        InitializeParams, ReferenceContext, TextDocumentIdentifier, TextDocumentItem,
+        VersionedTextDocumentIdentifier,
+    };
+    use std::fs;
+    use std::thread::JoinHandle;
+
+    /// An in-process client of a [`LanguageServer`] running in a thread.
+    struct Client {
+        connection: Connection,
+        server: JoinHandle<Result<(), Error>>,
+        root: PathBuf,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn start(notebook: Notebook) -> Self {
+            let root = notebook.root.clone();
+            let (server, connection) = Connection::memory();
+            let server = std::thread::spawn(move || LanguageServer::new(notebook).run(&server));
+            let mut client = Client {
+                connection,
+                server,
+                root,
+                next_id: 0,
+            };
+            client.request::<Initialize>(InitializeParams::default());
+            client.notify::<Initialized>(lsp_types::InitializedParams {});
+            client
+        }
+
+        /// Send a request and wait for its result, skipping notifications.
+        fn request<R: lsp_types::request::Request>(&mut self, params: R::Params) -> R::Result {
+            self.next_id += 1;
+            let id = RequestId::from(self.next_id);
+            let request = Request::new(id.clone(), R::METHOD.into(), params);
+            self.connection.sender.send(request.into()).unwrap();
+            loop {
+                if let Message::Response(response) = self.connection.receiver.recv().unwrap()
+                    && response.id == id
+                {
+                    return serde_json::from_value(response.result.unwrap()).unwrap();
+                }
+            }
+        }
+
+        fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) {
+            let notification = Notification::new(N::METHOD.into(), params);
+            self.connection.sender.send(notification.into()).unwrap();
+        }
+
+        /// Wait for the next diagnostics the server publishes.
+        fn diagnostics(&self) -> PublishDiagnosticsParams {
+            loop {
+                if let Message::Notification(notification) =
+                    self.connection.receiver.recv().unwrap()
+                    && notification.method == PublishDiagnostics::METHOD
+                {
+                    return serde_json::from_value(notification.params).unwrap();
+                }
+            }
+        }
+
+        fn uri(&self, path: &str) -> Uri {
+            Uri::from_str(&format!("file://{}", self.root.join(path).display())).unwrap()
+        }
+
+        fn at(&self, path: &str, line: u32, character: u32) -> TextDocumentPositionParams {
+            TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(self.uri(path)),
+                Position::new(line, character),
+            )
+        }
+
+        fn open(&self, path: &str, text: &str) {
+            let document = TextDocumentItem::new(self.uri(path), "markdown".into(), 1, text.into());
+            self.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+                text_document: document,
+            });
+        }
+
+        fn completion(&mut self, path: &str, line: u32, character: u32) -> Vec<CompletionItem> {
+            let params = CompletionParams {
+                text_document_position: self.at(path, line, character),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            };
+            match self.request::<Completion>(params) {
+                Some(CompletionResponse::Array(items)) => items,
+                _ => Vec::new(),
+            }
+        }
+
+        fn shutdown(mut self) {
+            self.request::<Shutdown>(());
+            self.notify::<Exit>(());
+            self.server.join().unwrap().unwrap();
+        }
+    }
+
+    fn notebook() -> Notebook {
+        let storage = MemoryStorage::new()
+            .with_file(".zk/config.toml", "")
+            .with_file("a.md", "# Alpha\n\nSee [[b]] and [the beta](b).\n#rust")
+            .with_file(
+                "b.md",
+                "---\ntags: [rust, lsp/server]\n---\n# Beta\n\nBody of b.",
+            )
+            .with_file("dir/c.md", "# Gamma\n\nUp to [Beta](../b).");
+        Notebook::load_from("/notebook", storage).unwrap()
+    }
+
+    fn edit_of(item: &CompletionItem) -> &TextEdit {
+        match &item.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => edit,
+            _ => panic!("no text edit in {item:?}"),
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".zk")).unwrap();
+        fs::write(
+            dir.path().join(".zk/config.toml"),
+            "[lsp.diagnostics]\nself-link = \"hint\"",
+        )
+        .unwrap();
+        fs::write(dir.path().join("a.md"), "# Alpha").unwrap();
+
+        let client = Client::start(Notebook::load(dir.path()).unwrap());
+        client.open("d.md", "# Delta\n\n[[a]] [[nowhere]]");
+        let published = client.diagnostics();
+        assert_eq!(published.uri, client.uri("d.md"));
+        let diagnostics: Vec<_> = published
+            .diagnostics
+            .iter()
+            .map(|d| (d.range, d.severity.unwrap(), d.message.as_str()))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [(
+                lsp_types::Range::new(Position::new(2, 8), Position::new(2, 15)),
+                DiagnosticSeverity::ERROR,
+                "dead link to nowhere"
+            )]
+        );
+
+        // Edits are analyzed before they are saved.
+        let text = "# Delta\n\n[[a]]\n[[d]]";
+        client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(client.uri("d.md"), 2),
+            content_changes: vec![lsp_types::TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.into(),
+            }],
+        });
+        let published = client.diagnostics();
+        assert_eq!(published.version, Some(2));
+        assert_eq!(published.diagnostics[0].message, "dead link to d");
+
+        // Once saved the note exists and links to itself.
+        fs::write(dir.path().join("d.md"), text).unwrap();
+        client.notify::<DidSaveTextDocument>(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(client.uri("d.md")),
+            text: None,
+        });
+        let published = client.diagnostics();
+        assert_eq!(published.diagnostics.len(), 1);
+        assert_eq!(
+            published.diagnostics[0].severity,
+            Some(DiagnosticSeverity::HINT)
+        );
+
+        client.notify::<DidCloseTextDocument>(DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(client.uri("d.md")),
+        });
+        assert!(client.diagnostics().diagnostics.is_empty());
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_link_completion() {
+        let mut client = Client::start(notebook());
+        client.open("dir/c.md", "# Gamma\n\nSee [[be]]\n");
+        client.diagnostics();
+
+        let items = client.completion("dir/c.md", 2, 8);
+        let labels: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["Alpha", "Beta"]);
+        assert_eq!(items[1].filter_text.as_deref(), Some("[[Beta b.md"));
+        // The closing brackets are replaced too.
+        let edit = edit_of(&items[1]);
+        assert_eq!(edit.new_text, "[Beta](../b)");
+        assert_eq!(
+            edit.range,
+            lsp_types::Range::new(Position::new(2, 4), Position::new(2, 10))
+        );
+
+        assert!(client.completion("dir/c.md", 0, 3).is_empty());
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_tag_completion() {
+        let mut client = Client::start(notebook());
+        client.open(
+            "e.md",
+            "---\ntags: [rust, ls]\naliases:\n  - x\n---\n# Epsilon #ru\n",
+        );
+        client.diagnostics();
+
+        let items = client.completion("e.md", 5, 13);
+        let labels: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["lsp/server", "rust"]);
+        assert_eq!(items[1].detail.as_deref(), Some("2 notes"));
+        assert_eq!(edit_of(&items[1]).range.start, Position::new(5, 11));
+
+        let items = client.completion("e.md", 1, 15);
+        assert_eq!(edit_of(&items[0]).range.start, Position::new(1, 13));
+        assert!(client.completion("e.md", 3, 5).is_empty());
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_definition_and_hover() {
+        let mut client = Client::start(notebook());
+
+        let definition = client.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: client.at("a.md", 2, 20),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let Some(GotoDefinitionResponse::Scalar(location)) = definition else {
+            panic!("no definition: {definition:?}");
+        };
+        assert_eq!(location.uri, client.uri("b.md"));
+
+        let hover = client
+            .request::<HoverRequest>(HoverParams {
+                text_document_position_params: client.at("a.md", 2, 5),
+                work_done_progress_params: Default::default(),
+            })
+            .unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("no markup");
+        };
+        assert_eq!(content.value, "# Beta\n\nBody of b.");
+        assert_eq!(
+            hover.range,
+            Some(lsp_types::Range::new(
+                Position::new(2, 4),
+                Position::new(2, 9)
+            ))
+        );
+
+        let hover = client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: client.at("a.md", 0, 2),
+            work_done_progress_params: Default::default(),
+        });
+        assert!(hover.is_none());
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_references() {
+        let mut client = Client::start(notebook());
+
+        let references = client
+            .request::<References>(ReferenceParams {
+                text_document_position: client.at("b.md", 4, 0),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: ReferenceContext {
+                    include_declaration: false,
+                },
+            })
+            .unwrap();
+        let found: Vec<(&str, lsp_types::Range)> = references
+            .iter()
+            .map(|location| {
+                let path = location.uri.as_str().rsplit_once("notebook/").unwrap().1;
+                (path, location.range)
+            })
+            .collect();
+        let range = |line, start, end| {
+            lsp_types::Range::new(Position::new(line, start), Position::new(line, end))
+        };
+        assert_eq!(
+            found,
+            [
+                ("a.md", range(2, 4, 9)),
+                ("a.md", range(2, 14, 27)),
+                ("dir/c.md", range(2, 6, 18)),
+            ]
+        );
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "a\nä😀b\n";
+        assert_eq!(offset(text, Position::new(1, 3)), 8);
+        assert_eq!(position(text, 8), Position::new(1, 3));
+        assert_eq!(offset(text, Position::new(1, 99)), 9);
+        assert_eq!(offset(text, Position::new(9, 0)), text.len());
+    }
+}
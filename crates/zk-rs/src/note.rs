@@ -1,10 +1,15 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
-#[derive(Clone, Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Note {
     pub(crate) filename: String,
     pub(crate) filename_stem: String,
     pub(crate) path: PathBuf,
+    /// Not cached, the notebook may have moved.
+    #[serde(skip)]
     pub(crate) abs_path: PathBuf,
     pub(crate) title: String,
     pub(crate) link: String,
@@ -14,7 +19,12 @@ pub struct Note {
     pub(crate) word_count: usize,
     pub(crate) tags: Vec<String>,
     pub(crate) aliases: Vec<String>,
+    /// Cached as YAML text, bincode cannot read `serde_yaml::Value`.
+    #[serde(with = "yaml_text")]
+    pub(crate) metadata: BTreeMap<String, serde_yaml::Value>,
     pub(crate) outgoing_links: Vec<String>,
+    /// Links to files that are not notes, as written.
+    pub(crate) attachment_links: Vec<String>,
     pub(crate) created: jiff::Timestamp,
     pub(crate) modified: jiff::Timestamp,
 }
@@ -68,6 +78,11 @@ impl Note {
         &self.aliases
     }
 
+    /// Return all frontmatter fields, with keys in lowercase like zk does.
+    pub fn metadata(&self) -> &BTreeMap<String, serde_yaml::Value> {
+        &self.metadata
+    }
+
     pub fn created(&self) -> jiff::Timestamp {
         self.created
     }
@@ -80,7 +95,52 @@ impl Note {
         &self.outgoing_links
     }
 
+    /// Return the targets of the markdown links and images to files that are
+    /// not notes, as written. See [`Notebook::attachment_references`](crate::Notebook::attachment_references).
+    pub fn attachment_links(&self) -> &[String] {
+        &self.attachment_links
+    }
+
+    /// Render the Handlebars `template` for this note, like `zk list --format`.
+    ///
+    /// The template sees `title`, `path`, `abs-path`, `filename`,
+    /// `filename-stem`, `link`, `lead`, `body`, `raw-content`, `word-count`,
+    /// `tags`, `metadata`, `created` and `modified`, and zk's helpers such as
+    /// `{{format-date created "elapsed"}}` and `{{join tags ", "}}`.
+    pub fn format(&self, template: &str) -> Result<String, crate::Error> {
+        crate::template::render(template, &crate::template::NoteContext::new(self), "format")
+    }
+
+    /// Check if the note has the tag `tag`, ignoring case. Tags nested in it
+    /// do not count, see [`Note::has_within`].
     pub fn has(&self, tag: &str) -> bool {
         self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
     }
+
+    /// Check if the note has the tag `tag` or a tag nested in it, e.g.
+    /// `project/weave` for `project`. Ignores case.
+    pub fn has_within(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| crate::tag::is_within(t, tag))
+    }
+}
+
+/// Serialize the metadata map as a YAML string.
+mod yaml_text {
+    use std::collections::BTreeMap;
+
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    type Map = BTreeMap<String, serde_yaml::Value>;
+
+    pub(super) fn serialize<S: Serializer>(map: &Map, serializer: S) -> Result<S::Ok, S::Error> {
+        let text = serde_yaml::to_string(map).map_err(S::Error::custom)?;
+        serializer.serialize_str(&text)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Map, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        serde_yaml::from_str(&text).map_err(D::Error::custom)
+    }
 }
@@ -1,9 +1,16 @@
-use std::collections::{HashMap, HashSet};
-use std::fs;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::MarkdownConfig;
 use crate::error::Error;
 use crate::note::Note;
+use crate::storage::FileMetadata;
+use crate::tag;
+use crate::template;
 
 /// Typed representation of the supported zk frontmatter fields.
 struct Frontmatter {
@@ -11,6 +18,8 @@ struct Frontmatter {
     date: Option<String>,
     tags: Vec<String>,
     aliases: Vec<String>,
+    /// All fields, including the ones above.
+    metadata: BTreeMap<String, serde_yaml::Value>,
 }
 
 impl Frontmatter {
@@ -20,16 +29,19 @@ impl Frontmatter {
             date: None,
             tags: Vec::new(),
             aliases: Vec::new(),
+            metadata: BTreeMap::new(),
         }
     }
 }
 
 /// Parse a note from its content and filesystem metadata.
+/// Inline tag syntaxes are only extracted if enabled in `markdown`.
 pub(crate) fn parse_note(
     content: &str,
     path: PathBuf,
     abs_path: PathBuf,
-    meta: &fs::Metadata,
+    meta: &FileMetadata,
+    markdown: &MarkdownConfig,
 ) -> Result<Note, Error> {
     let (frontmatter_str, body_start_offset) = extract_frontmatter(content);
 
@@ -46,12 +58,11 @@ pub(crate) fn parse_note(
     let body = body_ref.to_owned();
 
     // Now that title/body are owned, frontmatter borrow is released.
-    let modified = timestamp_from_mtime(meta);
-    let created = extract_created(&frontmatter)
-        .unwrap_or_else(|| timestamp_from_birthtime(meta).unwrap_or(modified));
+    let modified = meta.modified;
+    let created = extract_created(&frontmatter).unwrap_or(meta.created.unwrap_or(modified));
 
     // Merge frontmatter tags with inline tags (colon tags and hashtags) from body.
-    let inline_tags = extract_inline_tags(content_after_frontmatter);
+    let inline_tags = extract_inline_tags(content_after_frontmatter, markdown);
     let mut tags = frontmatter.tags;
     let mut seen: HashSet<String> = tags.iter().map(|t| t.to_lowercase()).collect();
     for tag in inline_tags {
@@ -61,7 +72,8 @@ pub(crate) fn parse_note(
         }
     }
 
-    let outgoing_links = extract_wiki_link_stems(&body);
+    let outgoing_links = extract_link_targets(&body);
+    let attachment_links = extract_attachment_targets(&body);
 
     let filename = path
         .file_name()
@@ -73,7 +85,7 @@ pub(crate) fn parse_note(
         .map(|f| f.to_string_lossy().into_owned())
         .unwrap_or_default();
 
-    let link = format!("[{title}]({})", path.display());
+    let link = note_link(&path, &title, markdown);
 
     let word_count = content.split_whitespace().count();
 
@@ -90,7 +102,9 @@ pub(crate) fn parse_note(
         word_count,
         tags,
         aliases: frontmatter.aliases,
+        metadata: frontmatter.metadata,
         outgoing_links,
+        attachment_links,
         created,
         modified,
     })
@@ -98,7 +112,7 @@ pub(crate) fn parse_note(
 
 /// Extract the YAML frontmatter block if present.
 /// Returns (Some(frontmatter_content), offset_after_closing_delimiter) or (None, 0).
-fn extract_frontmatter(content: &str) -> (Option<&str>, usize) {
+pub(crate) fn extract_frontmatter(content: &str) -> (Option<&str>, usize) {
     if !content.starts_with("---\n") && !content.starts_with("---\r\n") {
         return (None, 0);
     }
@@ -163,6 +177,7 @@ fn parse_yaml(yaml_str: &str, file_path: &Path) -> Result<Frontmatter, Error> {
         date,
         tags,
         aliases,
+        metadata: map.into_iter().collect(),
     })
 }
 
@@ -260,22 +275,55 @@ fn collect_tags(value: &serde_yaml::Value, tags: &mut Vec<String>, seen: &mut Ha
 }
 
 fn add_tag(raw: &str, tags: &mut Vec<String>, seen: &mut HashSet<String>) {
-    let tag = raw.trim_start_matches('#').trim();
-    if !tag.is_empty() {
-        let lower = tag.to_lowercase();
-        if seen.insert(lower) {
-            tags.push(tag.to_owned());
-        }
+    let tag = tag::normalize(raw.trim_start_matches('#'));
+    if !tag.is_empty() && seen.insert(tag.to_lowercase()) {
+        tags.push(tag);
     }
 }
 
-/// Extract inline tags from note content: colon-separated (`:tag1:tag2:`) and hashtags (`#tag`).
-/// Skips fenced code blocks.
-fn extract_inline_tags(content: &str) -> Vec<String> {
+/// Extract inline tags from note content: colon-separated (`:tag1:tag2:`) and hashtags (`#tag`),
+/// each only if enabled in `markdown`. Skips fenced code blocks.
+fn extract_inline_tags(content: &str, markdown: &MarkdownConfig) -> Vec<String> {
+    inline_tag_spans(content, markdown)
+        .into_iter()
+        .map(|tag| content[tag.span].to_owned())
+        .collect()
+}
+
+/// Syntax of an inline tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum InlineTag {
+    /// `:tag:`, possibly chained with others as in `:tag1:tag2:`.
+    Colon,
+    /// `#tag`
+    Hashtag,
+    /// `#multi word tag#`
+    Multiword,
+}
+
+/// An inline tag found by [`inline_tag_spans`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct InlineTagSpan {
+    /// Byte range of the tag name, without `#` or colons.
+    pub(crate) span: Range<usize>,
+    pub(crate) syntax: InlineTag,
+}
+
+/// Return the inline tags of note content in the syntaxes enabled in
+/// `markdown`, skipping fenced code blocks. Per line, colon tags come first.
+pub(crate) fn inline_tag_spans(content: &str, markdown: &MarkdownConfig) -> Vec<InlineTagSpan> {
     let mut tags = Vec::new();
     let mut in_code_block = false;
 
-    for line in content.lines() {
+    if !markdown.hashtags && !markdown.colon_tags {
+        return tags;
+    }
+
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
         let trimmed = line.trim_start();
         if trimmed.starts_with("```") {
             in_code_block = !in_code_block;
@@ -285,21 +333,72 @@ fn extract_inline_tags(content: &str) -> Vec<String> {
             continue;
         }
 
-        extract_colon_tags(line, &mut tags);
-        extract_hashtags(line, &mut tags);
+        let line = line.trim_end_matches(['\r', '\n']);
+        let shift = |span: Range<usize>| span.start + start..span.end + start;
+        if markdown.colon_tags {
+            tags.extend(colon_tag_spans(line).into_iter().map(|span| InlineTagSpan {
+                span: shift(span),
+                syntax: InlineTag::Colon,
+            }));
+        }
+        if markdown.hashtags {
+            tags.extend(
+                hashtag_spans(line, markdown.multiword_tags)
+                    .into_iter()
+                    .map(|(span, syntax)| InlineTagSpan {
+                        span: shift(span),
+                        syntax,
+                    }),
+            );
+        }
     }
 
     tags
 }
 
-fn is_tag_char(b: u8) -> bool {
+pub(crate) fn is_tag_char(b: u8) -> bool {
     b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
 }
 
-/// Extract `:colon:separated:tags:` from a line.
-fn extract_colon_tags(line: &str, tags: &mut Vec<String>) {
+/// Return the end of the tag name starting at `start`: tag characters, with
+/// a single `/` between the levels of a nested tag as in `project/weave`.
+fn tag_name_end(bytes: &[u8], start: usize) -> usize {
+    let mut end = start;
+    loop {
+        while end < bytes.len() && is_tag_char(bytes[end]) {
+            end += 1;
+        }
+        let nested = end > start
+            && bytes.get(end) == Some(&b'/')
+            && bytes.get(end + 1).is_some_and(|&b| is_tag_char(b));
+        if !nested {
+            return end;
+        }
+        end += 1;
+    }
+}
+
+/// Return `true` if `tag` can be written as `#tag` or `:tag:`.
+pub(crate) fn is_tag_name(tag: &str) -> bool {
+    !tag.is_empty() && tag_name_end(tag.as_bytes(), 0) == tag.len()
+}
+
+/// Return `true` if `tag` can be written as `#multi word tag#`: it contains
+/// a space and no level of the nested tag starts or ends with one.
+pub(crate) fn is_multiword_name(tag: &[u8]) -> bool {
+    tag.contains(&b' ')
+        && tag.split(|&b| b == b'/').all(|level| {
+            level.first().is_some_and(|&b| b != b' ')
+                && level.last().is_some_and(|&b| b != b' ')
+                && level.iter().all(|&b| is_tag_char(b) || b == b' ')
+        })
+}
+
+/// Return the byte ranges of the `:colon:separated:tags:` in a line.
+fn colon_tag_spans(line: &str) -> Vec<Range<usize>> {
     let bytes = line.as_bytes();
     let len = bytes.len();
+    let mut spans = Vec::new();
     let mut i = 0;
 
     while i < len {
@@ -309,9 +408,7 @@ fn extract_colon_tags(line: &str, tags: &mut Vec<String>) {
             // Parse one or more tag names separated by colons: :tag1:tag2:
             loop {
                 let tag_start = i;
-                while i < len && is_tag_char(bytes[i]) {
-                    i += 1;
-                }
+                i = tag_name_end(bytes, i);
 
                 if i == tag_start {
                     // Empty tag name — not a valid colon tag sequence
@@ -323,7 +420,7 @@ fn extract_colon_tags(line: &str, tags: &mut Vec<String>) {
                     break;
                 }
 
-                tags.push(line[tag_start..i].to_owned());
+                spans.push(tag_start..i);
                 i += 1; // skip closing :
 
                 // If next char is not a tag char, the sequence ended
@@ -335,80 +432,417 @@ fn extract_colon_tags(line: &str, tags: &mut Vec<String>) {
             i += 1;
         }
     }
+
+    spans
 }
 
-/// Extract `#hashtags` from a line.
-fn extract_hashtags(line: &str, tags: &mut Vec<String>) {
+/// Return the byte ranges of the `#hashtags` in a line, without the `#`.
+/// With `multiword`, Bear-style `#multi word tags#` are recognized as well.
+fn hashtag_spans(line: &str, multiword: bool) -> Vec<(Range<usize>, InlineTag)> {
     let bytes = line.as_bytes();
     let len = bytes.len();
+    let mut spans = Vec::new();
     let mut i = 0;
 
     while i < len {
         if bytes[i] == b'#' && (i == 0 || bytes[i - 1].is_ascii_whitespace()) {
             let start = i + 1;
-            let mut end = start;
-            while end < len && is_tag_char(bytes[end]) {
-                end += 1;
+
+            if multiword && let Some(end) = multiword_tag_end(bytes, start) {
+                spans.push((start..end, InlineTag::Multiword));
+                i = end + 1;
+                continue;
             }
+
+            let end = tag_name_end(bytes, start);
             if end > start {
-                tags.push(line[start..end].to_owned());
+                spans.push((start..end, InlineTag::Hashtag));
                 i = end;
                 continue;
             }
         }
         i += 1;
     }
+
+    spans
 }
 
-/// Check if a markdown link URL is a wiki-link (bare stem or ./stem or ../stem).
-/// Mirrors the WIKI_LINK_RE pattern in weave/src/md.rs.
-fn wiki_link_stem(url: &str) -> Option<&str> {
-    let mut rest = url;
-    loop {
-        if rest.starts_with("../") {
-            rest = &rest[3..];
-        } else if rest.starts_with("./") {
-            rest = &rest[2..];
-        } else {
-            break;
+/// Return the end of a `#multi word tag#` starting at `start` (just after the
+/// opening `#`), i.e. the position of the closing `#`. See
+/// [`is_multiword_name`] for valid tags.
+fn multiword_tag_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut end = start;
+    while end < bytes.len() && (is_tag_char(bytes[end]) || b" /".contains(&bytes[end])) {
+        end += 1;
+    }
+
+    let closed = end < bytes.len() && bytes[end] == b'#';
+    (closed && is_multiword_name(&bytes[start..end])).then_some(end)
+}
+
+/// Return the target of a markdown link URL if it may point to another note:
+/// no URL scheme, no pure `#fragment` and no file extension besides `.md`.
+/// The `#fragment` is dropped.
+pub(crate) fn markdown_link_target(url: &str) -> Option<&str> {
+    let url = url.split_whitespace().next()?;
+    let target = url.split('#').next().unwrap_or(url);
+
+    (!target.is_empty() && !has_scheme(target) && !has_foreign_extension(target)).then_some(target)
+}
+
+/// Return the target of a markdown link URL if it may point to an
+/// attachment, see [`attachment_target`]. The link title is dropped.
+fn attachment_link_target(url: &str) -> Option<&str> {
+    attachment_target(url.split_whitespace().next()?)
+}
+
+/// Return `target` if it may point to an attachment: no URL scheme and a file
+/// extension besides `.md`. The `#fragment` and `?query` are dropped.
+fn attachment_target(target: &str) -> Option<&str> {
+    let target = target.split(['#', '?']).next().unwrap_or(target).trim();
+    (!has_scheme(target) && has_foreign_extension(target)).then_some(target)
+}
+
+fn has_scheme(target: &str) -> bool {
+    target
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.contains('/'))
+}
+
+fn has_foreign_extension(target: &str) -> bool {
+    let file = target.rsplit('/').next().unwrap_or(target);
+    Path::new(file).extension().is_some_and(|ext| ext != "md")
+}
+
+/// Return the target of a `[[target]]`, `[[target|label]]` or
+/// `[[target#heading]]` wiki-link.
+pub(crate) fn double_bracket_target(inner: &str) -> Option<&str> {
+    let target = inner.split('|').next().unwrap_or(inner);
+    let target = target.split('#').next().unwrap_or(target).trim();
+    (!target.is_empty()).then_some(target)
+}
+
+/// Extract the targets of all links to other notes from a markdown body, as
+/// written. Targets are resolved with [`Notebook::resolve`](crate::Notebook::resolve).
+fn extract_link_targets(body: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    link_spans(body)
+        .into_iter()
+        .map(|span| &body[span])
+        .filter(|target| seen.insert(*target))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Extract the targets of all links to attachments from a markdown body, as
+/// written: `[label](url)` links and images, `[[file.pdf]]` wiki-links and
+/// `![[image.png]]` embeds, `[label]: url` reference definitions and the
+/// `src` and `href` attributes of HTML tags. Resolved with
+/// [`attachment::resolve`](crate::attachment::resolve).
+pub(crate) fn extract_attachment_targets(body: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    prose_lines(body)
+        .flat_map(|line| {
+            let urls = markdown_links(line, true)
+                .into_iter()
+                .filter_map(|(_, url)| attachment_link_target(url));
+            let targets = wiki_links(line)
+                .into_iter()
+                .filter_map(double_bracket_target)
+                .chain(reference_definition_url(line))
+                .chain(html_link_urls(line))
+                .filter_map(attachment_target);
+            urls.chain(targets).collect::<Vec<_>>()
+        })
+        .filter(|target| seen.insert(*target))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// A link to another note in a markdown body. Only the target is used
+/// without the `sqlite` index.
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+pub(crate) struct Link<'a> {
+    /// Label of the link, the target of a wiki-link without label.
+    pub(crate) title: &'a str,
+    /// URL of a markdown link without its title, inner text of a wiki-link
+    /// without its label.
+    pub(crate) href: &'a str,
+    /// Target as returned by [`markdown_link_target`] or
+    /// [`double_bracket_target`].
+    pub(crate) target: &'a str,
+    pub(crate) kind: LinkKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LinkKind {
+    Markdown,
+    WikiLink,
+}
+
+impl LinkKind {
+    /// Return the `type` zk stores for links of this kind.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            LinkKind::Markdown => "markdown",
+            LinkKind::WikiLink => "wiki-link",
         }
     }
-    if !rest.is_empty() && rest.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
-        Some(rest)
-    } else {
-        None
+}
+
+/// Return all links to other notes in `text`, in order. Scans for
+/// `[label](url)` and `[[target]]` patterns; skips fenced code blocks,
+/// inline code spans and images.
+pub(crate) fn extract_links(text: &str) -> Vec<Link<'_>> {
+    let mut links = Vec::new();
+    for line in prose_lines(text) {
+        for (title, url) in markdown_links(line, false) {
+            if let Some(target) = markdown_link_target(url) {
+                links.push(Link {
+                    title,
+                    href: url.split_whitespace().next().unwrap_or(url),
+                    target,
+                    kind: LinkKind::Markdown,
+                });
+            }
+        }
+
+        for inner in wiki_links(line) {
+            if let Some(target) = double_bracket_target(inner) {
+                let (href, title) = inner.split_once('|').unwrap_or((inner, inner));
+                links.push(Link {
+                    title: title.trim(),
+                    href: href.trim(),
+                    target,
+                    kind: LinkKind::WikiLink,
+                });
+            }
+        }
     }
+    links
 }
 
-/// Extract all wiki-link stems from a markdown body.
-/// Scans for `](url)` patterns; skips fenced code blocks.
-fn extract_wiki_link_stems(body: &str) -> Vec<String> {
-    let mut seen = std::collections::HashSet::new();
-    let mut stems = Vec::new();
-    let mut in_code_block = false;
+/// Return the byte ranges of the targets of all links to other notes in
+/// `text`, see [`extract_links`]. Labels, `#fragments` and link titles are
+/// outside of the ranges.
+pub(crate) fn link_spans(text: &str) -> Vec<Range<usize>> {
+    // All targets are slices of `text`.
+    extract_links(text)
+        .into_iter()
+        .map(|link| {
+            let start = link.target.as_ptr() as usize - text.as_ptr() as usize;
+            start..start + link.target.len()
+        })
+        .collect()
+}
 
-    for line in body.lines() {
+/// Return the lines of `text` outside of fenced code blocks.
+fn prose_lines(text: &str) -> impl Iterator<Item = &str> {
+    let mut in_code_block = false;
+    text.split_inclusive('\n').filter(move |line| {
         if line.trim_start().starts_with("```") {
             in_code_block = !in_code_block;
-            continue;
+            return false;
         }
-        if in_code_block {
+        !in_code_block
+    })
+}
+
+/// Return the labels and URLs of the `[label](url)` links in a line, URLs
+/// including link titles. `![alt](url)` images are only included with
+/// `images`. Links in inline code spans are skipped.
+fn markdown_links(line: &str, images: bool) -> Vec<(&str, &str)> {
+    let code = code_spans(line);
+    let mut links = Vec::new();
+    let mut from = 0;
+    while let Some(pos) = line[from..].find("](").map(|pos| from + pos) {
+        let start = line[from..pos].rfind('[').map(|start| from + start);
+        let url_start = pos + 2;
+        let end = line[url_start..]
+            .find([')', '\n'])
+            .map_or(line.len(), |end| url_start + end);
+        from = end;
+
+        let image = start.is_some_and(|start| line[..start].ends_with('!'));
+        if in_spans(&code, pos) || (image && !images) {
             continue;
         }
+        let label = start.map_or("", |start| &line[start + 1..pos]);
+        links.push((label, &line[url_start..end]));
+    }
+    links
+}
 
-        let mut rest = line;
-        while let Some(pos) = rest.find("](") {
-            rest = &rest[pos + 2..];
-            let end = rest.find([')', '\n']).unwrap_or(rest.len());
-            if let Some(stem) = wiki_link_stem(&rest[..end])
-                && seen.insert(stem.to_owned())
-            {
-                stems.push(stem.to_owned());
+/// Return the inner text of the `[[target|label]]` wiki-links and
+/// `![[target]]` embeds in a line, outside of inline code spans.
+fn wiki_links(line: &str) -> Vec<&str> {
+    let code = code_spans(line);
+    let mut links = Vec::new();
+    let mut from = 0;
+    while let Some(pos) = line[from..].find("[[").map(|pos| from + pos) {
+        let inner = pos + 2;
+        let Some(end) = line[inner..].find("]]").map(|end| inner + end) else {
+            break;
+        };
+        if !in_spans(&code, pos) {
+            links.push(&line[inner..end]);
+        }
+        from = end + 2;
+    }
+    links
+}
+
+/// Return the byte ranges of the `` `code` `` spans in a line, including
+/// their backticks. A span is closed by a run of as many backticks as it was
+/// opened with; unclosed backticks are literal.
+fn code_spans(line: &str) -> Vec<Range<usize>> {
+    let bytes = line.as_bytes();
+    let run_end = |mut i: usize| {
+        while bytes.get(i) == Some(&b'`') {
+            i += 1;
+        }
+        i
+    };
+
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while let Some(pos) = bytes[i..].iter().position(|&b| b == b'`') {
+        let start = i + pos;
+        let open = run_end(start);
+        i = open;
+
+        let mut j = open;
+        while let Some(pos) = bytes[j..].iter().position(|&b| b == b'`') {
+            let close = j + pos;
+            j = run_end(close);
+            if j - close == open - start {
+                spans.push(start..j);
+                i = j;
+                break;
+            }
+        }
+    }
+    spans
+}
+
+fn in_spans(spans: &[Range<usize>], pos: usize) -> bool {
+    spans.iter().any(|span| span.contains(&pos))
+}
+
+/// Return the URL of a `[label]: url` reference definition line, without
+/// its title. Footnotes like `[^1]: text` are not definitions.
+fn reference_definition_url(line: &str) -> Option<&str> {
+    let (label, rest) = line.trim_start().strip_prefix('[')?.split_once("]:")?;
+    if label.is_empty() || label.starts_with('^') {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    match rest.strip_prefix('<') {
+        Some(url) => url.split_once('>').map(|(url, _)| url),
+        None => rest.split_whitespace().next(),
+    }
+}
+
+/// Return the values of the `src` and `href` attributes of the HTML tags in
+/// a line, e.g. of `<img src="cat.png">`.
+fn html_link_urls(line: &str) -> Vec<&str> {
+    if !line.contains('<') {
+        return Vec::new();
+    }
+
+    // ASCII lowercasing keeps byte positions.
+    let lower = line.to_ascii_lowercase();
+    let mut urls = Vec::new();
+    for attr in ["src=", "href="] {
+        for (pos, _) in lower.match_indices(attr) {
+            if !line[..pos].ends_with(char::is_whitespace) {
+                continue;
             }
-            rest = &rest[end..];
+            let value = &line[pos + attr.len()..];
+            let url = match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => value[1..].split(quote).next(),
+                _ => value.split(|c: char| c.is_whitespace() || c == '>').next(),
+            };
+            urls.extend(url.filter(|url| !url.is_empty()));
         }
     }
-    stems
+    urls
+}
+
+/// Variables available in a custom `link-format` template.
+#[derive(Serialize)]
+struct LinkContext<'a> {
+    path: &'a str,
+    filename: &'a str,
+    title: &'a str,
+}
+
+/// Format a link to the note at `path` according to the `link-format`,
+/// `link-encode-path` and `link-drop-extension` options.
+pub(crate) fn format_link(
+    path: &Path,
+    title: &str,
+    markdown: &MarkdownConfig,
+) -> Result<String, Error> {
+    let target = if markdown.link_drop_extension {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    };
+    let target = target.to_string_lossy();
+    let encode = markdown
+        .link_encode_path
+        .unwrap_or(markdown.link_format == "markdown");
+    let target = if encode {
+        encode_path(&target)
+    } else {
+        target.into_owned()
+    };
+
+    match markdown.link_format.as_str() {
+        "markdown" => Ok(format!("[{title}]({target})")),
+        "wiki" => Ok(format!("[[{target}]]")),
+        template => {
+            let filename = path
+                .file_name()
+                .map(|f| f.to_string_lossy())
+                .unwrap_or_default();
+            let context = LinkContext {
+                path: &target,
+                filename: &filename,
+                title,
+            };
+            template::render(template, &context, "link-format")
+        }
+    }
+}
+
+/// Format the link of a note like [`format_link`], falling back to a markdown
+/// link if the custom `link-format` template fails. The failure is reported
+/// by [`Notebook::config_errors`](crate::Notebook::config_errors).
+pub(crate) fn note_link(path: &Path, title: &str, markdown: &MarkdownConfig) -> String {
+    format_link(path, title, markdown).unwrap_or_else(|_| {
+        let markdown = MarkdownConfig {
+            link_format: "markdown".into(),
+            ..markdown.clone()
+        };
+        format_link(path, title, &markdown).unwrap_or_default()
+    })
+}
+
+/// Percent-encode everything but unreserved characters and `/` separators.
+pub(crate) fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~' | b'/') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
 }
 
 /// Extract a list of strings from a YAML value (sequence or single string).
@@ -432,7 +866,7 @@ fn extract_created(frontmatter: &Frontmatter) -> Option<jiff::Timestamp> {
 }
 
 /// Try multiple date formats to parse a string into a jiff::Timestamp.
-fn parse_date_string(s: &str) -> Option<jiff::Timestamp> {
+pub(crate) fn parse_date_string(s: &str) -> Option<jiff::Timestamp> {
     let s = s.trim();
 
     // Try full ISO 8601 / RFC 3339 (e.g. "2024-01-15T10:30:00Z" or with offset)
@@ -457,23 +891,37 @@ fn parse_date_string(s: &str) -> Option<jiff::Timestamp> {
     None
 }
 
-fn timestamp_from_mtime(meta: &fs::Metadata) -> jiff::Timestamp {
-    meta.modified()
-        .ok()
-        .and_then(|st| jiff::Timestamp::try_from(st).ok())
-        .unwrap_or(jiff::Timestamp::UNIX_EPOCH)
-}
-
-fn timestamp_from_birthtime(meta: &fs::Metadata) -> Option<jiff::Timestamp> {
-    meta.created()
-        .ok()
-        .and_then(|st| jiff::Timestamp::try_from(st).ok())
+/// Return the SHA-256 checksum of `content` the way zk stores it.
+pub(crate) fn checksum(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn extract_colon_tags(line: &str, tags: &mut Vec<String>) {
+        tags.extend(
+            colon_tag_spans(line)
+                .into_iter()
+                .map(|span| line[span].to_owned()),
+        );
+    }
+
+    fn extract_hashtags(line: &str, multiword: bool, tags: &mut Vec<String>) {
+        let spans = hashtag_spans(line, multiword);
+        tags.extend(spans.into_iter().map(|(span, _)| line[span].to_owned()));
+    }
+
+    fn all_tag_syntaxes() -> MarkdownConfig {
+        MarkdownConfig {
+            hashtags: true,
+            colon_tags: true,
+            multiword_tags: false,
+            ..MarkdownConfig::default()
+        }
+    }
+
     #[test]
     fn test_extract_frontmatter() {
         let content = "---\ntitle: Hello\ntags: [a, b]\n---\n# Hello\n\nBody here.";
@@ -570,6 +1018,7 @@ mod tests {
             date: None,
             tags: Vec::new(),
             aliases: Vec::new(),
+            metadata: BTreeMap::new(),
         };
         let (title, body) = extract_title_and_body("# Heading\n\nBody text", &fm);
         assert_eq!(title, "FM Title");
@@ -609,22 +1058,37 @@ mod tests {
     #[test]
     fn test_hashtags() {
         let mut tags = Vec::new();
-        extract_hashtags("#rust #coding", &mut tags);
+        extract_hashtags("#rust #coding", false, &mut tags);
         assert_eq!(tags, vec!["rust", "coding"]);
     }
 
+    #[test]
+    fn test_nested_inline_tags() {
+        let mut tags = Vec::new();
+        extract_hashtags("#project/weave/ui #a/ #b//c #/d", false, &mut tags);
+        assert_eq!(tags, vec!["project/weave/ui", "a", "b"]);
+
+        let mut tags = Vec::new();
+        extract_hashtags("#project/big idea# #x", true, &mut tags);
+        assert_eq!(tags, vec!["project/big idea", "x"]);
+
+        let mut tags = Vec::new();
+        extract_colon_tags(":project/weave:se:", &mut tags);
+        assert_eq!(tags, vec!["project/weave", "se"]);
+    }
+
     #[test]
     fn test_hashtags_not_headings() {
         // Markdown heading should not produce a tag.
         let mut tags = Vec::new();
-        extract_hashtags("# Heading", &mut tags);
+        extract_hashtags("# Heading", false, &mut tags);
         assert!(tags.is_empty());
     }
 
     #[test]
     fn test_inline_tags_skips_code_blocks() {
         let content = "body\n\n```\n:code:tag:\n#codetag\n```\n\n:real:";
-        let tags = extract_inline_tags(content);
+        let tags = extract_inline_tags(content, &all_tag_syntaxes());
         assert_eq!(tags, vec!["real"]);
     }
 
@@ -632,54 +1096,236 @@ mod tests {
     fn test_inline_tags_real_note() {
         // Mimics the actual note format found in the user's zettelkasten.
         let content = "# Expression problem\n\nA challenge in programming.\n\n:se:programming:";
-        let tags = extract_inline_tags(content);
+        let tags = extract_inline_tags(content, &all_tag_syntaxes());
         assert_eq!(tags, vec!["se", "programming"]);
     }
 
     #[test]
-    fn test_wiki_link_stem_bare() {
-        assert_eq!(wiki_link_stem("abc123"), Some("abc123"));
+    fn test_inline_tags_respect_config() {
+        let content = "#hash :colon:";
+
+        let hashtags_only = MarkdownConfig {
+            colon_tags: false,
+            ..all_tag_syntaxes()
+        };
+        assert_eq!(extract_inline_tags(content, &hashtags_only), vec!["hash"]);
+
+        let colon_only = MarkdownConfig {
+            hashtags: false,
+            ..all_tag_syntaxes()
+        };
+        assert_eq!(extract_inline_tags(content, &colon_only), vec!["colon"]);
+
+        let none = MarkdownConfig {
+            hashtags: false,
+            colon_tags: false,
+            ..all_tag_syntaxes()
+        };
+        assert!(extract_inline_tags(content, &none).is_empty());
     }
 
     #[test]
-    fn test_wiki_link_stem_relative() {
-        assert_eq!(wiki_link_stem("./abc123"), Some("abc123"));
-        assert_eq!(wiki_link_stem("../abc123"), Some("abc123"));
-        assert_eq!(wiki_link_stem("../../abc123"), Some("abc123"));
+    fn test_multiword_hashtags() {
+        let mut tags = Vec::new();
+        extract_hashtags("#multi word tag# and #single", true, &mut tags);
+        assert_eq!(tags, vec!["multi word tag", "single"]);
     }
 
     #[test]
-    fn test_wiki_link_stem_rejects_url() {
-        assert_eq!(wiki_link_stem("https://example.com"), None);
-        assert_eq!(wiki_link_stem("file.md"), None);
+    fn test_multiword_hashtags_disabled() {
+        let mut tags = Vec::new();
+        extract_hashtags("#multi word tag#", false, &mut tags);
+        assert_eq!(tags, vec!["multi"]);
     }
 
     #[test]
-    fn test_wiki_link_stem_hyphens() {
-        assert_eq!(wiki_link_stem("my-note"), Some("my-note"));
-        assert_eq!(wiki_link_stem("a-b-c"), Some("a-b-c"));
-        assert_eq!(wiki_link_stem("./my-note"), Some("my-note"));
-        assert_eq!(wiki_link_stem("../my-note"), Some("my-note"));
+    fn test_multiword_hashtags_adjacent_single_words() {
+        // A trailing space before the next `#` is not a multi-word tag.
+        let mut tags = Vec::new();
+        extract_hashtags("#rust #coding", true, &mut tags);
+        assert_eq!(tags, vec!["rust", "coding"]);
     }
 
     #[test]
-    fn test_extract_wiki_link_stems_basic() {
+    fn test_markdown_link_target() {
+        assert_eq!(markdown_link_target("abc123"), Some("abc123"));
+        assert_eq!(markdown_link_target("./abc123"), Some("./abc123"));
+        assert_eq!(
+            markdown_link_target("../dir/my-note"),
+            Some("../dir/my-note")
+        );
+        assert_eq!(markdown_link_target("note.md#heading"), Some("note.md"));
+    }
+
+    #[test]
+    fn test_markdown_link_target_rejects_non_notes() {
+        assert_eq!(markdown_link_target("https://example.com"), None);
+        assert_eq!(markdown_link_target("mailto:me@example.com"), None);
+        assert_eq!(markdown_link_target("file.pdf"), None);
+        assert_eq!(markdown_link_target("#heading"), None);
+    }
+
+    #[test]
+    fn test_extract_attachment_targets() {
+        let body = "![cat](img/cat.png \"A cat\") and [paper](../paper.pdf#page=2), \
+                    [note](abc), [site](https://example.com/a.png) ![again](img/cat.png)\n\
+                    ```\n![code](code.png)\n```";
+        let targets = extract_attachment_targets(body);
+        assert_eq!(targets, vec!["img/cat.png", "../paper.pdf"]);
+    }
+
+    #[test]
+    fn test_extract_attachment_targets_other_syntaxes() {
+        let body = "![[cat.png]] [[my paper.pdf|Paper]] [[note]]\n\
+                    [ref]: img/ref.jpg \"Title\"\n\
+                    [spaced]: <img/my ref.jpg>\n\
+                    [^1]: footnote.png\n\
+                    <img alt=\"x\" SRC='html.png'> <a href=docs/a.pdf>a</a> \
+                    <a href=\"https://example.com/b.pdf\">b</a>";
+        let targets = extract_attachment_targets(body);
+        assert_eq!(
+            targets,
+            vec![
+                "cat.png",
+                "my paper.pdf",
+                "img/ref.jpg",
+                "img/my ref.jpg",
+                "html.png",
+                "docs/a.pdf"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_link_targets_basic() {
         let body = "See [note one](abc) and [note two](./def).";
-        let stems = extract_wiki_link_stems(body);
-        assert_eq!(stems, vec!["abc", "def"]);
+        let targets = extract_link_targets(body);
+        assert_eq!(targets, vec!["abc", "./def"]);
     }
 
     #[test]
-    fn test_extract_wiki_link_stems_skips_urls() {
+    fn test_extract_link_targets_skips_urls() {
         let body = "See [external](https://example.com) and [note](abc).";
-        let stems = extract_wiki_link_stems(body);
-        assert_eq!(stems, vec!["abc"]);
+        let targets = extract_link_targets(body);
+        assert_eq!(targets, vec!["abc"]);
     }
 
     #[test]
-    fn test_extract_wiki_link_stems_skips_code_blocks() {
+    fn test_extract_link_targets_skips_code_blocks() {
         let body = "Before.\n\n```\n[code](link_in_code)\n```\n\n[real](abc).";
-        let stems = extract_wiki_link_stems(body);
-        assert_eq!(stems, vec!["abc"]);
+        let targets = extract_link_targets(body);
+        assert_eq!(targets, vec!["abc"]);
+    }
+
+    #[test]
+    fn test_extract_link_targets_skips_code_spans() {
+        let body = "Write `[label](path)`, ``[[a]] `b` [c](d)`` or `[[e]]` for [real](abc).";
+        let targets = extract_link_targets(body);
+        assert_eq!(targets, vec!["abc"]);
+
+        // Unclosed backticks are literal.
+        let targets = extract_link_targets("A ``[[abc]]` and [def](def)");
+        assert_eq!(targets, vec!["def", "abc"]);
+    }
+
+    #[test]
+    fn test_extract_link_targets_skips_images() {
+        let body = "![alt](path) and ![[image]] and [![badge](badge)](abc).";
+        let targets = extract_link_targets(body);
+        assert_eq!(targets, vec!["abc", "image"]);
+
+        let attachments = extract_attachment_targets("![alt](cat.png) and `![alt](dog.png)`");
+        assert_eq!(attachments, vec!["cat.png"]);
+    }
+
+    #[test]
+    fn test_extract_double_bracket_links() {
+        let body = "See [[abc]], [[def|the label]] and [[My Note#Some heading]].";
+        let targets = extract_link_targets(body);
+        assert_eq!(targets, vec!["abc", "def", "My Note"]);
+    }
+
+    #[test]
+    fn test_extract_double_bracket_links_paths() {
+        let body = "[[journal/2024-01-01]] and [[notes/abc.md]] and [[ ]]";
+        let targets = extract_link_targets(body);
+        assert_eq!(targets, vec!["journal/2024-01-01", "notes/abc.md"]);
+    }
+
+    #[test]
+    fn test_extract_mixed_link_syntaxes() {
+        let body = "[one](abc) and [[abc]] and [[def]]\n\n```\n[[in-code]]\n```";
+        let targets = extract_link_targets(body);
+        assert_eq!(targets, vec!["abc", "def"]);
+    }
+
+    #[test]
+    fn test_link_spans() {
+        let text = "[label](dir/abc.md#top \"Title\") and [[ def | label]]";
+        let spans: Vec<_> = link_spans(text).into_iter().map(|s| &text[s]).collect();
+        assert_eq!(spans, vec!["dir/abc.md", "def"]);
+    }
+
+    #[test]
+    fn test_extract_links() {
+        let text = "[label](abc.md#top \"Title\"), [[def]] and [[ ghi#heading | other ]]";
+        let links: Vec<_> = extract_links(text)
+            .into_iter()
+            .map(|link| (link.title, link.href, link.target, link.kind))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                ("label", "abc.md#top", "abc.md", LinkKind::Markdown),
+                ("def", "def", "def", LinkKind::WikiLink),
+                ("other", "ghi#heading", "ghi", LinkKind::WikiLink),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_note_link_falls_back_to_markdown() {
+        let markdown = MarkdownConfig {
+            link_format: "{{#if}}".into(),
+            ..MarkdownConfig::default()
+        };
+        let path = Path::new("dir/my note.md");
+        assert!(format_link(path, "Title", &markdown).is_err());
+        assert_eq!(
+            note_link(path, "Title", &markdown),
+            "[Title](dir/my%20note)"
+        );
+
+        let meta = FileMetadata {
+            is_dir: false,
+            len: 0,
+            modified: jiff::Timestamp::UNIX_EPOCH,
+            created: None,
+        };
+        let note = parse_note("# Title", path.into(), path.into(), &meta, &markdown).unwrap();
+        assert_eq!(note.link(), "[Title](dir/my%20note)");
+    }
+
+    #[test]
+    fn test_format_link() {
+        let path = Path::new("dir/my note.md");
+        let mut markdown = MarkdownConfig::default();
+        assert_eq!(
+            format_link(path, "Title", &markdown).unwrap(),
+            "[Title](dir/my%20note)"
+        );
+
+        markdown.link_format = "wiki".into();
+        assert_eq!(
+            format_link(path, "Title", &markdown).unwrap(),
+            "[[dir/my note]]"
+        );
+
+        markdown.link_format = "[[{{path}}|{{title}}]]".into();
+        markdown.link_drop_extension = false;
+        assert_eq!(
+            format_link(path, "Title", &markdown).unwrap(),
+            "[[dir/my note.md|Title]]"
+        );
     }
 }
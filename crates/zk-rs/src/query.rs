@@ -0,0 +1,963 @@
+//! Filter and sort notes like `zk list`.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use jiff::tz::TimeZone;
+use jiff::{Span, Timestamp, civil};
+
+use crate::error::Error;
+use crate::note::Note;
+use crate::tag;
+use crate::template::get_date;
+
+/// Filters, sort order and limit for [`Notebook::query`](crate::Notebook::query),
+/// mirroring the options of `zk list`.
+///
+/// Build one with the methods below, [`parse`](NoteQuery::parse) it from a
+/// search string or read it from `zk list` arguments with
+/// [`from_args`](NoteQuery::from_args), as found in the `[filter]` config section.
+#[derive(Clone, Debug, Default)]
+pub struct NoteQuery {
+    pub(crate) paths: Vec<PathBuf>,
+    pub(crate) tags: Vec<TagFilter>,
+    pub(crate) exact_tags: Vec<String>,
+    pub(crate) metadata: Vec<MetadataFilter>,
+    pub(crate) match_text: Option<String>,
+    pub(crate) linked_by: Vec<String>,
+    pub(crate) link_to: Vec<String>,
+    pub(crate) orphan: bool,
+    pub(crate) created_after: Option<Timestamp>,
+    pub(crate) created_before: Option<Timestamp>,
+    pub(crate) modified_after: Option<Timestamp>,
+    pub(crate) modified_before: Option<Timestamp>,
+    pub(crate) sort: Vec<Sort>,
+    pub(crate) limit: Option<usize>,
+}
+
+impl NoteQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only notes in the notebook-relative file or directory `path`
+    /// (positional `zk list` arguments).
+    pub fn in_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Require notes to match the tag expression `expr` (`--tag`).
+    ///
+    /// Comma-separated terms must all match. Within a term, tags joined by
+    /// `OR` or `|` are alternatives, `NOT tag` or `-tag` excludes a tag and
+    /// `*` matches any characters, e.g. `"book-* OR reading, NOT archived"`.
+    /// A tag also matches the tags nested in it: `project` matches
+    /// `project/weave`.
+    pub fn tag(mut self, expr: &str) -> Self {
+        self.tags.push(TagFilter::parse(expr));
+        self
+    }
+
+    /// Only notes with the tag `tag` itself, ignoring case. Unlike
+    /// [`tag`](NoteQuery::tag), nested tags like `tag/child` do not count, see
+    /// [`Note::has`].
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.exact_tags.push(tag.to_owned());
+        self
+    }
+
+    /// Only notes whose frontmatter field `key` matches `value`, or holds a
+    /// list with a matching item. The comparison ignores case and `*` matches
+    /// any characters, so `*` alone requires the field to be set.
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.push(MetadataFilter::new(key, value, false));
+        self
+    }
+
+    /// Only notes without a frontmatter field `key` matching `value`, the
+    /// inverse of [`metadata`](NoteQuery::metadata).
+    pub fn without_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.push(MetadataFilter::new(key, value, true));
+        self
+    }
+
+    /// Require notes to match the full-text query `text` (`--match`), see
+    /// [`Notebook::search`](crate::Notebook::search).
+    pub fn matching(mut self, text: impl Into<String>) -> Self {
+        self.match_text = Some(text.into());
+        self
+    }
+
+    /// Only notes the note `id` links to (`--linked-by`).
+    pub fn linked_by(mut self, id: impl Into<String>) -> Self {
+        self.linked_by.push(id.into());
+        self
+    }
+
+    /// Only notes linking to the note `id` (`--link-to`).
+    pub fn link_to(mut self, id: impl Into<String>) -> Self {
+        self.link_to.push(id.into());
+        self
+    }
+
+    /// Only notes without backlinks (`--orphan`).
+    pub fn orphan(mut self) -> Self {
+        self.orphan = true;
+        self
+    }
+
+    /// Only notes created at or after `date` (`--created-after`).
+    pub fn created_after(mut self, date: Timestamp) -> Self {
+        self.created_after = Some(date);
+        self
+    }
+
+    /// Only notes created before `date` (`--created-before`).
+    pub fn created_before(mut self, date: Timestamp) -> Self {
+        self.created_before = Some(date);
+        self
+    }
+
+    /// Only notes modified at or after `date` (`--modified-after`).
+    pub fn modified_after(mut self, date: Timestamp) -> Self {
+        self.modified_after = Some(date);
+        self
+    }
+
+    /// Only notes modified before `date` (`--modified-before`).
+    pub fn modified_before(mut self, date: Timestamp) -> Self {
+        self.modified_before = Some(date);
+        self
+    }
+
+    /// Add a sort key (`--sort`). Earlier keys take precedence.
+    pub fn sort(mut self, sort: Sort) -> Self {
+        self.sort.push(sort);
+        self
+    }
+
+    /// Return at most `limit` notes (`--limit`).
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Return the full-text part of the query, if any.
+    pub fn match_text(&self) -> Option<&str> {
+        self.match_text.as_deref()
+    }
+
+    /// Return the note ids of the [`linked_by`](NoteQuery::linked_by) and
+    /// [`link_to`](NoteQuery::link_to) filters.
+    pub fn link_ids(&self) -> impl Iterator<Item = &str> {
+        self.linked_by
+            .iter()
+            .chain(&self.link_to)
+            .map(String::as_str)
+    }
+
+    /// Parse a search string such as `#rust -#archived created:>2024-01 borrow`.
+    ///
+    /// Recognized words:
+    /// - `#tag` and `-#tag` require or exclude a tag; `tag:"a OR b"` takes a
+    ///   full tag expression.
+    /// - `created:` and `modified:` take a date or period (`2024`, `2024-01`,
+    ///   `2024-01-15`, `yesterday`, `3 days ago`), prefixed with `>` for "on or
+    ///   after" and `<` for "before". Without prefix, the whole period matches.
+    /// - `linked-by:id`, `link-to:id` and `is:orphan` filter by links.
+    /// - `path:dir` only keeps notes in `dir`.
+    /// - `sort:modified-` and `limit:10` as in `zk list`.
+    /// - Any other `key:value`, e.g. `status:draft`, requires a frontmatter
+    ///   field, see [`metadata`](NoteQuery::metadata). `-key:value` excludes it.
+    ///
+    /// Everything else, including `"quoted phrases"`, is matched against the
+    /// note contents.
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        let mut query = Self::new();
+        let mut text = Vec::new();
+
+        for word in split_words(input) {
+            if let Some(tag) = word.strip_prefix("-#") {
+                query = query.tag(&format!("NOT {tag}"));
+                continue;
+            }
+            if let Some(tag) = word.strip_prefix('#')
+                && !tag.is_empty()
+            {
+                query = query.tag(tag);
+                continue;
+            }
+
+            let Some((key, value)) = word.split_once(':') else {
+                text.push(word);
+                continue;
+            };
+            let value = value.trim_matches('"');
+
+            match key {
+                "tag" => query = query.tag(value),
+                "path" => query = query.in_path(value),
+                "created" => {
+                    let (after, before) = date_filter(value)?;
+                    query.created_after = after.or(query.created_after);
+                    query.created_before = before.or(query.created_before);
+                }
+                "modified" => {
+                    let (after, before) = date_filter(value)?;
+                    query.modified_after = after.or(query.modified_after);
+                    query.modified_before = before.or(query.modified_before);
+                }
+                "linked-by" => query = query.linked_by(value),
+                "link-to" => query = query.link_to(value),
+                "is" if value == "orphan" => query = query.orphan(),
+                "sort" => {
+                    for sort in value.split(',') {
+                        query = query.sort(Sort::parse(sort)?);
+                    }
+                }
+                "limit" => {
+                    let limit = value
+                        .parse()
+                        .map_err(|_| Error::Query(format!("invalid limit: {value}")))?;
+                    query = query.limit(limit);
+                }
+                _ => {
+                    let (field, negated) = match key.strip_prefix('-') {
+                        Some(field) => (field, true),
+                        None => (key, false),
+                    };
+                    if key != "is" && is_metadata_key(field) && is_metadata_filter(value) {
+                        query
+                            .metadata
+                            .push(MetadataFilter::new(field, value, negated));
+                    } else {
+                        text.push(word);
+                    }
+                }
+            }
+        }
+
+        if !text.is_empty() {
+            query = query.matching(text.join(" "));
+        }
+
+        Ok(query)
+    }
+
+    /// Parse `zk list` arguments, e.g. `--tag "a OR b" --sort created- journal`.
+    ///
+    /// Supports `--tag`, `--match`, `--link-to`, `--linked-by`, `--orphan`,
+    /// `--created[-before|-after]`, `--modified[-before|-after]`, `--sort`,
+    /// `--limit`, their short forms and paths. Other options are rejected.
+    pub fn from_args(args: &str) -> Result<Self, Error> {
+        Self::from_arg_list(shell_words(args)?)
+    }
+
+    /// Parse `zk list` arguments that are already split into words, e.g.
+    /// command-line arguments. See [`from_args`](NoteQuery::from_args).
+    pub fn from_arg_list(args: impl IntoIterator<Item = String>) -> Result<Self, Error> {
+        let mut query = Self::new();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if arg.starts_with('-') => {
+                    (flag.to_owned(), Some(value.to_owned()))
+                }
+                _ => (arg, None),
+            };
+
+            if flag == "--orphan" {
+                query = query.orphan();
+                continue;
+            }
+            if !flag.starts_with('-') {
+                query = query.in_path(flag);
+                continue;
+            }
+
+            let value = match inline.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(Error::Query(format!("missing value for {flag}"))),
+            };
+
+            match flag.as_str() {
+                "--tag" | "-t" => query = query.tag(&value),
+                "--match" | "-m" => query = query.matching(value),
+                "--link-to" | "-l" => {
+                    for id in value.split(',') {
+                        query = query.link_to(id.trim());
+                    }
+                }
+                "--linked-by" | "-L" => {
+                    for id in value.split(',') {
+                        query = query.linked_by(id.trim());
+                    }
+                }
+                "--created" => {
+                    let (start, end) = period(&value)?;
+                    query.created_after = Some(start);
+                    query.created_before = Some(end);
+                }
+                "--created-after" => query.created_after = Some(period(&value)?.0),
+                "--created-before" => query.created_before = Some(period(&value)?.0),
+                "--modified" => {
+                    let (start, end) = period(&value)?;
+                    query.modified_after = Some(start);
+                    query.modified_before = Some(end);
+                }
+                "--modified-after" => query.modified_after = Some(period(&value)?.0),
+                "--modified-before" => query.modified_before = Some(period(&value)?.0),
+                "--sort" | "-s" => {
+                    for sort in value.split(',') {
+                        query = query.sort(Sort::parse(sort.trim())?);
+                    }
+                }
+                "--limit" | "-n" => {
+                    let limit = value
+                        .parse()
+                        .map_err(|_| Error::Query(format!("invalid limit: {value}")))?;
+                    query = query.limit(limit);
+                }
+                _ => return Err(Error::Query(format!("unsupported option: {flag}"))),
+            }
+        }
+
+        Ok(query)
+    }
+
+    /// Write the query as `zk list` arguments, the inverse of
+    /// [`from_args`](NoteQuery::from_args). Frontmatter field and
+    /// [`with_tag`](NoteQuery::with_tag) filters are left out, `zk list` has no
+    /// equivalent.
+    pub fn to_args(&self) -> String {
+        let mut args: Vec<String> = Vec::new();
+        let mut push = |flag: &str, value: &str| {
+            args.push(flag.to_owned());
+            args.push(shell_quote(value));
+        };
+
+        for filter in &self.tags {
+            push("--tag", &filter.expr);
+        }
+        if let Some(text) = &self.match_text {
+            push("--match", text);
+        }
+        for id in &self.link_to {
+            push("--link-to", id);
+        }
+        for id in &self.linked_by {
+            push("--linked-by", id);
+        }
+        let dates = [
+            ("--created-after", self.created_after),
+            ("--created-before", self.created_before),
+            ("--modified-after", self.modified_after),
+            ("--modified-before", self.modified_before),
+        ];
+        for (flag, date) in dates {
+            if let Some(date) = date {
+                push(flag, &format_bound(date));
+            }
+        }
+        if !self.sort.is_empty() {
+            let sort: Vec<String> = self.sort.iter().map(Sort::to_string).collect();
+            push("--sort", &sort.join(","));
+        }
+        if let Some(limit) = self.limit {
+            push("--limit", &limit.to_string());
+        }
+        if self.orphan {
+            args.push("--orphan".into());
+        }
+        for path in &self.paths {
+            args.push(shell_quote(&path.to_string_lossy()));
+        }
+
+        args.join(" ")
+    }
+
+    /// Combine two queries: notes must match both. Sort keys of `self` come
+    /// first and the smaller limit wins.
+    pub fn and(mut self, other: Self) -> Self {
+        self.paths.extend(other.paths);
+        self.tags.extend(other.tags);
+        self.exact_tags.extend(other.exact_tags);
+        self.metadata.extend(other.metadata);
+        self.match_text = match (self.match_text, other.match_text) {
+            (Some(a), Some(b)) => Some(format!("{a} {b}")),
+            (a, b) => a.or(b),
+        };
+        self.linked_by.extend(other.linked_by);
+        self.link_to.extend(other.link_to);
+        self.orphan |= other.orphan;
+        self.created_after = self.created_after.max(other.created_after);
+        self.created_before = min_bound(self.created_before, other.created_before);
+        self.modified_after = self.modified_after.max(other.modified_after);
+        self.modified_before = min_bound(self.modified_before, other.modified_before);
+        self.sort.extend(other.sort);
+        self.limit = min_bound(self.limit, other.limit);
+        self
+    }
+
+    /// Check the filters that only depend on the note itself.
+    pub(crate) fn matches(&self, note: &Note) -> bool {
+        (self.paths.is_empty() || self.paths.iter().any(|path| note.path().starts_with(path)))
+            && self.tags.iter().all(|filter| filter.matches(note))
+            && self.exact_tags.iter().all(|tag| note.has(tag))
+            && self.metadata.iter().all(|filter| filter.matches(note))
+            && self.created_after.is_none_or(|date| note.created() >= date)
+            && self.created_before.is_none_or(|date| note.created() < date)
+            && self
+                .modified_after
+                .is_none_or(|date| note.modified() >= date)
+            && self
+                .modified_before
+                .is_none_or(|date| note.modified() < date)
+    }
+}
+
+/// Return the smaller of two optional bounds, ignoring missing ones.
+fn min_bound<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Split shell arguments, honoring single and double quotes and backslashes.
+fn shell_words(input: &str) -> Result<Vec<String>, Error> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let current = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(Error::Query("unclosed quote".into())),
+                    }
+                }
+            }
+            '"' => {
+                let current = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => current.extend(chars.next()),
+                        Some(c) => current.push(c),
+                        None => return Err(Error::Query("unclosed quote".into())),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_default().extend(chars.next()),
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+/// Quote `value` for a shell if needed.
+fn shell_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_.,/:+*".contains(c));
+    if plain {
+        value.to_owned()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Write a date bound as a day if it falls on midnight UTC.
+fn format_bound(date: Timestamp) -> String {
+    let zoned = date.to_zoned(TimeZone::UTC);
+    if zoned.time() == civil::Time::midnight() {
+        zoned.date().to_string()
+    } else {
+        date.to_string()
+    }
+}
+
+/// Check if `key` of a `key:value` search word can name a frontmatter field.
+fn is_metadata_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Check if `value` of a `key:value` search word is a field value rather
+/// than the rest of a URL such as `https://example.com`.
+fn is_metadata_filter(value: &str) -> bool {
+    !value.is_empty() && !value.starts_with("//")
+}
+
+/// Split `input` on whitespace, keeping `"quoted parts"` together.
+fn split_words(input: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+
+    for (i, c) in input.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        match (c.is_whitespace() && !quoted, start) {
+            (true, Some(from)) => {
+                words.push(&input[from..i]);
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        words.push(&input[from..]);
+    }
+
+    words
+}
+
+/// Turn `>period`, `<period` or `period` into inclusive-after and
+/// exclusive-before bounds.
+fn date_filter(value: &str) -> Result<(Option<Timestamp>, Option<Timestamp>), Error> {
+    let (prefix, period) = match value.strip_prefix(['>', '<']) {
+        Some(rest) => (&value[..1], rest),
+        None => ("", value),
+    };
+    let (start, end) = self::period(period)?;
+
+    Ok(match prefix {
+        ">" => (Some(start), None),
+        "<" => (None, Some(start)),
+        _ => (Some(start), Some(end)),
+    })
+}
+
+fn period(value: &str) -> Result<(Timestamp, Timestamp), Error> {
+    parse_period(value).ok_or_else(|| Error::Query(format!("invalid date: {value}")))
+}
+
+/// Parse a year, month, day or relative date into the time span it covers.
+/// Exact timestamps cover just that instant.
+fn parse_period(value: &str) -> Option<(Timestamp, Timestamp)> {
+    let value = value.trim();
+    if let Ok(date) = value.parse::<Timestamp>() {
+        return Some((date, date));
+    }
+    let parts: Vec<&str> = value.split('-').collect();
+
+    let (start, span) = match parts[..] {
+        [year] if year.len() == 4 => (
+            civil::Date::new(year.parse().ok()?, 1, 1).ok()?,
+            Span::new().years(1),
+        ),
+        [year, month] if year.len() == 4 && month.len() == 2 => (
+            civil::Date::new(year.parse().ok()?, month.parse().ok()?, 1).ok()?,
+            Span::new().months(1),
+        ),
+        _ => {
+            let date = get_date(value, Timestamp::now())?;
+            (date.to_zoned(TimeZone::UTC).date(), Span::new().days(1))
+        }
+    };
+
+    let end = start.checked_add(span).ok()?;
+    Some((
+        start.to_zoned(TimeZone::UTC).ok()?.timestamp(),
+        end.to_zoned(TimeZone::UTC).ok()?.timestamp(),
+    ))
+}
+
+/// A parsed `--tag` expression: all terms must match, a term matches if any
+/// of its alternatives does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TagFilter {
+    /// The expression as written, kept for [`NoteQuery::to_args`].
+    expr: String,
+    terms: Vec<Vec<TagPattern>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TagPattern {
+    glob: String,
+    negated: bool,
+}
+
+impl TagFilter {
+    fn parse(expr: &str) -> Self {
+        let mut terms: Vec<Vec<TagPattern>> = Vec::new();
+
+        for part in expr.replace('|', " OR ").split(',') {
+            let mut alternative = false;
+            let mut negated = false;
+
+            for word in part.split_whitespace() {
+                match word {
+                    "OR" => alternative = true,
+                    "NOT" => negated = !negated,
+                    _ => {
+                        let (glob, negated_word) = match word.strip_prefix('-') {
+                            Some(glob) => (glob, true),
+                            None => (word, false),
+                        };
+                        let pattern = TagPattern {
+                            glob: glob.trim_start_matches('#').to_lowercase(),
+                            negated: negated != negated_word,
+                        };
+                        match terms.last_mut() {
+                            Some(term) if alternative => term.push(pattern),
+                            _ => terms.push(vec![pattern]),
+                        }
+                        alternative = false;
+                        negated = false;
+                    }
+                }
+            }
+        }
+
+        Self {
+            expr: expr.to_owned(),
+            terms,
+        }
+    }
+
+    fn matches(&self, note: &Note) -> bool {
+        self.terms.iter().all(|term| {
+            term.iter().any(|pattern| {
+                // A tag also matches the tags nested in it.
+                let found = note.tags().iter().any(|name| {
+                    tag::with_parents(&name.to_lowercase())
+                        .any(|name| glob_match(&pattern.glob, name))
+                });
+                found != pattern.negated
+            })
+        })
+    }
+}
+
+/// A frontmatter field filter, see [`NoteQuery::metadata`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct MetadataFilter {
+    key: String,
+    glob: String,
+    negated: bool,
+}
+
+impl MetadataFilter {
+    fn new(key: &str, value: &str, negated: bool) -> Self {
+        Self {
+            key: key.to_lowercase(),
+            glob: value.to_lowercase(),
+            negated,
+        }
+    }
+
+    fn matches(&self, note: &Note) -> bool {
+        let found = note
+            .metadata()
+            .get(&self.key)
+            .is_some_and(|value| self.matches_value(value));
+        found != self.negated
+    }
+
+    fn matches_value(&self, value: &serde_yaml::Value) -> bool {
+        use serde_yaml::Value;
+
+        let text = match value {
+            Value::Sequence(items) => return items.iter().any(|item| self.matches_value(item)),
+            Value::Tagged(tagged) => return self.matches_value(&tagged.value),
+            Value::Mapping(_) => return self.glob == "*",
+            Value::Null => String::new(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(value) => value.to_string(),
+            Value::String(value) => value.to_lowercase(),
+        };
+        glob_match(&self.glob, &text)
+    }
+}
+
+/// Match `text` against `glob`, where `*` stands for any characters.
+pub(crate) fn glob_match(glob: &str, text: &str) -> bool {
+    let mut parts = glob.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// A `--sort` key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sort {
+    pub field: SortField,
+    pub ascending: bool,
+}
+
+/// Fields notes can be sorted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortField {
+    Created,
+    Modified,
+    Path,
+    Title,
+    WordCount,
+    Random,
+}
+
+impl Sort {
+    /// Parse a zk sort key such as `created`, `title+` or `wc-`. Without a
+    /// `+` or `-` suffix, dates sort descending and everything else ascending.
+    pub fn parse(value: &str) -> Result<Self, Error> {
+        let (name, ascending) = match value.as_bytes().last() {
+            Some(b'+') => (&value[..value.len() - 1], Some(true)),
+            Some(b'-') => (&value[..value.len() - 1], Some(false)),
+            _ => (value, None),
+        };
+
+        let field = match name {
+            "created" | "c" => SortField::Created,
+            "modified" | "m" => SortField::Modified,
+            "path" | "p" => SortField::Path,
+            "title" | "t" => SortField::Title,
+            "word-count" | "wc" => SortField::WordCount,
+            "random" | "r" => SortField::Random,
+            _ => return Err(Error::Query(format!("invalid sort: {value}"))),
+        };
+        let ascending =
+            ascending.unwrap_or(!matches!(field, SortField::Created | SortField::Modified));
+
+        Ok(Self { field, ascending })
+    }
+
+    /// Compare two notes by this key. Random keys compare equal.
+    pub(crate) fn compare(&self, a: &Note, b: &Note) -> std::cmp::Ordering {
+        let ordering = match self.field {
+            SortField::Created => a.created().cmp(&b.created()),
+            SortField::Modified => a.modified().cmp(&b.modified()),
+            SortField::Path => a.path().cmp(b.path()),
+            SortField::Title => a.title().to_lowercase().cmp(&b.title().to_lowercase()),
+            SortField::WordCount => a.word_count().cmp(&b.word_count()),
+            SortField::Random => std::cmp::Ordering::Equal,
+        };
+
+        if self.ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.field {
+            SortField::Created => "created",
+            SortField::Modified => "modified",
+            SortField::Path => "path",
+            SortField::Title => "title",
+            SortField::WordCount => "word-count",
+            SortField::Random => "random",
+        };
+        write!(f, "{name}{}", if self.ascending { '+' } else { '-' })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(glob: &str, negated: bool) -> TagPattern {
+        TagPattern {
+            glob: glob.into(),
+            negated,
+        }
+    }
+
+    #[test]
+    fn test_tag_filter_parse() {
+        assert_eq!(
+            TagFilter::parse("a OR b, NOT c").terms,
+            vec![
+                vec![pattern("a", false), pattern("b", false)],
+                vec![pattern("c", true)],
+            ]
+        );
+        assert_eq!(
+            TagFilter::parse("a|#B -c").terms,
+            vec![
+                vec![pattern("a", false), pattern("b", false)],
+                vec![pattern("c", true)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("book-*", "book-fiction"));
+        assert!(glob_match("*fic*", "book-fiction"));
+        assert!(glob_match("rust", "rust"));
+        assert!(!glob_match("rust", "rustacean"));
+        assert!(!glob_match("book-*", "books"));
+    }
+
+    #[test]
+    fn test_parse_period() {
+        let (start, end) = parse_period("2024-02").unwrap();
+        assert_eq!(start, "2024-02-01T00:00:00Z".parse::<Timestamp>().unwrap());
+        assert_eq!(end, "2024-03-01T00:00:00Z".parse::<Timestamp>().unwrap());
+
+        let (start, end) = parse_period("2024").unwrap();
+        assert_eq!(start, "2024-01-01T00:00:00Z".parse::<Timestamp>().unwrap());
+        assert_eq!(end, "2025-01-01T00:00:00Z".parse::<Timestamp>().unwrap());
+
+        let (start, end) = parse_period("2024-02-29").unwrap();
+        assert_eq!(end.as_second() - start.as_second(), 86_400);
+        assert!(parse_period("soon").is_none());
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = NoteQuery::parse(
+            "#rust -#archived created:>2024-01 \"borrow checker\" sort:title limit:5",
+        )
+        .unwrap();
+        assert_eq!(query.tags.len(), 2);
+        assert_eq!(
+            query.created_after,
+            Some("2024-01-01T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(query.created_before, None);
+        assert_eq!(query.match_text(), Some("\"borrow checker\""));
+        assert_eq!(
+            query.sort,
+            [Sort {
+                field: SortField::Title,
+                ascending: true
+            }]
+        );
+        assert_eq!(query.limit, Some(5));
+
+        let query = NoteQuery::parse("Status:\"In review\" -due:* is:new").unwrap();
+        assert_eq!(
+            query.metadata,
+            [
+                MetadataFilter::new("status", "in review", false),
+                MetadataFilter::new("due", "*", true),
+            ]
+        );
+        assert_eq!(query.match_text(), Some("is:new"));
+
+        assert!(NoteQuery::parse("sort:size").is_err());
+        assert!(NoteQuery::parse("created:someday").is_err());
+        assert_eq!(
+            NoteQuery::parse("see https://example.com")
+                .unwrap()
+                .match_text(),
+            Some("see https://example.com")
+        );
+    }
+
+    #[test]
+    fn test_from_args() {
+        let query = NoteQuery::from_args(
+            "--tag 'a OR b' -t=\"NOT c\" --sort created- -n 3 --created-after 2024-01 --orphan journal",
+        )
+        .unwrap();
+        assert_eq!(query.tags.len(), 2);
+        assert_eq!(query.limit, Some(3));
+        assert!(query.orphan);
+        assert_eq!(query.paths, [PathBuf::from("journal")]);
+        assert_eq!(
+            query.created_after,
+            Some("2024-01-01T00:00:00Z".parse().unwrap())
+        );
+
+        let words = ["--tag", "a OR b", "journal"].map(str::to_owned);
+        let query = NoteQuery::from_arg_list(words).unwrap();
+        assert_eq!(query.to_args(), "--tag 'a OR b' journal");
+
+        assert!(NoteQuery::from_args("--interactive").is_err());
+        assert!(NoteQuery::from_args("--tag").is_err());
+        assert!(NoteQuery::from_args("--tag 'open").is_err());
+    }
+
+    #[test]
+    fn test_to_args_round_trip() {
+        let query = NoteQuery::parse("#rust -#archived created:>2024-01 \"it's here\" sort:title")
+            .unwrap()
+            .limit(5)
+            .in_path("journal");
+        let args = query.to_args();
+        assert_eq!(
+            args,
+            "--tag rust --tag 'NOT archived' --match '\"it'\\''s here\"' \
+             --created-after 2024-01-01 --sort title+ --limit 5 journal"
+        );
+
+        let parsed = NoteQuery::from_args(&args).unwrap();
+        assert_eq!(parsed.to_args(), args);
+        assert_eq!(parsed.match_text(), Some("\"it's here\""));
+    }
+
+    #[test]
+    fn test_and() {
+        let query = NoteQuery::parse("#a created:>2024 limit:10 one")
+            .unwrap()
+            .and(NoteQuery::parse("#b created:>2023 limit:3 two").unwrap());
+        assert_eq!(query.tags.len(), 2);
+        assert_eq!(query.match_text(), Some("one two"));
+        assert_eq!(
+            query.created_after,
+            Some("2024-01-01T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(query.limit, Some(3));
+
+        let query = NoteQuery::new()
+            .with_tag("public")
+            .linked_by("a")
+            .and(NoteQuery::new().with_tag("pin").link_to("b"));
+        assert_eq!(query.exact_tags, ["public", "pin"]);
+        assert_eq!(query.link_ids().collect::<Vec<_>>(), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_sort_parse() {
+        assert_eq!(
+            Sort::parse("created").unwrap(),
+            Sort {
+                field: SortField::Created,
+                ascending: false
+            }
+        );
+        assert_eq!(
+            Sort::parse("wc-").unwrap(),
+            Sort {
+                field: SortField::WordCount,
+                ascending: false
+            }
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Suggestions of notes related to a note.
+
+use crate::note::Note;
+
+/// A note related to another one, see
+/// [`Notebook::related`](crate::Notebook::related).
+///
+/// Each measure is between 0 and 1, the score is their sum.
+#[derive(Clone, Debug)]
+pub struct Related<'a> {
+    pub note: &'a Note,
+    pub score: f64,
+    /// Tags both notes have, in lowercase, sorted.
+    pub shared_tags: Vec<String>,
+    /// Overlap of the tags, rare tags weighing more than common ones.
+    pub tags: f64,
+    /// Overlap of the notes linking to each note (co-citation).
+    pub co_citation: f64,
+    /// Overlap of the notes each note links to (bibliographic coupling).
+    pub coupling: f64,
+    /// Cosine similarity of the words of both notes.
+    pub similarity: f64,
+}
+
+impl<'a> Related<'a> {
+    pub(crate) fn new(note: &'a Note) -> Self {
+        Related {
+            note,
+            score: 0.0,
+            shared_tags: Vec::new(),
+            tags: 0.0,
+            co_citation: 0.0,
+            coupling: 0.0,
+            similarity: 0.0,
+        }
+    }
+}
+
+/// Return how much two sets of `a` and `b` items sharing `shared` items
+/// overlap, from 0 (nothing shared) to 1 (the same items).
+pub(crate) fn overlap(shared: usize, a: usize, b: usize) -> f64 {
+    if shared == 0 {
+        return 0.0;
+    }
+    shared as f64 / ((a * b) as f64).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlap() {
+        assert_eq!(overlap(0, 0, 3), 0.0);
+        assert_eq!(overlap(2, 2, 2), 1.0);
+        assert_eq!(overlap(1, 1, 4), 0.5);
+    }
+}
@@ -0,0 +1,559 @@
+//! Full-text search over note titles and bodies.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::note::Note;
+
+/// BM25 term frequency saturation.
+const K1: f64 = 1.2;
+/// BM25 document length normalization.
+const B: f64 = 0.75;
+/// Approximate length of a snippet in bytes.
+const SNIPPET_LEN: usize = 160;
+/// Bytes of context shown before the first match of a snippet.
+const SNIPPET_LEAD: usize = 40;
+/// Number of the highest weighted words of a note compared by
+/// [`SearchIndex::similar`].
+const SIMILAR_TERMS: usize = 32;
+
+/// A note matching a full-text query, see [`Notebook::search`](crate::Notebook::search).
+#[derive(Debug)]
+pub struct SearchHit<'a> {
+    pub note: &'a Note,
+    /// BM25 relevance, higher is better.
+    pub score: f64,
+    /// Excerpt of the body around the best cluster of matches.
+    pub snippet: Snippet,
+}
+
+/// An excerpt of a note body with the matching words marked.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snippet {
+    /// Excerpt with whitespace collapsed, prefixed or suffixed with `…` when cut.
+    pub text: String,
+    /// Byte ranges of `text` that match the query, sorted and disjoint.
+    pub highlights: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    /// Split the text into consecutive fragments, flagging highlighted ones.
+    pub fn fragments(&self) -> impl Iterator<Item = (&str, bool)> {
+        let mut fragments = Vec::new();
+        let mut pos = 0;
+
+        for range in &self.highlights {
+            if pos < range.start {
+                fragments.push((&self.text[pos..range.start], false));
+            }
+            fragments.push((&self.text[range.clone()], true));
+            pos = range.end;
+        }
+        if pos < self.text.len() {
+            fragments.push((&self.text[pos..], false));
+        }
+
+        fragments.into_iter()
+    }
+}
+
+/// One part of a parsed query. All clauses must match.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Clause {
+    /// A single word.
+    Term(String),
+    /// A word ending in `*`, matching every word it starts.
+    Prefix(String),
+    /// Words in `"quotes"`, matching consecutive words.
+    Phrase(Vec<String>),
+}
+
+impl Clause {
+    /// Check if the single body word `token` counts as a match for snippets.
+    fn highlights(&self, token: &str) -> bool {
+        match self {
+            Clause::Term(term) => term == token,
+            Clause::Prefix(prefix) => token.starts_with(prefix.as_str()),
+            Clause::Phrase(terms) => terms.iter().any(|term| term == token),
+        }
+    }
+}
+
+/// Parse `query` into clauses: bare words, `prefix*` words and `"phrases"`.
+/// Words made of several tokens, like `foo-bar`, are treated as phrases.
+pub(crate) fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+
+    for (i, part) in query.split('"').enumerate() {
+        // Odd parts are inside quotes; an unclosed quote runs to the end.
+        if i % 2 == 1 {
+            clauses.extend(phrase(tokenize(part).map(|(_, token)| token).collect()));
+            continue;
+        }
+
+        for word in part.split_whitespace() {
+            let (word, prefix) = match word.strip_suffix('*') {
+                Some(word) => (word, true),
+                None => (word, false),
+            };
+            let mut tokens: Vec<String> = tokenize(word).map(|(_, token)| token).collect();
+            if prefix && tokens.len() == 1 {
+                clauses.push(Clause::Prefix(tokens.remove(0)));
+            } else {
+                clauses.extend(phrase(tokens));
+            }
+        }
+    }
+
+    clauses
+}
+
+fn phrase(mut tokens: Vec<String>) -> Option<Clause> {
+    match tokens.len() {
+        0 => None,
+        1 => Some(Clause::Term(tokens.remove(0))),
+        _ => Some(Clause::Phrase(tokens)),
+    }
+}
+
+/// Split `text` into lowercase alphanumeric words with their byte ranges.
+pub(crate) fn tokenize(text: &str) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    let mut chars = text.char_indices().peekable();
+
+    std::iter::from_fn(move || {
+        while chars.next_if(|(_, c)| !c.is_alphanumeric()).is_some() {}
+        let (start, _) = *chars.peek()?;
+        let mut end = start;
+        while let Some((i, c)) = chars.next_if(|(_, c)| c.is_alphanumeric()) {
+            end = i + c.len_utf8();
+        }
+        Some((start..end, text[start..end].to_lowercase()))
+    })
+}
+
+/// Inverted index from words to the notes and word positions they occur at.
+///
+/// Keyed by notebook-relative path so single notes can be added and removed
+/// without touching the rest of the index.
+#[derive(Default)]
+pub(crate) struct SearchIndex {
+    postings: BTreeMap<String, HashMap<PathBuf, Vec<u32>>>,
+    /// Number of words in each indexed note.
+    lengths: HashMap<PathBuf, usize>,
+    /// Distinct words of each indexed note, the postings to drop on removal.
+    terms: HashMap<PathBuf, Vec<String>>,
+    total_len: usize,
+}
+
+impl SearchIndex {
+    /// Index the title and body of `note`, replacing a previous version at
+    /// the same path.
+    pub(crate) fn insert(&mut self, note: &Note) {
+        self.remove(note.path());
+
+        let path = note.path().to_path_buf();
+        let mut len = 0;
+        let mut positions: HashMap<String, Vec<u32>> = HashMap::new();
+        // The gap between title and body keeps phrases from spanning both.
+        let title = tokenize(note.title()).map(|(_, token)| token);
+        let body = tokenize(note.body()).map(|(_, token)| token);
+        let tokens = title.map(Some).chain([None]).chain(body.map(Some));
+        for (pos, token) in tokens.enumerate() {
+            let Some(token) = token else { continue };
+            positions.entry(token).or_default().push(pos as u32);
+            len += 1;
+        }
+
+        let mut terms = Vec::with_capacity(positions.len());
+        for (term, positions) in positions {
+            terms.push(term.clone());
+            self.postings
+                .entry(term)
+                .or_default()
+                .insert(path.clone(), positions);
+        }
+
+        self.total_len += len;
+        self.lengths.insert(path.clone(), len);
+        self.terms.insert(path, terms);
+    }
+
+    /// Drop the note at `path` from the index. No-op if it is not indexed.
+    pub(crate) fn remove(&mut self, path: &Path) {
+        let Some(len) = self.lengths.remove(path) else {
+            return;
+        };
+
+        self.total_len -= len;
+        for term in self.terms.remove(path).unwrap_or_default() {
+            if let Some(docs) = self.postings.get_mut(&term) {
+                docs.remove(path);
+                if docs.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// Return the paths of notes matching all `clauses` with their BM25 score.
+    pub(crate) fn search(&self, clauses: &[Clause]) -> Vec<(&Path, f64)> {
+        let Some((first, rest)) = clauses.split_first() else {
+            return Vec::new();
+        };
+
+        let mut scores: HashMap<&Path, f64> = self.score(first);
+        for clause in rest {
+            let clause_scores = self.score(clause);
+            scores.retain(|path, score| match clause_scores.get(path) {
+                Some(s) => {
+                    *score += s;
+                    true
+                }
+                None => false,
+            });
+        }
+
+        scores.into_iter().collect()
+    }
+
+    /// Return the cosine similarity of the words of the note at `path` to
+    /// every other note sharing one of its [`SIMILAR_TERMS`] highest weighted
+    /// words. Words are weighted by TF-IDF, so words found in every note do
+    /// not count. Only the postings of those words are walked, not the whole
+    /// index.
+    pub(crate) fn similar(&self, path: &Path) -> HashMap<&Path, f64> {
+        let Some(terms) = self.terms.get(path) else {
+            return HashMap::new();
+        };
+
+        let mut weights: Vec<(&HashMap<PathBuf, Vec<u32>>, f64)> = terms
+            .iter()
+            .map(|term| {
+                let docs = &self.postings[term];
+                (docs, self.weight(docs[path].len(), docs.len()))
+            })
+            .filter(|&(_, weight)| weight > 0.0)
+            .collect();
+        let norm = weights
+            .iter()
+            .map(|(_, weight)| weight * weight)
+            .sum::<f64>()
+            .sqrt();
+        weights.sort_unstable_by(|(_, a), (_, b)| b.total_cmp(a));
+        weights.truncate(SIMILAR_TERMS);
+
+        let mut dots: HashMap<&Path, f64> = HashMap::new();
+        for (docs, own) in weights {
+            for (other, positions) in docs {
+                if other != path {
+                    let weight = self.weight(positions.len(), docs.len());
+                    *dots.entry(other).or_default() += own * weight;
+                }
+            }
+        }
+
+        dots.into_iter()
+            .map(|(other, dot)| (other, dot / (norm * self.norm(other))))
+            .collect()
+    }
+
+    /// Return the TF-IDF weight of a word found `tf` times in a note and in
+    /// `df` notes.
+    fn weight(&self, tf: usize, df: usize) -> f64 {
+        let n = self.lengths.len() as f64;
+        (1.0 + (tf as f64).ln()) * (n / df as f64).ln()
+    }
+
+    /// Return the length of the TF-IDF vector of the note at `path`.
+    fn norm(&self, path: &Path) -> f64 {
+        self.terms[path]
+            .iter()
+            .map(|term| {
+                let docs = &self.postings[term];
+                self.weight(docs[path].len(), docs.len()).powi(2)
+            })
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    /// Score every note matching `clause`.
+    fn score(&self, clause: &Clause) -> HashMap<&Path, f64> {
+        let frequencies = self.frequencies(clause);
+
+        let n = self.lengths.len() as f64;
+        let df = frequencies.len() as f64;
+        let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+        let avg_len = self.total_len as f64 / n.max(1.0);
+
+        frequencies
+            .into_iter()
+            .map(|(path, tf)| {
+                let len = self.lengths[path] as f64;
+                let norm = 1.0 - B + B * len / avg_len.max(1.0);
+                let tf = tf as f64;
+                (path, idf * tf * (K1 + 1.0) / (tf + K1 * norm))
+            })
+            .collect()
+    }
+
+    /// Count how often `clause` occurs in each note.
+    fn frequencies(&self, clause: &Clause) -> HashMap<&Path, usize> {
+        let mut frequencies: HashMap<&Path, usize> = HashMap::new();
+
+        match clause {
+            Clause::Term(term) => {
+                for (path, positions) in self.postings.get(term).into_iter().flatten() {
+                    frequencies.insert(path, positions.len());
+                }
+            }
+            Clause::Prefix(prefix) => {
+                let terms = self
+                    .postings
+                    .range(prefix.clone()..)
+                    .take_while(|(term, _)| term.starts_with(prefix.as_str()));
+                for (_, docs) in terms {
+                    for (path, positions) in docs {
+                        *frequencies.entry(path).or_default() += positions.len();
+                    }
+                }
+            }
+            Clause::Phrase(terms) => {
+                let Some(docs) = terms
+                    .iter()
+                    .map(|term| self.postings.get(term))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    return frequencies;
+                };
+
+                for (path, starts) in docs[0] {
+                    let count = starts
+                        .iter()
+                        .filter(|&&start| {
+                            docs[1..].iter().enumerate().all(|(i, doc)| {
+                                doc.get(path).is_some_and(|positions| {
+                                    positions.binary_search(&(start + i as u32 + 1)).is_ok()
+                                })
+                            })
+                        })
+                        .count();
+                    if count > 0 {
+                        frequencies.insert(path, count);
+                    }
+                }
+            }
+        }
+
+        frequencies
+    }
+}
+
+/// Cut a snippet out of `body` around the densest cluster of words matching
+/// `clauses`. Falls back to the start of the body if nothing matches.
+pub(crate) fn snippet(body: &str, clauses: &[Clause]) -> Snippet {
+    let matches: Vec<Range<usize>> = tokenize(body)
+        .filter(|(_, token)| clauses.iter().any(|clause| clause.highlights(token)))
+        .map(|(range, _)| range)
+        .collect();
+
+    // Start at the match followed by the most matches within one snippet.
+    let first = (0..matches.len())
+        .max_by_key(|&i| {
+            let count = matches[i..]
+                .iter()
+                .take_while(|m| m.end <= matches[i].start + SNIPPET_LEN)
+                .count();
+            (count, std::cmp::Reverse(i))
+        })
+        .map_or(0, |i| matches[i].start);
+
+    let mut start = floor_char_boundary(body, first.saturating_sub(SNIPPET_LEAD));
+    if start > 0 {
+        // Avoid starting mid-word.
+        start = body[start..first]
+            .find(char::is_whitespace)
+            .map_or(start, |i| start + i);
+    }
+    let end = floor_char_boundary(body, (start + SNIPPET_LEN).min(body.len()));
+    let end = if end < body.len() {
+        body[start..end]
+            .rfind(char::is_whitespace)
+            .map_or(end, |i| start + i)
+    } else {
+        end
+    };
+
+    let mut snippet = Snippet::default();
+    if start > 0 {
+        snippet.text.push('…');
+    }
+
+    let mut matches = matches
+        .into_iter()
+        .filter(|m| m.start >= start && m.end <= end)
+        .peekable();
+    let mut pending_space = false;
+    let mut highlight_start = None;
+
+    for (i, c) in body[start..end].char_indices() {
+        let i = start + i;
+        if let Some(m) = matches.peek()
+            && m.start == i
+        {
+            highlight_start = Some(snippet.text.len() + usize::from(pending_space));
+        }
+
+        if c.is_whitespace() {
+            pending_space = !snippet.text.is_empty() && !snippet.text.ends_with('…');
+        } else {
+            if pending_space {
+                snippet.text.push(' ');
+                pending_space = false;
+            }
+            snippet.text.push(c);
+        }
+
+        if let Some(m) = matches.peek()
+            && m.end == i + c.len_utf8()
+        {
+            if let Some(from) = highlight_start.take() {
+                snippet.highlights.push(from..snippet.text.len());
+            }
+            matches.next();
+        }
+    }
+
+    if end < body.len() {
+        snippet.text.push('…');
+    }
+
+    snippet
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MarkdownConfig;
+    use crate::parse::parse_note;
+    use crate::storage::FileMetadata;
+
+    fn tokens(text: &str) -> Vec<String> {
+        tokenize(text).map(|(_, token)| token).collect()
+    }
+
+    fn note(path: &str, content: &str) -> Note {
+        let meta = FileMetadata {
+            is_dir: false,
+            len: 0,
+            modified: jiff::Timestamp::UNIX_EPOCH,
+            created: None,
+        };
+        let path = PathBuf::from(path);
+        parse_note(
+            content,
+            path.clone(),
+            path,
+            &meta,
+            &MarkdownConfig::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_similar_compares_top_terms() {
+        let mut index = SearchIndex::default();
+        index.insert(&note("a.md", "shared rare words"));
+        index.insert(&note("b.md", "shared"));
+        index.insert(&note("c.md", "unrelated"));
+
+        let similar = index.similar(Path::new("a.md"));
+        assert_eq!(similar.keys().collect::<Vec<_>>(), [&Path::new("b.md")]);
+        let b = similar[Path::new("b.md")];
+        assert!(b > 0.0 && b < 1.0);
+        assert!((index.similar(Path::new("b.md"))[Path::new("a.md")] - b).abs() < 1e-9);
+
+        // Words found twice outweigh `shared`, which is no longer compared.
+        let words: Vec<String> = (0..SIMILAR_TERMS).map(|i| format!("w{i} w{i}")).collect();
+        index.insert(&note("a.md", &format!("shared {}", words.join(" "))));
+        assert!(index.similar(Path::new("a.md")).is_empty());
+        assert!(index.similar(Path::new("missing.md")).is_empty());
+    }
+
+    #[test]
+    fn test_remove_drops_only_own_postings() {
+        let mut index = SearchIndex::default();
+        index.insert(&note("a.md", "# Shared\n\nonly in a, twice a"));
+        index.insert(&note("b.md", "# Shared\n\nonly in b"));
+
+        index.remove(Path::new("a.md"));
+        assert!(!index.postings.contains_key("a"));
+        assert!(!index.postings.contains_key("twice"));
+        assert_eq!(
+            index.postings["shared"].keys().collect::<Vec<_>>(),
+            [Path::new("b.md")]
+        );
+        assert_eq!(index.total_len, index.lengths[Path::new("b.md")]);
+        assert!(!index.terms.contains_key(Path::new("a.md")));
+
+        // Reinserting replaces the previous version.
+        index.insert(&note("b.md", "# Other"));
+        assert!(!index.postings.contains_key("shared"));
+        assert_eq!(index.postings.len(), 1);
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokens("Hello, wörld! foo_bar 42"),
+            ["hello", "wörld", "foo", "bar", "42"]
+        );
+        let ranges: Vec<_> = tokenize("  ab cd").map(|(range, _)| range).collect();
+        assert_eq!(ranges, [2..4, 5..7]);
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query("Rust prog* \"zk notes\" foo-bar"),
+            [
+                Clause::Term("rust".into()),
+                Clause::Prefix("prog".into()),
+                Clause::Phrase(vec!["zk".into(), "notes".into()]),
+                Clause::Phrase(vec!["foo".into(), "bar".into()]),
+            ]
+        );
+        assert_eq!(parse_query("\"single\" *"), [Clause::Term("single".into())]);
+        assert!(parse_query("  ").is_empty());
+    }
+
+    #[test]
+    fn test_snippet_highlights() {
+        let body = "Intro.\n\nSome   text about Rust and more rust.";
+        let snippet = snippet(body, &parse_query("rust"));
+        assert_eq!(snippet.text, "Intro. Some text about Rust and more rust.");
+        let marked: Vec<&str> = snippet
+            .fragments()
+            .filter(|(_, marked)| *marked)
+            .map(|(text, _)| text)
+            .collect();
+        assert_eq!(marked, ["Rust", "rust"]);
+    }
+
+    #[test]
+    fn test_snippet_cuts_long_bodies() {
+        let body = format!("{} needle {}", "hay ".repeat(100), "hay ".repeat(100));
+        let snippet = snippet(&body, &parse_query("needle"));
+        assert!(snippet.text.starts_with('…'), "{}", snippet.text);
+        assert!(snippet.text.ends_with('…'), "{}", snippet.text);
+        assert!(snippet.text.len() <= SNIPPET_LEN + 8);
+        assert_eq!(snippet.fragments().filter(|(_, marked)| *marked).count(), 1);
+    }
+}
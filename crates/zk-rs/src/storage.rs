@@ -0,0 +1,361 @@
+//! Where the files of a notebook live.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// File system operations a [`Notebook`](crate::Notebook) needs.
+///
+/// Paths are relative to the notebook root, absolute paths (e.g. templates
+/// configured outside of the notebook) are used as is. Errors follow
+/// `std::fs`, a missing file is reported as [`ErrorKind::NotFound`].
+pub trait NotebookStorage: Send + Sync {
+    /// Return the entries of the directory `dir`, `""` being the root.
+    fn list(&self, dir: &Path) -> io::Result<Vec<DirEntry>>;
+
+    /// Return the content of the file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Write `content` to the file at `path`, replacing it if it exists.
+    /// Missing parent directories are created.
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()>;
+
+    /// Write `content` to a new file at `path`. Fails with
+    /// [`ErrorKind::AlreadyExists`] if the file exists. Missing parent
+    /// directories are created.
+    fn create(&self, path: &Path, content: &[u8]) -> io::Result<()>;
+
+    /// Move the file at `from` to `to`, replacing `to` if it exists.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Delete the file at `path`.
+    fn delete(&self, path: &Path) -> io::Result<()>;
+
+    /// Return the metadata of the file or directory at `path`.
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+
+    /// Return the content of the file at `path` as UTF-8 text.
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+    }
+}
+
+/// An entry returned by [`NotebookStorage::list`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    /// Path of the entry, including the listed directory.
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+/// Metadata returned by [`NotebookStorage::metadata`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub is_dir: bool,
+    /// Size in bytes.
+    pub len: u64,
+    pub modified: jiff::Timestamp,
+    /// Creation time, if the storage knows it.
+    pub created: Option<jiff::Timestamp>,
+}
+
+/// Notebook files in a directory on the local disk.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, path: &Path) -> PathBuf {
+        self.root.join(path)
+    }
+}
+
+impl NotebookStorage for LocalStorage {
+    fn list(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(self.path(dir))? {
+            let entry = entry?;
+            entries.push(DirEntry {
+                path: dir.join(entry.file_name()),
+                // Follow symlinks like the rest of the notebook does.
+                is_dir: entry.path().is_dir(),
+            });
+        }
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(self.path(path))
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        let path = self.path(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)
+    }
+
+    fn create(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        use std::io::Write;
+
+        let path = self.path(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(path)?
+            .write_all(content)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let to = self.path(to);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(self.path(from), to)
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(self.path(path))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let meta = fs::metadata(self.path(path))?;
+        let timestamp = |time: io::Result<std::time::SystemTime>| {
+            time.ok()
+                .and_then(|time| jiff::Timestamp::try_from(time).ok())
+        };
+
+        Ok(FileMetadata {
+            is_dir: meta.is_dir(),
+            len: meta.len(),
+            modified: timestamp(meta.modified()).unwrap_or(jiff::Timestamp::UNIX_EPOCH),
+            created: timestamp(meta.created()),
+        })
+    }
+}
+
+/// Notebook files kept in memory, for tests and embedding. Directories exist
+/// implicitly as long as they contain a file.
+#[derive(Default)]
+pub struct MemoryStorage {
+    files: Mutex<BTreeMap<PathBuf, MemoryFile>>,
+}
+
+struct MemoryFile {
+    content: Vec<u8>,
+    created: jiff::Timestamp,
+    modified: jiff::Timestamp,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, e.g. to set up a notebook. Returns `self` for chaining.
+    pub fn with_file(self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) -> Self {
+        let now = jiff::Timestamp::now();
+        self.files.lock().unwrap().insert(
+            path.into(),
+            MemoryFile {
+                content: content.into(),
+                created: now,
+                modified: now,
+            },
+        );
+        self
+    }
+
+    fn is_dir(files: &BTreeMap<PathBuf, MemoryFile>, dir: &Path) -> bool {
+        dir.as_os_str().is_empty()
+            || files
+                .keys()
+                .any(|path| path.starts_with(dir) && path != dir)
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(ErrorKind::NotFound, format!("{} not found", path.display()))
+}
+
+impl NotebookStorage for MemoryStorage {
+    fn list(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        let files = self.files.lock().unwrap();
+        if !Self::is_dir(&files, dir) {
+            return Err(not_found(dir));
+        }
+
+        let mut entries: Vec<DirEntry> = Vec::new();
+        for path in files.keys() {
+            let Ok(rest) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let mut components = rest.components();
+            let Some(name) = components.next() else {
+                continue;
+            };
+
+            let entry = DirEntry {
+                path: dir.join(name),
+                is_dir: components.next().is_some(),
+            };
+            if !entries.contains(&entry) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let files = self.files.lock().unwrap();
+        files
+            .get(path)
+            .map(|file| file.content.clone())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        let now = jiff::Timestamp::now();
+        let mut files = self.files.lock().unwrap();
+        let file = files.entry(path.to_path_buf()).or_insert(MemoryFile {
+            content: Vec::new(),
+            created: now,
+            modified: now,
+        });
+        file.content = content.to_vec();
+        file.modified = now;
+        Ok(())
+    }
+
+    fn create(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        if self.files.lock().unwrap().contains_key(path) {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        self.write(path, content)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap();
+        let file = files.remove(from).ok_or_else(|| not_found(from))?;
+        files.insert(to.to_path_buf(), file);
+        Ok(())
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap();
+        files
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let files = self.files.lock().unwrap();
+        if let Some(file) = files.get(path) {
+            return Ok(FileMetadata {
+                is_dir: false,
+                len: file.content.len() as u64,
+                modified: file.modified,
+                created: Some(file.created),
+            });
+        }
+        if Self::is_dir(&files, path) {
+            return Ok(FileMetadata {
+                is_dir: true,
+                len: 0,
+                modified: jiff::Timestamp::UNIX_EPOCH,
+                created: None,
+            });
+        }
+        Err(not_found(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage() -> MemoryStorage {
+        MemoryStorage::new()
+            .with_file("a.md", "# A")
+            .with_file("sub/b.md", "# B")
+            .with_file("sub/deep/c.md", "# C")
+    }
+
+    #[test]
+    fn test_memory_list() {
+        let storage = storage();
+        let mut root = storage.list(Path::new("")).unwrap();
+        root.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(
+            root,
+            [
+                DirEntry {
+                    path: "a.md".into(),
+                    is_dir: false
+                },
+                DirEntry {
+                    path: "sub".into(),
+                    is_dir: true
+                },
+            ]
+        );
+
+        let sub = storage.list(Path::new("sub")).unwrap();
+        assert_eq!(sub.len(), 2);
+        assert!(sub.contains(&DirEntry {
+            path: "sub/deep".into(),
+            is_dir: true
+        }));
+
+        let err = storage.list(Path::new("missing")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_memory_write_and_create() {
+        let storage = storage();
+        storage.write(Path::new("a.md"), b"# New A").unwrap();
+        assert_eq!(
+            storage.read_to_string(Path::new("a.md")).unwrap(),
+            "# New A"
+        );
+
+        let err = storage.create(Path::new("a.md"), b"").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        storage.create(Path::new("new/d.md"), b"# D").unwrap();
+        assert!(storage.metadata(Path::new("new")).unwrap().is_dir);
+        assert_eq!(storage.metadata(Path::new("new/d.md")).unwrap().len, 3);
+    }
+
+    #[test]
+    fn test_memory_rename_and_delete() {
+        let storage = storage();
+        storage
+            .rename(Path::new("sub/b.md"), Path::new("b.md"))
+            .unwrap();
+        assert!(storage.read(Path::new("sub/b.md")).is_err());
+        assert_eq!(storage.read(Path::new("b.md")).unwrap(), b"# B");
+
+        storage.delete(Path::new("sub/deep/c.md")).unwrap();
+        assert_eq!(
+            storage.metadata(Path::new("sub")).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert!(storage.delete(Path::new("sub/deep/c.md")).is_err());
+    }
+}
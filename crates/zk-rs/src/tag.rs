@@ -0,0 +1,486 @@
+//! Nested tags, and rewriting tags in note content to rename, merge or
+//! delete them across a notebook.
+//!
+//! Tags nest with `/`: `project/weave/ui` is nested in `project/weave`, which
+//! is nested in `project`.
+//!
+//! Tags are rewritten in place in all their syntaxes: frontmatter lists and
+//! strings, `#hashtags`, `#multi word tags#` and `:colon:tags:`. Inline tags
+//! are found like the parser does, so fenced code blocks and disabled
+//! syntaxes are left alone.
+
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::config::MarkdownConfig;
+use crate::error::Error;
+use crate::parse::{self, InlineTag};
+
+/// Frontmatter keys holding tags, checked by the parser as well.
+const TAG_KEYS: [&str; 4] = ["tags", "tag", "keywords", "keyword"];
+
+/// Return `tag` without empty levels and whitespace around its levels, e.g.
+/// `project/weave` for `/project / weave/`.
+pub(crate) fn normalize(tag: &str) -> String {
+    tag.split('/')
+        .map(str::trim)
+        .filter(|level| !level.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Return `tag` followed by the tags it is nested in, e.g. `a/b/c`, `a/b` and
+/// `a` for `a/b/c`.
+pub(crate) fn with_parents(tag: &str) -> impl Iterator<Item = &str> {
+    std::iter::once(tag).chain(tag.rmatch_indices('/').map(|(i, _)| &tag[..i]))
+}
+
+/// Return `true` if `tag` is `parent` or nested in it, ignoring case.
+pub(crate) fn is_within(tag: &str, parent: &str) -> bool {
+    with_parents(tag).any(|tag| tag.eq_ignore_ascii_case(parent))
+}
+
+/// Return `true` if `tag` can be written in frontmatter, i.e. it has no
+/// surrounding whitespace, no leading `#`, no empty levels and nothing that
+/// needs YAML quoting.
+pub(crate) fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && normalize(tag) == tag
+        && !tag.starts_with(['#', '-', '!', '&', '*', '%', '@', '`', '|', '>', '?'])
+        && !tag.contains(|c: char| c.is_control() || ",[]{}\"'".contains(c))
+        && !tag.contains(" #")
+        && !tag.contains(": ")
+}
+
+/// Replace the tags `from` (lowercase) by `to` in `content`, or delete them
+/// if `to` is `None`. Returns `None` if the content has none of the tags.
+///
+/// Renamed frontmatter entries that duplicate another entry are dropped, so
+/// merging tags does not leave `tags: [rust, rust]` behind.
+pub(crate) fn rewrite_tags(
+    content: &str,
+    path: &Path,
+    from: &[String],
+    to: Option<&str>,
+    markdown: &MarkdownConfig,
+) -> Result<Option<String>, Error> {
+    let matches = |name: &str| from.contains(&normalize(name).to_lowercase());
+    let unsupported = |tag: &str| Error::TagSyntax {
+        tag: tag.to_owned(),
+        path: path.to_path_buf(),
+    };
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+
+    let (frontmatter, body_start) = parse::extract_frontmatter(content);
+    if let Some(frontmatter) = frontmatter {
+        let offset = frontmatter.as_ptr() as usize - content.as_ptr() as usize;
+        let shift = |span: Range<usize>| span.start + offset..span.end + offset;
+
+        for entries in frontmatter_tags(frontmatter) {
+            let mut seen: HashSet<String> = entries
+                .iter()
+                .map(|entry| normalize(&frontmatter[entry.name.clone()]).to_lowercase())
+                .filter(|name| !from.contains(name))
+                .collect();
+
+            for entry in entries {
+                if !matches(&frontmatter[entry.name.clone()]) {
+                    continue;
+                }
+                match to {
+                    Some(to) if seen.insert(to.to_lowercase()) => {
+                        if entry.style == ListStyle::Words && to.contains(char::is_whitespace) {
+                            return Err(unsupported(to));
+                        }
+                        edits.push((shift(entry.name), to.to_owned()));
+                    }
+                    _ => edits.push((shift(entry.remove), String::new())),
+                }
+            }
+        }
+    }
+
+    let body = &content[body_start..];
+    let tags = parse::inline_tag_spans(body, markdown);
+    let shift = |span: &Range<usize>| span.start + body_start..span.end + body_start;
+    let mut i = 0;
+    while i < tags.len() {
+        let tag = &tags[i];
+        let span = shift(&tag.span);
+        i += 1;
+
+        if tag.syntax == InlineTag::Colon {
+            // Handle a whole `:tag1:tag2:` sequence at once, deleting all of
+            // its tags removes the surrounding colons as well.
+            let mut sequence = vec![span];
+            while i < tags.len()
+                && tags[i].syntax == InlineTag::Colon
+                && tags[i].span.start == tags[i - 1].span.end + 1
+            {
+                sequence.push(shift(&tags[i].span));
+                i += 1;
+            }
+
+            let matching: Vec<_> = sequence
+                .iter()
+                .filter(|span| matches(&content[(*span).clone()]))
+                .cloned()
+                .collect();
+            match to {
+                _ if matching.is_empty() => {}
+                Some(to) if !to.bytes().all(parse::is_tag_char) => return Err(unsupported(to)),
+                Some(to) => edits.extend(matching.into_iter().map(|span| (span, to.to_owned()))),
+                None if matching.len() == sequence.len() => {
+                    let all = sequence[0].start - 1..sequence[sequence.len() - 1].end + 1;
+                    edits.push((with_space(content, all), String::new()));
+                }
+                // The closing colon of each tag goes with it.
+                None => edits.extend(
+                    matching
+                        .into_iter()
+                        .map(|span| (span.start..span.end + 1, String::new())),
+                ),
+            }
+            continue;
+        }
+
+        if !matches(&content[span.clone()]) {
+            continue;
+        }
+        // The whole tag, including `#` and the closing `#` of multi-word tags.
+        let whole = match tag.syntax {
+            InlineTag::Multiword => span.start - 1..span.end + 1,
+            _ => span.start - 1..span.end,
+        };
+        let Some(to) = to else {
+            edits.push((with_space(content, whole), String::new()));
+            continue;
+        };
+
+        if parse::is_tag_name(to) {
+            edits.push((whole, format!("#{to}")));
+        } else if markdown.multiword_tags && is_multiword_tag(to) {
+            edits.push((whole, format!("#{to}#")));
+        } else {
+            return Err(unsupported(to));
+        }
+    }
+
+    if edits.is_empty() {
+        return Ok(None);
+    }
+
+    let mut content = content.to_owned();
+    let mut end = content.len();
+    edits.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+    for (span, text) in edits {
+        // Adjacent deleted tags may both claim the space between them.
+        let span = span.start..span.end.min(end);
+        end = span.start;
+        content.replace_range(span, &text);
+    }
+    Ok(Some(content))
+}
+
+/// Return `true` if `tag` can be written as `#multi word tag#`.
+fn is_multiword_tag(tag: &str) -> bool {
+    parse::is_multiword_name(tag.as_bytes())
+}
+
+/// Extend `span` over one adjacent space, so removing it from a line does not
+/// leave a double space behind.
+fn with_space(content: &str, span: Range<usize>) -> Range<usize> {
+    let is_space = |b: Option<&u8>| matches!(b, Some(b' ' | b'\t'));
+    let bytes = content.as_bytes();
+    if is_space(bytes.get(span.end)) {
+        span.start..span.end + 1
+    } else if span.start > 0 && is_space(bytes.get(span.start - 1)) {
+        span.start - 1..span.end
+    } else {
+        span
+    }
+}
+
+/// How a frontmatter tag list is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ListStyle {
+    /// `tags: [a, b]`
+    Flow,
+    /// `tags:` followed by `- a` lines.
+    Block,
+    /// `tags: a b`, a space-separated string.
+    Words,
+}
+
+/// A tag entry in the frontmatter.
+#[derive(Debug)]
+struct FrontmatterTag {
+    /// Byte range of the tag name, without quotes or `#`.
+    name: Range<usize>,
+    /// Byte range to remove to delete the entry, including separators.
+    remove: Range<usize>,
+    style: ListStyle,
+}
+
+/// Return the tag entries of each top-level tag key in `frontmatter`.
+fn frontmatter_tags(frontmatter: &str) -> Vec<Vec<FrontmatterTag>> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in frontmatter.split_inclusive('\n') {
+        lines.push((offset, line));
+        offset += line.len();
+    }
+
+    let mut tags = Vec::new();
+    for (i, &(start, line)) in lines.iter().enumerate() {
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        if !TAG_KEYS.contains(&key.trim().to_lowercase().as_str()) {
+            continue;
+        }
+
+        let value_start = start + key.len() + 1;
+        let value = value.trim_end();
+        let trimmed = value.trim_start();
+        let value_start = value_start + value.len() - trimmed.len();
+
+        if trimmed.starts_with('[') {
+            let Some(end) = frontmatter[value_start..].find(']') else {
+                continue;
+            };
+            tags.push(list_entries(
+                frontmatter,
+                value_start + 1..value_start + end,
+                ListStyle::Flow,
+            ));
+        } else if trimmed.is_empty() {
+            let mut entries = Vec::new();
+            for &(start, line) in &lines[i + 1..] {
+                let item = line.trim();
+                if item.is_empty() {
+                    continue;
+                }
+                let Some(item) = item.strip_prefix('-') else {
+                    break;
+                };
+                let item = item.trim_start();
+                let item_start = item.as_ptr() as usize - frontmatter.as_ptr() as usize;
+                // The last line has no newline, remove the one before it.
+                let remove = if line.ends_with('\n') || start == 0 {
+                    start..start + line.len()
+                } else {
+                    start - 1..start + line.len()
+                };
+                entries.push(FrontmatterTag {
+                    name: entry_name(frontmatter, item_start..item_start + item.len()),
+                    remove,
+                    style: ListStyle::Block,
+                });
+            }
+            tags.push(entries);
+        } else {
+            let mut span = value_start..value_start + trimmed.len();
+            if let Some(unquoted) = unquote(frontmatter, span.clone()) {
+                span = unquoted;
+            }
+            tags.push(list_entries(frontmatter, span, ListStyle::Words));
+        }
+    }
+
+    tags
+}
+
+/// Return the entries of a flow list or a space-separated string in `span`.
+fn list_entries(frontmatter: &str, span: Range<usize>, style: ListStyle) -> Vec<FrontmatterTag> {
+    let text = &frontmatter[span.clone()];
+    let parts: Vec<&str> = match style {
+        ListStyle::Words => text.split_whitespace().collect(),
+        _ => text.split(',').map(str::trim).collect(),
+    };
+    let items: Vec<Range<usize>> = parts
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let start = part.as_ptr() as usize - frontmatter.as_ptr() as usize;
+            start..start + part.len()
+        })
+        .collect();
+
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            // Remove the separator after the entry, or before the last one.
+            let remove = if let Some(next) = items.get(i + 1) {
+                item.start..next.start
+            } else if i > 0 {
+                items[i - 1].end..item.end
+            } else {
+                item.clone()
+            };
+            FrontmatterTag {
+                name: entry_name(frontmatter, item.clone()),
+                remove,
+                style,
+            }
+        })
+        .collect()
+}
+
+/// Return the tag name of the entry at `span`, without quotes and `#`.
+fn entry_name(frontmatter: &str, span: Range<usize>) -> Range<usize> {
+    let span = unquote(frontmatter, span.clone()).unwrap_or(span);
+    if frontmatter[span.clone()].starts_with('#') {
+        span.start + 1..span.end
+    } else {
+        span
+    }
+}
+
+/// Return the range inside the quotes if the value at `span` is quoted.
+fn unquote(frontmatter: &str, span: Range<usize>) -> Option<Range<usize>> {
+    let value = &frontmatter[span.clone()];
+    let quoted = value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')));
+    quoted.then(|| span.start + 1..span.end - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(content: &str, from: &[&str], to: Option<&str>) -> Option<String> {
+        let markdown = MarkdownConfig {
+            hashtags: true,
+            colon_tags: true,
+            multiword_tags: true,
+            ..MarkdownConfig::default()
+        };
+        let from: Vec<String> = from.iter().map(|tag| tag.to_string()).collect();
+        rewrite_tags(content, Path::new("note.md"), &from, to, &markdown).unwrap()
+    }
+
+    #[test]
+    fn test_rename_frontmatter_styles() {
+        let content = "---\ntags: [rust, \"#Old\", go]\nkeywords: old other\n---\n# Title";
+        assert_eq!(
+            rewrite(content, &["old"], Some("new")).unwrap(),
+            "---\ntags: [rust, \"#new\", go]\nkeywords: new other\n---\n# Title"
+        );
+
+        let content = "---\ntitle: Old\ntags:\n  - old\n  - 'other'\n---\n";
+        assert_eq!(
+            rewrite(content, &["old"], Some("new")).unwrap(),
+            "---\ntitle: Old\ntags:\n  - new\n  - 'other'\n---\n"
+        );
+        assert!(rewrite(content, &["missing"], Some("new")).is_none());
+    }
+
+    #[test]
+    fn test_delete_frontmatter_entries() {
+        let content = "---\ntags: [a, old, b]\ntag: old\nkeywords: [a, old]\n---\n";
+        assert_eq!(
+            rewrite(content, &["old"], None).unwrap(),
+            "---\ntags: [a, b]\ntag: \nkeywords: [a]\n---\n"
+        );
+
+        let content = "---\ntags:\n  - a\n  - old\ndate: 2024-01-01\n---\n";
+        assert_eq!(
+            rewrite(content, &["old"], None).unwrap(),
+            "---\ntags:\n  - a\ndate: 2024-01-01\n---\n"
+        );
+        let content = "---\ntags:\n  - a\n  - old\n---\n";
+        assert_eq!(
+            rewrite(content, &["old"], None).unwrap(),
+            "---\ntags:\n  - a\n---\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_drops_duplicates() {
+        let content = "---\ntags: [a, b, c]\n---\n";
+        assert_eq!(
+            rewrite(content, &["a", "c"], Some("b")).unwrap(),
+            "---\ntags: [b]\n---\n"
+        );
+        assert_eq!(
+            rewrite(content, &["a", "c"], Some("d")).unwrap(),
+            "---\ntags: [d, b]\n---\n"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_inline_tags() {
+        let content = "# Title\n\n#old and #Old, #multi word# :a:old:b:\n\n```\n#old\n```\n";
+        assert_eq!(
+            rewrite(content, &["old", "multi word"], Some("new")).unwrap(),
+            "# Title\n\n#new and #new, #new :a:new:b:\n\n```\n#old\n```\n"
+        );
+
+        let content = "---\ntags: [project/weave]\n---\n#project/weave #project/weave/ui";
+        assert_eq!(
+            rewrite(content, &["project/weave"], Some("work/weave idea")).unwrap(),
+            "---\ntags: [work/weave idea]\n---\n#work/weave idea# #project/weave/ui"
+        );
+    }
+
+    #[test]
+    fn test_delete_inline_tags() {
+        let content = "See #old here.\n:old: :a:old:\n#old #old\n";
+        assert_eq!(
+            rewrite(content, &["old"], None).unwrap(),
+            "See here.\n:a:\n\n"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_syntax() {
+        let markdown = MarkdownConfig {
+            hashtags: true,
+            colon_tags: true,
+            multiword_tags: true,
+            ..MarkdownConfig::default()
+        };
+        let from = ["old".to_owned()];
+        let path = Path::new("note.md");
+        let err = rewrite_tags("#old :old:", path, &from, Some("new tag"), &markdown);
+        assert!(matches!(err, Err(Error::TagSyntax { .. })));
+
+        let content = "---\ntags: old\n---\n";
+        let err = rewrite_tags(content, path, &from, Some("new tag"), &markdown);
+        assert!(matches!(err, Err(Error::TagSyntax { .. })));
+        let content = "---\ntags: [old]\n---\n#old";
+        let new = rewrite_tags(content, path, &from, Some("new tag"), &markdown).unwrap();
+        assert_eq!(new.unwrap(), "---\ntags: [new tag]\n---\n#new tag#");
+    }
+
+    #[test]
+    fn test_is_valid_tag() {
+        assert!(is_valid_tag("rust"));
+        assert!(is_valid_tag("multi word"));
+        assert!(!is_valid_tag(""));
+        assert!(!is_valid_tag(" rust"));
+        assert!(!is_valid_tag("#rust"));
+        assert!(!is_valid_tag("a, b"));
+        assert!(is_valid_tag("project/weave ui"));
+        assert!(!is_valid_tag("project/"));
+        assert!(!is_valid_tag("project / weave"));
+    }
+
+    #[test]
+    fn test_nested_tags() {
+        assert_eq!(normalize("/project / weave//ui "), "project/weave/ui");
+        let parents: Vec<&str> = with_parents("a/b/c").collect();
+        assert_eq!(parents, ["a/b/c", "a/b", "a"]);
+        assert!(is_within("Project/Weave", "project"));
+        assert!(is_within("project", "project"));
+        assert!(!is_within("projects/weave", "project"));
+        assert!(!is_within("project", "project/weave"));
+    }
+}
@@ -0,0 +1,392 @@
+//! Handlebars rendering compatible with zk's filename and note templates.
+
+use handlebars::{
+    Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderErrorReason,
+};
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::note::Note;
+use crate::parse::parse_date_string;
+
+/// Render the Handlebars `template` with `context`. `name` identifies the
+/// template in error messages.
+pub(crate) fn render(
+    template: &str,
+    context: &impl Serialize,
+    name: &str,
+) -> Result<String, Error> {
+    registry()
+        .render_template(template, context)
+        .map_err(|source| Error::Template {
+            name: name.to_owned(),
+            source: Box::new(source),
+        })
+}
+
+fn registry() -> Handlebars<'static> {
+    let mut registry = Handlebars::new();
+    // zk writes Markdown and filenames, not HTML.
+    registry.register_escape_fn(handlebars::no_escape);
+    registry.register_helper("slug", Box::new(slug_helper));
+    registry.register_helper("format-date", Box::new(format_date_helper));
+    registry.register_helper("get-date", Box::new(get_date_helper));
+    registry.register_helper("concat", Box::new(concat_helper));
+    registry.register_helper("join", Box::new(join_helper));
+    registry
+}
+
+/// Variables available in note templates, see [`Note::format`].
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct NoteContext<'a> {
+    pub filename: &'a str,
+    pub filename_stem: &'a str,
+    pub path: &'a std::path::Path,
+    pub abs_path: &'a std::path::Path,
+    pub title: &'a str,
+    /// The title, or the path of notes without one.
+    pub title_or_path: std::borrow::Cow<'a, str>,
+    pub link: &'a str,
+    pub lead: &'a str,
+    pub body: &'a str,
+    pub raw_content: &'a str,
+    pub word_count: usize,
+    pub tags: &'a [String],
+    pub metadata: &'a std::collections::BTreeMap<String, serde_yaml::Value>,
+    pub created: jiff::Timestamp,
+    pub modified: jiff::Timestamp,
+}
+
+impl<'a> NoteContext<'a> {
+    pub(crate) fn new(note: &'a Note) -> Self {
+        NoteContext {
+            filename: note.filename(),
+            filename_stem: note.filename_stem(),
+            path: note.path(),
+            abs_path: note.abs_path(),
+            title: note.title(),
+            title_or_path: match note.title() {
+                "" => note.path().to_string_lossy(),
+                title => title.into(),
+            },
+            link: note.link(),
+            lead: note.lead(),
+            body: note.body(),
+            raw_content: note.raw_content(),
+            word_count: note.word_count(),
+            tags: note.tags(),
+            metadata: note.metadata(),
+            created: note.created(),
+            modified: note.modified(),
+        }
+    }
+}
+
+/// Turn `text` into a lowercase, hyphen-separated string safe for filenames.
+pub(crate) fn slug(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if ch.is_alphanumeric() {
+            slug.extend(ch.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_owned()
+}
+
+/// Format a date according to one of zk's named formats or a strftime pattern.
+/// Without a format, dates are rendered as `YYYY-MM-DD`. Fails on invalid
+/// patterns.
+pub(crate) fn format_date(
+    date: jiff::Timestamp,
+    format: Option<&str>,
+) -> Result<String, jiff::Error> {
+    let zoned = date.to_zoned(jiff::tz::TimeZone::system());
+
+    let pattern = match format {
+        None => "%Y-%m-%d",
+        Some("short") => "%m/%d/%Y",
+        Some("medium") => "%b %-d, %Y",
+        Some("long") => "%B %-d, %Y",
+        Some("full") => "%A, %B %-d, %Y",
+        Some("year") => "%Y",
+        Some("time") => "%H:%M",
+        Some("timestamp") => "%Y%m%d%H%M",
+        Some("timestamp-unix") => return Ok(date.as_second().to_string()),
+        Some("elapsed") => return Ok(elapsed(date, jiff::Timestamp::now())),
+        Some(pattern) => pattern,
+    };
+
+    jiff::fmt::strtime::format(pattern, &zoned)
+}
+
+/// Resolve a date expression like `today`, `yesterday`, `3 days ago`,
+/// `in 2 days` or an ISO 8601 date relative to `now`.
+pub(crate) fn get_date(expr: &str, now: jiff::Timestamp) -> Option<jiff::Timestamp> {
+    const DAY: i64 = 86_400;
+
+    let lower = expr.trim().to_lowercase();
+    let days = match lower.as_str() {
+        "now" | "today" => 0,
+        "yesterday" => -1,
+        "tomorrow" => 1,
+        _ => {
+            if let Some(n) = lower.strip_suffix(" days ago") {
+                -n.trim().parse::<i64>().ok()?
+            } else if let Some(n) = lower
+                .strip_prefix("in ")
+                .and_then(|rest| rest.strip_suffix(" days"))
+            {
+                n.trim().parse::<i64>().ok()?
+            } else {
+                return parse_date_string(expr);
+            }
+        }
+    };
+
+    now.checked_add(jiff::SignedDuration::from_secs(days * DAY))
+        .ok()
+}
+
+/// Describe the time between `date` and `now` in words, e.g. "3 days ago".
+fn elapsed(date: jiff::Timestamp, now: jiff::Timestamp) -> String {
+    let seconds = now.as_second() - date.as_second();
+    if seconds < 60 {
+        return "now".into();
+    }
+
+    let (count, unit) = match seconds {
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86_400 => (s / 3600, "hour"),
+        s if s < 30 * 86_400 => (s / 86_400, "day"),
+        s if s < 365 * 86_400 => (s / (30 * 86_400), "month"),
+        s => (s / (365 * 86_400), "year"),
+    };
+
+    if count == 1 {
+        format!("1 {unit} ago")
+    } else {
+        format!("{count} {unit}s ago")
+    }
+}
+
+/// `{{slug title}}`
+fn slug_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let text = h.param(0).and_then(|p| p.value().as_str()).unwrap_or("");
+    out.write(&slug(text))?;
+    Ok(())
+}
+
+/// `{{format-date now}}` or `{{format-date now "%Y-%m"}}`
+fn format_date_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let date = h
+        .param(0)
+        .and_then(|p| p.value().as_str())
+        .and_then(parse_date_string);
+    let format = h.param(1).and_then(|p| p.value().as_str());
+
+    if let Some(date) = date {
+        let formatted = format_date(date, format).map_err(|err| {
+            RenderErrorReason::Other(format!("invalid date format {format:?}: {err}"))
+        })?;
+        out.write(&formatted)?;
+    }
+
+    Ok(())
+}
+
+/// `{{get-date "yesterday"}}`, usually combined with `format-date`.
+fn get_date_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let date = h
+        .param(0)
+        .and_then(|p| p.value().as_str())
+        .and_then(|expr| get_date(expr, jiff::Timestamp::now()));
+
+    if let Some(date) = date {
+        out.write(&date.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// `{{concat "a" title "b"}}`
+fn concat_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    for param in h.params() {
+        match param.value() {
+            handlebars::JsonValue::String(s) => out.write(s)?,
+            handlebars::JsonValue::Null => {}
+            value => out.write(&value.to_string())?,
+        }
+    }
+    Ok(())
+}
+
+/// `{{join tags ", "}}`
+fn join_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let separator = h.param(1).and_then(|p| p.value().as_str()).unwrap_or(", ");
+    let Some(items) = h.param(0).and_then(|p| p.value().as_array()) else {
+        return Ok(());
+    };
+
+    let items: Vec<String> = items
+        .iter()
+        .map(|item| match item {
+            handlebars::JsonValue::String(s) => s.clone(),
+            item => item.to_string(),
+        })
+        .collect();
+    out.write(&items.join(separator))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("Hello World"), "hello-world");
+        assert_eq!(slug("  Rust: the good parts! "), "rust-the-good-parts");
+        assert_eq!(slug("Über Café"), "über-café");
+        assert_eq!(slug(""), "");
+    }
+
+    #[test]
+    fn test_format_date_named() {
+        let date: jiff::Timestamp = "2024-06-15T12:00:00Z".parse().unwrap();
+        assert_eq!(format_date(date, Some("year")).unwrap(), "2024");
+        assert_eq!(
+            format_date(date, Some("timestamp-unix")).unwrap(),
+            "1718452800"
+        );
+    }
+
+    #[test]
+    fn test_format_date_invalid_pattern() {
+        let date: jiff::Timestamp = "2024-06-15T12:00:00Z".parse().unwrap();
+        assert!(format_date(date, Some("%E")).is_err());
+
+        let context = std::collections::HashMap::from([("now", "2024-06-15T12:00:00Z")]);
+        let result = render("{{format-date now '%E'}}-x", &context, "filename");
+        assert!(matches!(result, Err(Error::Template { .. })));
+    }
+
+    #[test]
+    fn test_elapsed() {
+        let now: jiff::Timestamp = "2024-06-15T12:00:00Z".parse().unwrap();
+        let date: jiff::Timestamp = "2024-06-12T12:00:00Z".parse().unwrap();
+        assert_eq!(elapsed(date, now), "3 days ago");
+        assert_eq!(elapsed(now, now), "now");
+    }
+
+    #[test]
+    fn test_get_date() {
+        let now: jiff::Timestamp = "2024-06-15T12:00:00Z".parse().unwrap();
+        let yesterday: jiff::Timestamp = "2024-06-14T12:00:00Z".parse().unwrap();
+        assert_eq!(get_date("today", now), Some(now));
+        assert_eq!(get_date("Yesterday", now), Some(yesterday));
+        assert_eq!(get_date("1 days ago", now), Some(yesterday));
+        assert_eq!(
+            get_date("in 2 days", now),
+            "2024-06-17T12:00:00Z".parse().ok()
+        );
+        assert!(get_date("2024-01-01", now).is_some());
+        assert!(get_date("someday", now).is_none());
+    }
+
+    #[test]
+    fn test_render_nested_helpers() {
+        #[derive(Serialize)]
+        struct Ctx {}
+
+        let rendered = render(
+            "{{concat \"y\" (format-date (get-date \"2024-03-01\") \"year\")}}",
+            &Ctx {},
+            "test",
+        )
+        .unwrap();
+        assert_eq!(rendered, "y2024");
+    }
+
+    #[test]
+    fn test_render_helpers() {
+        #[derive(Serialize)]
+        struct Ctx {
+            title: &'static str,
+            now: &'static str,
+        }
+
+        let ctx = Ctx {
+            title: "My Note",
+            now: "2024-06-15T12:00:00Z",
+        };
+
+        let rendered = render(
+            "{{ slug title }}-{{format-date now \"year\"}}",
+            &ctx,
+            "test",
+        )
+        .unwrap();
+        assert_eq!(rendered, "my-note-2024");
+    }
+
+    #[test]
+    fn test_render_join() {
+        #[derive(Serialize)]
+        struct Ctx {
+            tags: Vec<&'static str>,
+        }
+
+        let ctx = Ctx {
+            tags: vec!["a", "b"],
+        };
+        assert_eq!(
+            render("{{join tags \" | \"}}", &ctx, "test").unwrap(),
+            "a | b"
+        );
+        assert_eq!(render("{{join tags}}", &ctx, "test").unwrap(), "a, b");
+    }
+
+    #[test]
+    fn test_render_does_not_escape() {
+        #[derive(Serialize)]
+        struct Ctx {
+            title: &'static str,
+        }
+
+        let rendered = render("{{title}}", &Ctx { title: "A & B" }, "test").unwrap();
+        assert_eq!(rendered, "A & B");
+    }
+}
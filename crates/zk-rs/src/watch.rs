@@ -0,0 +1,295 @@
+//! Watch a notebook directory for changes of its files.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{EventKind, Watcher};
+
+use crate::error::Error;
+use crate::event::FileChange;
+
+/// How long changes are collected after the first one of a batch.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches the files of a notebook and reports their changes in batches, to
+/// be applied with [`Notebook::apply`](crate::Notebook::apply).
+///
+/// The changes of a batch are coalesced to one change per file, so a note
+/// saved by writing a temporary file or moved in several reported steps is
+/// changed once.
+pub struct NotebookWatcher {
+    /// Watches as long as it lives.
+    _watcher: notify::RecommendedWatcher,
+    changes: mpsc::Receiver<FileChange>,
+}
+
+impl NotebookWatcher {
+    /// Watch the notebook directory `root` and its subdirectories.
+    pub fn new(root: impl AsRef<Path>) -> Result<Self, Error> {
+        let (tx, changes) = mpsc::channel();
+        let mut moved_from = None;
+        let mut watcher = notify::recommended_watcher(move |result| {
+            if let Ok(event) = result {
+                for change in file_changes(event, &mut moved_from) {
+                    let _ = tx.send(change);
+                }
+            }
+        })?;
+        watcher.watch(root.as_ref(), notify::RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// Wait for files to change and return the coalesced changes, or `None`
+    /// if the watcher stopped.
+    pub fn recv(&self) -> Option<Vec<FileChange>> {
+        let first = self.changes.recv().ok()?;
+        std::thread::sleep(DEBOUNCE);
+        let changes = std::iter::once(first).chain(self.changes.try_iter());
+        Some(coalesce(changes))
+    }
+}
+
+/// Translate a file system event into file changes. `moved_from` keeps the
+/// tracker and path of the last file moved away, to pair it with the event of
+/// its new path.
+fn file_changes(
+    event: notify::Event,
+    moved_from: &mut Option<(Option<usize>, PathBuf)>,
+) -> Vec<FileChange> {
+    let tracker = event.tracker();
+    let paths = event.paths;
+    let from = moved_from.take();
+    match event.kind {
+        // inotify also reports the two halves of a rename on their own.
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            match <[PathBuf; 2]>::try_from(paths) {
+                Ok([from, to]) => vec![FileChange::Renamed { from, to }],
+                Err(_) => Vec::new(),
+            }
+        }
+        // Removed unless moved within the notebook.
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            if let [path] = paths.as_slice() {
+                *moved_from = Some((tracker, path.clone()));
+            }
+            paths.into_iter().map(FileChange::Removed).collect()
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => match (from, paths.as_slice()) {
+            (Some((from_tracker, from)), [to]) if from_tracker == tracker => {
+                vec![FileChange::Renamed {
+                    from,
+                    to: to.clone(),
+                }]
+            }
+            _ => paths.into_iter().map(FileChange::Written).collect(),
+        },
+        EventKind::Remove(_) => paths.into_iter().map(FileChange::Removed).collect(),
+        EventKind::Access(AccessKind::Close(AccessMode::Write))
+        | EventKind::Create(_)
+        | EventKind::Modify(ModifyKind::Name(_)) => {
+            paths.into_iter().map(FileChange::Written).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Merge `changes` to at most one change per file, keeping their order.
+fn coalesce(changes: impl IntoIterator<Item = FileChange>) -> Vec<FileChange> {
+    let mut merged: Vec<FileChange> = Vec::new();
+    let renamed_to = |merged: &[FileChange], path: &Path| {
+        merged
+            .iter()
+            .position(|change| matches!(change, FileChange::Renamed { to, .. } if to == path))
+    };
+    let forget = |merged: &mut Vec<FileChange>, path: &Path| {
+        merged.retain(|change| {
+            !matches!(change, FileChange::Written(p) | FileChange::Removed(p) if p == path)
+        });
+    };
+
+    for change in changes {
+        match change {
+            FileChange::Written(path) => {
+                // Renamed files are read again anyway.
+                if renamed_to(&merged, &path).is_none() {
+                    forget(&mut merged, &path);
+                    merged.push(FileChange::Written(path));
+                }
+            }
+            FileChange::Removed(path) => match renamed_to(&merged, &path) {
+                // Moved away and deleted, as editors do with backup files.
+                Some(pos) => {
+                    let FileChange::Renamed { from, .. } = merged.remove(pos) else {
+                        unreachable!();
+                    };
+                    let rewritten = merged
+                        .iter()
+                        .any(|change| matches!(change, FileChange::Written(p) if *p == from));
+                    if !rewritten {
+                        merged.insert(pos, FileChange::Removed(from));
+                    }
+                }
+                None => {
+                    forget(&mut merged, &path);
+                    merged.push(FileChange::Removed(path));
+                }
+            },
+            FileChange::Renamed { from, to } => {
+                // Reported once paired and once as a whole.
+                if matches!(merged.last(), Some(FileChange::Renamed { from: f, to: t }) if *f == from && *t == to)
+                {
+                    continue;
+                }
+                merged.retain(|change| match change {
+                    FileChange::Removed(path) => *path != from,
+                    FileChange::Written(path) => *path != to,
+                    FileChange::Renamed { .. } => true,
+                });
+                // Follow a note moved several times back to where it was.
+                let from = match renamed_to(&merged, &from) {
+                    Some(pos) => {
+                        let FileChange::Renamed { from, .. } = merged.remove(pos) else {
+                            unreachable!();
+                        };
+                        from
+                    }
+                    None => from,
+                };
+                merged.push(FileChange::Renamed { from, to });
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use FileChange::*;
+
+    fn path(path: &str) -> PathBuf {
+        PathBuf::from(path)
+    }
+
+    fn renamed(from: &str, to: &str) -> FileChange {
+        Renamed {
+            from: path(from),
+            to: path(to),
+        }
+    }
+
+    #[test]
+    fn test_coalesce_writes() {
+        let changes = coalesce([
+            Written(path("a.md")),
+            Written(path("b.md")),
+            Written(path("a.md")),
+        ]);
+        assert_eq!(changes, [Written(path("b.md")), Written(path("a.md"))]);
+
+        // Editors deleting and writing a note again.
+        let changes = coalesce([Removed(path("a.md")), Written(path("a.md"))]);
+        assert_eq!(changes, [Written(path("a.md"))]);
+    }
+
+    #[test]
+    fn test_coalesce_renames() {
+        // inotify reports both halves and then the whole rename.
+        let changes = coalesce([
+            Removed(path("a.md")),
+            Written(path("b.md")),
+            renamed("a.md", "b.md"),
+            Written(path("b.md")),
+        ]);
+        assert_eq!(changes, [renamed("a.md", "b.md")]);
+
+        let changes = coalesce([renamed("a.md", "b.md"), renamed("b.md", "c.md")]);
+        assert_eq!(changes, [renamed("a.md", "c.md")]);
+
+        let changes = coalesce([renamed("a.md", "b.md"), Removed(path("b.md"))]);
+        assert_eq!(changes, [Removed(path("a.md"))]);
+    }
+
+    #[test]
+    fn test_paired_rename() {
+        use notify::event::{CreateKind, EventKind, ModifyKind, RenameMode};
+
+        let name = |mode| EventKind::Modify(ModifyKind::Name(mode));
+        let mut moved_from = None;
+        let mut changes = Vec::new();
+        for event in [
+            notify::Event::new(name(RenameMode::From))
+                .add_path(path("a.md"))
+                .set_tracker(1),
+            notify::Event::new(name(RenameMode::To))
+                .add_path(path("b.md"))
+                .set_tracker(1),
+            notify::Event::new(name(RenameMode::Both))
+                .add_path(path("a.md"))
+                .add_path(path("b.md"))
+                .set_tracker(1),
+        ] {
+            changes.extend(file_changes(event, &mut moved_from));
+        }
+        assert_eq!(coalesce(changes), [renamed("a.md", "b.md")]);
+
+        // Moved away and another file created in between.
+        let mut changes = Vec::new();
+        for event in [
+            notify::Event::new(name(RenameMode::From))
+                .add_path(path("a.md"))
+                .set_tracker(2),
+            notify::Event::new(EventKind::Create(CreateKind::File)).add_path(path("c.md")),
+            notify::Event::new(name(RenameMode::To))
+                .add_path(path("b.md"))
+                .set_tracker(2),
+        ] {
+            changes.extend(file_changes(event, &mut moved_from));
+        }
+        assert_eq!(
+            coalesce(changes),
+            [
+                Removed(path("a.md")),
+                Written(path("c.md")),
+                Written(path("b.md"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_backup_save() {
+        // Vim moves the note to a backup, writes it and deletes the backup.
+        let changes = coalesce([
+            renamed("a.md", "a.md~"),
+            Written(path("a.md")),
+            Removed(path("a.md~")),
+        ]);
+        assert_eq!(changes, [Written(path("a.md"))]);
+    }
+
+    #[test]
+    fn test_watch() {
+        let dir = tempfile::tempdir().unwrap();
+        let watcher = NotebookWatcher::new(dir.path()).unwrap();
+        std::fs::write(dir.path().join("a.md"), "# A").unwrap();
+
+        let changes = watcher.recv().unwrap();
+        assert!(changes.contains(&Written(dir.path().join("a.md"))));
+
+        std::fs::rename(dir.path().join("a.md"), dir.path().join("b.md")).unwrap();
+        let changes = watcher.recv().unwrap();
+        assert_eq!(
+            changes,
+            [Renamed {
+                from: dir.path().join("a.md"),
+                to: dir.path().join("b.md"),
+            }]
+        );
+    }
+}